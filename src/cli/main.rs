@@ -2,8 +2,8 @@ use common::config::{config_folder_path, Config};
 
 use std::{
     env,
-    io::{Read, Write},
-    path::PathBuf,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
     process,
 };
 
@@ -89,6 +89,10 @@ fn socket_file(conf_file: Option<PathBuf>) -> PathBuf {
         eprintln!("Error getting config: {}", e);
         process::exit(1);
     });
+    if let Some(name) = config.rpc_abstract_socket {
+        return PathBuf::from(format!("\0{}", name));
+    }
+
     let data_dir = config.data_dir.unwrap_or_else(|| {
         config_folder_path().unwrap_or_else(|e| {
             eprintln!("{}", e);
@@ -106,13 +110,37 @@ fn socket_file(conf_file: Option<PathBuf>) -> PathBuf {
     .collect()
 }
 
+// The standard library can't connect to an abstract socket, so we go through mio and get the
+// connected socket back in blocking mode.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn connect(socket_file: &Path) -> io::Result<UnixStream> {
+    use std::os::unix::{
+        ffi::OsStrExt,
+        io::{FromRawFd, IntoRawFd},
+    };
+
+    if socket_file.as_os_str().as_bytes().first() != Some(&0) {
+        return UnixStream::connect(socket_file);
+    }
+
+    let stream = mio::net::UnixStream::connect(socket_file)?;
+    let stream = unsafe { UnixStream::from_raw_fd(stream.into_raw_fd()) };
+    stream.set_nonblocking(false)?;
+    Ok(stream)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn connect(socket_file: &Path) -> io::Result<UnixStream> {
+    UnixStream::connect(socket_file)
+}
+
 fn trimmed(mut vec: Vec<u8>, bytes_read: usize) -> Vec<u8> {
     vec.truncate(bytes_read);
 
     // Until there is some whatever-newline character, pop.
     while let Some(byte) = vec.last() {
         // Of course, we assume utf-8
-        if byte < &0x0a || byte > &0x0d {
+        if !(&0x0a..=&0x0d).contains(&byte) {
             break;
         }
         vec.pop();
@@ -128,7 +156,7 @@ fn main() {
     let socket_file = socket_file(conf_file);
     let mut raw_response = vec![0; 256];
 
-    let mut socket = UnixStream::connect(&socket_file).unwrap_or_else(|e| {
+    let mut socket = connect(&socket_file).unwrap_or_else(|e| {
         eprintln!("Could not connect to {:?}: '{}'", socket_file, e);
         process::exit(1);
     });
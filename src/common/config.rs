@@ -3,7 +3,10 @@ use std::{net::SocketAddr, path::PathBuf, str::FromStr, time::Duration, vec::Vec
 use revault_net::noise::PublicKey as NoisePubkey;
 use revault_tx::{
     bitcoin::{hashes::hex::FromHex, util::bip32, Network},
-    miniscript::descriptor::{DescriptorPublicKey, DescriptorXKey, Wildcard},
+    miniscript::{
+        descriptor::{DescriptorPublicKey, DescriptorXKey, Wildcard, WshInner},
+        Descriptor,
+    },
     scripts::{CpfpDescriptor, DepositDescriptor, EmergencyAddress, UnvaultDescriptor},
 };
 
@@ -26,7 +29,7 @@ where
 {
     let data = String::deserialize(deserializer)?;
     FromHex::from_hex(&data)
-        .map_err(|e| de::Error::custom(e))
+        .map_err(de::Error::custom)
         .map(NoisePubkey)
 }
 
@@ -54,6 +57,18 @@ fn default_poll_interval() -> Duration {
     Duration::from_secs(30)
 }
 
+fn default_chaininfo_cache_duration() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_rebroadcast_interval() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+fn default_fresh_wallet_lookback() -> Duration {
+    Duration::from_secs(2 * 60 * 60)
+}
+
 fn default_sig_poll_interval() -> Duration {
     Duration::from_secs(60)
 }
@@ -62,13 +77,77 @@ fn default_minconf() -> u32 {
     6
 }
 
+fn default_cancel_minconf() -> u32 {
+    1
+}
+
+fn default_tip_finality_depth() -> u32 {
+    6
+}
+
+fn default_revocation_feerate_min() -> u64 {
+    44
+}
+
+fn default_revocation_feerate_max() -> u64 {
+    176
+}
+
+fn default_rpc_max_entries() -> usize {
+    10_000
+}
+
+fn default_rpc_log_level() -> log::LevelFilter {
+    log::LevelFilter::Off
+}
+
+fn default_broadcast_batch_size() -> usize {
+    100
+}
+
+fn default_import_retries() -> u32 {
+    5
+}
+
+fn default_import_retry_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_address_import_flush_interval() -> Duration {
+    Duration::from_secs(2)
+}
+
+fn default_clock_skew_threshold() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+fn default_bitcoind_min_version() -> u64 {
+    // 0.21.0, the first version with descriptor wallets
+    210_000
+}
+
+fn default_load_wallet_on_startup() -> bool {
+    true
+}
+
+fn default_wallet_tx_cache_size() -> usize {
+    256
+}
+
 /// Everything we need to know for talking to bitcoind serenely
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct BitcoindConfig {
     /// The network we are operating on, one of "bitcoin", "testnet", "regtest"
     pub network: Network,
     /// Path to bitcoind's cookie file, to authenticate the RPC connection
-    pub cookie_path: PathBuf,
+    pub cookie_path: Option<PathBuf>,
+    /// The user to authenticate the RPC connection with, instead of the cookie file. Needs
+    /// either a password or a password file.
+    pub rpc_user: Option<String>,
+    pub rpc_password: Option<String>,
+    /// A file containing the RPC password, so that it doesn't need to be in the configuration
+    pub rpc_password_file: Option<PathBuf>,
     /// The IP:port bitcoind's RPC is listening on
     pub addr: SocketAddr,
     /// The poll interval for bitcoind
@@ -77,9 +156,86 @@ pub struct BitcoindConfig {
         default = "default_poll_interval"
     )]
     pub poll_interval_secs: Duration,
+    /// For how long to reuse a 'getblockchaininfo' result before querying bitcoind again
+    #[serde(
+        deserialize_with = "deserialize_duration",
+        default = "default_chaininfo_cache_duration"
+    )]
+    pub chaininfo_cache_secs: Duration,
+    /// How many wallet transactions to keep in memory instead of querying bitcoind each time.
+    /// The cache is dropped on each new block. Set to 0 to disable it.
+    #[serde(default = "default_wallet_tx_cache_size")]
+    pub wallet_tx_cache_size: usize,
+    /// How often to re-broadcast the revocation transactions that were dropped from the mempool
+    #[serde(
+        deserialize_with = "deserialize_duration",
+        default = "default_rebroadcast_interval"
+    )]
+    pub rebroadcast_interval_secs: Duration,
+    /// How far in the past to rescan when importing descriptors into a freshly created
+    /// watchonly wallet. Larger values make it less likely to miss a deposit sent right before
+    /// the wallet creation, at the cost of a longer rescan on first startup.
+    #[serde(
+        deserialize_with = "deserialize_duration",
+        default = "default_fresh_wallet_lookback"
+    )]
+    pub fresh_wallet_lookback_secs: Duration,
+    /// The maximum number of transactions to send to bitcoind in a single batch request
+    #[serde(default = "default_broadcast_batch_size")]
+    pub broadcast_batch_size: usize,
+    /// How many times to retry an 'importdescriptors' call that failed with a transient error
+    /// (for instance if the wallet was just loaded and is still busy)
+    #[serde(default = "default_import_retries")]
+    pub import_retries: u32,
+    /// How long to wait before the first retry of a failed 'importdescriptors' call. The delay is
+    /// doubled at each subsequent retry.
+    #[serde(
+        deserialize_with = "deserialize_duration",
+        default = "default_import_retry_interval"
+    )]
+    pub import_retry_interval_secs: Duration,
+    /// For how long to buffer the addresses derived for new deposits before importing them
+    /// all in a single 'importdescriptors' call.
+    #[serde(
+        deserialize_with = "deserialize_duration",
+        default = "default_address_import_flush_interval"
+    )]
+    pub address_import_flush_secs: Duration,
+    /// Only import the Unvault descriptor of a vault into the watchonly wallet once it is
+    /// secured, instead of importing the Unvault descriptors for all the deposit addresses
+    /// at startup.
+    #[serde(default)]
+    pub lazy_unvault_import: bool,
+    /// Never import descriptors into the watchonly wallet, it is managed by someone else and
+    /// must already watch all our addresses. Incompatible with `lazy_unvault_import`.
+    #[serde(default)]
+    pub observer_mode: bool,
+    /// Whether to add our watchonly wallet to bitcoind's list of wallets to load at startup
+    /// when creating or loading it.
+    #[serde(default = "default_load_wallet_on_startup")]
+    pub load_wallet_on_startup: bool,
+    /// Unload our watchonly wallet from bitcoind when shutting down cleanly.
+    #[serde(default)]
+    pub unload_wallet_on_shutdown: bool,
+    /// Above which difference between our clock and bitcoind's view of the time to warn at
+    /// startup
+    #[serde(
+        deserialize_with = "deserialize_duration",
+        default = "default_clock_skew_threshold"
+    )]
+    pub clock_skew_threshold_secs: Duration,
+    /// The feerate to use, in sat/vB, when bitcoind can't give us an estimate
+    pub fallback_feerate_sat_vb: Option<u64>,
+    /// The minimum bitcoind version to run against, as reported by 'getnetworkinfo'
+    #[serde(default = "default_bitcoind_min_version")]
+    pub min_version: u64,
+    /// Run against bitcoind versions newer than the latest one we tested
+    #[serde(default)]
+    pub allow_untested_version: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ScriptsConfig {
     #[serde(deserialize_with = "deserialize_fromstr")]
     pub deposit_descriptor: DepositDescriptor,
@@ -87,9 +243,13 @@ pub struct ScriptsConfig {
     pub unvault_descriptor: UnvaultDescriptor,
     #[serde(deserialize_with = "deserialize_fromstr")]
     pub cpfp_descriptor: CpfpDescriptor,
+    /// The master fingerprints of the stakeholders' keys we expect in the deposit descriptor
+    #[serde(default)]
+    pub deposit_fingerprints: Option<Vec<bip32::Fingerprint>>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct WatchtowerConfig {
     pub host: SocketAddr,
     #[serde(deserialize_with = "deserialize_noisepubkey")]
@@ -97,7 +257,8 @@ pub struct WatchtowerConfig {
 }
 
 /// If we are a stakeholder, we need to connect to our watchtower(s)
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct StakeholderConfig {
     pub xpub: bip32::ExtendedPubKey,
     pub watchtowers: Vec<WatchtowerConfig>,
@@ -105,7 +266,8 @@ pub struct StakeholderConfig {
 }
 
 // Same fields as the WatchtowerConfig struct for now, but leave them separate.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct CosignerConfig {
     // TODO: Tor
     pub host: SocketAddr,
@@ -114,14 +276,16 @@ pub struct CosignerConfig {
 }
 
 /// If we are a manager, we need to connect to cosigning servers
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ManagerConfig {
     pub xpub: bip32::ExtendedPubKey,
     pub cosigners: Vec<CosignerConfig>,
 }
 
 /// Static informations we require to operate
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     /// Everything we need to know to talk to bitcoind
     pub bitcoind_config: BitcoindConfig,
@@ -154,6 +318,48 @@ pub struct Config {
     /// After how many blocks should we consider a deposit as confirmed?
     #[serde(default = "default_minconf")]
     pub min_conf: u32,
+    /// After how many blocks should we consider a Cancel as final?
+    #[serde(default = "default_cancel_minconf")]
+    pub cancel_min_conf: u32,
+    /// After how many confirmations should we consider a block as final?
+    #[serde(default = "default_tip_finality_depth")]
+    pub tip_finality_depth: u32,
+    /// How many deposit addresses to derive and watch ahead, to be handed out at once
+    #[serde(default)]
+    pub precreate_deposit_addresses: u32,
+    /// The minimum feerate, in sat/vB, we accept for the revocation transactions we are asked
+    /// to sign (default: 44)
+    #[serde(default = "default_revocation_feerate_min")]
+    pub revocation_feerate_min_sat_vb: u64,
+    /// The maximum feerate, in sat/vB, we accept for the revocation transactions we are asked
+    /// to sign (default: 176)
+    #[serde(default = "default_revocation_feerate_max")]
+    pub revocation_feerate_max_sat_vb: u64,
+    /// The maximum number of entries returned by a listing RPC command before truncating it
+    #[serde(default = "default_rpc_max_entries")]
+    pub rpc_max_entries: usize,
+    /// At which level to log every JSONRPC request and response, if at all (default: off)
+    #[serde(
+        deserialize_with = "deserialize_loglevel",
+        default = "default_rpc_log_level"
+    )]
+    pub rpc_log_level: log::LevelFilter,
+    /// Re-verify the presigned transactions stored in database, and their signatures, at startup
+    #[serde(default)]
+    pub verify_signatures_on_startup: bool,
+    /// Bind the JSONRPC server to an abstract Unix socket with this name instead of a socket
+    /// file in the data directory. Only supported on Linux.
+    pub rpc_abstract_socket: Option<String>,
+    /// Use SQLite's Write-Ahead Logging, so that reading the database never blocks on writers
+    #[serde(default)]
+    pub sqlite_wal_mode: bool,
+    /// A file to create once we are synced and serving RPC requests, and to remove at shutdown
+    pub ready_file: Option<PathBuf>,
+    /// Encrypt the presigned and Spend transactions stored in the database
+    #[serde(default)]
+    pub db_encryption: bool,
+    /// A file containing the database passphrase. If not set, it is asked on the terminal.
+    pub db_passphrase_file: Option<PathBuf>,
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -219,12 +425,82 @@ impl Config {
                 toml::from_slice::<Config>(&file_content)
                     .map_err(|e| ConfigError(format!("Parsing configuration file: {}", e)))
             })?;
+        let bitcoind_config = &config.bitcoind_config;
+        match (
+            &bitcoind_config.cookie_path,
+            &bitcoind_config.rpc_user,
+            &bitcoind_config.rpc_password,
+            &bitcoind_config.rpc_password_file,
+        ) {
+            (Some(_), None, None, None)
+            | (None, Some(_), Some(_), None)
+            | (None, Some(_), None, Some(_)) => {}
+            _ => {
+                return Err(ConfigError(
+                    r#"bitcoind RPC authentication must be set with either a "cookie_path", or a "rpc_user" and one of "rpc_password" or "rpc_password_file""#
+                        .to_string(),
+                ))
+            }
+        }
+        if config.bitcoind_config.broadcast_batch_size == 0 {
+            return Err(ConfigError(
+                r#""broadcast_batch_size" must be strictly positive"#.to_string(),
+            ));
+        }
+        if config.bitcoind_config.fallback_feerate_sat_vb == Some(0) {
+            return Err(ConfigError(
+                r#""fallback_feerate_sat_vb" must be strictly positive"#.to_string(),
+            ));
+        }
+        if config.bitcoind_config.observer_mode && config.bitcoind_config.lazy_unvault_import {
+            return Err(ConfigError(
+                r#""lazy_unvault_import" can't be set in "observer_mode", as we couldn't import the Unvault descriptors"#
+                    .to_string(),
+            ));
+        }
+        if config.cancel_min_conf == 0 {
+            return Err(ConfigError(
+                r#""cancel_min_conf" must be strictly positive"#.to_string(),
+            ));
+        }
+        if config.db_passphrase_file.is_some() && !config.db_encryption {
+            return Err(ConfigError(
+                r#""db_passphrase_file" is set but "db_encryption" is not"#.to_string(),
+            ));
+        }
+        if config.tip_finality_depth == 0 {
+            return Err(ConfigError(
+                r#""tip_finality_depth" must be strictly positive"#.to_string(),
+            ));
+        }
+        if config.revocation_feerate_min_sat_vb > config.revocation_feerate_max_sat_vb {
+            return Err(ConfigError(
+                r#""revocation_feerate_min_sat_vb" must not be greater than "revocation_feerate_max_sat_vb""#
+                    .to_string(),
+            ));
+        }
+        if config.rpc_max_entries == 0 {
+            return Err(ConfigError(
+                r#""rpc_max_entries" must be strictly positive"#.to_string(),
+            ));
+        }
+
+        // We can only get the stakeholders' keys out of a P2WSH Miniscript descriptor.
+        let is_wsh_ms = match config.scripts_config.deposit_descriptor.inner() {
+            Descriptor::Wsh(wsh) => matches!(wsh.as_inner(), WshInner::Ms(_)),
+            _ => false,
+        };
+        if !is_wsh_ms {
+            return Err(ConfigError(
+                r#""deposit_descriptor" must be a P2WSH Miniscript descriptor"#.to_string(),
+            ));
+        }
         let stk_xpubs = config.scripts_config.deposit_descriptor.xpubs();
 
         if let Some(ref stk_config) = config.stakeholder_config {
             let our_desc_xpub = DescriptorPublicKey::XPub(DescriptorXKey {
                 origin: None,
-                xkey: stk_config.xpub.clone(),
+                xkey: stk_config.xpub,
                 derivation_path: bip32::DerivationPath::from(vec![]),
                 wildcard: Wildcard::Unhardened,
             });
@@ -249,7 +525,7 @@ impl Config {
         if let Some(ref man_config) = config.manager_config {
             let our_desc_xpub = DescriptorPublicKey::XPub(DescriptorXKey {
                 origin: None,
-                xkey: man_config.xpub.clone(),
+                xkey: man_config.xpub,
                 derivation_path: bip32::DerivationPath::from(vec![]),
                 wildcard: Wildcard::Unhardened,
             });
@@ -317,7 +593,59 @@ mod tests {
             watchtowers = [ { host = "127.0.0.1:1", noise_key = "46084f8a7da40ef7ffc38efa5af8a33a742b90f920885d17c533bb2a0b680cb3" } ]
             emergency_address = "bc1qwqdg6squsna38e46795at95yu9atm8azzmyvckulcc7kytlcckxswvvzej"
         "#;
-        toml::from_str::<Config>(toml_str).expect("Deserializing stakeholder toml_str");
+        let config =
+            toml::from_str::<Config>(toml_str).expect("Deserializing stakeholder toml_str");
+        assert_eq!(config.bitcoind_config.broadcast_batch_size, 100);
+        assert_eq!(config.bitcoind_config.import_retries, 5);
+        assert_eq!(
+            config.bitcoind_config.import_retry_interval_secs,
+            std::time::Duration::from_secs(1)
+        );
+        assert_eq!(
+            config.bitcoind_config.address_import_flush_secs,
+            std::time::Duration::from_secs(2)
+        );
+        assert!(!config.bitcoind_config.lazy_unvault_import);
+        assert!(!config.bitcoind_config.observer_mode);
+        assert!(config.bitcoind_config.load_wallet_on_startup);
+        assert!(!config.bitcoind_config.unload_wallet_on_shutdown);
+        assert_eq!(
+            config.bitcoind_config.clock_skew_threshold_secs,
+            std::time::Duration::from_secs(3600)
+        );
+        assert!(config.bitcoind_config.fallback_feerate_sat_vb.is_none());
+        assert_eq!(config.bitcoind_config.min_version, 210_000);
+        assert!(!config.bitcoind_config.allow_untested_version);
+        assert!(config.bitcoind_config.rpc_user.is_none());
+        assert!(config.bitcoind_config.rpc_password.is_none());
+        assert!(config.bitcoind_config.rpc_password_file.is_none());
+        assert_eq!(config.rpc_max_entries, 10_000);
+        assert_eq!(config.cancel_min_conf, 1);
+        assert_eq!(config.tip_finality_depth, 6);
+        assert_eq!(config.revocation_feerate_min_sat_vb, 44);
+        assert_eq!(config.revocation_feerate_max_sat_vb, 176);
+        assert_eq!(config.precreate_deposit_addresses, 0);
+        assert_eq!(config.rpc_log_level, log::LevelFilter::Off);
+        assert!(!config.verify_signatures_on_startup);
+        assert!(config.rpc_abstract_socket.is_none());
+        assert!(!config.sqlite_wal_mode);
+        assert!(config.ready_file.is_none());
+        assert!(!config.db_encryption);
+        assert!(config.db_passphrase_file.is_none());
+        assert!(config.scripts_config.deposit_fingerprints.is_none());
+        assert_eq!(
+            config.bitcoind_config.fresh_wallet_lookback_secs,
+            std::time::Duration::from_secs(2 * 60 * 60)
+        );
+        assert_eq!(
+            config.bitcoind_config.rebroadcast_interval_secs,
+            std::time::Duration::from_secs(300)
+        );
+        assert_eq!(
+            config.bitcoind_config.chaininfo_cache_secs,
+            std::time::Duration::from_secs(1)
+        );
+        assert_eq!(config.bitcoind_config.wallet_tx_cache_size, 256);
 
         // A valid manager config
         let toml_str = r#"
@@ -346,6 +674,24 @@ mod tests {
         "#;
         toml::from_str::<Config>(toml_str).expect("Deserializing manager toml_str");
 
+        // A misspelled key must not be silently ignored, whatever the section
+        let typos = [
+            format!("min_confs = 3\n{}", toml_str),
+            toml_str.replace("[bitcoind_config]", "[bitcoind_config]\npoll_interval = 4"),
+            toml_str.replace("[scripts_config]", "[scripts_config]\ncpfp_desc = \"\""),
+            toml_str.replace("[manager_config]", "[manager_config]\ncosigner = []"),
+        ];
+        for (typo, key) in typos
+            .iter()
+            .zip(["min_confs", "poll_interval", "cpfp_desc", "cosigner"].iter())
+        {
+            let config_err =
+                toml::from_str::<Config>(typo).expect_err("Deserializing an unknown field");
+            assert!(config_err
+                .to_string()
+                .contains(&format!("unknown field `{}`", key)));
+        }
+
         // A valid sakeholder-manager config
         let toml_str = r#"
             daemon = false
@@ -407,6 +753,25 @@ mod tests {
         let config_res: Result<Config, toml::de::Error> = toml::from_str(toml_str);
         config_res.expect_err("Deserializing an invalid toml_str");
 
+        // A non-ranged deposit descriptor
+        let toml_str = r#"
+            coordinator_host = "127.0.0.1:1"
+            coordinator_noise_key = "d91563973102454a7830137e92d0548bc83b4ea2799f1df04622ca1307381402"
+
+            [scripts_config]
+            cpfp_descriptor = "wsh(thresh(1,pk(xpub6BaZSKgpaVvibu2k78QsqeDWXp92xLHZxiu1WoqLB9hKhsBf3miBUDX7PJLgSPvkj66ThVHTqdnbXpeu8crXFmDUd4HeM4s4miQS2xsv3Qb/*)))#cwycq5xu"
+            deposit_descriptor = "wsh(multi(2,xpub6AHA9hZDN11k2ijHMeS5QqHx2KP9aMBRhTDqANMnwVtdyw2TDYRmF8PjpvwUFcL1Et8Hj59S3gTSMcUQ5gAqTz3Wd8EsMTmF3DChhqPQBnU,xpub6AaffFGfH6WXfm6pwWzmUMuECQnoLeB3agMKaLyEBZ5ZVfwtnS5VJKqXBt8o5ooCWVy2H87GsZshp7DeKE25eWLyd1Ccuh2ZubQUkgpiVux))"
+            unvault_descriptor = "wsh(andor(thresh(1,pk(xpub6BaZSKgpaVvibu2k78QsqeDWXp92xLHZxiu1WoqLB9hKhsBf3miBUDX7PJLgSPvkj66ThVHTqdnbXpeu8crXFmDUd4HeM4s4miQS2xsv3Qb/*)),and_v(v:multi(2,03b506a1dbe57b4bf48c95e0c7d417b87dd3b4349d290d2e7e9ba72c912652d80a,0295e7f5d12a2061f1fd2286cefec592dff656a19f55f4f01305d6aa56630880ce),older(4)),thresh(2,pkh(xpub6AHA9hZDN11k2ijHMeS5QqHx2KP9aMBRhTDqANMnwVtdyw2TDYRmF8PjpvwUFcL1Et8Hj59S3gTSMcUQ5gAqTz3Wd8EsMTmF3DChhqPQBnU/*),a:pkh(xpub6AaffFGfH6WXfm6pwWzmUMuECQnoLeB3agMKaLyEBZ5ZVfwtnS5VJKqXBt8o5ooCWVy2H87GsZshp7DeKE25eWLyd1Ccuh2ZubQUkgpiVux/*))))#532k8uvf"
+
+            [bitcoind_config]
+            network = "bitcoin"
+            cookie_path = "/home/user/.bitcoin/.cookie"
+            addr = "127.0.0.1:8332"
+        "#;
+        let config_err = toml::from_str::<Config>(toml_str)
+            .expect_err("Deserializing a non-ranged deposit descriptor");
+        assert!(config_err.to_string().contains("deposit_descriptor"));
+
         // Not enough parameters
         let toml_str = r#"
             daemon = false
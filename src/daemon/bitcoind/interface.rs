@@ -1,21 +1,30 @@
-use crate::{bitcoind::BitcoindError, revaultd::BlockchainTip};
+use crate::{bitcoind::BitcoindError, revaultd::BlockchainTip, threadmessages::WalletTransaction};
 use common::config::BitcoindConfig;
 use revault_tx::{
-    bitcoin::{consensus::encode, Address, Amount, BlockHash, OutPoint, Transaction, TxOut, Txid},
+    bitcoin::{
+        consensus::encode,
+        util::{amount::Denomination, bip32::ChildNumber},
+        Amount, BlockHash, OutPoint, Script, Transaction, TxOut, Txid,
+    },
     transactions::{DUST_LIMIT, UNVAULT_CPFP_VALUE},
 };
 
 use std::{
     any::Any,
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
     fs,
     str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     time::{Duration, Instant},
 };
 
 use jsonrpc::{
     arg,
     client::Client,
+    error::RpcError,
     simple_http::{Error as HttpError, SimpleHttpTransport},
 };
 use serde_json::Value as Json;
@@ -24,9 +33,230 @@ use serde_json::Value as Json;
 // transaction fee. To have a one-value-fits-all, just take a 5% leeway.
 const MIN_DEPOSIT_VALUE: u64 = (DUST_LIMIT + UNVAULT_CPFP_VALUE) * 105 / 100;
 
+// No amount can be larger than the total supply, in sats.
+const MAX_MONEY: u64 = 21_000_000 * 100_000_000;
+
+// Bitcoind gives us amounts as BTC floats with (at most) 8 decimals. `Amount::from_btc` goes
+// through the float's shortest string representation, which may differ from the one bitcoind
+// formatted for large values. Any valid amount in sats fits in a f64 mantissa, so just round.
+fn sat_from_btc(btc: f64) -> Option<u64> {
+    if !btc.is_finite() || btc < 0.0 {
+        return None;
+    }
+
+    let sats = (btc * 100_000_000.0).round();
+    if sats > MAX_MONEY as f64 {
+        return None;
+    }
+
+    Some(sats as u64)
+}
+
+// Amounts are JSON numbers, but be lenient and accept numeric strings too in case a bitcoind
+// build or proxy formats them differently. Strings are parsed as exact decimals.
+fn sat_from_json_amount(amount: &Json) -> Option<u64> {
+    match amount {
+        Json::Number(n) => n.as_f64().and_then(sat_from_btc),
+        Json::String(s) => Amount::from_str_in(s.trim(), Denomination::Bitcoin)
+            .ok()
+            .map(|amount| amount.as_sat())
+            .filter(|sats| *sats <= MAX_MONEY),
+        _ => None,
+    }
+}
+
+// Feerates are given by bitcoind in BTC/kvB, we use sat/vB. Round up so we never undershoot.
+fn feerate_vb_from_btc_kvb(feerate: &Json) -> Option<u64> {
+    sat_from_json_amount(feerate).map(|sat_kvb| (sat_kvb + 999) / 1000)
+}
+
+// Responses to a batch request are matched to the requests by id by rust-jsonrpc. Bitcoind may
+// not answer all of them, make it a per-request error instead of discarding the whole batch.
+fn batch_results(responses: Vec<Option<jsonrpc::Response>>) -> Vec<Result<Json, BitcoindError>> {
+    responses
+        .into_iter()
+        .map(|resp| {
+            resp.ok_or(BitcoindError::BatchMissingResponse)
+                .and_then(|resp| resp.result().map_err(BitcoindError::Server))
+        })
+        .collect()
+}
+
+// Right after 'loadwallet' (or while bitcoind is still starting) the wallet may not be able to
+// process an import yet. These errors are worth waiting for, as opposed to invalid descriptors.
+fn is_transient_import_error(e: &RpcError) -> bool {
+    match e.code {
+        // RPC_IN_WARMUP, RPC_WALLET_NOT_FOUND
+        -28 | -18 => true,
+        // RPC_WALLET_ERROR is also used for genuine import failures, only retry on a rescan.
+        -4 => e.message.contains("rescanning"),
+        _ => false,
+    }
+}
+
+// bitcoind refuses to broadcast a transaction it already has in its mempool or in the chain. When
+// (re-)broadcasting, that's as good as a success.
+fn is_already_known_tx_error(e: &RpcError) -> bool {
+    match e.code {
+        // RPC_VERIFY_ALREADY_IN_CHAIN
+        -27 => true,
+        // RPC_VERIFY_REJECTED is also used for genuine policy or consensus failures
+        -26 => {
+            e.message.contains("txn-already-known") || e.message.contains("txn-already-in-mempool")
+        }
+        _ => false,
+    }
+}
+
+// Parse the result of a 'gettransaction' call into the transaction hex, its blockheight if it's
+// confirmed and the time bitcoind received it.
+fn wallet_transaction_from_json(
+    txid: &Txid,
+    res: Json,
+) -> Result<WalletTransaction, BitcoindError> {
+    let tx_hex = res
+        .get("hex")
+        .ok_or_else(|| {
+            BitcoindError::Custom(format!(
+                "API break: no 'hex' in 'gettransaction' result (txid: {})",
+                txid
+            ))
+        })?
+        .as_str()
+        .ok_or_else(|| BitcoindError::Custom("API break: 'hex' is not a string ????".to_string()))?
+        .to_string();
+    let blockheight = res.get("blockheight").map(|bh| bh.as_u64().unwrap() as u32);
+    let received = res
+        .get("timereceived")
+        .ok_or_else(|| {
+            BitcoindError::Custom(format!(
+                "API break: no 'time_received' in 'gettransaction' result (txid: {})",
+                txid
+            ))
+        })?
+        .as_u64()
+        .ok_or_else(|| {
+            BitcoindError::Custom(format!(
+                "API break: invalid 'time_received' in 'gettransaction' result (txid: {})",
+                txid
+            ))
+        })? as u32;
+    // Only there if the inputs are from the wallet, and given as a negative amount
+    let fee = res
+        .get("fee")
+        .and_then(|fee| fee.as_f64())
+        .and_then(|fee| sat_from_btc(-fee));
+
+    Ok(WalletTransaction {
+        hex: tx_hex,
+        blockheight,
+        received_time: received,
+        fee,
+    })
+}
+
+// BIP68 nSequence flags and mask
+const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+const SEQUENCE_LOCKTIME_MASK: u32 = 0x00_00_ff_ff;
+
+// The wallet transactions we recently fetched, evicting the least recently used one when full.
+// The entries are only valid for the tip they were fetched at, since their confirmation status
+// may change with a new block.
+struct WalletTxCache {
+    capacity: usize,
+    tip: Option<BlockchainTip>,
+    entries: HashMap<Txid, WalletTransaction>,
+    // The cached txids, from the least to the most recently used
+    usage: VecDeque<Txid>,
+}
+
+impl WalletTxCache {
+    fn new(capacity: usize) -> Self {
+        WalletTxCache {
+            capacity,
+            tip: None,
+            entries: HashMap::with_capacity(capacity),
+            usage: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    fn get(&mut self, txid: &Txid) -> Option<WalletTransaction> {
+        let tx = self.entries.get(txid)?.clone();
+        if let Some(pos) = self.usage.iter().position(|t| t == txid) {
+            self.usage.remove(pos);
+        }
+        self.usage.push_back(*txid);
+        Some(tx)
+    }
+
+    fn insert(&mut self, txid: Txid, tx: WalletTransaction) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.insert(txid, tx).is_some() {
+            if let Some(pos) = self.usage.iter().position(|t| *t == txid) {
+                self.usage.remove(pos);
+            }
+        } else if self.entries.len() > self.capacity {
+            if let Some(lru_txid) = self.usage.pop_front() {
+                self.entries.remove(&lru_txid);
+            }
+        }
+        self.usage.push_back(txid);
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.usage.clear();
+    }
+
+    // Drop all entries if they were fetched at a different tip.
+    fn set_tip(&mut self, tip: &BlockchainTip) {
+        if self.tip.as_ref() != Some(tip) {
+            self.clear();
+            self.tip = Some(*tip);
+        }
+    }
+}
+
 pub struct BitcoinD {
     node_client: Client,
     watchonly_client: Client,
+    broadcast_batch_size: usize,
+    // The last 'getblockchaininfo' response and when we got it, shared between callers.
+    chaininfo_cache: Mutex<Option<(Instant, Json)>>,
+    chaininfo_cache_duration: Duration,
+    wallet_tx_cache: Mutex<WalletTxCache>,
+    // The deposit utxos we ignored because of their script type, so we only warn once about each
+    mismatched_deposits: Mutex<HashSet<OutPoint>>,
+    // Set by the user to prevent us from broadcasting anything
+    maintenance: Arc<AtomicBool>,
+    import_retries: u32,
+    import_retry_interval: Duration,
+    load_wallet_on_startup: bool,
+}
+
+// The descriptor with its checksum from a 'getdescriptorinfo' result
+fn descriptor_from_info(info: Json) -> Result<String, BitcoindError> {
+    Ok(info
+        .get("descriptor")
+        .ok_or_else(|| BitcoindError::Custom("No 'descriptor' in 'getdescriptorinfo'".to_string()))?
+        .as_str()
+        .ok_or_else(|| {
+            BitcoindError::Custom(
+                "'descriptor' in 'getdescriptorinfo' isn't a string anymore".to_string(),
+            )
+        })?
+        .to_string())
+}
+
+// Whether the address is ours from a 'getaddressinfo' result
+fn is_mine_from_info(info: Json) -> Result<bool, BitcoindError> {
+    info.get("ismine")
+        .and_then(|m| m.as_bool())
+        .ok_or_else(|| BitcoindError::Custom("No valid 'ismine' in 'getaddressinfo'".to_string()))
 }
 
 macro_rules! params {
@@ -39,21 +269,44 @@ macro_rules! params {
     };
 }
 
+// The 'user:password' credentials to authenticate to bitcoind's RPC with. That's the content of
+// the cookie file, if we use one.
+fn rpc_auth_string(config: &BitcoindConfig) -> Result<String, BitcoindError> {
+    if let Some(ref cookie_path) = config.cookie_path {
+        return fs::read_to_string(cookie_path)
+            .map_err(|e| BitcoindError::Custom(format!("Reading cookie file: {}", e)));
+    }
+
+    let user = config
+        .rpc_user
+        .as_ref()
+        .expect("Checked by the configuration parsing");
+    let password = match (&config.rpc_password, &config.rpc_password_file) {
+        (Some(password), _) => password.clone(),
+        (None, Some(password_file)) => fs::read_to_string(password_file)
+            .map_err(|e| BitcoindError::Custom(format!("Reading RPC password file: {}", e)))?
+            .trim_end_matches(&['\r', '\n'][..])
+            .to_string(),
+        (None, None) => unreachable!("Checked by the configuration parsing"),
+    };
+
+    Ok(format!("{}:{}", user, password))
+}
+
 impl BitcoinD {
     pub fn new(
         config: &BitcoindConfig,
         watchonly_wallet_path: String,
+        maintenance: Arc<AtomicBool>,
     ) -> Result<BitcoinD, BitcoindError> {
-        let cookie_string = fs::read_to_string(&config.cookie_path).map_err(|e| {
-            BitcoindError::Custom(format!("Reading cookie file: {}", e.to_string()))
-        })?;
+        let auth_string = rpc_auth_string(config)?;
 
         let node_client = Client::with_transport(
             SimpleHttpTransport::builder()
                 .url(&config.addr.to_string())
                 .map_err(BitcoindError::from)?
                 .timeout(Duration::from_secs(30))
-                .cookie_auth(cookie_string.clone())
+                .cookie_auth(auth_string.clone())
                 .build(),
         );
 
@@ -63,22 +316,31 @@ impl BitcoinD {
                 .url(&url)
                 .map_err(BitcoindError::from)?
                 .timeout(Duration::from_secs(30))
-                .cookie_auth(cookie_string)
+                .cookie_auth(auth_string)
                 .build(),
         );
 
         Ok(BitcoinD {
             node_client,
             watchonly_client,
+            broadcast_batch_size: config.broadcast_batch_size,
+            chaininfo_cache: Mutex::new(None),
+            chaininfo_cache_duration: config.chaininfo_cache_secs,
+            wallet_tx_cache: Mutex::new(WalletTxCache::new(config.wallet_tx_cache_size)),
+            mismatched_deposits: Mutex::new(HashSet::new()),
+            maintenance,
+            import_retries: config.import_retries,
+            import_retry_interval: config.import_retry_interval_secs,
+            load_wallet_on_startup: config.load_wallet_on_startup,
         })
     }
 
     fn deposit_utxos_label(&self) -> String {
-        "revault-deposit".to_string()
+        utxos_label("deposit")
     }
 
     fn unvault_utxos_label(&self) -> String {
-        "revault-unvault".to_string()
+        utxos_label("unvault")
     }
 
     // Reasonably try to be robust to possible spurious communication error.
@@ -138,13 +400,13 @@ impl BitcoinD {
         Ok(())
     }
 
-    fn make_request<'a, 'b>(
+    fn make_request(
         &self,
         client: &Client,
-        method: &'a str,
-        params: &'b [Box<serde_json::value::RawValue>],
+        method: &str,
+        params: &[Box<serde_json::value::RawValue>],
     ) -> Result<Json, BitcoindError> {
-        let req = client.build_request(method, &params);
+        let req = client.build_request(method, params);
         log::trace!("Sending to bitcoind: {:#?}", req);
 
         // Trying to be robust on bitcoind's spurious failures. We try to support bitcoind failing
@@ -167,11 +429,11 @@ impl BitcoinD {
         }
     }
 
-    fn make_requests<'a, 'b>(
+    fn make_requests(
         &self,
         client: &Client,
         reqs: &[jsonrpc::Request],
-    ) -> Result<Vec<Json>, BitcoindError> {
+    ) -> Result<Vec<Result<Json, BitcoindError>>, BitcoindError> {
         log::trace!("Sending to bitcoind: {:#?}", reqs);
 
         // Trying to be robust on bitcoind's spurious failures. We try to support bitcoind failing
@@ -179,22 +441,11 @@ impl BitcoinD {
         // example, if we got the RPC listening address or path to the cookie wrong).
         let start = Instant::now();
         loop {
-            match client.send_batch(&reqs.clone()) {
+            match client.send_batch(reqs) {
                 Ok(resp) => {
-                    let res = resp
-                        .into_iter()
-                        .filter_map(|r| r)
-                        .map(|resp| resp.result())
-                        .collect::<Result<Vec<Json>, jsonrpc::Error>>()
-                        .map_err(BitcoindError::Server)?;
+                    let res = batch_results(resp);
                     log::trace!("Got from bitcoind: {:#?}", res);
 
-                    // FIXME: why is rust-jsonrpc even returning a Vec of Option in the first
-                    // place??
-                    if res.len() != reqs.len() {
-                        return Err(BitcoindError::BatchMissingResponse);
-                    }
-
                     return Ok(res);
                 }
                 Err(e) => {
@@ -205,31 +456,84 @@ impl BitcoinD {
         }
     }
 
-    fn make_node_request<'a, 'b>(
+    fn make_node_request(
         &self,
-        method: &'a str,
-        params: &'b [Box<serde_json::value::RawValue>],
+        method: &str,
+        params: &[Box<serde_json::value::RawValue>],
     ) -> Result<Json, BitcoindError> {
         self.make_request(&self.node_client, method, params)
     }
 
-    fn make_watchonly_request<'a, 'b>(
+    fn make_watchonly_request(
         &self,
-        method: &'a str,
-        params: &'b [Box<serde_json::value::RawValue>],
+        method: &str,
+        params: &[Box<serde_json::value::RawValue>],
     ) -> Result<Json, BitcoindError> {
         self.make_request(&self.watchonly_client, method, params)
     }
 
-    fn make_node_requests<'a, 'b>(
+    // An 'importdescriptors' request, retried with an exponential backoff if the wallet reports
+    // a transient error.
+    fn make_import_request(
+        &self,
+        params: &[Box<serde_json::value::RawValue>],
+    ) -> Result<Json, BitcoindError> {
+        let mut delay = self.import_retry_interval;
+        let mut retries = 0;
+        loop {
+            match self.make_watchonly_request("importdescriptors", params) {
+                Err(BitcoindError::Server(jsonrpc::Error::Rpc(ref e)))
+                    if retries < self.import_retries && is_transient_import_error(e) =>
+                {
+                    retries += 1;
+                    log::warn!(
+                        "Transient error when importing descriptors: '{}'. Retrying in {:?} \
+                         ({}/{}).",
+                        e.message,
+                        delay,
+                        retries,
+                        self.import_retries
+                    );
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    fn make_node_requests(
         &self,
         requests: &[jsonrpc::Request],
-    ) -> Result<Vec<Json>, BitcoindError> {
+    ) -> Result<Vec<Result<Json, BitcoindError>>, BitcoindError> {
         self.make_requests(&self.node_client, requests)
     }
 
+    /// Get the 'getblockchaininfo' result. It is cached for `chaininfo_cache_secs` in order to
+    /// avoid hammering bitcoind with the same request from different places in a single poll.
     pub fn getblockchaininfo(&self) -> Result<Json, BitcoindError> {
-        self.make_node_request("getblockchaininfo", &[])
+        let mut cache = self.chaininfo_cache.lock().unwrap();
+        if let Some((fetched_at, ref chaininfo)) = *cache {
+            if fetched_at.elapsed() < self.chaininfo_cache_duration {
+                return Ok(chaininfo.clone());
+            }
+        }
+
+        let chaininfo = self.make_node_request("getblockchaininfo", &[])?;
+        *cache = Some((Instant::now(), chaininfo.clone()));
+        Ok(chaininfo)
+    }
+
+    // Drop the cached 'getblockchaininfo' result if it's not about this block height anymore.
+    fn invalidate_chaininfo_cache(&self, height: u32) {
+        let mut cache = self.chaininfo_cache.lock().unwrap();
+        let cached_height = cache
+            .as_ref()
+            .and_then(|(_, chaininfo)| chaininfo.get("blocks"))
+            .and_then(|b| b.as_u64());
+        if cached_height.is_some() && cached_height != Some(height as u64) {
+            *cache = None;
+        }
     }
 
     pub fn getblockhash(&self, height: u32) -> Result<BlockHash, BitcoindError> {
@@ -253,12 +557,125 @@ impl BitcoinD {
             BitcoindError::Custom("API break, 'getblockcount' didn't return an u64.".to_string())
         })? as u32;
         let hash = self.getblockhash(height)?;
+        self.invalidate_chaininfo_cache(height);
+        let tip = BlockchainTip { height, hash };
+        self.wallet_tx_cache.lock().unwrap().set_tip(&tip);
+
+        Ok(tip)
+    }
+
+    /// The hex-encoded merkle proof that this transaction was included in this block
+    pub fn gettxoutproof(
+        &self,
+        txid: &Txid,
+        block_hash: &BlockHash,
+    ) -> Result<String, BitcoindError> {
+        self.make_node_request(
+            "gettxoutproof",
+            &params!(
+                Json::Array(vec![Json::String(txid.to_string())]),
+                Json::String(block_hash.to_string()),
+            ),
+        )?
+        .as_str()
+        .map(|proof| proof.to_string())
+        .ok_or_else(|| {
+            BitcoindError::Custom("API break, 'gettxoutproof' didn't return a string.".to_string())
+        })
+    }
+
+    /// The timestamp of the block at the tip of bitcoind's chain
+    pub fn tip_time(&self) -> Result<u64, BitcoindError> {
+        let tip = self.get_tip()?;
+        self.header_time(&tip.hash)
+    }
+
+    /// The timestamp of the block at this height in bitcoind's chain
+    pub fn block_time(&self, height: u32) -> Result<u64, BitcoindError> {
+        let hash = self.getblockhash(height)?;
+        self.header_time(&hash)
+    }
 
-        Ok(BlockchainTip { height, hash })
+    fn header_time(&self, block_hash: &BlockHash) -> Result<u64, BitcoindError> {
+        self.make_node_request(
+            "getblockheader",
+            &params!(Json::String(block_hash.to_string())),
+        )?
+        .get("time")
+        .and_then(|t| t.as_u64())
+        .ok_or_else(|| {
+            BitcoindError::Custom("No valid 'time' in getblockheader response?".to_owned())
+        })
+    }
+
+    /// The offset, in seconds, of bitcoind's clock from the median of its peers' clocks
+    pub fn network_time_offset(&self) -> Result<i64, BitcoindError> {
+        let netinfo = self.make_node_request("getnetworkinfo", &[])?;
+        netinfo
+            .get("timeoffset")
+            .and_then(|o| o.as_i64())
+            .ok_or_else(|| {
+                BitcoindError::Custom(
+                    "No valid 'timeoffset' in getnetworkinfo response?".to_owned(),
+                )
+            })
+    }
+
+    /// The version of bitcoind, as an integer (eg 210100 for 0.21.1)
+    pub fn version(&self) -> Result<u64, BitcoindError> {
+        let netinfo = self.make_node_request("getnetworkinfo", &[])?;
+        netinfo
+            .get("version")
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| {
+                BitcoindError::Custom("No valid 'version' in getnetworkinfo response?".to_owned())
+            })
+    }
+
+    /// The minimum feerate for a transaction to be relayed by bitcoind, in sat/vB
+    pub fn relay_feerate(&self) -> Result<u64, BitcoindError> {
+        let netinfo = self.make_node_request("getnetworkinfo", &[])?;
+        netinfo
+            .get("relayfee")
+            .and_then(feerate_vb_from_btc_kvb)
+            .ok_or_else(|| {
+                BitcoindError::Custom("No valid 'relayfee' in getnetworkinfo response?".to_owned())
+            })
+    }
+
+    /// Estimate the feerate, in sat/vB, for a transaction to confirm within `conf_target` blocks.
+    /// None if bitcoind doesn't have enough data to give an estimate.
+    pub fn estimate_feerate(&self, conf_target: u32) -> Result<Option<u64>, BitcoindError> {
+        let estimate = self.make_node_request("estimatesmartfee", &params!(conf_target))?;
+        Ok(estimate.get("feerate").and_then(feerate_vb_from_btc_kvb))
+    }
+
+    /// The height of the first block bitcoind still has the data of, None if it is not pruned
+    pub fn prune_height(&self) -> Result<Option<u32>, BitcoindError> {
+        let chaininfo = self.getblockchaininfo()?;
+        let pruned = chaininfo
+            .get("pruned")
+            .and_then(|p| p.as_bool())
+            .ok_or_else(|| {
+                BitcoindError::Custom("No valid 'pruned' in getblockchaininfo response?".to_owned())
+            })?;
+        if !pruned {
+            return Ok(None);
+        }
+
+        chaininfo
+            .get("pruneheight")
+            .and_then(|h| h.as_u64())
+            .map(|h| Some(h as u32))
+            .ok_or_else(|| {
+                BitcoindError::Custom(
+                    "No valid 'pruneheight' in getblockchaininfo response?".to_owned(),
+                )
+            })
     }
 
     pub fn synchronization_info(&self) -> Result<SyncInfo, BitcoindError> {
-        let chaininfo = self.make_node_request("getblockchaininfo", &[])?;
+        let chaininfo = self.getblockchaininfo()?;
         Ok(SyncInfo {
             headers: chaininfo
                 .get("headers")
@@ -300,12 +717,12 @@ impl BitcoinD {
             "createwallet",
             &params!(
                 Json::String(wallet_path),
-                Json::Bool(true),             // watchonly
-                Json::Bool(false),            // blank
-                Json::String("".to_string()), // passphrase,
-                Json::Bool(false),            // avoid_reuse
-                Json::Bool(true),             // descriptors
-                Json::Bool(true),             // load_on_startup
+                Json::Bool(true),                        // watchonly
+                Json::Bool(false),                       // blank
+                Json::String("".to_string()),            // passphrase,
+                Json::Bool(false),                       // avoid_reuse
+                Json::Bool(true),                        // descriptors
+                Json::Bool(self.load_wallet_on_startup), // load_on_startup
             ),
         )?;
 
@@ -347,7 +764,7 @@ impl BitcoinD {
             "loadwallet",
             &params!(
                 Json::String(wallet_path),
-                Json::Bool(true), // load_on_startup
+                Json::Bool(self.load_wallet_on_startup), // load_on_startup
             ),
         )?;
 
@@ -363,43 +780,104 @@ impl BitcoinD {
 
     pub fn unloadwallet(&self, wallet_path: String) -> Result<(), BitcoindError> {
         let res = self.make_node_request("unloadwallet", &params!(Json::String(wallet_path),))?;
+        Self::unloadwallet_result(res)
+    }
 
-        let warning = res
-            .get("warning")
-            .map(|w| w.as_str())
-            .flatten()
-            .ok_or_else(|| {
-                BitcoindError::Custom(
-                    "No or invalid 'warning' in 'unloadwallet' result".to_string(),
-                )
-            })?;
-        if warning.len() > 0 {
+    /// Unload our watchonly wallet, updating its entry in bitcoind's auto-load list according
+    /// to our configuration.
+    pub fn unloadwallet_shutdown(&self, wallet_path: String) -> Result<(), BitcoindError> {
+        let res = self.make_node_request(
+            "unloadwallet",
+            &params!(
+                Json::String(wallet_path),
+                Json::Bool(self.load_wallet_on_startup), // load_on_startup
+            ),
+        )?;
+        Self::unloadwallet_result(res)
+    }
+
+    fn unloadwallet_result(res: Json) -> Result<(), BitcoindError> {
+        let warning = res.get("warning").and_then(|w| w.as_str()).ok_or_else(|| {
+            BitcoindError::Custom("No or invalid 'warning' in 'unloadwallet' result".to_string())
+        })?;
+        if !warning.is_empty() {
             Err(BitcoindError::Custom(warning.to_string()))
         } else {
             Ok(())
         }
     }
 
+    /// Get the 'getwalletinfo' result for our watchonly wallet
+    pub fn getwalletinfo(&self) -> Result<Json, BitcoindError> {
+        self.make_watchonly_request("getwalletinfo", &[])
+    }
+
     /// Constructs an `addr()` descriptor out of an address
     pub fn addr_descriptor(&self, address: &str) -> Result<String, BitcoindError> {
         let desc_wo_checksum = format!("addr({})", address);
 
-        Ok(self
-            .make_watchonly_request(
-                "getdescriptorinfo",
-                &params!(Json::String(desc_wo_checksum)),
-            )?
-            .get("descriptor")
-            .ok_or_else(|| {
-                BitcoindError::Custom("No 'descriptor' in 'getdescriptorinfo'".to_string())
-            })?
-            .as_str()
+        descriptor_from_info(self.make_watchonly_request(
+            "getdescriptorinfo",
+            &params!(Json::String(desc_wo_checksum)),
+        )?)
+    }
+
+    /// Constructs the `addr()` descriptors of these addresses in a single batch request
+    pub fn addr_descriptors(&self, addresses: &[String]) -> Result<Vec<String>, BitcoindError> {
+        let params: Vec<[Box<serde_json::value::RawValue>; 1]> = addresses
+            .iter()
+            .map(|address| params!(Json::String(format!("addr({})", address))))
+            .collect();
+        let reqs: Vec<jsonrpc::Request> = params
+            .iter()
+            .map(|p| {
+                self.watchonly_client
+                    .build_request("getdescriptorinfo", p.as_ref())
+            })
+            .collect();
+
+        self.make_requests(&self.watchonly_client, &reqs)?
+            .into_iter()
+            .map(|res| descriptor_from_info(res?))
+            .collect()
+    }
+
+    /// Get the indexes bitcoind maintains, and whether they are synced
+    pub fn getindexinfo(&self) -> Result<HashMap<String, bool>, BitcoindError> {
+        self.make_node_request("getindexinfo", &[])?
+            .as_object()
             .ok_or_else(|| {
                 BitcoindError::Custom(
-                    "'descriptor' in 'getdescriptorinfo' isn't a string anymore".to_string(),
+                    "API break, 'getindexinfo' didn't return an object".to_string(),
                 )
             })?
-            .to_string())
+            .iter()
+            .map(|(name, info)| {
+                let synced = info
+                    .get("synced")
+                    .and_then(|s| s.as_bool())
+                    .ok_or_else(|| {
+                        BitcoindError::Custom(format!(
+                            "API break, 'getindexinfo' entry '{}' didn't contain a valid 'synced'",
+                            name
+                        ))
+                    })?;
+                Ok((name.clone(), synced))
+            })
+            .collect()
+    }
+
+    /// Whether bitcoind considers this descriptor as ranged, according to 'getdescriptorinfo'
+    pub fn is_range_descriptor(&self, descriptor: &str) -> Result<bool, BitcoindError> {
+        self.make_node_request(
+            "getdescriptorinfo",
+            &params!(Json::String(descriptor.to_string())),
+        )?
+        .get("isrange")
+        .and_then(|r| r.as_bool())
+        .ok_or_else(|| {
+            BitcoindError::Custom("No valid 'isrange' in 'getdescriptorinfo'".to_string())
+        })
     }
 
     fn bulk_import_descriptors(
@@ -409,21 +887,19 @@ impl BitcoinD {
         label: String,
         fresh_wallet: bool,
     ) -> Result<(), BitcoindError> {
+        // For a fresh wallet the caller gives us a timestamp only slightly in the past, so
+        // bitcoind will only rescan the last few blocks.
+        if !fresh_wallet {
+            log::debug!("Not a fresh wallet, rescan *may* take some time.");
+        }
         let all_descriptors: Vec<Json> = descriptors
             .into_iter()
             .map(|desc| {
                 let mut desc_map = serde_json::Map::with_capacity(3);
                 desc_map.insert("desc".to_string(), Json::String(desc));
-                // We set to "now" the timestamp for fresh wallet, as otherwise bitcoind
-                // will rescan the last few blocks for each of them.
                 desc_map.insert(
                     "timestamp".to_string(),
-                    if fresh_wallet {
-                        Json::String("now".to_string())
-                    } else {
-                        log::debug!("Not a fresh wallet, rescan *may* take some time.");
-                        Json::Number(serde_json::Number::from(timestamp))
-                    },
+                    Json::Number(serde_json::Number::from(timestamp)),
                 );
                 desc_map.insert("label".to_string(), Json::String(label.clone()));
 
@@ -431,8 +907,7 @@ impl BitcoinD {
             })
             .collect();
 
-        let res = self
-            .make_watchonly_request("importdescriptors", &params!(Json::Array(all_descriptors)))?;
+        let res = self.make_import_request(&params!(Json::Array(all_descriptors)))?;
         if res.get(0).map(|x| x.get("success")) == Some(Some(&Json::Bool(true))) {
             return Ok(());
         }
@@ -471,36 +946,107 @@ impl BitcoinD {
         )
     }
 
-    fn import_fresh_descriptor(
+    // Import all these descriptors, each with its own rescan timestamp, in a single
+    // 'importdescriptors' call
+    fn import_descriptors(
         &self,
-        descriptor: String,
+        descriptors: Vec<(String, Json)>,
         label: String,
     ) -> Result<(), BitcoindError> {
-        let mut desc_map = serde_json::Map::with_capacity(3);
-        desc_map.insert("desc".to_string(), Json::String(descriptor));
-        desc_map.insert("timestamp".to_string(), Json::String("now".to_string()));
-        desc_map.insert("label".to_string(), Json::String(label));
-
-        let res = self.make_watchonly_request(
-            "importdescriptors",
-            &params!(Json::Array(vec![Json::Object(desc_map,)])),
-        )?;
-        if res.get(0).map(|x| x.get("success")).flatten() == Some(&Json::Bool(true)) {
+        let all_descriptors: Vec<Json> = descriptors
+            .into_iter()
+            .map(|(desc, timestamp)| {
+                let mut desc_map = serde_json::Map::with_capacity(3);
+                desc_map.insert("desc".to_string(), Json::String(desc));
+                desc_map.insert("timestamp".to_string(), timestamp);
+                desc_map.insert("label".to_string(), Json::String(label.clone()));
+
+                Json::Object(desc_map)
+            })
+            .collect();
+
+        let res = self.make_import_request(&params!(Json::Array(all_descriptors)))?;
+        let all_success = res
+            .as_array()
+            .map(|results| {
+                results
+                    .iter()
+                    .all(|r| r.get("success") == Some(&Json::Bool(true)))
+            })
+            .unwrap_or(false);
+        if all_success {
             return Ok(());
         }
 
         Err(BitcoindError::Custom(format!(
-            "In import_fresh descriptor, no success returned from 'importdescriptor': {:?}",
+            "Importing descriptors, not all succeeded in 'importdescriptors': {:?}",
             res
         )))
     }
 
-    pub fn import_fresh_deposit_descriptor(&self, descriptor: String) -> Result<(), BitcoindError> {
-        self.import_fresh_descriptor(descriptor, self.deposit_utxos_label())
+    // Import all these never-used descriptors in a single 'importdescriptors' call
+    fn import_fresh_descriptors(
+        &self,
+        descriptors: Vec<String>,
+        label: String,
+    ) -> Result<(), BitcoindError> {
+        self.import_descriptors(
+            descriptors
+                .into_iter()
+                .map(|desc| (desc, Json::String("now".to_string())))
+                .collect(),
+            label,
+        )
+    }
+
+    /// Import the Unvault descriptors of these vaults in a single call, each rescanning from
+    /// its own timestamp
+    pub fn import_unvault_descriptors(
+        &self,
+        descriptors: Vec<(String, u32)>,
+    ) -> Result<(), BitcoindError> {
+        self.import_descriptors(
+            descriptors
+                .into_iter()
+                .map(|(desc, timestamp)| (desc, Json::Number(serde_json::Number::from(timestamp))))
+                .collect(),
+            self.unvault_utxos_label(),
+        )
+    }
+
+    /// Whether each of these addresses is already watched by our watchonly wallet, in a single
+    /// batch request
+    pub fn watched_addresses(&self, addresses: &[String]) -> Result<Vec<bool>, BitcoindError> {
+        let params: Vec<[Box<serde_json::value::RawValue>; 1]> = addresses
+            .iter()
+            .map(|address| params!(Json::String(address.clone())))
+            .collect();
+        let reqs: Vec<jsonrpc::Request> = params
+            .iter()
+            .map(|p| {
+                self.watchonly_client
+                    .build_request("getaddressinfo", p.as_ref())
+            })
+            .collect();
+
+        self.make_requests(&self.watchonly_client, &reqs)?
+            .into_iter()
+            .map(|res| is_mine_from_info(res?))
+            .collect()
+    }
+
+    pub fn import_fresh_deposit_descriptors(
+        &self,
+        descriptors: Vec<String>,
+    ) -> Result<(), BitcoindError> {
+        self.import_fresh_descriptors(descriptors, self.deposit_utxos_label())
     }
 
-    pub fn import_fresh_unvault_descriptor(&self, descriptor: String) -> Result<(), BitcoindError> {
-        self.import_fresh_descriptor(descriptor, self.unvault_utxos_label())
+    pub fn import_fresh_unvault_descriptors(
+        &self,
+        descriptors: Vec<String>,
+    ) -> Result<(), BitcoindError> {
+        self.import_fresh_descriptors(descriptors, self.unvault_utxos_label())
     }
 
     // A routine to get the txid,vout pair out of a listunspent entry
@@ -519,10 +1065,7 @@ impl BitcoinD {
                 )
             })?;
         let txid = Txid::from_str(txid).map_err(|e| {
-            BitcoindError::Custom(format!(
-                "Converting txid from str in 'listunspent': {}.",
-                e.to_string()
-            ))
+            BitcoindError::Custom(format!("Converting txid from str in 'listunspent': {}.", e))
         })?;
         let vout = utxo
             .get("vout")
@@ -543,19 +1086,74 @@ impl BitcoinD {
         })
     }
 
-    /// Repeatedly called by our main loop to stay in sync with bitcoind.
-    /// We take the currently known utxos, and return both the new and the spent ones for this
-    /// label.
-    fn sync_chainstate(
+    // Parse an entry of the 'listunspent' result
+    fn labeled_utxo_from_json(&self, utxo: &Json) -> Result<LabeledUtxo, BitcoindError> {
+        let confirmations = utxo
+            .get("confirmations")
+            .ok_or_else(|| {
+                BitcoindError::Custom(
+                    "API break, 'listunspent' entry didn't contain a 'confirmations'.".to_string(),
+                )
+            })?
+            .as_u64()
+            .ok_or_else(|| {
+                BitcoindError::Custom(
+                    "API break, 'listunspent' entry didn't contain a valid 'confirmations'."
+                        .to_string(),
+                )
+            })?;
+        let outpoint = self.outpoint_from_utxo(utxo)?;
+        // We match the utxos to our derivation indexes by scriptPubKey. Decoding it is much
+        // cheaper than parsing the (bech32) address, which adds up on large wallets.
+        let script_pubkey = utxo
+            .get("scriptPubKey")
+            .ok_or_else(|| {
+                BitcoindError::Custom(
+                    "API break, 'listunspent' entry didn't contain a 'scriptPubKey'.".to_string(),
+                )
+            })?
+            .as_str()
+            .ok_or_else(|| {
+                BitcoindError::Custom(
+                    "API break, 'listunspent' entry didn't contain a string 'scriptPubKey'."
+                        .to_string(),
+                )
+            })?;
+        let script_pubkey = Script::from_str(script_pubkey).map_err(|e| {
+            BitcoindError::Custom(format!(
+                "Could not parse 'scriptPubKey' from 'listunspent' entry: {}",
+                e
+            ))
+        })?;
+        let amount = utxo.get("amount").ok_or_else(|| {
+            BitcoindError::Custom(
+                "API break, 'listunspent' entry didn't contain an 'amount'.".to_string(),
+            )
+        })?;
+        let value = sat_from_json_amount(amount).ok_or_else(|| {
+            BitcoindError::Custom(format!(
+                "Could not convert 'listunspent' entry's 'amount' to an Amount: {}",
+                amount
+            ))
+        })?;
+
+        Ok(LabeledUtxo {
+            outpoint,
+            txo: TxOut {
+                value,
+                script_pubkey,
+            },
+            confirmations,
+        })
+    }
+
+    /// List the wallet utxos that were imported with this label. If `min_amount` is set, only
+    /// the utxos with a value greater or equal to it (in BTC) are returned.
+    pub fn list_labeled_utxos(
         &self,
-        current_utxos: &HashMap<OutPoint, UtxoInfo>,
-        label: String,
-        min_conf: u32,
+        label: &str,
         min_amount: Option<f64>,
-    ) -> Result<OnchainDescriptorState, BitcoindError> {
-        let (mut new_utxos, mut confirmed_utxos) = (HashMap::new(), HashMap::new());
-        // All seen utxos, if an utxo remains unseen by listunspent then it's spent.
-        let mut spent_utxos = current_utxos.clone();
+    ) -> Result<Vec<LabeledUtxo>, BitcoindError> {
         let label_json: Json = label.into();
 
         let req = if let Some(min_amount) = min_amount {
@@ -583,94 +1181,128 @@ impl BitcoinD {
             )
         };
 
+        let mut utxos = Vec::new();
         for utxo in req?.as_array().ok_or_else(|| {
             BitcoindError::Custom("API break, 'listunspent' didn't return an array.".to_string())
         })? {
             if utxo.get("label") != Some(&label_json) {
                 continue;
             }
-            let confirmations = utxo
-                .get("confirmations")
-                .ok_or_else(|| {
-                    BitcoindError::Custom(
-                        "API break, 'listunspent' entry didn't contain a 'confirmations'."
-                            .to_string(),
-                    )
-                })?
-                .as_u64()
-                .ok_or_else(|| {
-                    BitcoindError::Custom(
-                        "API break, 'listunspent' entry didn't contain a valid 'confirmations'."
-                            .to_string(),
-                    )
-                })?;
+            utxos.push(self.labeled_utxo_from_json(utxo)?);
+        }
 
-            let outpoint = self.outpoint_from_utxo(&utxo)?;
-            // Not obvious at first sight:
-            //  - spent_utxos == existing_utxos before the loop
-            //  - listunspent won't send duplicated entries
-            //  - remove() will return None if it was not present in the map
-            // Therefore if there is an utxo at this outpoint, it's an already known deposit
-            if let Some(utxo) = spent_utxos.remove(&outpoint) {
-                // It may be known but still unconfirmed, though.
-                if !utxo.is_confirmed && confirmations >= min_conf as u64 {
-                    confirmed_utxos.insert(outpoint, utxo);
+        Ok(utxos)
+    }
+
+    /// Repeatedly called by our main loop to stay in sync with bitcoind: get the tip of the chain
+    /// along with both our deposit and Unvault utxos, in a single round-trip.
+    pub fn wallet_snapshot(&self) -> Result<WalletSnapshot, BitcoindError> {
+        // The Unvault output value is always above the dust limit (and the deposits' one above
+        // MIN_DEPOSIT_VALUE), so have bitcoind filter out the smaller coins for us. We can't use
+        // a 'maxconf' though, as we'd then consider the older outputs as spent.
+        let mut query_options = serde_json::Map::with_capacity(1);
+        query_options.insert(
+            "minimumAmount".into(),
+            Amount::from_sat(DUST_LIMIT).as_btc().into(),
+        );
+        let listunspent_params = params!(
+            Json::Number(0.into()),       // minconf
+            Json::Number(9999999.into()), // maxconf (default)
+            Json::Array(vec![]),          // addresses (default)
+            Json::Bool(true),             // include_unsafe (default)
+            Json::Object(query_options),  // query_options
+        );
+        // The wallet endpoint serves the node RPCs too. We use 'getblockchaininfo' as it gives
+        // both the height and the hash of the tip at once.
+        let reqs = [
+            self.watchonly_client
+                .build_request("getblockchaininfo", &[]),
+            self.watchonly_client
+                .build_request("listunspent", &listunspent_params),
+        ];
+        let mut results = self
+            .make_requests(&self.watchonly_client, &reqs)?
+            .into_iter();
+        let chaininfo = results
+            .next()
+            .ok_or(BitcoindError::BatchMissingResponse)??;
+        let utxos = results
+            .next()
+            .ok_or(BitcoindError::BatchMissingResponse)??;
+
+        let height = chaininfo
+            .get("blocks")
+            .and_then(|b| b.as_u64())
+            .ok_or_else(|| {
+                BitcoindError::Custom("No valid 'blocks' in getblockchaininfo response?".to_owned())
+            })? as u32;
+        let hash = chaininfo
+            .get("bestblockhash")
+            .and_then(|h| h.as_str())
+            .and_then(|h| BlockHash::from_str(h).ok())
+            .ok_or_else(|| {
+                BitcoindError::Custom(
+                    "No valid 'bestblockhash' in getblockchaininfo response?".to_owned(),
+                )
+            })?;
+        let tip = BlockchainTip { height, hash };
+        *self.chaininfo_cache.lock().unwrap() = Some((Instant::now(), chaininfo));
+        self.wallet_tx_cache.lock().unwrap().set_tip(&tip);
+
+        let deposit_label: Json = self.deposit_utxos_label().into();
+        let unvault_label: Json = self.unvault_utxos_label().into();
+        let (mut deposits, mut unvaults) = (Vec::new(), Vec::new());
+        for utxo in utxos.as_array().ok_or_else(|| {
+            BitcoindError::Custom("API break, 'listunspent' didn't return an array.".to_string())
+        })? {
+            let label = utxo.get("label");
+            if label == Some(&deposit_label) {
+                let utxo = self.labeled_utxo_from_json(utxo)?;
+                if utxo.txo.value >= MIN_DEPOSIT_VALUE {
+                    deposits.push(utxo);
                 }
-                continue;
+            } else if label == Some(&unvault_label) {
+                unvaults.push(self.labeled_utxo_from_json(utxo)?);
             }
+        }
 
-            let address = utxo
-                .get("address")
-                .ok_or_else(|| {
-                    BitcoindError::Custom(
-                        "API break, 'listunspent' entry didn't contain an 'address'.".to_string(),
-                    )
-                })?
-                .as_str()
-                .ok_or_else(|| {
-                    BitcoindError::Custom(
-                        "API break, 'listunspent' entry didn't contain a string 'address'."
-                            .to_string(),
-                    )
-                })?;
-            let script_pubkey = Address::from_str(address)
-                .map_err(|e| {
-                    BitcoindError::Custom(format!(
-                        "Could not parse 'address' from 'listunspent' entry: {}",
-                        e.to_string()
-                    ))
-                })?
-                .script_pubkey();
-            let amount = utxo
-                .get("amount")
-                .ok_or_else(|| {
-                    BitcoindError::Custom(
-                        "API break, 'listunspent' entry didn't contain an 'amount'.".to_string(),
-                    )
-                })?
-                .as_f64()
-                .ok_or_else(|| {
-                    BitcoindError::Custom(
-                        "API break, 'listunspent' entry didn't contain a valid 'amount'."
-                            .to_string(),
-                    )
-                })?;
-            let value = Amount::from_btc(amount)
-                .map_err(|e| {
-                    BitcoindError::Custom(format!(
-                        "Could not convert 'listunspent' entry's 'amount' to an Amount: {}",
-                        e.to_string()
-                    ))
-                })?
-                .as_sat();
+        Ok(WalletSnapshot {
+            tip,
+            deposits,
+            unvaults,
+        })
+    }
+
+    /// We take the currently known utxos, and return both the new and the spent ones among the
+    /// utxos of a snapshot.
+    fn sync_labeled_utxos(
+        &self,
+        current_utxos: &HashMap<OutPoint, UtxoInfo>,
+        utxos: Vec<LabeledUtxo>,
+        min_conf: u32,
+    ) -> OnchainDescriptorState {
+        let (mut new_utxos, mut confirmed_utxos) = (HashMap::new(), HashMap::new());
+        // All seen utxos, if an utxo remains unseen by listunspent then it's spent.
+        let mut spent_utxos = current_utxos.clone();
+
+        for utxo in utxos {
+            // Not obvious at first sight:
+            //  - spent_utxos == existing_utxos before the loop
+            //  - listunspent won't send duplicated entries
+            //  - remove() will return None if it was not present in the map
+            // Therefore if there is an utxo at this outpoint, it's an already known deposit
+            if let Some(known_utxo) = spent_utxos.remove(&utxo.outpoint) {
+                // It may be known but still unconfirmed, though.
+                if !known_utxo.is_confirmed && utxo.confirmations >= min_conf as u64 {
+                    confirmed_utxos.insert(utxo.outpoint, known_utxo);
+                }
+                continue;
+            }
 
             new_utxos.insert(
-                outpoint,
+                utxo.outpoint,
                 UtxoInfo {
-                    txo: TxOut {
-                        value,
-                        script_pubkey,
-                    },
+                    txo: utxo.txo,
                     // All new utxos are marked as unconfirmed. This allows for a proper state
                     // transition.
                     is_confirmed: false,
@@ -678,31 +1310,74 @@ impl BitcoinD {
             );
         }
 
-        Ok(OnchainDescriptorState {
+        OnchainDescriptorState {
             new_unconf: new_utxos,
             new_conf: confirmed_utxos,
             new_spent: spent_utxos,
-        })
+        }
     }
 
+    // Whether this deposit utxo pays to one of the scripts derived from our deposit descriptor,
+    // warning (once) if not.
+    fn check_deposit_script(
+        &self,
+        deposit_scripts: &HashMap<Script, ChildNumber>,
+        outpoint: &OutPoint,
+        txo: &TxOut,
+    ) -> bool {
+        if deposit_scripts.contains_key(&txo.script_pubkey) {
+            return true;
+        }
+
+        if self.mismatched_deposits.lock().unwrap().insert(*outpoint) {
+            log::warn!(
+                "Ignoring deposit utxo '{}' ({} sats): its scriptPubKey '{}' is not one derived \
+                 from the deposit descriptor. It was likely sent to a wrong address by mistake.",
+                outpoint,
+                txo.value,
+                txo.script_pubkey
+            );
+        }
+        false
+    }
+
+    /// List the deposit utxos the watchonly wallet knows about, including the unconfirmed ones.
+    /// Those not paying to one of the `deposit_scripts` are ignored.
+    pub fn list_deposit_utxos(
+        &self,
+        deposit_scripts: &HashMap<Script, ChildNumber>,
+    ) -> Result<Vec<LabeledUtxo>, BitcoindError> {
+        let mut utxos = self.list_labeled_utxos(
+            &self.deposit_utxos_label(),
+            Some(Amount::from_sat(MIN_DEPOSIT_VALUE).as_btc()),
+        )?;
+        utxos.retain(|utxo| self.check_deposit_script(deposit_scripts, &utxo.outpoint, &utxo.txo));
+        Ok(utxos)
+    }
+
+    /// Sync the deposit utxos out of a wallet snapshot. The new ones not paying to one of the
+    /// `deposit_scripts` are ignored.
     pub fn sync_deposits(
         &self,
         deposits_utxos: &HashMap<OutPoint, UtxoInfo>,
+        snapshot_deposits: Vec<LabeledUtxo>,
         min_conf: u32,
-    ) -> Result<OnchainDescriptorState, BitcoindError> {
-        self.sync_chainstate(
-            deposits_utxos,
-            self.deposit_utxos_label(),
-            min_conf,
-            Some(Amount::from_sat(MIN_DEPOSIT_VALUE).as_btc()),
-        )
+        deposit_scripts: &HashMap<Script, ChildNumber>,
+    ) -> OnchainDescriptorState {
+        let mut state = self.sync_labeled_utxos(deposits_utxos, snapshot_deposits, min_conf);
+        // The known ones were already checked when they were new
+        state.new_unconf.retain(|outpoint, utxo| {
+            self.check_deposit_script(deposit_scripts, outpoint, &utxo.txo)
+        });
+        state
     }
 
     pub fn sync_unvaults(
         &self,
         unvault_utxos: &HashMap<OutPoint, UtxoInfo>,
-    ) -> Result<OnchainDescriptorState, BitcoindError> {
-        self.sync_chainstate(unvault_utxos, self.unvault_utxos_label(), 1, None)
+        snapshot_unvaults: Vec<LabeledUtxo>,
+    ) -> OnchainDescriptorState {
+        self.sync_labeled_utxos(unvault_utxos, snapshot_unvaults, 1)
     }
 
     // FIXME: this should return a struct not a footguny tuple.
@@ -712,72 +1387,182 @@ impl BitcoinD {
         &self,
         txid: &Txid,
     ) -> Result<(String, Option<u32>, u32), BitcoindError> {
+        if let Some(tx) = self.wallet_tx_cache.lock().unwrap().get(txid) {
+            return Ok((tx.hex, tx.blockheight, tx.received_time));
+        }
+
         let res = self
             .make_watchonly_request("gettransaction", &params!(Json::String(txid.to_string())))?;
-        let tx_hex = res
-            .get("hex")
-            .ok_or_else(|| {
-                BitcoindError::Custom(format!(
-                    "API break: no 'hex' in 'gettransaction' result (txid: {})",
-                    txid
-                ))
-            })?
-            .as_str()
-            .ok_or_else(|| {
-                BitcoindError::Custom("API break: 'hex' is not a string ????".to_string())
-            })?
-            .to_string();
-        let blockheight = res.get("blockheight").map(|bh| bh.as_u64().unwrap() as u32);
-        let received = res
-            .get("timereceived")
-            .ok_or_else(|| {
-                BitcoindError::Custom(format!(
-                    "API break: no 'time_received' in 'gettransaction' result (txid: {})",
-                    txid
-                ))
-            })?
-            .as_u64()
-            .ok_or_else(|| {
-                BitcoindError::Custom(format!(
-                    "API break: invalid 'time_received' in 'gettransaction' result (txid: {})",
-                    txid
-                ))
-            })? as u32;
+        let wallet_tx = wallet_transaction_from_json(txid, res)?;
+        self.wallet_tx_cache
+            .lock()
+            .unwrap()
+            .insert(*txid, wallet_tx.clone());
+
+        Ok((
+            wallet_tx.hex,
+            wallet_tx.blockheight,
+            wallet_tx.received_time,
+        ))
+    }
 
-        Ok((tx_hex, blockheight, received))
+    /// Drop the wallet transactions we cached, for instance to make sure to get their latest
+    /// confirmation status.
+    pub fn invalidate_wallet_tx_cache(&self) {
+        self.wallet_tx_cache.lock().unwrap().clear();
+    }
+
+    /// Get these transactions from the watchonly wallet in a single batch request. None for the
+    /// transactions that are not part of the wallet.
+    pub fn get_wallet_transactions(
+        &self,
+        txids: &[Txid],
+    ) -> Result<Vec<Option<WalletTransaction>>, BitcoindError> {
+        // Only ask bitcoind for the ones we don't have already
+        let mut wallet_txs: Vec<Option<WalletTransaction>> = {
+            let mut cache = self.wallet_tx_cache.lock().unwrap();
+            txids.iter().map(|txid| cache.get(txid)).collect()
+        };
+        let missing_txids: Vec<Txid> = txids
+            .iter()
+            .zip(wallet_txs.iter())
+            .filter_map(|(txid, tx)| if tx.is_none() { Some(*txid) } else { None })
+            .collect();
+        if missing_txids.is_empty() {
+            return Ok(wallet_txs);
+        }
+
+        let params: Vec<[Box<serde_json::value::RawValue>; 1]> = missing_txids
+            .iter()
+            .map(|txid| params!(Json::String(txid.to_string())))
+            .collect();
+        let reqs: Vec<jsonrpc::Request> = params
+            .iter()
+            .map(|p| {
+                self.watchonly_client
+                    .build_request("gettransaction", p.as_ref())
+            })
+            .collect();
+
+        let mut fetched = self
+            .make_requests(&self.watchonly_client, &reqs)?
+            .into_iter()
+            .zip(missing_txids.iter())
+            .map(
+                |(res, txid)| match res.and_then(|res| wallet_transaction_from_json(txid, res)) {
+                    Ok(wallet_tx) => Some(wallet_tx),
+                    Err(e) => {
+                        log::trace!(
+                            "Got '{:?}' from bitcoind when requesting wallet transaction '{}'",
+                            e,
+                            txid
+                        );
+                        None
+                    }
+                },
+            );
+
+        let mut cache = self.wallet_tx_cache.lock().unwrap();
+        for (txid, wallet_tx) in txids.iter().zip(wallet_txs.iter_mut()) {
+            if wallet_tx.is_some() {
+                continue;
+            }
+            *wallet_tx = fetched.next().flatten();
+            if let Some(ref tx) = wallet_tx {
+                cache.insert(*txid, tx.clone());
+            }
+        }
+
+        Ok(wallet_txs)
     }
 
     /// Broadcast a transaction with 'sendrawtransaction', discarding the returned txid
     pub fn broadcast_transaction(&self, tx: &Transaction) -> Result<(), BitcoindError> {
         let tx_hex = encode::serialize_hex(tx);
         log::debug!("Broadcasting '{}'", tx_hex);
-        self.make_watchonly_request("sendrawtransaction", &params!(Json::String(tx_hex)))
-            .map(|_| ())
+        self.send_raw_transaction(&tx.txid(), tx_hex)
+    }
+
+    // All broadcasts go through here or 'broadcast_transactions'
+    fn check_maintenance(&self) -> Result<(), BitcoindError> {
+        if self.maintenance.load(Ordering::Relaxed) {
+            return Err(BitcoindError::Maintenance);
+        }
+        Ok(())
+    }
+
+    // A 'sendrawtransaction' request that doesn't fail if bitcoind already knows the transaction
+    fn send_raw_transaction(&self, txid: &Txid, tx_hex: String) -> Result<(), BitcoindError> {
+        self.check_maintenance()?;
+        match self.make_watchonly_request("sendrawtransaction", &params!(Json::String(tx_hex))) {
+            Ok(_) => Ok(()),
+            Err(BitcoindError::Server(jsonrpc::Error::Rpc(ref e)))
+                if is_already_known_tx_error(e) =>
+            {
+                log::debug!(
+                    "Transaction '{}' is already known by bitcoind: '{}'",
+                    txid,
+                    e.message
+                );
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
     }
 
-    /// Broadcast a batch of transactions with 'sendrawtransaction'
+    /// Broadcast a batch of transactions with 'sendrawtransaction'. The batch is split in
+    /// chunks of at most `broadcast_batch_size` requests, so we don't hit bitcoind's limits
+    /// (or our timeout) for large batches.
+    /// A failure to broadcast a chunk does not prevent the next ones from being sent, the
+    /// first error encountered is returned.
     pub fn broadcast_transactions(&self, txs: &[Transaction]) -> Result<(), BitcoindError> {
-        let txs_hex: Vec<[Box<serde_json::value::RawValue>; 1]> = txs
-            .iter()
-            .map(|tx| params!(Json::String(encode::serialize_hex(tx))))
-            .collect();
-        log::debug!("Batch-broadcasting {:?}", txs_hex);
-        let reqs: Vec<jsonrpc::Request> = txs_hex
-            .iter()
-            .map(|hex| {
-                self.node_client
-                    .build_request("sendrawtransaction", hex.as_ref())
-            })
-            .collect();
-        self.make_node_requests(&reqs).map(|_| ())
+        self.check_maintenance()?;
+        let mut res = Ok(());
+
+        for chunk in txs.chunks(self.broadcast_batch_size) {
+            let txs_hex: Vec<[Box<serde_json::value::RawValue>; 1]> = chunk
+                .iter()
+                .map(|tx| params!(Json::String(encode::serialize_hex(tx))))
+                .collect();
+            log::debug!("Batch-broadcasting {:?}", txs_hex);
+            let reqs: Vec<jsonrpc::Request> = txs_hex
+                .iter()
+                .map(|hex| {
+                    self.node_client
+                        .build_request("sendrawtransaction", hex.as_ref())
+                })
+                .collect();
+            let results = match self.make_node_requests(&reqs) {
+                Ok(results) => results,
+                Err(e) => {
+                    log::error!("Error batch-broadcasting transactions: '{}'", e);
+                    if res.is_ok() {
+                        res = Err(e);
+                    }
+                    continue;
+                }
+            };
+
+            // One failing transaction doesn't prevent the others in the batch from being
+            // broadcast.
+            for (tx, tx_res) in chunk.iter().zip(results) {
+                if let Err(e) = tx_res {
+                    log::error!("Error broadcasting transaction '{}': '{}'", tx.txid(), e);
+                    if res.is_ok() {
+                        res = Err(e);
+                    }
+                }
+            }
+        }
+
+        res
     }
 
     /// Broadcast a transaction that is already part of the wallet
     pub fn rebroadcast_wallet_tx(&self, txid: &Txid) -> Result<(), BitcoindError> {
         let (hex, _, _) = self.get_wallet_transaction(txid)?;
         log::debug!("Re-broadcasting '{}'", hex);
-        self.make_watchonly_request("sendrawtransaction", &params!(Json::String(hex)))
-            .map(|_| ())
+        self.send_raw_transaction(txid, hex)
     }
 
     /// So, bitcoind has no API for getting the transaction spending a wallet UTXO. Instead we are
@@ -786,19 +1571,20 @@ impl BitcoinD {
     /// So, what we do there is listing all outgoing transactions of the wallet since the last poll
     /// and iterating through each of those to check if it spends the transaction we are interested
     /// in (requiring an other RPC call for each!!).
+    /// The spender is classified against the `known` transactions that may spend this outpoint.
     pub fn get_spender_txid(
         &self,
         spent_outpoint: &OutPoint,
         block_hash: &BlockHash,
-    ) -> Result<Option<Txid>, BitcoindError> {
+        known: &KnownSpenders,
+    ) -> Result<Option<Spender>, BitcoindError> {
         let lsb_res = self.make_watchonly_request(
             "listsinceblock",
             &params!(Json::String(block_hash.to_string())),
         )?;
         let transactions = lsb_res
             .get("transactions")
-            .map(|t| t.as_array())
-            .flatten()
+            .and_then(|t| t.as_array())
             .ok_or_else(|| {
                 BitcoindError::Custom(format!(
                     "API break: no or invalid 'transactions' in 'listsinceblock' result (blockhash: {})",
@@ -807,7 +1593,7 @@ impl BitcoinD {
             })?;
 
         for transaction in transactions {
-            if transaction.get("category").map(|c| c.as_str()).flatten() != Some("send") {
+            if transaction.get("category").and_then(|c| c.as_str()) != Some("send") {
                 continue;
             }
 
@@ -816,8 +1602,7 @@ impl BitcoinD {
 
             let spending_txid = transaction
                 .get("txid")
-                .map(|t| t.as_str())
-                .flatten()
+                .and_then(|t| t.as_str())
                 .ok_or_else(|| {
                     BitcoindError::Custom(format!(
                         "API break: no or invalid 'txid' in 'listsinceblock' entry (blockhash: {})",
@@ -835,8 +1620,7 @@ impl BitcoinD {
             )?;
             let vin = gettx_res
                 .get("decoded")
-                .map(|d| d.get("vin").map(|vin| vin.as_array()))
-                .flatten()
+                .and_then(|d| d.get("vin").map(|vin| vin.as_array()))
                 .flatten()
                 .ok_or_else(|| {
                     BitcoindError::Custom(format!(
@@ -848,15 +1632,14 @@ impl BitcoinD {
             for input in vin {
                 let txid = input
                     .get("txid")
-                    .map(|t| t.as_str().map(|t| Txid::from_str(t).ok()))
-                    .flatten()
+                    .and_then(|t| t.as_str().map(|t| Txid::from_str(t).ok()))
                     .flatten().ok_or_else(|| {
                     BitcoindError::Custom(format!(
                         "API break: Invalid or no txid in 'vin' entry in 'gettransaction' (blockhash: {})",
                         block_hash
                     ))
                 })?;
-                let vout = input.get("vout").map(|v| v.as_u64()).flatten().ok_or_else(|| {
+                let vout = input.get("vout").and_then(|v| v.as_u64()).ok_or_else(|| {
                     BitcoindError::Custom(format!(
                         "API break: Invalid or no vout in 'vin' entry in 'gettransaction' (blockhash: {})",
                         block_hash
@@ -865,14 +1648,19 @@ impl BitcoinD {
                 let input_outpoint = OutPoint { txid, vout };
 
                 if spent_outpoint == &input_outpoint {
-                    return Txid::from_str(spending_txid)
-                        .map(|txid| Some(txid))
-                        .map_err(|e| {
-                            BitcoindError::Custom(format!(
-                                "bitcoind gave an invalid txid in 'listsinceblock': '{}'",
-                                e
-                            ))
-                        });
+                    let sequence = input.get("sequence").and_then(|s| s.as_u64()).ok_or_else(|| {
+                        BitcoindError::Custom(format!(
+                            "API break: Invalid or no sequence in 'vin' entry in 'gettransaction' (blockhash: {})",
+                            block_hash
+                        ))
+                    })? as u32;
+                    let spending_txid = Txid::from_str(spending_txid).map_err(|e| {
+                        BitcoindError::Custom(format!(
+                            "bitcoind gave an invalid txid in 'listsinceblock': '{}'",
+                            e
+                        ))
+                    })?;
+                    return Ok(Some(known.classify(spending_txid, sequence)));
                 }
             }
         }
@@ -903,6 +1691,40 @@ impl BitcoinD {
             Ok((_, None, _)) => self.is_in_mempool(txid),
         }
     }
+
+    /// Check whether this output of a wallet transaction is spent by a confirmed transaction.
+    /// The output of an unconfirmed transaction is never considered as such.
+    pub fn is_spent_in_chain(&self, outpoint: &OutPoint) -> Result<bool, BitcoindError> {
+        if self.get_wallet_transaction(&outpoint.txid)?.1.is_none() {
+            return Ok(false);
+        }
+
+        // Not including the mempool, it's only null once the spending transaction confirmed
+        Ok(self
+            .make_node_request(
+                "gettxout",
+                &params!(
+                    Json::String(outpoint.txid.to_string()),
+                    Json::Number(serde_json::Number::from(outpoint.vout)),
+                    Json::Bool(false),
+                ),
+            )?
+            .is_null())
+    }
+}
+
+/// The label attached to the utxos of this category in the watchonly wallet. We use
+/// "deposit" and "unvault", but any category may be used for tracking other utxos.
+pub fn utxos_label(category: &str) -> String {
+    format!("revault-{}", category)
+}
+
+/// An utxo of the watchonly wallet, as returned by 'listunspent'.
+#[derive(Debug, Clone)]
+pub struct LabeledUtxo {
+    pub outpoint: OutPoint,
+    pub txo: TxOut,
+    pub confirmations: u64,
 }
 
 /// Information about an utxo one of our descriptors points to.
@@ -912,6 +1734,14 @@ pub struct UtxoInfo {
     pub is_confirmed: bool,
 }
 
+/// bitcoind's tip along with the deposit and Unvault utxos of the watchonly wallet
+#[derive(Debug, Clone)]
+pub struct WalletSnapshot {
+    pub tip: BlockchainTip,
+    pub deposits: Vec<LabeledUtxo>,
+    pub unvaults: Vec<LabeledUtxo>,
+}
+
 /// New informations about sets of utxos represented by a descriptor that actually ended
 /// up onchain.
 pub struct OnchainDescriptorState {
@@ -923,9 +1753,820 @@ pub struct OnchainDescriptorState {
     pub new_spent: HashMap<OutPoint, UtxoInfo>,
 }
 
+/// What kind of transaction spent an Unvault txo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpenderKind {
+    Cancel,
+    UnvaultEmergency,
+    /// One of the Spend transactions we know about
+    Spend,
+    /// None of the transactions we know about, possibly a theft attempt
+    Unknown,
+    /// None of the transactions we know about, and it can't be spending through the managers'
+    /// path. Since it isn't one of the revocation transactions either, the stakeholders' keys
+    /// were used to sign it.
+    Unexpected,
+}
+
+/// A transaction spending an Unvault txo, as returned by `get_spender_txid`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spender {
+    pub txid: Txid,
+    pub kind: SpenderKind,
+}
+
+/// The transactions we expect may spend an Unvault txo.
+#[derive(Debug, Clone, Default)]
+pub struct KnownSpenders {
+    pub cancel: Option<Txid>,
+    pub unvault_emer: Option<Txid>,
+    pub spends: Vec<Txid>,
+    /// The relative timelock of the managers' path of the Unvault descriptor
+    pub csv: u32,
+}
+
+impl KnownSpenders {
+    // Whether an input with this nSequence may spend through the managers' path, ie if it
+    // enables a relative timelock in blocks of at least the CSV (BIP68). Only the converse
+    // is certain: anyone can set such a nSequence on an input spending through another path.
+    fn may_be_managers_path(&self, sequence: u32) -> bool {
+        sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG == 0
+            && sequence & SEQUENCE_LOCKTIME_TYPE_FLAG == 0
+            && sequence & SEQUENCE_LOCKTIME_MASK >= self.csv
+    }
+
+    /// Classify the transaction spending the Unvault txo with an input of this nSequence.
+    pub fn classify(&self, txid: Txid, sequence: u32) -> Spender {
+        let kind = if self.cancel == Some(txid) {
+            SpenderKind::Cancel
+        } else if self.unvault_emer == Some(txid) {
+            SpenderKind::UnvaultEmergency
+        } else if self.spends.contains(&txid) {
+            SpenderKind::Spend
+        } else if !self.may_be_managers_path(sequence) {
+            SpenderKind::Unexpected
+        } else {
+            SpenderKind::Unknown
+        };
+
+        Spender { txid, kind }
+    }
+}
+
 pub struct SyncInfo {
     pub headers: u64,
     pub blocks: u64,
     pub ibd: bool,
     pub progress: f64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        batch_results, feerate_vb_from_btc_kvb, is_already_known_tx_error,
+        is_transient_import_error, sat_from_btc, sat_from_json_amount, utxos_label, BitcoinD,
+        KnownSpenders, OnchainDescriptorState, SpenderKind, UtxoInfo, WalletTxCache,
+    };
+    use crate::{
+        bitcoind::BitcoindError,
+        jsonrpc::UserRole,
+        revaultd::{BlockchainTip, RevaultD},
+        threadmessages::WalletTransaction,
+        utils::test_utils::{dummy_revaultd, test_datadir},
+    };
+    use common::config::BitcoindConfig;
+    use revault_tx::bitcoin::{
+        hashes::Hash, util::bip32::ChildNumber, Address, BlockHash, Network, OutPoint, Script, Txid,
+    };
+    use serde_json::Value as Json;
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+        collections::HashMap,
+        fs,
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    // Keeps track of the heap used by each thread, for the memory benchmarks. This way the
+    // allocations of a mock bitcoind, on its own thread, are not accounted.
+    struct ThreadHeapCounter;
+
+    thread_local! {
+        static HEAP_CURRENT: Cell<isize> = Cell::new(0);
+        static HEAP_PEAK: Cell<isize> = Cell::new(0);
+    }
+
+    fn heap_track(diff: isize) {
+        let _ = HEAP_CURRENT.try_with(|current| {
+            current.set(current.get() + diff);
+            let _ = HEAP_PEAK.try_with(|peak| peak.set(peak.get().max(current.get())));
+        });
+    }
+
+    // Start measuring the peak heap usage of this thread from now, returns the current usage.
+    fn heap_reset_peak() -> isize {
+        let current = HEAP_CURRENT.with(Cell::get);
+        HEAP_PEAK.with(|peak| peak.set(current));
+        current
+    }
+
+    unsafe impl GlobalAlloc for ThreadHeapCounter {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc(layout);
+            if !ptr.is_null() {
+                heap_track(layout.size() as isize);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+            heap_track(-(layout.size() as isize));
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let new_ptr = System.realloc(ptr, layout, new_size);
+            if !new_ptr.is_null() {
+                heap_track(new_size as isize - layout.size() as isize);
+            }
+            new_ptr
+        }
+    }
+
+    #[global_allocator]
+    static HEAP_COUNTER: ThreadHeapCounter = ThreadHeapCounter;
+
+    // A dumb bitcoind answering any batch of 'getblockchaininfo' and 'listunspent' with the
+    // given responses, and of 'gettransaction' with a dummy confirmed transaction. Counts the
+    // HTTP requests it received.
+    fn mock_bitcoind(chaininfo: Json, utxos: Json) -> (std::net::SocketAddr, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let req_count = Arc::new(AtomicUsize::new(0));
+
+        let counter = req_count.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    let mut header = line.splitn(2, ':');
+                    if header.next() == Some("Content-Length") {
+                        content_length = header.next().unwrap().trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let reqs: Json = serde_json::from_slice(&body).unwrap();
+                let resps: Vec<Json> = reqs
+                    .as_array()
+                    .expect("We only ever get batches")
+                    .iter()
+                    .map(|req| {
+                        let result = match req["method"].as_str().unwrap() {
+                            "getblockchaininfo" => chaininfo.clone(),
+                            "listunspent" => utxos.clone(),
+                            "gettransaction" => serde_json::json!({
+                                "hex": "00".repeat(300),
+                                "blockheight": 800_000,
+                                "timereceived": 1_600_000_000,
+                            }),
+                            m => panic!("Unexpected request '{}'", m),
+                        };
+                        serde_json::json!({"result": result, "error": null, "id": req["id"]})
+                    })
+                    .collect();
+                let resp_body = serde_json::to_string(&resps).unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}\n",
+                    resp_body.len() + 1,
+                    resp_body
+                )
+                .unwrap();
+            }
+        });
+
+        (addr, req_count)
+    }
+
+    fn bitcoind_client(addr: std::net::SocketAddr) -> BitcoinD {
+        let config: BitcoindConfig = toml::from_str(&format!(
+            "network = \"regtest\"\nrpc_user = \"revault\"\nrpc_password = \"pass\"\naddr = \"{}\"",
+            addr
+        ))
+        .unwrap();
+        BitcoinD::new(
+            &config,
+            "revaultd-watchonly-wallet".to_string(),
+            Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn sat_from_btc_lossless() {
+        // The amounts as formatted by bitcoind, and what we expect in sats.
+        for (btc_str, sats) in &[
+            ("0.00000001", 1),
+            ("0.1", 10_000_000),
+            ("0.29", 29_000_000),
+            ("1.00000003", 100_000_003),
+            ("0.30000001", 30_000_001),
+            ("12345678.87654321", 1_234_567_887_654_321),
+            ("20999999.99999999", 2_099_999_999_999_999),
+            ("21000000.00000000", 2_100_000_000_000_000),
+        ] {
+            let btc = serde_json::from_str::<Json>(btc_str)
+                .unwrap()
+                .as_f64()
+                .unwrap();
+            assert_eq!(sat_from_btc(btc), Some(*sats), "{}", btc_str);
+        }
+
+        assert_eq!(sat_from_btc(-0.00000001), None);
+        assert_eq!(sat_from_btc(21_000_000.00000001), None);
+        assert_eq!(sat_from_btc(f64::NAN), None);
+        assert_eq!(sat_from_btc(f64::INFINITY), None);
+    }
+
+    #[test]
+    fn sat_from_json_amount_representations() {
+        for (amount, sats) in &[
+            ("0.00000001", 1),
+            ("\"0.00000001\"", 1),
+            ("0.30000001", 30_000_001),
+            ("\"0.30000001\"", 30_000_001),
+            ("\"12345678.87654321\"", 1_234_567_887_654_321),
+            ("\"21000000\"", 2_100_000_000_000_000),
+            ("\" 1.5 \"", 150_000_000),
+        ] {
+            let amount = serde_json::from_str::<Json>(amount).unwrap();
+            assert_eq!(sat_from_json_amount(&amount), Some(*sats), "{}", amount);
+        }
+
+        for amount in &[
+            "\"\"",
+            "\"one\"",
+            "\"-0.1\"",
+            "\"0.000000001\"",
+            "\"21000000.00000001\"",
+            "-1",
+            "null",
+            "true",
+            "[1]",
+        ] {
+            let amount = serde_json::from_str::<Json>(amount).unwrap();
+            assert_eq!(sat_from_json_amount(&amount), None, "{}", amount);
+        }
+    }
+
+    #[test]
+    fn batch_results_mixed() {
+        let success = |id: u64, res: &str| jsonrpc::Response {
+            result: Some(serde_json::value::RawValue::from_string(res.to_string()).unwrap()),
+            error: None,
+            id: Json::from(id),
+            jsonrpc: Some("2.0".to_string()),
+        };
+        let failure = |id: u64| jsonrpc::Response {
+            result: None,
+            error: Some(jsonrpc::error::RpcError {
+                code: -26,
+                message: "txn-mempool-conflict".to_string(),
+                data: None,
+            }),
+            id: Json::from(id),
+            jsonrpc: Some("2.0".to_string()),
+        };
+
+        let results = batch_results(vec![
+            Some(success(0, "\"aa\"")),
+            Some(failure(1)),
+            None,
+            Some(success(3, "\"bb\"")),
+        ]);
+        assert_eq!(results.len(), 4);
+        assert_eq!(
+            results[0].as_ref().unwrap(),
+            &Json::String("aa".to_string())
+        );
+        assert!(matches!(
+            results[1],
+            Err(BitcoindError::Server(jsonrpc::Error::Rpc(ref e))) if e.code == -26
+        ));
+        assert!(matches!(
+            results[2],
+            Err(BitcoindError::BatchMissingResponse)
+        ));
+        assert_eq!(
+            results[3].as_ref().unwrap(),
+            &Json::String("bb".to_string())
+        );
+    }
+
+    #[test]
+    fn transient_import_errors() {
+        let rpc_err = |code: i32, message: &str| jsonrpc::error::RpcError {
+            code,
+            message: message.to_string(),
+            data: None,
+        };
+
+        assert!(is_transient_import_error(&rpc_err(
+            -28,
+            "Loading wallet..."
+        )));
+        assert!(is_transient_import_error(&rpc_err(
+            -18,
+            "Requested wallet does not exist or is not loaded"
+        )));
+        assert!(is_transient_import_error(&rpc_err(
+            -4,
+            "Wallet is currently rescanning. Abort existing rescan or wait."
+        )));
+
+        // Genuine descriptor or wallet errors must not be retried
+        assert!(!is_transient_import_error(&rpc_err(
+            -4,
+            "Cannot import descriptor without private keys to a wallet with private keys enabled"
+        )));
+        assert!(!is_transient_import_error(&rpc_err(
+            -5,
+            "Invalid descriptor"
+        )));
+        assert!(!is_transient_import_error(&rpc_err(
+            -8,
+            "Invalid parameter"
+        )));
+    }
+
+    #[test]
+    fn wallet_tx_cache() {
+        let txid = |i: u8| Txid::from_slice(&[i; 32]).unwrap();
+        let wallet_tx = |blockheight: Option<u32>| WalletTransaction {
+            hex: "00".to_string(),
+            blockheight,
+            received_time: 1615297315,
+            fee: None,
+        };
+        let tip = |height: u32| BlockchainTip {
+            height,
+            hash: BlockHash::from_slice(&[height as u8; 32]).unwrap(),
+        };
+
+        let mut cache = WalletTxCache::new(2);
+        cache.set_tip(&tip(100));
+        cache.insert(txid(1), wallet_tx(None));
+        cache.insert(txid(2), wallet_tx(Some(99)));
+        assert!(cache.get(&txid(1)).is_some());
+        assert_eq!(cache.get(&txid(2)).unwrap().blockheight, Some(99));
+
+        // The least recently used entry is evicted when full
+        assert!(cache.get(&txid(1)).is_some());
+        cache.insert(txid(3), wallet_tx(None));
+        assert!(cache.get(&txid(2)).is_none());
+        assert!(cache.get(&txid(1)).is_some());
+        assert!(cache.get(&txid(3)).is_some());
+
+        // Updating an entry doesn't evict anything
+        cache.insert(txid(3), wallet_tx(Some(100)));
+        assert_eq!(cache.get(&txid(3)).unwrap().blockheight, Some(100));
+        assert!(cache.get(&txid(1)).is_some());
+
+        // Entries are kept as long as the tip doesn't change, dropped otherwise
+        cache.set_tip(&tip(100));
+        assert!(cache.get(&txid(1)).is_some());
+        cache.set_tip(&tip(101));
+        assert!(cache.get(&txid(1)).is_none());
+        assert!(cache.get(&txid(3)).is_none());
+
+        // A null capacity disables the cache
+        let mut cache = WalletTxCache::new(0);
+        cache.insert(txid(1), wallet_tx(None));
+        assert!(cache.get(&txid(1)).is_none());
+    }
+
+    #[test]
+    fn already_known_tx_errors() {
+        let rpc_err = |code: i32, message: &str| jsonrpc::error::RpcError {
+            code,
+            message: message.to_string(),
+            data: None,
+        };
+
+        // Already confirmed, as reported by older and newer bitcoind versions
+        assert!(is_already_known_tx_error(&rpc_err(
+            -27,
+            "Transaction already in block chain"
+        )));
+        assert!(is_already_known_tx_error(&rpc_err(
+            -27,
+            "Transaction outputs already in utxo set"
+        )));
+        // Already in mempool
+        assert!(is_already_known_tx_error(&rpc_err(
+            -26,
+            "txn-already-known"
+        )));
+        assert!(is_already_known_tx_error(&rpc_err(
+            -26,
+            "txn-already-in-mempool"
+        )));
+
+        // Genuine broadcast failures are still errors
+        assert!(!is_already_known_tx_error(&rpc_err(
+            -26,
+            "txn-mempool-conflict"
+        )));
+        assert!(!is_already_known_tx_error(&rpc_err(-26, "non-BIP68-final")));
+        assert!(!is_already_known_tx_error(&rpc_err(
+            -25,
+            "bad-txns-inputs-missingorspent"
+        )));
+    }
+
+    #[test]
+    fn feerate_conversion() {
+        // The default minimum relay fee
+        assert_eq!(
+            feerate_vb_from_btc_kvb(&serde_json::json!(0.00001)),
+            Some(1)
+        );
+        assert_eq!(
+            feerate_vb_from_btc_kvb(&serde_json::json!(0.00024)),
+            Some(24)
+        );
+        // Rounded up
+        assert_eq!(
+            feerate_vb_from_btc_kvb(&serde_json::json!(0.00001001)),
+            Some(2)
+        );
+        assert_eq!(feerate_vb_from_btc_kvb(&serde_json::json!(0.0)), Some(0));
+        assert_eq!(feerate_vb_from_btc_kvb(&serde_json::json!(-0.0001)), None);
+        assert_eq!(feerate_vb_from_btc_kvb(&Json::Null), None);
+    }
+
+    #[test]
+    fn classify_spenders() {
+        use revault_tx::bitcoin::Txid;
+        use std::str::FromStr;
+
+        let txid = |c: char| Txid::from_str(&c.to_string().repeat(64)).unwrap();
+        let known = KnownSpenders {
+            cancel: Some(txid('a')),
+            unvault_emer: Some(txid('b')),
+            spends: vec![txid('c'), txid('d')],
+            csv: 144,
+        };
+
+        // The transactions we know are recognized whatever their nSequence
+        assert_eq!(
+            known.classify(txid('a'), 0xff_ff_ff_fd).kind,
+            SpenderKind::Cancel
+        );
+        assert_eq!(
+            known.classify(txid('b'), 0xff_ff_ff_fd).kind,
+            SpenderKind::UnvaultEmergency
+        );
+        assert_eq!(known.classify(txid('d'), 144).kind, SpenderKind::Spend);
+
+        // We can't tell an unknown transaction enabling the CSV isn't spending through the
+        // managers' path
+        let spender = known.classify(txid('e'), 144);
+        assert_eq!(spender.kind, SpenderKind::Unknown);
+        assert_eq!(spender.txid, txid('e'));
+        assert_eq!(known.classify(txid('e'), 1000).kind, SpenderKind::Unknown);
+
+        // But one that doesn't is unexpected
+        assert_eq!(known.classify(txid('e'), 143).kind, SpenderKind::Unexpected);
+        // No relative timelock
+        assert_eq!(
+            known.classify(txid('e'), 0xff_ff_ff_fd).kind,
+            SpenderKind::Unexpected
+        );
+        // A time-based relative timelock
+        assert_eq!(
+            known.classify(txid('e'), (1 << 22) | 144).kind,
+            SpenderKind::Unexpected
+        );
+
+        // A stakeholder-only or manager-only view may not know all of them
+        let known = KnownSpenders {
+            cancel: Some(txid('a')),
+            csv: 144,
+            ..KnownSpenders::default()
+        };
+        assert_eq!(known.classify(txid('b'), 0).kind, SpenderKind::Unexpected);
+        assert_eq!(known.classify(txid('c'), 144).kind, SpenderKind::Unknown);
+    }
+
+    // The poller gets the tip and all the deposit and unvault utxos of a round in a single
+    // round-trip to bitcoind.
+    #[test]
+    fn wallet_snapshot_single_round_trip() {
+        let tip_hash = BlockHash::from_inner([1; 32]);
+        let chaininfo = serde_json::json!({
+            "chain": "regtest",
+            "blocks": 142,
+            "bestblockhash": tip_hash.to_string(),
+        });
+        let address = |n: u8| Address::p2wsh(&Script::from(vec![n]), Network::Regtest);
+        let utxo = |n: u8, label: &str, amount: f64| {
+            serde_json::json!({
+                "txid": Txid::from_inner([n; 32]).to_string(),
+                "vout": 0,
+                "address": address(n).to_string(),
+                "scriptPubKey": format!("{:x}", address(n).script_pubkey()),
+                "label": label,
+                "amount": amount,
+                "confirmations": 3,
+            })
+        };
+        let utxos = Json::Array(vec![
+            utxo(1, &utxos_label("deposit"), 1.0),
+            utxo(2, &utxos_label("unvault"), 0.98),
+            // Below MIN_DEPOSIT_VALUE, so not a deposit for us
+            utxo(3, &utxos_label("deposit"), 0.002),
+            // Not one of ours
+            utxo(4, "", 12.0),
+            utxo(5, &utxos_label("deposit"), 0.5),
+        ]);
+        let (addr, req_count) = mock_bitcoind(chaininfo, utxos);
+
+        let bitcoind = bitcoind_client(addr);
+
+        let snapshot = bitcoind.wallet_snapshot().unwrap();
+        assert_eq!(req_count.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            snapshot.tip,
+            BlockchainTip {
+                height: 142,
+                hash: tip_hash
+            }
+        );
+        let deposits: Vec<Script> = snapshot
+            .deposits
+            .iter()
+            .map(|u| u.txo.script_pubkey.clone())
+            .collect();
+        assert_eq!(
+            deposits,
+            vec![address(1).script_pubkey(), address(5).script_pubkey()]
+        );
+        assert_eq!(snapshot.deposits[0].txo.value, 100_000_000);
+        assert_eq!(snapshot.deposits[0].confirmations, 3);
+        assert_eq!(snapshot.unvaults.len(), 1);
+        assert_eq!(
+            snapshot.unvaults[0].txo.script_pubkey,
+            address(2).script_pubkey()
+        );
+        assert_eq!(
+            snapshot.unvaults[0].outpoint.txid,
+            Txid::from_inner([2; 32])
+        );
+
+        // Syncing the deposits and the Unvaults is free, it reuses the snapshot.
+        let deposit_scripts: HashMap<Script, ChildNumber> = [1, 5]
+            .iter()
+            .map(|n| (address(*n).script_pubkey(), ChildNumber::from(*n as u32)))
+            .collect();
+        let deposits_state = bitcoind.sync_deposits(
+            &Default::default(),
+            snapshot.deposits.clone(),
+            1,
+            &deposit_scripts,
+        );
+        assert_eq!(deposits_state.new_unconf.len(), 2);
+        let unvaults_state = bitcoind.sync_unvaults(&Default::default(), snapshot.unvaults);
+        assert_eq!(unvaults_state.new_unconf.len(), 1);
+        assert_eq!(req_count.load(Ordering::SeqCst), 1);
+
+        // A deposit utxo paying to a script that wasn't derived from our deposit descriptor was
+        // sent to a wrong address, it's ignored.
+        let deposit_scripts: HashMap<Script, ChildNumber> =
+            vec![(address(1).script_pubkey(), ChildNumber::from(1))]
+                .into_iter()
+                .collect();
+        let deposits_state =
+            bitcoind.sync_deposits(&Default::default(), snapshot.deposits, 1, &deposit_scripts);
+        assert_eq!(deposits_state.new_unconf.len(), 1);
+        assert!(deposits_state
+            .new_unconf
+            .contains_key(&OutPoint::new(Txid::from_inner([1; 32]), 0)));
+    }
+
+    // A mock bitcoind with a deposit and an Unvault utxo for each of the first `n_vaults`
+    // derivation indexes, which are watched by the returned revaultd.
+    fn large_wallet(
+        datadir: std::path::PathBuf,
+        n_vaults: u32,
+    ) -> (RevaultD, std::net::SocketAddr, Arc<AtomicUsize>) {
+        let mut revaultd = dummy_revaultd(datadir, UserRole::ManagerStakeholder);
+        let indexes: Vec<ChildNumber> = (0..n_vaults).map(ChildNumber::from).collect();
+        revaultd.watch_deposit_indexes(&indexes);
+
+        let chaininfo = serde_json::json!({
+            "blocks": 800_000,
+            "bestblockhash": BlockHash::from_inner([2; 32]).to_string(),
+        });
+        let utxo = |i: u32, address: Address, category: &str| {
+            let mut txid = [0; 32];
+            txid[..4].copy_from_slice(&i.to_be_bytes());
+            txid[4] = category.len() as u8;
+            serde_json::json!({
+                "txid": Txid::from_inner(txid).to_string(),
+                "vout": 0,
+                "address": address.to_string(),
+                "scriptPubKey": format!("{:x}", address.script_pubkey()),
+                "label": utxos_label(category),
+                "amount": 0.5,
+                "confirmations": 6,
+            })
+        };
+        let utxos = Json::Array(
+            indexes
+                .iter()
+                .map(|index| utxo((*index).into(), revaultd.vault_address(*index), "deposit"))
+                .chain(indexes.iter().map(|index| {
+                    utxo((*index).into(), revaultd.unvault_address(*index), "unvault")
+                }))
+                .collect(),
+        );
+        let (addr, req_count) = mock_bitcoind(chaininfo, utxos);
+
+        (revaultd, addr, req_count)
+    }
+
+    // A large wallet is synced out of a single request, and each of its deposits is matched to
+    // its derivation index by the map the poller uses.
+    #[test]
+    fn wallet_snapshot_large_wallet() {
+        const N_VAULTS: u32 = 1_000;
+
+        let datadir = test_datadir();
+        let (revaultd, addr, req_count) = large_wallet(datadir.clone(), N_VAULTS);
+        let bitcoind = bitcoind_client(addr);
+
+        let snapshot = bitcoind.wallet_snapshot().unwrap();
+        assert_eq!(snapshot.deposits.len(), N_VAULTS as usize);
+        assert_eq!(snapshot.unvaults.len(), N_VAULTS as usize);
+        let deposits = bitcoind.sync_deposits(
+            &Default::default(),
+            snapshot.deposits,
+            1,
+            &revaultd.derivation_index_map,
+        );
+        assert_eq!(deposits.new_unconf.len(), N_VAULTS as usize);
+        let unvaults = bitcoind.sync_unvaults(&Default::default(), snapshot.unvaults);
+        assert_eq!(unvaults.new_unconf.len(), N_VAULTS as usize);
+        assert_eq!(req_count.load(Ordering::SeqCst), 1);
+
+        // Every deposit is matched to the index it was derived at by its scriptPubKey
+        for (outpoint, utxo) in deposits.new_unconf.iter() {
+            let raw_index = u32::from_be_bytes([
+                outpoint.txid[0],
+                outpoint.txid[1],
+                outpoint.txid[2],
+                outpoint.txid[3],
+            ]);
+            assert_eq!(
+                revaultd.derivation_index_map.get(&utxo.txo.script_pubkey),
+                Some(&ChildNumber::from(raw_index))
+            );
+        }
+
+        fs::remove_dir_all(&datadir).unwrap_or(());
+    }
+
+    // How long a poll of a wallet with thousands of utxos takes, once they are all known: the
+    // snapshot round-trip to bitcoind and the diff against our caches. Run it with
+    // `cargo test --release wallet_snapshot_latency -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn wallet_snapshot_latency() {
+        const N_VAULTS: u32 = 5_000;
+        const N_POLLS: usize = 20;
+
+        let datadir = test_datadir();
+        let (revaultd, addr, _) = large_wallet(datadir.clone(), N_VAULTS);
+        let bitcoind = bitcoind_client(addr);
+
+        // The first poll discovers them all
+        let snapshot = bitcoind.wallet_snapshot().unwrap();
+        let known = |state: OnchainDescriptorState| -> HashMap<OutPoint, UtxoInfo> {
+            state
+                .new_unconf
+                .into_iter()
+                .map(|(outpoint, utxo)| {
+                    (
+                        outpoint,
+                        UtxoInfo {
+                            is_confirmed: true,
+                            ..utxo
+                        },
+                    )
+                })
+                .collect()
+        };
+        let deposits_cache = known(bitcoind.sync_deposits(
+            &Default::default(),
+            snapshot.deposits,
+            1,
+            &revaultd.derivation_index_map,
+        ));
+        let unvaults_cache = known(bitcoind.sync_unvaults(&Default::default(), snapshot.unvaults));
+        assert_eq!(deposits_cache.len(), N_VAULTS as usize);
+        assert_eq!(unvaults_cache.len(), N_VAULTS as usize);
+
+        let mut latencies: Vec<Duration> = (0..N_POLLS)
+            .map(|_| {
+                let start = Instant::now();
+                let snapshot = bitcoind.wallet_snapshot().unwrap();
+                let deposits = bitcoind.sync_deposits(
+                    &deposits_cache,
+                    snapshot.deposits,
+                    1,
+                    &revaultd.derivation_index_map,
+                );
+                let unvaults = bitcoind.sync_unvaults(&unvaults_cache, snapshot.unvaults);
+                let elapsed = start.elapsed();
+                assert!(deposits.new_unconf.is_empty() && deposits.new_spent.is_empty());
+                assert!(unvaults.new_unconf.is_empty() && unvaults.new_spent.is_empty());
+                elapsed
+            })
+            .collect();
+        latencies.sort();
+        println!(
+            "Poll of a wallet with {} utxos over {} runs: min {:?}, median {:?}, max {:?}",
+            2 * N_VAULTS,
+            N_POLLS,
+            latencies[0],
+            latencies[N_POLLS / 2],
+            latencies[N_POLLS - 1]
+        );
+
+        fs::remove_dir_all(&datadir).unwrap_or(());
+    }
+
+    // The peak heap used to get the onchain transactions of a 10k-vault wallet from bitcoind, by
+    // chunks of 100 vaults as listonchaintransactions does, and all at once as it used to. Run
+    // it with `cargo test --release wallet_transactions_memory -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn wallet_transactions_memory() {
+        const N_VAULTS: usize = 10_000;
+        // Deposit, Unvault, Cancel, Emergency, UnvaultEmergency and Spend
+        const TXS_PER_VAULT: usize = 6;
+        const CHUNK_SIZE: usize = 100;
+
+        let (addr, _) = mock_bitcoind(Json::Null, Json::Null);
+        let bitcoind = bitcoind_client(addr);
+        let txids: Vec<Txid> = (0..N_VAULTS * TXS_PER_VAULT)
+            .map(|i| {
+                let mut txid = [0; 32];
+                txid[..8].copy_from_slice(&(i as u64).to_be_bytes());
+                Txid::from_inner(txid)
+            })
+            .collect();
+
+        // The transactions are kept until the end, like the response we are building
+        let peak_heap = |chunk_size: usize| {
+            let start = heap_reset_peak();
+            let mut txs = Vec::with_capacity(txids.len());
+            for chunk in txids.chunks(chunk_size) {
+                txs.extend(bitcoind.get_wallet_transactions(chunk).unwrap());
+            }
+            assert!(txs.iter().all(Option::is_some));
+            HEAP_PEAK.with(Cell::get) - start
+        };
+        let chunked = peak_heap(CHUNK_SIZE * TXS_PER_VAULT);
+        let at_once = peak_heap(txids.len());
+
+        const MIB: f64 = 1024.0 * 1024.0;
+        println!(
+            "Peak heap to get the {} transactions of {} vaults: {:.1} MiB by chunks of {} vaults, {:.1} MiB at once",
+            txids.len(),
+            N_VAULTS,
+            chunked as f64 / MIB,
+            CHUNK_SIZE,
+            at_once as f64 / MIB
+        );
+    }
+}
@@ -2,25 +2,21 @@ pub mod interface;
 pub mod poller;
 pub mod utils;
 
-use crate::{
-    database::DatabaseError,
-    revaultd::RevaultD,
-    threadmessages::{BitcoindMessageOut, WalletTransaction},
-};
-use common::{assume_ok, config::BitcoindConfig};
+use crate::{database::DatabaseError, revaultd::RevaultD, threadmessages::BitcoindMessageOut};
+use common::assume_ok;
 use interface::BitcoinD;
 use poller::poller_main;
-use revault_tx::bitcoin::{Network, Txid};
+use revault_tx::bitcoin::Network;
 
 use std::{
     process,
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc::Receiver,
+        mpsc::{self, Receiver},
         Arc, RwLock,
     },
     thread,
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use jsonrpc::{
@@ -37,7 +33,9 @@ pub enum BitcoindError {
     Server(Error),
     /// They replied to a batch request omitting some responses
     BatchMissingResponse,
-    RevaultTx(revault_tx::Error),
+    RevaultTx(Box<revault_tx::Error>),
+    /// We were asked not to broadcast anything
+    Maintenance,
 }
 
 impl BitcoindError {
@@ -58,9 +56,13 @@ impl std::fmt::Display for BitcoindError {
             BitcoindError::Server(ref e) => write!(f, "Bitcoind server error: {}", e),
             BitcoindError::BatchMissingResponse => write!(
                 f,
-                "Bitcoind server replied without enough responses to our batched request"
+                "Bitcoind server didn't reply to one of our batched requests"
             ),
             BitcoindError::RevaultTx(ref s) => write!(f, "Bitcoind manager error: {}", s),
+            BitcoindError::Maintenance => write!(
+                f,
+                "Maintenance mode is enabled, not broadcasting any transaction"
+            ),
         }
     }
 }
@@ -81,7 +83,7 @@ impl From<simple_http::Error> for BitcoindError {
 
 impl From<revault_tx::Error> for BitcoindError {
     fn from(e: revault_tx::Error) -> Self {
-        Self::RevaultTx(e)
+        Self::RevaultTx(Box::new(e))
     }
 }
 
@@ -113,13 +115,140 @@ fn check_bitcoind_network(
     Ok(())
 }
 
-/// Some sanity checks to be done at startup to make sure our bitcoind isn't going to fail under
-/// our feet for a legitimate reason.
-fn bitcoind_sanity_checks(
+// The latest bitcoind version we were tested against. The semantics of the wallet RPCs (notably
+// 'importdescriptors' and 'listdescriptors') change across versions.
+const LATEST_TESTED_VERSION: u64 = 210_100;
+
+// Display a bitcoind version as in its releases. Versions starting from 22.0 dropped the '0.'.
+fn version_string(version: u64) -> String {
+    let (major, minor) = (version / 10_000 % 100, version / 100 % 100);
+    if version < 220_000 {
+        format!("0.{}.{}", major, minor)
+    } else {
+        format!("{}.{}", major, minor)
+    }
+}
+
+fn check_bitcoind_version(bitcoind: &BitcoinD, revaultd: &RevaultD) -> Result<(), BitcoindError> {
+    let config = &revaultd.bitcoind_config;
+    let version = bitcoind.version()?;
+    log::info!(
+        "Detected bitcoind version {}, supported versions are {} to {}",
+        version_string(version),
+        version_string(config.min_version),
+        version_string(LATEST_TESTED_VERSION)
+    );
+
+    if version < config.min_version {
+        return Err(BitcoindError::Custom(format!(
+            "bitcoind version {} is not supported, the minimum is {} ('min_version')",
+            version_string(version),
+            version_string(config.min_version)
+        )));
+    }
+    // Compare the major versions, any minor release of a tested version is fine
+    if version / 10_000 > LATEST_TESTED_VERSION / 10_000 {
+        if !config.allow_untested_version {
+            return Err(BitcoindError::Custom(format!(
+                "bitcoind version {} is newer than the latest tested one ({}). Set \
+                 'allow_untested_version' to run against it anyways.",
+                version_string(version),
+                version_string(LATEST_TESTED_VERSION)
+            )));
+        }
+        log::warn!(
+            "Running against bitcoind version {}, which is newer than the latest tested one ({})",
+            version_string(version),
+            version_string(LATEST_TESTED_VERSION)
+        );
+    }
+
+    Ok(())
+}
+
+// We derive deposit addresses from the deposit descriptor, make sure bitcoind agrees it's ranged.
+// The Unvault and CPFP descriptors use Miniscript fragments bitcoind can't parse, but all of
+// them are already checked for wildcard keys when parsing the configuration.
+fn check_deposit_descriptor_ranged(
     bitcoind: &BitcoinD,
-    bitcoind_config: &BitcoindConfig,
+    revaultd: &RevaultD,
 ) -> Result<(), BitcoindError> {
-    check_bitcoind_network(&bitcoind, &bitcoind_config.network)
+    let deposit_desc = revaultd.deposit_descriptor.to_string();
+    if !bitcoind.is_range_descriptor(&deposit_desc)? {
+        return Err(BitcoindError::Custom(format!(
+            "The deposit descriptor '{}' is not ranged",
+            deposit_desc
+        )));
+    }
+
+    Ok(())
+}
+
+// We don't need the txindex, but it's handy for analyzing unexpected spends of our coins.
+fn log_txindex(bitcoind: &BitcoinD) -> Result<(), BitcoindError> {
+    match bitcoind.getindexinfo()?.get("txindex") {
+        Some(true) => log::info!("bitcoind's txindex is enabled and synced"),
+        Some(false) => log::info!("bitcoind's txindex is enabled but not synced yet"),
+        None => log::info!("bitcoind's txindex is not enabled"),
+    }
+
+    Ok(())
+}
+
+// We can run against a pruned bitcoind as long as we never need it to rescan the blocks it
+// pruned, which we check when importing descriptors.
+fn log_pruning(bitcoind: &BitcoinD) -> Result<(), BitcoindError> {
+    if let Some(height) = bitcoind.prune_height()? {
+        log::info!(
+            "bitcoind is pruned, the blocks below height {} are not available",
+            height
+        );
+    }
+
+    Ok(())
+}
+
+// We import descriptors with bitcoind's "now" as timestamp but use our own clock for the
+// wallet birthday and when comparing received times, so a skew between the two clocks may make
+// us miss deposits. We can't query bitcoind's clock directly, so check it against its peers' and
+// check ours against the tip of the chain (which shouldn't be in our future). This only warns.
+fn check_clock_skew(bitcoind: &BitcoinD, revaultd: &RevaultD) -> Result<(), BitcoindError> {
+    let threshold = revaultd.bitcoind_config.clock_skew_threshold_secs.as_secs();
+
+    let offset = bitcoind.network_time_offset()?;
+    if offset.abs() as u64 > threshold {
+        log::warn!(
+            "bitcoind's clock is {} seconds off from its peers'. Please check the system clock \
+             of the machine bitcoind is running on, this may make us miss deposits.",
+            offset
+        );
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let tip_time = bitcoind.tip_time()?;
+    if tip_time.saturating_sub(now) > threshold {
+        log::warn!(
+            "The tip of the chain is {} seconds ahead of our clock. Please check the system \
+             clock, this may make us miss deposits.",
+            tip_time - now
+        );
+    }
+
+    Ok(())
+}
+
+/// Some sanity checks to be done at startup to make sure our bitcoind isn't going to fail under
+/// our feet for a legitimate reason.
+fn bitcoind_sanity_checks(bitcoind: &BitcoinD, revaultd: &RevaultD) -> Result<(), BitcoindError> {
+    check_bitcoind_network(bitcoind, &revaultd.bitcoind_config.network)?;
+    check_bitcoind_version(bitcoind, revaultd)?;
+    check_deposit_descriptor_ranged(bitcoind, revaultd)?;
+    check_clock_skew(bitcoind, revaultd)?;
+    log_txindex(bitcoind)?;
+    log_pruning(bitcoind)
 }
 
 /// Connects to and sanity checks bitcoind.
@@ -129,12 +258,11 @@ pub fn start_bitcoind(revaultd: &mut RevaultD) -> Result<BitcoinD, BitcoindError
         revaultd
             .watchonly_wallet_file()
             .expect("Wallet id is set at startup in setup_db()"),
+        revaultd.maintenance.clone(),
     )
-    .map_err(|e| {
-        BitcoindError::Custom(format!("Could not connect to bitcoind: {}", e.to_string()))
-    })?;
+    .map_err(|e| BitcoindError::Custom(format!("Could not connect to bitcoind: {}", e)))?;
 
-    while let Err(e) = bitcoind_sanity_checks(&bitcoind, &revaultd.bitcoind_config) {
+    while let Err(e) = bitcoind_sanity_checks(&bitcoind, revaultd) {
         if e.is_warming_up() {
             log::info!("Bitcoind is warming up. Waiting for it to be back up.");
             thread::sleep(Duration::from_secs(3))
@@ -146,24 +274,6 @@ pub fn start_bitcoind(revaultd: &mut RevaultD) -> Result<BitcoinD, BitcoindError
     Ok(bitcoind)
 }
 
-fn wallet_transaction(bitcoind: &BitcoinD, txid: Txid) -> Option<WalletTransaction> {
-    let res = bitcoind.get_wallet_transaction(&txid);
-    if let Ok((hex, blockheight, received_time)) = res {
-        Some(WalletTransaction {
-            hex,
-            blockheight,
-            received_time,
-        })
-    } else {
-        log::trace!(
-            "Got '{:?}' from bitcoind when requesting wallet transaction '{}'",
-            res,
-            txid
-        );
-        None
-    }
-}
-
 /// The bitcoind event loop.
 /// Listens for bitcoind requests (wallet / chain) and poll bitcoind every 30 seconds,
 /// updating our state accordingly.
@@ -178,15 +288,30 @@ pub fn bitcoind_main_loop(
     let sync_progress = Arc::new(RwLock::new(0.0f64));
     // Used to shutdown the poller thread
     let shutdown = Arc::new(AtomicBool::new(false));
+    // Used to ask the poller thread for an immediate poll
+    let (forcesync_tx, forcesync_rx) = mpsc::channel();
 
     // We use a thread to 1) wait for bitcoind to be synced 2) poll listunspent
-    let poller_thread = std::thread::spawn({
-        let _revaultd = revaultd.clone();
-        let _bitcoind = bitcoind.clone();
-        let _sync_progress = sync_progress.clone();
-        let _shutdown = shutdown.clone();
-        move || poller_main(_revaultd, _bitcoind, _sync_progress, _shutdown)
-    });
+    let poller_thread = assume_ok!(
+        std::thread::Builder::new()
+            .name("bitcoind-poller".to_string())
+            .spawn({
+                let _revaultd = revaultd.clone();
+                let _bitcoind = bitcoind.clone();
+                let _sync_progress = sync_progress.clone();
+                let _shutdown = shutdown.clone();
+                move || {
+                    poller_main(
+                        _revaultd,
+                        _bitcoind,
+                        _sync_progress,
+                        _shutdown,
+                        forcesync_rx,
+                    )
+                }
+            }),
+        "Spawning bitcoind poller thread"
+    );
 
     for msg in rx {
         match msg {
@@ -197,6 +322,17 @@ pub fn bitcoind_main_loop(
                     assume_ok!(poller_thread.join(), "Joining bitcoind poller thread"),
                     "Error in bitcoind poller thread"
                 );
+
+                let revaultd = revaultd.read().unwrap();
+                if revaultd.bitcoind_config.unload_wallet_on_shutdown {
+                    let wallet_path = revaultd
+                        .watchonly_wallet_file()
+                        .expect("Wallet id is set at startup in setup_db()");
+                    log::info!("Unloading watchonly wallet '{}'", wallet_path);
+                    if let Err(e) = bitcoind.read().unwrap().unloadwallet_shutdown(wallet_path) {
+                        log::error!("Error unloading watchonly wallet: '{}'", e);
+                    }
+                }
                 return Ok(());
             }
             BitcoindMessageOut::SyncProgress(resp_tx) => {
@@ -207,14 +343,14 @@ pub fn bitcoind_main_loop(
                     ))
                 })?;
             }
-            BitcoindMessageOut::WalletTransaction(txid, resp_tx) => {
-                log::trace!("Received 'wallettransaction' from main thread");
+            BitcoindMessageOut::WalletTransactions(txids, resp_tx) => {
+                log::trace!("Received 'wallettransactions' from main thread");
                 // FIXME: what if bitcoind isn't synced?
                 resp_tx
-                    .send(wallet_transaction(&bitcoind.read().unwrap(), txid))
+                    .send(bitcoind.read().unwrap().get_wallet_transactions(&txids))
                     .map_err(|e| {
                         BitcoindError::Custom(format!(
-                            "Sending wallet transaction to main thread: {}",
+                            "Sending wallet transactions to main thread: {}",
                             e
                         ))
                     })?;
@@ -230,8 +366,83 @@ pub fn bitcoind_main_loop(
                         ))
                     })?;
             }
+            BitcoindMessageOut::LabeledUtxos(label, resp_tx) => {
+                log::trace!("Received 'labeledutxos' from main thread");
+                resp_tx
+                    .send(bitcoind.read().unwrap().list_labeled_utxos(&label, None))
+                    .map_err(|e| {
+                        BitcoindError::Custom(format!(
+                            "Sending labeled utxos to main thread: {}",
+                            e
+                        ))
+                    })?;
+            }
+            BitcoindMessageOut::ForceSync(resp_tx) => {
+                log::trace!("Received 'forcesync' from main thread");
+                // The poller replies directly. If it's gone, we'll notice on shutdown.
+                if let Err(mpsc::SendError(resp_tx)) = forcesync_tx.send(resp_tx) {
+                    resp_tx
+                        .send(Err(BitcoindError::Custom(
+                            "The bitcoind poller thread is not running".to_string(),
+                        )))
+                        .map_err(|e| {
+                            BitcoindError::Custom(format!(
+                                "Sending sync result to main thread: {}",
+                                e
+                            ))
+                        })?;
+                }
+            }
+            BitcoindMessageOut::RelayFeerate(resp_tx) => {
+                log::trace!("Received 'relayfeerate' from main thread");
+                resp_tx
+                    .send(bitcoind.read().unwrap().relay_feerate())
+                    .map_err(|e| {
+                        BitcoindError::Custom(format!(
+                            "Sending relay feerate to main thread: {}",
+                            e
+                        ))
+                    })?;
+            }
+            BitcoindMessageOut::EstimateFeerate(conf_target, resp_tx) => {
+                log::trace!("Received 'estimatefeerate' from main thread");
+                resp_tx
+                    .send(bitcoind.read().unwrap().estimate_feerate(conf_target))
+                    .map_err(|e| {
+                        BitcoindError::Custom(format!(
+                            "Sending feerate estimate to main thread: {}",
+                            e
+                        ))
+                    })?;
+            }
+            BitcoindMessageOut::TxOutProof(txid, height, resp_tx) => {
+                log::trace!("Received 'txoutproof' from main thread");
+                let bitcoind = bitcoind.read().unwrap();
+                let proof = bitcoind.getblockhash(height).and_then(|block_hash| {
+                    bitcoind
+                        .gettxoutproof(&txid, &block_hash)
+                        .map(|proof| (block_hash, proof))
+                });
+                resp_tx.send(proof).map_err(|e| {
+                    BitcoindError::Custom(format!("Sending txout proof to main thread: {}", e))
+                })?;
+            }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::version_string;
+
+    #[test]
+    fn bitcoind_version_string() {
+        assert_eq!(version_string(210_000), "0.21.0");
+        assert_eq!(version_string(210_100), "0.21.1");
+        assert_eq!(version_string(200_200), "0.20.2");
+        assert_eq!(version_string(220_000), "22.0");
+        assert_eq!(version_string(230_100), "23.1");
+    }
+}
@@ -1,6 +1,9 @@
 use crate::{
     bitcoind::{
-        interface::{BitcoinD, OnchainDescriptorState, SyncInfo, UtxoInfo},
+        interface::{
+            BitcoinD, KnownSpenders, OnchainDescriptorState, SpenderKind, SyncInfo, UtxoInfo,
+            WalletSnapshot,
+        },
         utils::{
             cancel_txid, emer_txid, populate_deposit_cache, populate_unvaults_cache,
             presigned_transactions, unemer_txid, unvault_txin_from_deposit,
@@ -10,36 +13,43 @@ use crate::{
     database::{
         actions::{
             db_cancel_unvault, db_confirm_deposit, db_confirm_unvault, db_emer_unvault,
-            db_insert_new_unconfirmed_vault, db_mark_broadcasted_spend, db_mark_canceled_unvault,
-            db_mark_emergencied_unvault, db_mark_emergencied_vault, db_mark_emergencying_vault,
-            db_mark_rebroadcastable_spend, db_mark_spent_unvault, db_spend_unvault,
+            db_insert_new_unconfirmed_vault, db_mark_aborted_vault, db_mark_broadcasted_spend,
+            db_mark_canceled_unvault, db_mark_emergencied_unvault, db_mark_emergencied_vault,
+            db_mark_emergencying_vault, db_mark_rebroadcastable_spend, db_mark_spent_unvault,
+            db_revert_unvault, db_spend_unvault, db_unabort_vault, db_unabort_vault_dbtx,
             db_unconfirm_cancel_dbtx, db_unconfirm_deposit_dbtx, db_unconfirm_emer_dbtx,
             db_unconfirm_spend_dbtx, db_unconfirm_unemer_dbtx, db_unconfirm_unvault_dbtx,
-            db_unvault_deposit, db_update_deposit_index, db_update_tip, db_update_tip_dbtx,
+            db_unexpected_spend_unvault, db_unvault_deposit, db_update_deposit_index,
+            db_update_tip, db_update_tip_dbtx,
         },
+        encryption::DbKey,
         interface::{
-            db_broadcastable_spend_transactions, db_cancel_dbtx, db_canceling_vaults,
-            db_emering_vaults, db_exec, db_spending_vaults, db_tip, db_unemering_vaults,
-            db_unvault_dbtx, db_unvault_transaction, db_vault_by_deposit, db_vault_by_unvault_txid,
-            db_vaults_dbtx, db_wallet,
+            db_broadcastable_spend_transactions, db_cancel_dbtx, db_canceling_vaults, db_deposits,
+            db_emering_vaults, db_exec, db_list_spends, db_spending_vaults, db_tip,
+            db_unemering_vaults, db_unvault_dbtx, db_unvault_transaction, db_vault_by_deposit,
+            db_vault_by_unvault_txid, db_vaults_by_derivation_index, db_vaults_dbtx,
+            db_vaults_with_status, db_wallet,
         },
         schema::DbVault,
     },
     revaultd::{BlockchainTip, RevaultD, VaultStatus},
+    threadmessages::BitcoindEvent,
 };
 use common::config::BitcoindConfig;
 use revault_tx::{
-    bitcoin::{Amount, OutPoint, Txid},
+    bitcoin::{util::bip32::ChildNumber, Amount, OutPoint, TxOut, Txid},
+    miniscript::DescriptorTrait,
     transactions::{RevaultTransaction, UnvaultTransaction},
     txins::RevaultTxIn,
     txouts::RevaultTxOut,
 };
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, SyncSender},
         Arc, RwLock,
     },
     thread,
@@ -51,9 +61,14 @@ fn maybe_broadcast_spend_transactions(
     revaultd: &Arc<RwLock<RevaultD>>,
     bitcoind: &BitcoinD,
 ) -> Result<(), BitcoindError> {
+    // They'll be broadcast once we get out of maintenance
+    if revaultd.read().unwrap().is_maintenance() {
+        return Ok(());
+    }
     let db_path = revaultd.read().unwrap().db_file();
+    let db_key = revaultd.read().unwrap().db_key.clone();
 
-    for db_spendtx in db_broadcastable_spend_transactions(&db_path)? {
+    for db_spendtx in db_broadcastable_spend_transactions(&db_path, db_key.as_ref())? {
         let mut psbt = db_spendtx.psbt;
         let txid = psbt.txid();
         log::debug!("Trying to broadcast Spend tx '{}'", &txid);
@@ -91,7 +106,7 @@ fn maybe_confirm_spend(
     spend_txid: &Txid,
 ) -> Result<bool, BitcoindError> {
     if let (_, Some(height), _) = bitcoind.get_wallet_transaction(spend_txid)? {
-        db_mark_spent_unvault(&db_path, db_vault.id)?;
+        db_mark_spent_unvault(db_path, db_vault.id)?;
         log::debug!(
             "Spend tx '{}', spending vault {:x?} was confirmed at height '{}'",
             &spend_txid,
@@ -113,8 +128,9 @@ fn mark_confirmed_spends(
     unvaults_cache: &mut HashMap<OutPoint, UtxoInfo>,
 ) -> Result<(), BitcoindError> {
     let db_path = revaultd.read().unwrap().db_file();
+    let db_key = revaultd.read().unwrap().db_key.clone();
 
-    for (db_vault, unvault_tx) in db_spending_vaults(&db_path)? {
+    for (db_vault, unvault_tx) in db_spending_vaults(&db_path, db_key.as_ref())? {
         let der_unvault_descriptor = revaultd
             .read()
             .unwrap()
@@ -123,7 +139,7 @@ fn mark_confirmed_spends(
         let unvault_outpoint = unvault_txin.outpoint();
         let spend_txid = &db_vault.spend_txid.expect("Must be set for 'spending'");
 
-        match maybe_confirm_spend(&db_path, bitcoind, &db_vault, &spend_txid) {
+        match maybe_confirm_spend(&db_path, bitcoind, &db_vault, spend_txid) {
             Ok(false) => {}
             Ok(true) => continue,
             Err(e) => {
@@ -176,7 +192,8 @@ fn mark_unvaulted(
     unvaults_cache: &mut HashMap<OutPoint, UtxoInfo>,
     db_vault: &DbVault,
 ) -> Result<(), BitcoindError> {
-    let (_, unvault_tx) = db_unvault_transaction(&db_path, db_vault.id)?;
+    let db_key = revaultd.read().unwrap().db_key.clone();
+    let (_, unvault_tx) = db_unvault_transaction(db_path, db_key.as_ref(), db_vault.id)?;
     let unvault_descriptor = revaultd.read().unwrap().unvault_descriptor.derive(
         db_vault.derivation_index,
         &revaultd.read().unwrap().secp_ctx,
@@ -184,7 +201,7 @@ fn mark_unvaulted(
     let unvault_txin = unvault_tx.revault_unvault_txin(&unvault_descriptor);
     let unvault_outpoint = unvault_txin.outpoint();
 
-    db_confirm_unvault(&db_path, &unvault_tx.tx().txid())?;
+    db_confirm_unvault(db_path, &unvault_tx.tx().txid())?;
 
     let txo = unvault_txin.into_txout().into_txout();
     unvaults_cache.insert(
@@ -205,14 +222,31 @@ fn mark_unvaulted(
     Ok(())
 }
 
+// Returns true if the Cancel is included in a block, false if it is unconfirmed (or unknown to
+// the wallet). The vault is marked as canceled once the Cancel has `cancel_min_conf`
+// confirmations, before that it's left 'canceling' but we still return true as there is no need
+// to check the mempool for it.
 fn maybe_confirm_cancel(
     db_path: &Path,
     bitcoind: &BitcoinD,
     db_vault: &DbVault,
     cancel_txid: &Txid,
+    cancel_min_conf: u32,
 ) -> Result<bool, BitcoindError> {
     if let (_, Some(height), _) = bitcoind.get_wallet_transaction(cancel_txid)? {
-        db_mark_canceled_unvault(&db_path, db_vault.id)?;
+        let tip_height = db_tip(db_path)?.height;
+        let confirmations = (tip_height + 1).saturating_sub(height);
+        if confirmations < cancel_min_conf {
+            log::trace!(
+                "Cancel tx '{}' has '{}' confirmations, waiting for '{}'",
+                &cancel_txid,
+                confirmations,
+                cancel_min_conf
+            );
+            return Ok(true);
+        }
+
+        db_mark_canceled_unvault(db_path, db_vault.id)?;
         log::debug!(
             "Cancel tx '{}', spending vault {:x?} was confirmed at height '{}'",
             &cancel_txid,
@@ -231,11 +265,15 @@ fn mark_confirmed_cancels(
     bitcoind: &BitcoinD,
     unvaults_cache: &mut HashMap<OutPoint, UtxoInfo>,
 ) -> Result<(), BitcoindError> {
-    let db_path = revaultd.read().unwrap().db_file();
+    let db_key = revaultd.read().unwrap().db_key.clone();
+    let (db_path, cancel_min_conf) = {
+        let revaultd = revaultd.read().unwrap();
+        (revaultd.db_file(), revaultd.cancel_min_conf)
+    };
 
-    for (db_vault, cancel_tx) in db_canceling_vaults(&db_path)? {
+    for (db_vault, cancel_tx) in db_canceling_vaults(&db_path, db_key.as_ref())? {
         let cancel_txid = cancel_tx.txid();
-        match maybe_confirm_cancel(&db_path, bitcoind, &db_vault, &cancel_txid) {
+        match maybe_confirm_cancel(&db_path, bitcoind, &db_vault, &cancel_txid, cancel_min_conf) {
             Ok(false) => {}
             Ok(true) => continue,
             Err(e) => {
@@ -268,7 +306,7 @@ fn maybe_confirm_unemer(
     unemer_txid: &Txid,
 ) -> Result<bool, BitcoindError> {
     if let (_, Some(height), _) = bitcoind.get_wallet_transaction(unemer_txid)? {
-        db_mark_emergencied_unvault(&db_path, db_vault.id)?;
+        db_mark_emergencied_unvault(db_path, db_vault.id)?;
         log::warn!(
             "UnvaultEmergency tx '{}', spending vault {:x?} was confirmed at height '{}'",
             &unemer_txid,
@@ -288,8 +326,9 @@ fn mark_confirmed_unemers(
     unvaults_cache: &mut HashMap<OutPoint, UtxoInfo>,
 ) -> Result<(), BitcoindError> {
     let db_path = revaultd.read().unwrap().db_file();
+    let db_key = revaultd.read().unwrap().db_key.clone();
 
-    for (db_vault, unemer_tx) in db_unemering_vaults(&db_path)? {
+    for (db_vault, unemer_tx) in db_unemering_vaults(&db_path, db_key.as_ref())? {
         let unemer_txid = unemer_tx.txid();
         match maybe_confirm_unemer(&db_path, bitcoind, &db_vault, &unemer_txid) {
             Ok(false) => {}
@@ -330,7 +369,7 @@ fn maybe_confirm_emer(
     emer_txid: &Txid,
 ) -> Result<bool, BitcoindError> {
     if let (_, Some(height), _) = bitcoind.get_wallet_transaction(emer_txid)? {
-        db_mark_emergencied_vault(&db_path, db_vault.id)?;
+        db_mark_emergencied_vault(db_path, db_vault.id)?;
         log::warn!(
             "Emergency tx '{}', spending vault {:x?} was confirmed at height '{}'",
             &emer_txid,
@@ -350,8 +389,9 @@ fn mark_confirmed_emers(
     unvaults_cache: &mut HashMap<OutPoint, UtxoInfo>,
 ) -> Result<(), BitcoindError> {
     let db_path = revaultd.read().unwrap().db_file();
+    let db_key = revaultd.read().unwrap().db_key.clone();
 
-    for (db_vault, emer_tx) in db_emering_vaults(&db_path)? {
+    for (db_vault, emer_tx) in db_emering_vaults(&db_path, db_key.as_ref())? {
         let emer_txid = emer_tx.txid();
         match maybe_confirm_emer(&db_path, bitcoind, &db_vault, &emer_txid) {
             Ok(false) => {}
@@ -382,6 +422,43 @@ fn mark_confirmed_emers(
     Ok(())
 }
 
+// Cancel and Emergency transactions we broadcast may have been dropped from the mempool. They
+// need to be there to be enforced, so broadcast them again if they are neither confirmed nor
+// in the mempool.
+fn rebroadcast_revocation_txs(
+    revaultd: &Arc<RwLock<RevaultD>>,
+    bitcoind: &BitcoinD,
+) -> Result<(), BitcoindError> {
+    let db_path = revaultd.read().unwrap().db_file();
+    let db_key = revaultd.read().unwrap().db_key.clone();
+
+    let cancel_txids = db_canceling_vaults(&db_path, db_key.as_ref())?
+        .into_iter()
+        .map(|(_, tx)| tx.txid());
+    let emer_txids = db_emering_vaults(&db_path, db_key.as_ref())?
+        .into_iter()
+        .map(|(_, tx)| tx.txid());
+    let unemer_txids = db_unemering_vaults(&db_path, db_key.as_ref())?
+        .into_iter()
+        .map(|(_, tx)| tx.txid());
+
+    for txid in cancel_txids.chain(emer_txids).chain(unemer_txids) {
+        if bitcoind.is_current(&txid)? {
+            continue;
+        }
+
+        log::warn!(
+            "Revocation tx '{}' is neither confirmed nor in mempool. Re-broadcasting it.",
+            txid
+        );
+        if let Err(e) = bitcoind.rebroadcast_wallet_tx(&txid) {
+            log::error!("Error re-broadcasting revocation tx '{}': '{}'", txid, e);
+        }
+    }
+
+    Ok(())
+}
+
 // Everything we do when the chain moves forward
 fn new_tip_event(
     revaultd: &Arc<RwLock<RevaultD>>,
@@ -419,6 +496,7 @@ fn unconfirm_unvault(
     vault: &DbVault,
     unvault_tx: &UnvaultTransaction,
 ) -> Result<(), BitcoindError> {
+    let db_key = revaultd.read().unwrap().db_key.clone();
     let unvault_txid = unvault_tx.txid();
 
     // Mark it as 'unvaulting', note however that if it was Spent or Canceled it will be marked as
@@ -448,7 +526,10 @@ fn unconfirm_unvault(
     let unvault_txin = unvault_tx.revault_unvault_txin(&der_unvault_descriptor);
     let unvault_outpoint = unvault_txin.outpoint();
     let txo = unvault_txin.into_txout().into_txout();
-    if matches!(vault.status, VaultStatus::Spent | VaultStatus::Spending) {
+    if matches!(
+        vault.status,
+        VaultStatus::Spent | VaultStatus::Spending | VaultStatus::UnexpectedSpend
+    ) {
         // Don't forget rebroadcast the Spend transaction at the next tip update!
         // NOTE: it won't do anything if there is no spend_transaction awaiting (eg for a
         // stakeholder).
@@ -471,7 +552,7 @@ fn unconfirm_unvault(
             .is_confirmed = false;
     } else if matches!(vault.status, VaultStatus::Canceled | VaultStatus::Canceling) {
         // Just in case, rebroadcast it.
-        let cancel_tx = match db_cancel_dbtx(&db_tx, vault.id)? {
+        let cancel_tx = match db_cancel_dbtx(db_tx, db_key.as_ref(), vault.id)? {
             Some(tx) => tx,
             None => {
                 log::error!(
@@ -501,6 +582,27 @@ fn unconfirm_unvault(
     Ok(())
 }
 
+// The deposit output of this vault, to track it again
+fn deposit_utxo(
+    revaultd: &Arc<RwLock<RevaultD>>,
+    db_vault: &DbVault,
+    is_confirmed: bool,
+) -> UtxoInfo {
+    let script_pubkey = revaultd
+        .read()
+        .unwrap()
+        .derived_deposit_descriptor(db_vault.derivation_index)
+        .inner()
+        .script_pubkey();
+    UtxoInfo {
+        txo: TxOut {
+            script_pubkey,
+            value: db_vault.amount.as_sat(),
+        },
+        is_confirmed,
+    }
+}
+
 // Rewind the state of a vault for which the Unvault transaction was never broadcast.
 // Will panic if called for an unconfirmed vault.
 fn unconfirm_vault(
@@ -511,6 +613,7 @@ fn unconfirm_vault(
     unvaults_cache: &mut HashMap<OutPoint, UtxoInfo>,
     vault: &DbVault,
 ) -> Result<(), BitcoindError> {
+    let db_key = revaultd.read().unwrap().db_key.clone();
     // Just in case, rebroadcast the deposit transaction anyways
     let deposit_txid = vault.deposit_outpoint.txid;
     match bitcoind.rebroadcast_wallet_tx(&deposit_txid) {
@@ -528,6 +631,7 @@ fn unconfirm_vault(
         | VaultStatus::Unvaulted
         | VaultStatus::Spending
         | VaultStatus::Spent
+        | VaultStatus::UnexpectedSpend
         | VaultStatus::Canceling
         | VaultStatus::Canceled
         | VaultStatus::UnvaultEmergencyVaulting
@@ -536,12 +640,13 @@ fn unconfirm_vault(
             // broadcast), just rewind the state to this point. This is tremendously unlikely in
             // real conditions (min conf for the deposits + say, X blocks of CSV if Spent already
             // so that'd be a reorg of several dozens of blocks).
-            let unvault_tx = db_unvault_dbtx(db_tx, vault.id)?.ok_or_else(|| {
-                BitcoindError::Custom(format!(
-                    "Vault '{}' has status '{}' but no Unvault in database!",
-                    vault.deposit_outpoint, vault.status
-                ))
-            })?;
+            let unvault_tx =
+                db_unvault_dbtx(db_tx, db_key.as_ref(), vault.id)?.ok_or_else(|| {
+                    BitcoindError::Custom(format!(
+                        "Vault '{}' has status '{}' but no Unvault in database!",
+                        vault.deposit_outpoint, vault.status
+                    ))
+                })?;
             unconfirm_unvault(
                 revaultd,
                 bitcoind,
@@ -571,6 +676,27 @@ fn unconfirm_vault(
 
             // TODO: If it was in Emergency, re-broadcast all the Emergency transactions
 
+            Ok(())
+        }
+        VaultStatus::Aborted => {
+            // It may have been aborted before getting 'min_conf' confirmations
+            if bitcoind.is_spent_in_chain(&vault.deposit_outpoint)? {
+                log::debug!(
+                    "Deposit of aborted vault '{}' is still spent in chain",
+                    vault.deposit_outpoint
+                );
+                return Ok(());
+            }
+
+            // The transaction spending the deposit was unconfirmed along with it. Track the
+            // deposit again, it'll get aborted again if its spender confirms.
+            db_unconfirm_deposit_dbtx(db_tx, vault.id)?;
+            deposits_cache.insert(vault.deposit_outpoint, deposit_utxo(revaultd, vault, false));
+            log::warn!(
+                "Deposit of aborted vault '{}' was unconfirmed, tracking it again",
+                vault.deposit_outpoint
+            );
+
             Ok(())
         }
     }
@@ -590,8 +716,9 @@ fn comprehensive_rescan(
     deposits_cache: &mut HashMap<OutPoint, UtxoInfo>,
     unvaults_cache: &mut HashMap<OutPoint, UtxoInfo>,
 ) -> Result<(), BitcoindError> {
+    let db_key = revaultd.read().unwrap().db_key.clone();
     log::info!("Starting rescan of all vaults in db..");
-    let mut vaults = db_vaults_dbtx(&db_tx)?;
+    let mut vaults = db_vaults_dbtx(db_tx)?;
     let mut tip = bitcoind.get_tip()?;
 
     // Try to get the last tip
@@ -645,7 +772,7 @@ fn comprehensive_rescan(
         // vault as unconfirmed and be done.
         let deposit_conf = tip.height.checked_sub(dep_height).expect("Checked above") + 1;
         let min_conf = revaultd.read().unwrap().min_conf;
-        if deposit_conf < min_conf as u32 {
+        if deposit_conf < min_conf {
             unconfirm_vault(
                 revaultd,
                 bitcoind,
@@ -670,6 +797,25 @@ fn comprehensive_rescan(
             min_conf
         );
 
+        // If the transaction spending the deposit of an aborted vault got unconfirmed, restore
+        // the vault. It'll get aborted again if its spender confirms.
+        if matches!(vault.status, VaultStatus::Aborted) {
+            if bitcoind.is_spent_in_chain(&vault.deposit_outpoint)? {
+                continue;
+            }
+            let status = db_unabort_vault_dbtx(db_tx, vault.id)?;
+            deposits_cache.insert(
+                vault.deposit_outpoint,
+                deposit_utxo(revaultd, &vault, status != VaultStatus::Unconfirmed),
+            );
+            log::warn!(
+                "The spender of aborted vault '{}' got unconfirmed. Restoring it as '{}'.",
+                vault.deposit_outpoint,
+                status
+            );
+            continue;
+        }
+
         // Now, if the Emergency transaction got unconfirmed mark the vault as such.
         if matches!(vault.status, VaultStatus::EmergencyVaulted) {
             let emer_txid = match emer_txid(revaultd, &vault)? {
@@ -717,12 +863,13 @@ fn comprehensive_rescan(
             VaultStatus::Unvaulted
                 | VaultStatus::Spending
                 | VaultStatus::Spent
+                | VaultStatus::UnexpectedSpend
                 | VaultStatus::Canceling
                 | VaultStatus::Canceled
                 | VaultStatus::UnvaultEmergencyVaulting
                 | VaultStatus::UnvaultEmergencyVaulted
         ) {
-            let unvault_tx = match db_unvault_dbtx(db_tx, vault.id)? {
+            let unvault_tx = match db_unvault_dbtx(db_tx, db_key.as_ref(), vault.id)? {
                 Some(tx) => tx,
                 None => {
                     log::error!(
@@ -819,11 +966,16 @@ fn comprehensive_rescan(
                     );
                 }
                 let (_, blockheight, _) = bitcoind.get_wallet_transaction(&cancel_txid)?;
-                if let Some(height) = blockheight {
+                // It may still be confirmed, but not deeply enough to be considered final anymore.
+                let cancel_min_conf = revaultd.read().unwrap().cancel_min_conf;
+                let is_final = blockheight
+                    .map(|height| (tip.height + 1).saturating_sub(height) >= cancel_min_conf)
+                    .unwrap_or(false);
+                if is_final {
                     log::debug!(
-                        "Vault {}'s Cancel transaction is still confirmed (height '{}')",
+                        "Vault {}'s Cancel transaction is still confirmed (height '{:?}')",
                         vault.deposit_outpoint,
-                        height
+                        blockheight
                     );
                 } else {
                     db_unconfirm_cancel_dbtx(db_tx, vault.id)?;
@@ -885,28 +1037,36 @@ fn comprehensive_rescan(
 
 // Check the latest tip, if it does not change or moves forward just do nothing or
 // update in in the database. However if it goes backward or the tip block hash changes
-// resynchronize ourself with the Bitcoin network.
+// resynchronize ourself with the Bitcoin network, in which case the snapshot is taken again.
 // Returns the previous tip.
 fn update_tip(
     revaultd: &mut Arc<RwLock<RevaultD>>,
     bitcoind: &BitcoinD,
+    snapshot: &mut WalletSnapshot,
     deposits_cache: &mut HashMap<OutPoint, UtxoInfo>,
     unvaults_cache: &mut HashMap<OutPoint, UtxoInfo>,
 ) -> Result<BlockchainTip, BitcoindError> {
     let current_tip = db_tip(&revaultd.read().unwrap().db_file())?;
-    let tip = bitcoind.get_tip()?;
+    let tip = snapshot.tip;
 
     // Nothing changed, shortcut.
     if tip == current_tip {
         return Ok(tip);
     }
 
+    // We never stored a tip yet. Don't compare heights: bitcoind may well be at height 0 too, on
+    // a fresh regtest chain, and that's not a reorg.
+    if current_tip.is_unset() {
+        new_tip_event(revaultd, bitcoind, &tip, unvaults_cache)?;
+        return Ok(current_tip);
+    }
+
     if tip.height > current_tip.height {
         // May just be a new (set of) block(s), make sure we are on the same chain
         let bit_curr_hash = bitcoind.getblockhash(current_tip.height)?;
-        if bit_curr_hash == current_tip.hash || current_tip.height == 0 {
+        if bit_curr_hash == current_tip.hash {
             // We moved forward, everything is fine.
-            new_tip_event(&revaultd, bitcoind, &tip, unvaults_cache)?;
+            new_tip_event(revaultd, bitcoind, &tip, unvaults_cache)?;
             return Ok(current_tip);
         }
     }
@@ -916,6 +1076,19 @@ fn update_tip(
         &current_tip,
         &tip
     );
+    // We can't tell how deep the reorg is, but if the chain got shorter than what we considered
+    // final we may have reported a wrong state.
+    let finalized_height = revaultd
+        .read()
+        .unwrap()
+        .finalized_height(current_tip.height);
+    if tip.height < finalized_height {
+        log::error!(
+            "The reorg unconfirmed blocks we considered final (up to height '{}'). Consider \
+             increasing 'tip_finality_depth'.",
+            finalized_height
+        );
+    }
     db_exec(&revaultd.read().unwrap().db_file(), |db_tx| {
         comprehensive_rescan(revaultd, db_tx, bitcoind, deposits_cache, unvaults_cache)
             .unwrap_or_else(|e| {
@@ -925,6 +1098,8 @@ fn update_tip(
         Ok(())
     })?;
     log::info!("Rescan of all vaults in db done.");
+    // The rescan updated our state past the snapshot's utxos, don't diff against them.
+    *snapshot = bitcoind.wallet_snapshot()?;
 
     Ok(current_tip)
 }
@@ -934,12 +1109,43 @@ fn update_tip(
 enum UnvaultSpender {
     // The Cancel, spending via the stakeholders path to a new deposit
     Cancel(Txid),
-    // The Spend, any transaction spending via the managers path
+    // The Spend, one of the Spend transactions we know about
     Spend(Txid),
+    // Any other transaction spending via the managers path
+    UnknownSpend(Txid),
+    // Any other transaction spending via the stakeholders path
+    Unexpected(Txid),
     // The Emergency, spending via the stakeholders path to the EDV
     Emergency(Txid),
 }
 
+// The Unvault of this 'unvaulting' vault isn't in the mempool anymore. Get back to tracking its
+// deposit: the Unvault (its txid can't change, it's presigned) may be broadcast again, or we'll
+// find out which transaction replaced it when polling the deposits.
+fn revert_unvault(
+    revaultd: &Arc<RwLock<RevaultD>>,
+    db_path: &Path,
+    deposits_cache: &mut HashMap<OutPoint, UtxoInfo>,
+    unvaults_cache: &mut HashMap<OutPoint, UtxoInfo>,
+    db_vault: &DbVault,
+    unvault_outpoint: &OutPoint,
+) -> Result<(), BitcoindError> {
+    db_revert_unvault(db_path, &unvault_outpoint.txid)?;
+    unvaults_cache.remove(unvault_outpoint);
+    deposits_cache.insert(
+        db_vault.deposit_outpoint,
+        deposit_utxo(revaultd, db_vault, true),
+    );
+    log::warn!(
+        "Unvault transaction '{}' of vault at '{}' left the mempool. Marking the vault as active \
+         again.",
+        unvault_outpoint.txid,
+        db_vault.deposit_outpoint
+    );
+
+    Ok(())
+}
+
 // Retrieve the transaction kind (and its txid) that spent an Unvault
 fn unvault_spender(
     revaultd: &mut Arc<RwLock<RevaultD>>,
@@ -948,14 +1154,15 @@ fn unvault_spender(
     unvault_outpoint: &OutPoint,
 ) -> Result<Option<UnvaultSpender>, BitcoindError> {
     let db_path = revaultd.read().unwrap().db_file();
+    let db_key = revaultd.read().unwrap().db_key.clone();
 
-    let (vault, _) =
-        db_vault_by_unvault_txid(&db_path, &unvault_outpoint.txid)?.ok_or_else(|| {
-            BitcoindError::Custom(format!(
-                "No vault for {}, but it *is* being spent",
-                unvault_outpoint
-            ))
-        })?;
+    let (vault, _) = db_vault_by_unvault_txid(&db_path, db_key.as_ref(), &unvault_outpoint.txid)?
+        .ok_or_else(|| {
+        BitcoindError::Custom(format!(
+            "No vault for {}, but it *is* being spent",
+            unvault_outpoint
+        ))
+    })?;
 
     // First, check if it was spent by a Cancel, it's cheaper.
     let cancel_txid = cancel_txid(revaultd, &vault)?;
@@ -972,23 +1179,53 @@ fn unvault_spender(
     }
 
     // Finally, fetch the spending transaction
-    if let Some(spender_txid) = bitcoind.get_spender_txid(&unvault_outpoint, &previous_tip.hash)? {
+    let spends = db_list_spends(&db_path, db_key.as_ref())?
+        .into_iter()
+        .filter_map(|(txid, (_, outpoints))| {
+            if outpoints.contains(&vault.deposit_outpoint) {
+                Some(txid)
+            } else {
+                None
+            }
+        })
+        .collect();
+    let known = KnownSpenders {
+        cancel: Some(cancel_txid),
+        unvault_emer: unemer_txid,
+        spends,
+        csv: revaultd.read().unwrap().unvault_descriptor.csv_value(),
+    };
+    if let Some(spender) =
+        bitcoind.get_spender_txid(unvault_outpoint, &previous_tip.hash, &known)?
+    {
         // FIXME: be smarter, all the information are in the previous call, no need for a
         // second one.
 
-        // Let's double-check that we didn't fetch the cancel, nor the unemer
-        // In theory (read edge cases), the Cancel and UnEmer could have not been
-        // current at the last bitcoind poll but could be now.
-        // Be sure to not wrongly mark a Cancel or UnEmer as a Spend!
-        if spender_txid == cancel_txid || Some(spender_txid) == unemer_txid {
-            // Alright, the spender is the cancel or the unemer,
-            // but we just checked and they weren't current. We'll return None
-            // so the checker will call this function again.
-            return Ok(None);
-        }
-
-        if bitcoind.is_current(&spender_txid)? {
-            return Ok(Some(UnvaultSpender::Spend(spender_txid)));
+        match spender.kind {
+            // In theory (read edge cases), the Cancel and UnEmer could have not been
+            // current at the last bitcoind poll but could be now.
+            // Be sure to not wrongly mark a Cancel or UnEmer as a Spend!
+            SpenderKind::Cancel | SpenderKind::UnvaultEmergency => {
+                // Alright, the spender is the cancel or the unemer,
+                // but we just checked and they weren't current. We'll return None
+                // so the checker will call this function again.
+                return Ok(None);
+            }
+            SpenderKind::Spend => {
+                if bitcoind.is_current(&spender.txid)? {
+                    return Ok(Some(UnvaultSpender::Spend(spender.txid)));
+                }
+            }
+            SpenderKind::Unknown => {
+                if bitcoind.is_current(&spender.txid)? {
+                    return Ok(Some(UnvaultSpender::UnknownSpend(spender.txid)));
+                }
+            }
+            SpenderKind::Unexpected => {
+                if bitcoind.is_current(&spender.txid)? {
+                    return Ok(Some(UnvaultSpender::Unexpected(spender.txid)));
+                }
+            }
         }
     }
 
@@ -1000,15 +1237,37 @@ fn handle_spent_unvault(
     revaultd: &mut Arc<RwLock<RevaultD>>,
     db_path: &Path,
     bitcoind: &BitcoinD,
+    deposits_cache: &mut HashMap<OutPoint, UtxoInfo>,
     unvaults_cache: &mut HashMap<OutPoint, UtxoInfo>,
     previous_tip: &BlockchainTip,
     unvault_outpoint: &OutPoint,
 ) -> Result<(), BitcoindError> {
-    match unvault_spender(revaultd, bitcoind, previous_tip, &unvault_outpoint)? {
+    let db_key = revaultd.read().unwrap().db_key.clone();
+    let spender = unvault_spender(revaultd, bitcoind, previous_tip, unvault_outpoint)?;
+    if let Some(UnvaultSpender::UnknownSpend(txid)) = spender {
+        // Managers store the Spend transactions they create, stakeholders usually don't hear
+        // about them at all.
+        if revaultd.read().unwrap().is_manager() {
+            log::error!(
+                "Unvault transaction at {} is being spent by '{}', which is not a Spend \
+                 transaction we know of. This may be a theft attempt!",
+                &unvault_outpoint,
+                &txid
+            );
+        } else {
+            log::warn!(
+                "Unvault transaction at {} is being spent by an unknown transaction '{}'",
+                &unvault_outpoint,
+                &txid
+            );
+        }
+    }
+
+    match spender {
         Some(UnvaultSpender::Cancel(txid)) => {
-            db_cancel_unvault(&db_path, &unvault_outpoint.txid)?;
+            db_cancel_unvault(db_path, &unvault_outpoint.txid)?;
             unvaults_cache
-                .remove(&unvault_outpoint)
+                .remove(unvault_outpoint)
                 .expect("An unknown unvault got spent?");
             log::debug!(
                 "Unvault transaction at {} is now being canceled",
@@ -1016,23 +1275,45 @@ fn handle_spent_unvault(
             );
 
             // Immediately check if it was confirmed, just in case
-            let (db_vault, _) = db_vault_by_unvault_txid(&db_path, &unvault_outpoint.txid)?
-                .ok_or_else(|| {
-                    BitcoindError::Custom(format!(
-                        "No vault for Unvault '{}'",
-                        &unvault_outpoint.txid
-                    ))
-                })?;
-            match maybe_confirm_cancel(&db_path, bitcoind, &db_vault, &txid) {
+            let (db_vault, _) =
+                db_vault_by_unvault_txid(db_path, db_key.as_ref(), &unvault_outpoint.txid)?
+                    .ok_or_else(|| {
+                        BitcoindError::Custom(format!(
+                            "No vault for Unvault '{}'",
+                            &unvault_outpoint.txid
+                        ))
+                    })?;
+            let cancel_min_conf = revaultd.read().unwrap().cancel_min_conf;
+            match maybe_confirm_cancel(db_path, bitcoind, &db_vault, &txid, cancel_min_conf) {
                 Ok(_) => {}
                 Err(e) => {
                     log::error!("Error checking if Cancel '{}' is confirmed: '{}'", &txid, e);
                 }
             }
         }
-        Some(UnvaultSpender::Spend(txid)) => {
-            db_spend_unvault(&db_path, &unvault_outpoint.txid, &txid)?;
-            unvaults_cache.remove(&unvault_outpoint).ok_or_else(|| {
+        Some(UnvaultSpender::Unexpected(txid)) => {
+            db_unexpected_spend_unvault(db_path, &unvault_outpoint.txid, &txid)?;
+            unvaults_cache.remove(unvault_outpoint).ok_or_else(|| {
+                BitcoindError::Custom("An unknown unvault got spent?".to_string())
+            })?;
+            log::error!(
+                "Unvault transaction at {} is being spent by '{}', which is neither a revocation \
+                 transaction nor a Spend through the managers' path. This may be a theft attempt!",
+                &unvault_outpoint,
+                &txid
+            );
+            revaultd
+                .read()
+                .unwrap()
+                .events
+                .publish(BitcoindEvent::UnexpectedSpend {
+                    unvault_outpoint: *unvault_outpoint,
+                    spender_txid: txid,
+                });
+        }
+        Some(UnvaultSpender::Spend(txid)) | Some(UnvaultSpender::UnknownSpend(txid)) => {
+            db_spend_unvault(db_path, &unvault_outpoint.txid, &txid)?;
+            unvaults_cache.remove(unvault_outpoint).ok_or_else(|| {
                 BitcoindError::Custom("An unknown unvault got spent?".to_string())
             })?;
             log::debug!(
@@ -1041,14 +1322,15 @@ fn handle_spent_unvault(
             );
 
             // Immediately check if it was confirmed, just in case
-            let (db_vault, _) = db_vault_by_unvault_txid(&db_path, &unvault_outpoint.txid)?
-                .ok_or_else(|| {
-                    BitcoindError::Custom(format!(
-                        "No vault for Unvault '{}'",
-                        &unvault_outpoint.txid
-                    ))
-                })?;
-            match maybe_confirm_spend(&db_path, bitcoind, &db_vault, &txid) {
+            let (db_vault, _) =
+                db_vault_by_unvault_txid(db_path, db_key.as_ref(), &unvault_outpoint.txid)?
+                    .ok_or_else(|| {
+                        BitcoindError::Custom(format!(
+                            "No vault for Unvault '{}'",
+                            &unvault_outpoint.txid
+                        ))
+                    })?;
+            match maybe_confirm_spend(db_path, bitcoind, &db_vault, &txid) {
                 Ok(_) => {}
                 Err(e) => {
                     log::error!("Error checking if Spend '{}' is confirmed: '{}'", &txid, e);
@@ -1056,8 +1338,8 @@ fn handle_spent_unvault(
             }
         }
         Some(UnvaultSpender::Emergency(txid)) => {
-            db_emer_unvault(&db_path, &unvault_outpoint.txid)?;
-            unvaults_cache.remove(&unvault_outpoint).ok_or_else(|| {
+            db_emer_unvault(db_path, &unvault_outpoint.txid)?;
+            unvaults_cache.remove(unvault_outpoint).ok_or_else(|| {
                 BitcoindError::Custom("An unknown unvault got spent?".to_string())
             })?;
             log::warn!(
@@ -1066,14 +1348,15 @@ fn handle_spent_unvault(
             );
 
             // Immediately check if it was confirmed, just in case
-            let (db_vault, _) = db_vault_by_unvault_txid(&db_path, &unvault_outpoint.txid)?
-                .ok_or_else(|| {
-                    BitcoindError::Custom(format!(
-                        "No vault for Unvault '{}'",
-                        &unvault_outpoint.txid
-                    ))
-                })?;
-            match maybe_confirm_unemer(&db_path, bitcoind, &db_vault, &txid) {
+            let (db_vault, _) =
+                db_vault_by_unvault_txid(db_path, db_key.as_ref(), &unvault_outpoint.txid)?
+                    .ok_or_else(|| {
+                        BitcoindError::Custom(format!(
+                            "No vault for Unvault '{}'",
+                            &unvault_outpoint.txid
+                        ))
+                    })?;
+            match maybe_confirm_unemer(db_path, bitcoind, &db_vault, &txid) {
                 Ok(_) => {}
                 Err(e) => {
                     log::error!(
@@ -1085,6 +1368,31 @@ fn handle_spent_unvault(
             }
         }
         None => {
+            // The Unvault txo may have vanished because the unconfirmed Unvault transaction itself
+            // left the mempool. It's signaling for RBF, so it may have been replaced by another
+            // transaction spending the deposit (such as the Emergency).
+            let (db_vault, _) =
+                db_vault_by_unvault_txid(db_path, db_key.as_ref(), &unvault_outpoint.txid)?
+                    .ok_or_else(|| {
+                        BitcoindError::Custom(format!(
+                            "No vault for Unvault '{}'",
+                            &unvault_outpoint.txid
+                        ))
+                    })?;
+            if db_vault.status == VaultStatus::Unvaulting
+                && !bitcoind.is_current(&unvault_outpoint.txid)?
+            {
+                revert_unvault(
+                    revaultd,
+                    db_path,
+                    deposits_cache,
+                    unvaults_cache,
+                    &db_vault,
+                    unvault_outpoint,
+                )?;
+                return Ok(());
+            }
+
             // We don't remove it from the cache, so we'll check this outpoint at the next poll
             log::info!(
                 "Could not find a current transaction spending the Unvault txo at '{}', will check again at next poll",
@@ -1099,12 +1407,60 @@ fn handle_spent_unvault(
     Ok(())
 }
 
+// Called when the deposit of a vault we already know appears again in the listunspent result.
+fn handle_unspent_again_deposit(
+    revaultd: &Arc<RwLock<RevaultD>>,
+    db_path: &Path,
+    deposits_cache: &mut HashMap<OutPoint, UtxoInfo>,
+    unvaults_cache: &mut HashMap<OutPoint, UtxoInfo>,
+    db_vault: &DbVault,
+    outpoint: OutPoint,
+    mut utxo: UtxoInfo,
+) -> Result<(), BitcoindError> {
+    if db_vault.status == VaultStatus::Unvaulting {
+        let unvault_outpoint = unvault_txin_from_deposit(revaultd, &outpoint, utxo.txo)?.outpoint();
+        return revert_unvault(
+            revaultd,
+            db_path,
+            deposits_cache,
+            unvaults_cache,
+            db_vault,
+            &unvault_outpoint,
+        );
+    }
+
+    // The transaction spending it before the vault got secured was reorged out and evicted
+    if db_vault.status == VaultStatus::Aborted {
+        let status = db_unabort_vault(db_path, db_vault.id)?;
+        log::warn!(
+            "Deposit of aborted vault '{}' is unspent again. Restoring it as '{}'.",
+            outpoint,
+            status
+        );
+        // It needs to get through the confirmation again
+        if status == VaultStatus::Unconfirmed {
+            utxo.is_confirmed = false;
+        }
+        deposits_cache.insert(outpoint, utxo);
+        return Ok(());
+    }
+
+    log::debug!(
+        "Deposit at '{}' (status '{}') is unspent again",
+        outpoint,
+        db_vault.status
+    );
+    deposits_cache.insert(outpoint, utxo);
+    Ok(())
+}
+
 // Update our state when a new UTXO appears that is paying to the Deposit descriptor
 fn handle_new_deposit(
     revaultd: &mut Arc<RwLock<RevaultD>>,
     db_path: &Path,
     bitcoind: &BitcoinD,
     deposits_cache: &mut HashMap<OutPoint, UtxoInfo>,
+    new_addresses: &mut NewAddresses,
     outpoint: OutPoint,
     utxo: UtxoInfo,
 ) -> Result<(), BitcoindError> {
@@ -1127,6 +1483,21 @@ fn handle_new_deposit(
             BitcoindError::Custom(format!("Unknown derivation index for: {:#?}", &utxo))
         })?;
 
+    // Each outpoint is a distinct vault, but an address being reused might indicate a confused
+    // depositor.
+    let reused_by: Vec<String> = db_vaults_by_derivation_index(db_path, derivation_index)?
+        .into_iter()
+        .filter(|db_vault| db_vault.deposit_outpoint != outpoint)
+        .map(|db_vault| db_vault.deposit_outpoint.to_string())
+        .collect();
+    if !reused_by.is_empty() {
+        log::warn!(
+            "Deposit address reuse: new deposit at '{}' pays to the same address as '{}'",
+            &outpoint,
+            reused_by.join(", ")
+        );
+    }
+
     let received_at = bitcoind.get_wallet_transaction(&outpoint.txid)?.2;
     // Note that the deposit *might* have already MIN_CONF confirmations, that's fine. We'll
     // confim it during the next poll.
@@ -1152,36 +1523,115 @@ fn handle_new_deposit(
     deposits_cache.insert(outpoint, utxo);
 
     // Mind the gap! https://www.youtube.com/watch?v=UOPyGKDQuRk
-    // FIXME: of course, that's rudimentary
+    // The deposit may be at any index within the gap limit window (for instance if they asked
+    // for an address at an explicit index), the first unused one is then the following index.
     let current_first_index = revaultd.read().unwrap().current_unused_index;
     if derivation_index >= current_first_index {
-        let new_index = revaultd
-            .read()
-            .unwrap()
-            .current_unused_index
-            .increment()
-            .map_err(|e| {
-                // FIXME: we should probably go back to 0 at this point.
-                BitcoindError::Custom(format!("Deriving next index: {}", e))
-            })?;
+        let new_index = match derivation_index.increment() {
+            Ok(index) => index,
+            Err(e) => {
+                log::error!(
+                    "The deposit descriptor range is exhausted, the last deposit address (at \
+                     index {}) was used: '{}'. New deposits must be made to a descriptor with \
+                     fresh keys or another derivation path.",
+                    derivation_index,
+                    e
+                );
+                return Ok(());
+            }
+        };
         db_update_deposit_index(&revaultd.read().unwrap().db_file(), new_index)?;
-        revaultd.write().unwrap().current_unused_index = new_index;
-        let next_addr = bitcoind
-            .addr_descriptor(&revaultd.read().unwrap().last_deposit_address().to_string())?;
-        bitcoind.import_fresh_deposit_descriptor(next_addr)?;
-        let next_addr = bitcoind
-            .addr_descriptor(&revaultd.read().unwrap().last_unvault_address().to_string())?;
-        bitcoind.import_fresh_unvault_descriptor(next_addr)?;
+        let new_indexes = revaultd.write().unwrap().set_unused_index(new_index);
+        // Near the end of the descriptors' range there is nothing more to watch
+        let revaultd = revaultd.read().unwrap();
+        for index in new_indexes {
+            new_addresses.push_deposit(revaultd.vault_address(index).to_string());
+            if !revaultd.bitcoind_config.lazy_unvault_import {
+                new_addresses.push_unvault(revaultd.unvault_address(index).to_string());
+            }
+        }
 
         log::debug!(
-            "Incremented deposit derivation index from {}",
-            current_first_index
+            "Incremented deposit derivation index from {} to {}",
+            current_first_index,
+            new_index
         );
     }
 
     Ok(())
 }
 
+// The addresses we derived when receiving new deposits. They are buffered across polls and
+// imported all at once when the buffer is flushed, instead of one 'importdescriptors' call per
+// deposit.
+#[derive(Debug, Default)]
+struct NewAddresses {
+    deposit: Vec<String>,
+    unvault: Vec<String>,
+    // When the oldest address still in the buffer was derived
+    pending_since: Option<Instant>,
+}
+
+impl NewAddresses {
+    fn push_deposit(&mut self, address: String) {
+        self.deposit.push(address);
+        self.pending_since.get_or_insert_with(Instant::now);
+    }
+
+    fn push_unvault(&mut self, address: String) {
+        self.unvault.push(address);
+        self.pending_since.get_or_insert_with(Instant::now);
+    }
+
+    // Whether the oldest buffered address has been waiting for at least `flush_interval`
+    fn should_flush(&self, now: Instant, flush_interval: Duration) -> bool {
+        self.pending_since
+            .map(|since| now.duration_since(since) >= flush_interval)
+            .unwrap_or(false)
+    }
+}
+
+// Import all the buffered addresses, leaving the buffer empty. The derivation index was already
+// bumped (under the write lock) when the addresses were buffered, so the buffer only ever holds
+// each index once.
+fn import_new_addresses(
+    bitcoind: &BitcoinD,
+    new_addresses: &mut NewAddresses,
+    observer_mode: bool,
+) -> Result<(), BitcoindError> {
+    let NewAddresses {
+        deposit, unvault, ..
+    } = std::mem::take(new_addresses);
+    if observer_mode {
+        log::debug!(
+            "Not importing {} new deposit and {} new unvault descriptors in observer mode",
+            deposit.len(),
+            unvault.len()
+        );
+        return Ok(());
+    }
+
+    if !deposit.is_empty() {
+        log::debug!("Importing {} new deposit descriptors", deposit.len());
+        let descriptors = deposit
+            .iter()
+            .map(|addr| bitcoind.addr_descriptor(addr))
+            .collect::<Result<Vec<String>, BitcoindError>>()?;
+        bitcoind.import_fresh_deposit_descriptors(descriptors)?;
+    }
+
+    if !unvault.is_empty() {
+        log::debug!("Importing {} new unvault descriptors", unvault.len());
+        let descriptors = unvault
+            .iter()
+            .map(|addr| bitcoind.addr_descriptor(addr))
+            .collect::<Result<Vec<String>, BitcoindError>>()?;
+        bitcoind.import_fresh_unvault_descriptors(descriptors)?;
+    }
+
+    Ok(())
+}
+
 // Update our state when we notice a deeply-enough confirmed deposit UTXO
 fn handle_confirmed_deposit(
     revaultd: &mut Arc<RwLock<RevaultD>>,
@@ -1191,6 +1641,7 @@ fn handle_confirmed_deposit(
     outpoint: OutPoint,
     utxo: UtxoInfo,
 ) -> Result<(), BitcoindError> {
+    let db_key = revaultd.read().unwrap().db_key.clone();
     let blockheight =
         if let (_, Some(height), _) = bitcoind.get_wallet_transaction(&outpoint.txid)? {
             height
@@ -1223,6 +1674,7 @@ fn handle_confirmed_deposit(
 
     db_confirm_deposit(
         db_path,
+        db_key.as_ref(),
         &outpoint,
         blockheight,
         &unvault_tx,
@@ -1236,6 +1688,11 @@ fn handle_confirmed_deposit(
         .is_confirmed = true;
 
     log::debug!("Vault at {} is now confirmed", &outpoint);
+    revaultd
+        .read()
+        .unwrap()
+        .events
+        .publish(BitcoindEvent::DepositConfirmed(outpoint));
 
     Ok(())
 }
@@ -1251,7 +1708,7 @@ fn handle_spent_deposit(
     deposit_outpoint: OutPoint,
     utxo: UtxoInfo,
 ) -> Result<(), BitcoindError> {
-    let unvault_txin = match unvault_txin_from_deposit(&revaultd, &deposit_outpoint, utxo.txo) {
+    let unvault_txin = match unvault_txin_from_deposit(revaultd, &deposit_outpoint, utxo.txo) {
         Ok(txin) => txin,
         Err(e) => {
             log::error!(
@@ -1273,7 +1730,7 @@ fn handle_spent_deposit(
             &deposit_outpoint
         );
 
-        db_unvault_deposit(&db_path, &unvault_outpoint.txid)?;
+        db_unvault_deposit(db_path, &unvault_outpoint.txid)?;
         unvaults_cache.insert(
             unvault_outpoint,
             UtxoInfo {
@@ -1290,10 +1747,10 @@ fn handle_spent_deposit(
 
     // Was it spent by the Emergency transaction?
     let db_vault =
-        db_vault_by_deposit(&db_path, &deposit_outpoint)?.expect("Spent deposit doesn't exist?");
+        db_vault_by_deposit(db_path, &deposit_outpoint)?.expect("Spent deposit doesn't exist?");
     if let Some(emer_txid) = emer_txid(revaultd, &db_vault)? {
         if bitcoind.is_current(&emer_txid)? {
-            db_mark_emergencying_vault(&db_path, db_vault.id)?;
+            db_mark_emergencying_vault(db_path, db_vault.id)?;
             deposits_cache
                 .remove(&deposit_outpoint)
                 .expect("It was in spent_deposits, it must still be here.");
@@ -1302,6 +1759,37 @@ fn handle_spent_deposit(
 
     // TODO: handle bypass
 
+    // Was it spent before the vault got secured? Then it never will be. But only once the
+    // spending transaction is confirmed, as it may otherwise be evicted from the mempool and
+    // the deposit be unspent again.
+    if matches!(
+        db_vault.status,
+        VaultStatus::Unconfirmed | VaultStatus::Funded | VaultStatus::Securing
+    ) && bitcoind.is_current(&deposit_outpoint.txid)?
+    {
+        if !bitcoind.is_spent_in_chain(&deposit_outpoint)? {
+            // We don't remove it from the cache, so we'll check this outpoint at the next poll
+            log::info!(
+                "Deposit at '{}' was spent by an unconfirmed transaction before the vault got \
+                 secured, will check again at next poll",
+                &deposit_outpoint
+            );
+            return Ok(());
+        }
+
+        db_mark_aborted_vault(db_path, db_vault.id)?;
+        deposits_cache
+            .remove(&deposit_outpoint)
+            .expect("It was in spent_deposits, it must still be here.");
+        log::warn!(
+            "Deposit at '{}' was spent before the vault got secured (status was '{}'). \
+             Marking the vault as aborted.",
+            &deposit_outpoint,
+            db_vault.status
+        );
+        return Ok(());
+    }
+
     // Only remove the deposit from the cache if it's not in mempool nor in block chain.
     if bitcoind.is_current(&deposit_outpoint.txid)? {
         log::error!(
@@ -1324,6 +1812,58 @@ fn handle_spent_deposit(
     Ok(())
 }
 
+// Called when an Unvault UTXO we did not know about appears in the listunspent result. This is
+// usually because the Unvault was broadcast since the last poll, but it may also have been
+// broadcast (and even confirmed) while we were not running.
+fn handle_new_unvault(
+    db_path: &Path,
+    db_key: Option<&DbKey>,
+    bitcoind: &BitcoinD,
+    deposits_cache: &mut HashMap<OutPoint, UtxoInfo>,
+    unvaults_cache: &mut HashMap<OutPoint, UtxoInfo>,
+    outpoint: OutPoint,
+    mut utxo: UtxoInfo,
+) -> Result<(), BitcoindError> {
+    let db_vault = match db_vault_by_unvault_txid(db_path, db_key, &outpoint.txid)? {
+        Some((db_vault, _)) => db_vault,
+        None => {
+            log::error!(
+                "Unvault utxo at '{}' does not correspond to any vault we know of",
+                outpoint
+            );
+            return Ok(());
+        }
+    };
+
+    // The deposit was spent by this Unvault. We may not have noticed it yet if the Unvault
+    // was broadcast in between our two listunspent calls, or while we were down.
+    if deposits_cache.remove(&db_vault.deposit_outpoint).is_some() {
+        log::debug!(
+            "Deposit at '{}' was spent by Unvault at '{}'",
+            db_vault.deposit_outpoint,
+            outpoint
+        );
+    }
+
+    db_unvault_deposit(db_path, &outpoint.txid)?;
+    // If the Unvault is already confirmed (eg it was mined while we were down) advance the
+    // vault state right away instead of waiting for the next poll.
+    if let (_, Some(height), _) = bitcoind.get_wallet_transaction(&outpoint.txid)? {
+        db_confirm_unvault(db_path, &outpoint.txid)?;
+        utxo.is_confirmed = true;
+        log::debug!(
+            "Got a new unvault utxo at {}, already confirmed at height {}",
+            outpoint,
+            height
+        );
+    } else {
+        log::debug!("Got a new unconfirmed unvault utxo at {} ", outpoint);
+    }
+    unvaults_cache.insert(outpoint, utxo);
+
+    Ok(())
+}
+
 // This syncs with bitcoind our onchain utxos. We track the deposits and unvaults ones, and react
 // to their creation, confirmation, and spending. We are then tracking their spending depending on
 // their kind. Pretty much like a tree, for which we actively track the trunk with the watchonly
@@ -1335,21 +1875,55 @@ fn handle_spent_deposit(
 fn update_utxos(
     revaultd: &mut Arc<RwLock<RevaultD>>,
     bitcoind: &BitcoinD,
+    snapshot: WalletSnapshot,
     deposits_cache: &mut HashMap<OutPoint, UtxoInfo>,
     unvaults_cache: &mut HashMap<OutPoint, UtxoInfo>,
+    new_addresses: &mut NewAddresses,
     previous_tip: &BlockchainTip,
-) -> Result<(), BitcoindError> {
+) -> Result<usize, BitcoindError> {
     let db_path = revaultd.read().unwrap().db_file();
+    let db_key = revaultd.read().unwrap().db_key.clone();
+    let mut n_changes = 0;
 
     // First, let's check our deposits.
     let OnchainDescriptorState {
         new_unconf: new_deposits,
         new_conf: conf_deposits,
         new_spent: spent_deposits,
-    } = bitcoind.sync_deposits(&deposits_cache, revaultd.read().unwrap().min_conf)?;
+    } = {
+        let revaultd = revaultd.read().unwrap();
+        bitcoind.sync_deposits(
+            deposits_cache,
+            snapshot.deposits,
+            revaultd.min_conf,
+            &revaultd.derivation_index_map,
+        )
+    };
+    n_changes += new_deposits.len() + conf_deposits.len() + spent_deposits.len();
 
     for (outpoint, utxo) in new_deposits {
-        handle_new_deposit(revaultd, &db_path, bitcoind, deposits_cache, outpoint, utxo)?;
+        // It may not be new: its Unvault may have left the mempool, making it unspent again.
+        if let Some(db_vault) = db_vault_by_deposit(&db_path, &outpoint)? {
+            handle_unspent_again_deposit(
+                revaultd,
+                &db_path,
+                deposits_cache,
+                unvaults_cache,
+                &db_vault,
+                outpoint,
+                utxo,
+            )?;
+            continue;
+        }
+        handle_new_deposit(
+            revaultd,
+            &db_path,
+            bitcoind,
+            deposits_cache,
+            new_addresses,
+            outpoint,
+            utxo,
+        )?;
     }
 
     for (outpoint, utxo) in conf_deposits {
@@ -1368,19 +1942,25 @@ fn update_utxos(
         )?;
     }
 
-    // Now, check the Unvault utxos.
+    // Now, check the Unvault utxos. They are from the same snapshot as the deposits, so the
+    // Unvaults of the deposits we just saw being spent are already part of the known ones.
     let OnchainDescriptorState {
         new_unconf: new_unvaults,
         new_conf: conf_unvaults,
         new_spent: spent_unvaults,
-    } = bitcoind.sync_unvaults(&unvaults_cache)?;
+    } = bitcoind.sync_unvaults(unvaults_cache, snapshot.unvaults);
+    n_changes += new_unvaults.len() + conf_unvaults.len() + spent_unvaults.len();
 
     for (outpoint, utxo) in new_unvaults {
-        // Note that it *might* have actually been confirmed in-between the last poll, but we keep
-        // single transitions, and it's no big deal to mark it confirmed during the next poll.
-        db_unvault_deposit(&revaultd.read().unwrap().db_file(), &outpoint.txid)?;
-        unvaults_cache.insert(outpoint, utxo);
-        log::debug!("Got a new unconfirmed unvault utxo at {} ", outpoint);
+        handle_new_unvault(
+            &db_path,
+            db_key.as_ref(),
+            bitcoind,
+            deposits_cache,
+            unvaults_cache,
+            outpoint,
+            utxo,
+        )?;
     }
 
     for (outpoint, _) in conf_unvaults {
@@ -1397,13 +1977,14 @@ fn update_utxos(
             revaultd,
             &db_path,
             bitcoind,
+            deposits_cache,
             unvaults_cache,
             previous_tip,
             &unvault_outpoint,
         )?;
     }
 
-    Ok(())
+    Ok(n_changes)
 }
 
 /// Bitcoind uses a guess for the value of verificationprogress. It will eventually get to
@@ -1415,7 +1996,7 @@ fn roundup_progress(progress: f64) -> f64 {
     if progress_rounded >= precision as u64 {
         1.0
     } else {
-        (progress_rounded as f64 / precision) as f64
+        progress_rounded as f64 / precision
     }
 }
 
@@ -1474,7 +2055,7 @@ fn bitcoind_sync_status(
     // (~7h for 500_000 blocks), so we divide it by 2 here in order to be
     // conservative. Eg if 10_000 are left to be downloaded we'll check back
     // in ~4min.
-    let delta = headers.checked_sub(blocks).unwrap_or(0);
+    let delta = headers.saturating_sub(blocks);
     *sleep_duration = Some(std::cmp::max(
         Duration::from_secs(delta / 20 / 2),
         Duration::from_secs(5),
@@ -1485,8 +2066,24 @@ fn bitcoind_sync_status(
     Ok(())
 }
 
+// bitcoind rescans from the first block with a timestamp within this window before the import
+// timestamp, as the block timestamps are not monotonic.
+const RESCAN_TIMESTAMP_WINDOW: u32 = 2 * 60 * 60;
+
+// The prune height of bitcoind, and the earliest timestamp to import descriptors with so that
+// it doesn't need to rescan a block below it. None if bitcoind isn't pruned.
+fn pruned_rescan_limit(bitcoind: &BitcoinD) -> Result<Option<(u32, u32)>, BitcoindError> {
+    match bitcoind.prune_height()? {
+        Some(height) => {
+            let block_time = bitcoind.block_time(height)? as u32;
+            Ok(Some((height, block_time + RESCAN_TIMESTAMP_WINDOW)))
+        }
+        None => Ok(None),
+    }
+}
+
 // This creates the actual wallet file, and imports the descriptors
-fn maybe_create_wallet(revaultd: &mut RevaultD, bitcoind: &BitcoinD) -> Result<(), BitcoindError> {
+fn maybe_create_wallet(revaultd: &RevaultD, bitcoind: &BitcoinD) -> Result<(), BitcoindError> {
     let wallet = db_wallet(&revaultd.db_file())?;
     let bitcoind_wallet_path = revaultd
         .watchonly_wallet_file()
@@ -1495,13 +2092,32 @@ fn maybe_create_wallet(revaultd: &mut RevaultD, bitcoind: &BitcoinD) -> Result<(
     let curr_timestamp = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .map(|dur| dur.as_secs())
-        .map_err(|e| {
-            BitcoindError::Custom(format!("Computing time since epoch: {}", e.to_string()))
-        })?;
+        .map_err(|e| BitcoindError::Custom(format!("Computing time since epoch: {}", e)))?;
     let fresh_wallet = (curr_timestamp - wallet.timestamp as u64) < 30;
+    // If the wallet is fresh we don't need to rescan the whole chain, but still look back a bit
+    // to not miss a deposit made right before we created it.
+    let import_timestamp = if fresh_wallet {
+        let lookback = revaultd
+            .bitcoind_config
+            .fresh_wallet_lookback_secs
+            .as_secs();
+        curr_timestamp.saturating_sub(lookback) as u32
+    } else {
+        wallet.timestamp
+    };
 
     // TODO: sanity check descriptors are imported when migrating to 0.22
 
+    if revaultd.bitcoind_config.observer_mode {
+        if !PathBuf::from(bitcoind_wallet_path.clone()).exists() {
+            return Err(BitcoindError::Custom(format!(
+                "We are in observer mode but the watchonly wallet '{}' does not exist",
+                bitcoind_wallet_path
+            )));
+        }
+        return Ok(());
+    }
+
     if !PathBuf::from(bitcoind_wallet_path.clone()).exists() {
         // Remove any leftover. This can happen if we delete the watchonly wallet but don't restart
         // bitcoind.
@@ -1512,6 +2128,20 @@ fn maybe_create_wallet(revaultd: &mut RevaultD, bitcoind: &BitcoinD) -> Result<(
             }
         }
 
+        // On a pruned node we can't look for deposits in the blocks bitcoind got rid of.
+        if let Some((prune_height, earliest)) = pruned_rescan_limit(bitcoind)? {
+            if import_timestamp < earliest {
+                return Err(BitcoindError::Custom(format!(
+                    "The watchonly wallet needs to be rescanned from timestamp {} but bitcoind is \
+                     pruned up to height {}, so deposits would be missed. Please point revaultd \
+                     to a bitcoind that has the blocks since this date (for instance by \
+                     re-syncing it with a larger 'prune' value or without pruning), or for a new \
+                     wallet decrease 'fresh_wallet_lookback_secs'.",
+                    import_timestamp, prune_height
+                )));
+            }
+        }
+
         bitcoind.createwallet_startup(bitcoind_wallet_path)?;
         log::info!("Importing descriptors to bitcoind watchonly wallet.");
 
@@ -1525,27 +2155,203 @@ fn maybe_create_wallet(revaultd: &mut RevaultD, bitcoind: &BitcoinD) -> Result<(
         // Therefore, we derive [max index] `addr()` descriptors to import into bitcoind, and handle
         // the derivation index mess ourselves :'(
         let mut addresses = revaultd.all_deposit_addresses();
-        for i in 0..addresses.len() {
-            addresses[i] = bitcoind.addr_descriptor(&addresses[i])?;
+        for addr in addresses.iter_mut() {
+            *addr = bitcoind.addr_descriptor(addr)?;
         }
         log::trace!("Importing deposit descriptors '{:?}'", &addresses);
-        bitcoind.startup_import_deposit_descriptors(addresses, wallet.timestamp, fresh_wallet)?;
+        bitcoind.startup_import_deposit_descriptors(addresses, import_timestamp, fresh_wallet)?;
+
+        // If configured to, we only import the unvault descriptors once the vaults are secured.
+        if revaultd.bitcoind_config.lazy_unvault_import {
+            return Ok(());
+        }
 
         // As a consequence, we don't have enough information to opportunistically import a
         // descriptor at the reception of a deposit anymore. Thus we need to blindly import *both*
         // deposit and unvault descriptors..
         // FIXME: maybe we actually have, with the derivation_index_map ?
         let mut addresses = revaultd.all_unvault_addresses();
-        for i in 0..addresses.len() {
-            addresses[i] = bitcoind.addr_descriptor(&addresses[i])?;
+        for addr in addresses.iter_mut() {
+            *addr = bitcoind.addr_descriptor(addr)?;
         }
         log::trace!("Importing unvault descriptors '{:?}'", &addresses);
-        bitcoind.startup_import_unvault_descriptors(addresses, wallet.timestamp, fresh_wallet)?;
+        bitcoind.startup_import_unvault_descriptors(addresses, import_timestamp, fresh_wallet)?;
     }
 
     Ok(())
 }
 
+// The addresses our watchonly wallet doesn't watch yet, for instance the ones left from a previous
+// startup with a smaller window.
+fn unwatched_addresses(
+    bitcoind: &BitcoinD,
+    addresses: Vec<String>,
+) -> Result<Vec<String>, BitcoindError> {
+    let watched = bitcoind.watched_addresses(&addresses)?;
+    Ok(addresses
+        .into_iter()
+        .zip(watched)
+        .filter_map(|(addr, is_watched)| if is_watched { None } else { Some(addr) })
+        .collect())
+}
+
+// If we were told to pre-create more deposit addresses than the default gap limit, the wallet
+// may have been created with a smaller window. Make sure bitcoind watches all of them.
+fn import_precreated_addresses(
+    revaultd: &RevaultD,
+    bitcoind: &BitcoinD,
+) -> Result<(), BitcoindError> {
+    let (deposit_addresses, unvault_addresses) = revaultd.precreated_extra_addresses();
+    if deposit_addresses.is_empty() {
+        return Ok(());
+    }
+
+    // They were never handed out before, no need to rescan.
+    let curr_timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_secs())
+        .map_err(|e| BitcoindError::Custom(format!("Computing time since epoch: {}", e)))?;
+    let deposit_addresses = unwatched_addresses(bitcoind, deposit_addresses)?;
+    if !deposit_addresses.is_empty() {
+        log::info!(
+            "Importing {} pre-created deposit addresses",
+            deposit_addresses.len()
+        );
+        let descriptors = bitcoind.addr_descriptors(&deposit_addresses)?;
+        bitcoind.startup_import_deposit_descriptors(descriptors, curr_timestamp as u32, true)?;
+    }
+    if revaultd.bitcoind_config.lazy_unvault_import {
+        return Ok(());
+    }
+    let unvault_addresses = unwatched_addresses(bitcoind, unvault_addresses)?;
+    if !unvault_addresses.is_empty() {
+        let descriptors = bitcoind.addr_descriptors(&unvault_addresses)?;
+        bitcoind.startup_import_unvault_descriptors(descriptors, curr_timestamp as u32, true)?;
+    }
+
+    Ok(())
+}
+
+// In observer mode the watchonly wallet is managed by someone else, make sure it watches all the
+// addresses we would have imported.
+fn check_observed_descriptors(
+    revaultd: &RevaultD,
+    bitcoind: &BitcoinD,
+) -> Result<(), BitcoindError> {
+    // The configuration makes sure we don't lazily import the Unvault descriptors in observer
+    // mode, we would never be able to.
+    let (extra_deposit_addresses, extra_unvault_addresses) = revaultd.precreated_extra_addresses();
+    let mut addresses = revaultd.all_deposit_addresses();
+    addresses.extend(extra_deposit_addresses);
+    addresses.extend(revaultd.all_unvault_addresses());
+    addresses.extend(extra_unvault_addresses);
+
+    // They may be watched through any kind of descriptor, so ask bitcoind about each address.
+    let missing = unwatched_addresses(bitcoind, addresses)?;
+    if !missing.is_empty() {
+        return Err(BitcoindError::Custom(format!(
+            "We are in observer mode but the watchonly wallet does not watch {} of our addresses, \
+             such as '{}'",
+            missing.len(),
+            missing[0]
+        )));
+    }
+    log::info!(
+        "Observer mode: the watchonly wallet watches all our addresses, not importing any \
+         descriptor."
+    );
+
+    Ok(())
+}
+
+// When importing the unvault descriptors lazily, make sure bitcoind watches the Unvault output of
+// all the vaults that may be unvaulted. We keep track of the derivation indexes we already checked
+// to only query bitcoind once per vault, and query it for all the new ones at once. Returns
+// whether we imported any.
+fn import_secured_unvault_descriptors(
+    revaultd: &RevaultD,
+    bitcoind: &BitcoinD,
+    imported: &mut HashSet<ChildNumber>,
+) -> Result<bool, BitcoindError> {
+    // Only the vaults that are, or were, secured may have their Unvault broadcast.
+    let mut to_check_indexes = HashSet::new();
+    let to_check: Vec<DbVault> = db_vaults_with_status(
+        &revaultd.db_file(),
+        &[
+            VaultStatus::Secured,
+            VaultStatus::Activating,
+            VaultStatus::Active,
+            VaultStatus::Unvaulting,
+            VaultStatus::Unvaulted,
+            VaultStatus::Spending,
+            VaultStatus::Spent,
+            VaultStatus::UnexpectedSpend,
+            VaultStatus::Canceling,
+            VaultStatus::Canceled,
+            VaultStatus::UnvaultEmergencyVaulting,
+            VaultStatus::UnvaultEmergencyVaulted,
+        ],
+        imported,
+    )?
+    .into_iter()
+    // The same derivation index may be reused by more than one vault
+    .filter(|db_vault| to_check_indexes.insert(db_vault.derivation_index))
+    .collect();
+    if to_check.is_empty() {
+        return Ok(false);
+    }
+
+    let addresses: Vec<String> = to_check
+        .iter()
+        .map(|db_vault| {
+            revaultd
+                .unvault_address(db_vault.derivation_index)
+                .to_string()
+        })
+        .collect();
+    let watched = bitcoind.watched_addresses(&addresses)?;
+    let (to_import, addresses): (Vec<&DbVault>, Vec<String>) = to_check
+        .iter()
+        .zip(addresses)
+        .zip(watched)
+        .filter_map(|(entry, watched)| if watched { None } else { Some(entry) })
+        .unzip();
+
+    if !to_import.is_empty() {
+        // The Unvault can't have been broadcast before we received the deposit. But it may have
+        // been since then, so we can't miss any block.
+        if let Some((prune_height, earliest)) = pruned_rescan_limit(bitcoind)? {
+            if let Some(db_vault) = to_import.iter().find(|v| v.received_at < earliest) {
+                return Err(BitcoindError::Custom(format!(
+                    "The Unvault of vault at '{}' needs to be looked for from timestamp {} \
+                     but bitcoind is pruned up to height {}, so it could be missed. Please \
+                     point revaultd to a bitcoind that has the blocks since this date (for \
+                     instance by re-syncing it with a larger 'prune' value or without \
+                     pruning), or disable 'lazy_unvault_import'.",
+                    db_vault.deposit_outpoint, db_vault.received_at, prune_height
+                )));
+            }
+        }
+
+        let descriptors = bitcoind.addr_descriptors(&addresses)?;
+        let descriptors = to_import
+            .iter()
+            .zip(descriptors)
+            .map(|(db_vault, descriptor)| {
+                log::debug!(
+                    "Importing unvault descriptor for vault at '{}'",
+                    db_vault.deposit_outpoint
+                );
+                (descriptor, db_vault.received_at)
+            })
+            .collect();
+        bitcoind.import_unvault_descriptors(descriptors)?;
+    }
+    imported.extend(to_check_indexes);
+
+    Ok(!to_import.is_empty())
+}
+
 fn maybe_load_wallet(revaultd: &RevaultD, bitcoind: &BitcoinD) -> Result<(), BitcoindError> {
     let bitcoind_wallet_path = revaultd
         .watchonly_wallet_file()
@@ -1576,6 +2382,62 @@ fn maybe_load_wallet(revaultd: &RevaultD, bitcoind: &BitcoinD) -> Result<(), Bit
     }
 }
 
+// We import descriptors, so we need a descriptor wallet. If a legacy wallet with the same
+// name was there, it would be loaded instead and we'd fail later with cryptic errors.
+fn check_descriptor_wallet(revaultd: &RevaultD, bitcoind: &BitcoinD) -> Result<(), BitcoindError> {
+    let is_descriptor_wallet = bitcoind
+        .getwalletinfo()?
+        .get("descriptors")
+        .and_then(|d| d.as_bool())
+        // Versions of bitcoind without descriptor wallets don't have the field
+        .unwrap_or(false);
+
+    if !is_descriptor_wallet {
+        return Err(BitcoindError::Custom(format!(
+            "The watchonly wallet '{}' loaded on bitcoind is a legacy wallet, but we need a \
+             descriptor wallet. Please unload and move it away, a fresh descriptor wallet will \
+             be created at next startup.",
+            revaultd
+                .watchonly_wallet_file()
+                .expect("Wallet id is set at startup in setup_db()")
+        )));
+    }
+
+    Ok(())
+}
+
+// Compare the vaults we didn't see move onchain with the deposit utxos of the watchonly wallet.
+// They should match: a discrepancy is a sign that either the database or the wallet was tampered
+// with, or lost data. We don't fix it up here, but log it and record it for getinfo.
+// Returns the discrepancy between the number of deposits in database and in the watchonly wallet,
+// if any.
+fn reconcile_deposits(
+    revaultd: &RevaultD,
+    bitcoind: &BitcoinD,
+) -> Result<Option<(usize, usize)>, BitcoindError> {
+    let db_count = db_deposits(&revaultd.db_file())?.len();
+    let wallet_count = bitcoind
+        .list_deposit_utxos(&revaultd.derivation_index_map)?
+        .len();
+
+    log::info!(
+        "Reconciliation: {} deposit(s) in database, {} deposit utxo(s) in the watchonly wallet",
+        db_count,
+        wallet_count
+    );
+    if db_count != wallet_count {
+        log::warn!(
+            "The number of deposits in database ({}) doesn't match the number of deposit utxos in \
+             the watchonly wallet ({}). Check that neither the database nor the wallet is stale.",
+            db_count,
+            wallet_count
+        );
+        return Ok(Some((db_count, wallet_count)));
+    }
+
+    Ok(None)
+}
+
 // Update the progress made by bitcoind toward the tip.
 fn update_sync_status(
     revaultd: &Arc<RwLock<RevaultD>>,
@@ -1595,37 +2457,103 @@ fn update_sync_status(
         }
     }
 
+    // Don't hold the write lock while querying bitcoind, `getinfo` reads the progress.
+    let mut progress = *sync_progress.read().unwrap();
     bitcoind_sync_status(
         &bitcoind.read().unwrap(),
         &revaultd.read().unwrap().bitcoind_config,
         sync_waittime,
-        &mut sync_progress.write().unwrap(),
+        &mut progress,
     )?;
+    *sync_progress.write().unwrap() = progress;
 
     // Ok. Sync, done. Now just be sure the watchonly wallet is properly loaded, and
     // to create it if it's first run.
-    if *sync_progress.read().unwrap() as u32 >= 1 {
-        let mut revaultd = revaultd.write().unwrap();
-        let bitcoind = bitcoind.read().unwrap();
-        maybe_create_wallet(&mut revaultd, &bitcoind).map_err(|e| {
-            BitcoindError::Custom(format!("Error while creating wallet: {}", e.to_string()))
-        })?;
-        maybe_load_wallet(&revaultd, &bitcoind).map_err(|e| {
-            BitcoindError::Custom(format!("Error while loading wallet: {}", e.to_string()))
-        })?;
+    if progress as u32 >= 1 {
+        // Importing the descriptors may take a while, so don't hold the write lock as it would
+        // stall the RPC server. We only take it for the time of applying the result.
+        let discrepancy = {
+            let revaultd = revaultd.read().unwrap();
+            let bitcoind = bitcoind.read().unwrap();
+            setup_watchonly_wallet(&revaultd, &bitcoind)?;
+            // Not being able to reconcile them must not prevent us from starting.
+            reconcile_deposits(&revaultd, &bitcoind)
+                .map_err(|e| {
+                    log::error!("Error while reconciling deposits with the wallet: {}", e);
+                })
+                .ok()
+        };
+        if let Some(discrepancy) = discrepancy {
+            revaultd.write().unwrap().deposits_discrepancy = discrepancy;
+        }
 
         log::info!("bitcoind now synced.");
+        // The JSONRPC server was started before us, we are now fully operational.
+        if let Err(e) = revaultd.read().unwrap().create_ready_file() {
+            log::error!("Error creating the ready file: {}", e);
+        }
     }
 
     *last_poll = Some(now);
     Ok(())
 }
 
+// Make sure the watchonly wallet is properly loaded, and create it if it's first run.
+fn setup_watchonly_wallet(revaultd: &RevaultD, bitcoind: &BitcoinD) -> Result<(), BitcoindError> {
+    maybe_create_wallet(revaultd, bitcoind)
+        .map_err(|e| BitcoindError::Custom(format!("Error while creating wallet: {}", e)))?;
+    maybe_load_wallet(revaultd, bitcoind)
+        .map_err(|e| BitcoindError::Custom(format!("Error while loading wallet: {}", e)))?;
+    check_descriptor_wallet(revaultd, bitcoind)?;
+    if revaultd.bitcoind_config.observer_mode {
+        check_observed_descriptors(revaultd, bitcoind)
+    } else {
+        import_precreated_addresses(revaultd, bitcoind)
+            .map_err(|e| BitcoindError::Custom(format!("Error while importing addresses: {}", e)))
+    }
+}
+
+// Import the buffered new addresses if the oldest one has waited long enough, or right away
+// if `force` is set.
+fn maybe_import_new_addresses(
+    revaultd: &Arc<RwLock<RevaultD>>,
+    bitcoind: &Arc<RwLock<BitcoinD>>,
+    new_addresses: &mut NewAddresses,
+    now: Instant,
+    force: bool,
+) -> Result<(), BitcoindError> {
+    let (flush_interval, observer_mode) = {
+        let bitcoind_config = &revaultd.read().unwrap().bitcoind_config;
+        (
+            bitcoind_config.address_import_flush_secs,
+            bitcoind_config.observer_mode,
+        )
+    };
+    if new_addresses.pending_since.is_none()
+        || !(force || new_addresses.should_flush(now, flush_interval))
+    {
+        return Ok(());
+    }
+
+    import_new_addresses(&bitcoind.read().unwrap(), new_addresses, observer_mode)
+}
+
+// Whoever asked for a forced poll may have given up waiting, that's not a reason to stop polling.
+fn send_sync_result(
+    resp_tx: SyncSender<Result<usize, BitcoindError>>,
+    result: Result<usize, BitcoindError>,
+) {
+    if let Err(e) = resp_tx.send(result) {
+        log::error!("Sending sync result to main thread: {}", e);
+    }
+}
+
 pub fn poller_main(
     mut revaultd: Arc<RwLock<RevaultD>>,
     bitcoind: Arc<RwLock<BitcoinD>>,
     sync_progress: Arc<RwLock<f64>>,
     shutdown: Arc<AtomicBool>,
+    forcesync_rx: Receiver<SyncSender<Result<usize, BitcoindError>>>,
 ) -> Result<(), BitcoindError> {
     let mut last_poll = None;
     let mut sync_waittime = None;
@@ -1633,13 +2561,26 @@ pub fn poller_main(
     let mut deposits_cache = populate_deposit_cache(&revaultd.read().unwrap())?;
     // Same for the unvaults
     let mut unvaults_cache = populate_unvaults_cache(&revaultd.read().unwrap())?;
-    // When bitcoind is synced, we poll each 30s. On regtest we speed it up for testing.
-    let poll_interval = revaultd.read().unwrap().bitcoind_config.poll_interval_secs;
+    let mut last_rebroadcast: Option<Instant> = None;
+    // The addresses derived for new deposits that we didn't import yet
+    let mut new_addresses = NewAddresses::default();
+    // The vaults for which we made sure the unvault descriptor is imported, if we do it lazily
+    let mut imported_unvaults = HashSet::new();
 
     while !shutdown.load(Ordering::Relaxed) {
         let now = Instant::now();
+        // Someone may have asked us to poll right away
+        let forcesync_resp = forcesync_rx.try_recv().ok();
 
         if (*sync_progress.read().unwrap() as u32) < 1 {
+            if let Some(resp_tx) = forcesync_resp {
+                send_sync_result(
+                    resp_tx,
+                    Err(BitcoindError::Custom(
+                        "bitcoind is still synchronizing".to_string(),
+                    )),
+                );
+            }
             update_sync_status(
                 &revaultd,
                 &bitcoind,
@@ -1651,28 +2592,115 @@ pub fn poller_main(
             continue;
         }
 
-        if let Some(last_poll) = last_poll {
+        if let (Some(last_poll), None) = (last_poll, &forcesync_resp) {
+            // When bitcoind is synced, we poll each 30s. On regtest we speed it up for testing.
+            // Read it each time as it may be changed by a configuration reload.
+            let poll_interval = revaultd.read().unwrap().bitcoind_config.poll_interval_secs;
             if now.duration_since(last_poll) < poll_interval {
+                maybe_import_new_addresses(&revaultd, &bitcoind, &mut new_addresses, now, false)?;
                 thread::sleep(Duration::from_millis(500));
                 continue;
             }
         }
 
         last_poll = Some(now);
+        // A forced poll must not rely on a cached confirmation status
+        if forcesync_resp.is_some() {
+            bitcoind.read().unwrap().invalidate_wallet_tx_cache();
+        }
+        // The tip and all our utxos in a single round-trip
+        let mut snapshot = bitcoind.read().unwrap().wallet_snapshot()?;
         let previous_tip = update_tip(
             &mut revaultd,
             &bitcoind.read().unwrap(),
+            &mut snapshot,
             &mut deposits_cache,
             &mut unvaults_cache,
         )?;
-        update_utxos(
+        let (lazy_unvault_import, observer_mode) = {
+            let bitcoind_config = &revaultd.read().unwrap().bitcoind_config;
+            (
+                bitcoind_config.lazy_unvault_import,
+                bitcoind_config.observer_mode,
+            )
+        };
+        if lazy_unvault_import
+            && !observer_mode
+            && import_secured_unvault_descriptors(
+                &revaultd.read().unwrap(),
+                &bitcoind.read().unwrap(),
+                &mut imported_unvaults,
+            )?
+        {
+            // Don't wait for the next poll to notice the Unvaults we just started watching. A
+            // block may have been connected in the meantime, process it before the utxos.
+            snapshot = bitcoind.read().unwrap().wallet_snapshot()?;
+            update_tip(
+                &mut revaultd,
+                &bitcoind.read().unwrap(),
+                &mut snapshot,
+                &mut deposits_cache,
+                &mut unvaults_cache,
+            )?;
+        }
+        let tip = snapshot.tip;
+        let n_changes = update_utxos(
             &mut revaultd,
             &bitcoind.read().unwrap(),
+            snapshot,
             &mut deposits_cache,
             &mut unvaults_cache,
+            &mut new_addresses,
             &previous_tip,
         )?;
+        // Only once we processed everything that happened up to it
+        if tip != previous_tip {
+            revaultd
+                .read()
+                .unwrap()
+                .events
+                .publish(BitcoindEvent::NewTip(tip));
+        }
+        // Whoever forced the poll expects us to be up to date when we reply
+        maybe_import_new_addresses(
+            &revaultd,
+            &bitcoind,
+            &mut new_addresses,
+            now,
+            forcesync_resp.is_some(),
+        )?;
+        if let Some(resp_tx) = forcesync_resp {
+            log::debug!("Forced poll done, processed {} utxo changes", n_changes);
+            send_sync_result(resp_tx, Ok(n_changes));
+        }
+
+        // Like any other broadcast, they are paused during maintenance. As for the poll
+        // interval, read it each time as it may be changed by a configuration reload.
+        let rebroadcast_interval = revaultd
+            .read()
+            .unwrap()
+            .bitcoind_config
+            .rebroadcast_interval_secs;
+        if !revaultd.read().unwrap().is_maintenance()
+            && last_rebroadcast
+                .map(|last| now.duration_since(last) >= rebroadcast_interval)
+                .unwrap_or(true)
+        {
+            last_rebroadcast = Some(now);
+            // Not being able to rebroadcast them this time must not stop us from polling, we'll
+            // retry at the next interval.
+            if let Err(e) = rebroadcast_revocation_txs(&revaultd, &bitcoind.read().unwrap()) {
+                log::error!("Error re-broadcasting revocation transactions: '{}'", e);
+            }
+        }
     }
 
-    Ok(())
+    // Don't lose the addresses we derived but didn't import yet
+    maybe_import_new_addresses(
+        &revaultd,
+        &bitcoind,
+        &mut new_addresses,
+        Instant::now(),
+        true,
+    )
 }
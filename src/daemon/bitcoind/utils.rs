@@ -114,7 +114,7 @@ pub fn populate_deposit_cache(
 pub fn populate_unvaults_cache(
     revaultd: &RevaultD,
 ) -> Result<HashMap<OutPoint, UtxoInfo>, BitcoindError> {
-    let db_unvaults = db_unvaulted_vaults(&revaultd.db_file())?;
+    let db_unvaults = db_unvaulted_vaults(&revaultd.db_file(), revaultd.db_key.as_ref())?;
     let mut cache = HashMap::with_capacity(db_unvaults.len());
 
     for (db_vault, unvault_tx) in db_unvaults.into_iter() {
@@ -146,11 +146,13 @@ pub fn unvault_txin_from_deposit(
 ) -> Result<UnvaultTxIn, BitcoindError> {
     let revaultd = revaultd.read().unwrap();
     let db_path = revaultd.db_file();
-    let db_vault = db_vault_by_deposit(&db_path, &deposit_outpoint)?
+    let db_vault = db_vault_by_deposit(&db_path, deposit_outpoint)?
         .expect("Checking Unvault txid for an unknow deposit");
     let unvault_descriptor = revaultd.derived_unvault_descriptor(db_vault.derivation_index);
 
-    let unvault_tx = if let Some(tx) = db_unvault_from_deposit(&db_path, &deposit_outpoint)? {
+    let unvault_tx = if let Some(tx) =
+        db_unvault_from_deposit(&db_path, revaultd.db_key.as_ref(), deposit_outpoint)?
+    {
         tx
     } else {
         let deposit_descriptor = revaultd.derived_deposit_descriptor(db_vault.derivation_index);
@@ -183,7 +185,9 @@ pub fn cancel_txid(
     let revaultd = revaultd.read().unwrap();
     let db_path = revaultd.db_file();
 
-    let cancel_tx = if let Some((_, db_tx)) = db_cancel_transaction(&db_path, db_vault.id)? {
+    let cancel_tx = if let Some((_, db_tx)) =
+        db_cancel_transaction(&db_path, revaultd.db_key.as_ref(), db_vault.id)?
+    {
         db_tx
     } else {
         let (_, cancel_tx) = transaction_chain_manager(
@@ -211,26 +215,27 @@ pub fn unemer_txid(
     let db_path = revaultd.db_file();
 
     if revaultd.is_stakeholder() {
-        let unemer_tx =
-            if let Some((_, db_tx)) = db_unvault_emer_transaction(&db_path, db_vault.id)? {
-                db_tx
-            } else {
-                let (_, _, _, unemer_tx) = transaction_chain(
-                    db_vault.deposit_outpoint,
-                    db_vault.amount,
-                    &revaultd.deposit_descriptor,
-                    &revaultd.unvault_descriptor,
-                    &revaultd.cpfp_descriptor,
-                    db_vault.derivation_index,
-                    revaultd
-                        .emergency_address
-                        .clone()
-                        .expect("Just checked we were a stakeholder"),
-                    revaultd.lock_time,
-                    &revaultd.secp_ctx,
-                )?;
-                unemer_tx
-            };
+        let unemer_tx = if let Some((_, db_tx)) =
+            db_unvault_emer_transaction(&db_path, revaultd.db_key.as_ref(), db_vault.id)?
+        {
+            db_tx
+        } else {
+            let (_, _, _, unemer_tx) = transaction_chain(
+                db_vault.deposit_outpoint,
+                db_vault.amount,
+                &revaultd.deposit_descriptor,
+                &revaultd.unvault_descriptor,
+                &revaultd.cpfp_descriptor,
+                db_vault.derivation_index,
+                revaultd
+                    .emergency_address
+                    .clone()
+                    .expect("Just checked we were a stakeholder"),
+                revaultd.lock_time,
+                &revaultd.secp_ctx,
+            )?;
+            unemer_tx
+        };
 
         return Ok(Some(unemer_tx.txid()));
     }
@@ -247,7 +252,9 @@ pub fn emer_txid(
     let db_path = revaultd.db_file();
 
     if revaultd.is_stakeholder() {
-        let unemer_tx = if let Some((_, db_tx)) = db_emer_transaction(&db_path, db_vault.id)? {
+        let unemer_tx = if let Some((_, db_tx)) =
+            db_emer_transaction(&db_path, revaultd.db_key.as_ref(), db_vault.id)?
+        {
             db_tx
         } else {
             let (_, _, emer_tx, _) = transaction_chain(
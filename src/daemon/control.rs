@@ -3,11 +3,15 @@
 //! fetcher thread.
 
 use crate::{
-    bitcoind::BitcoindError,
+    bitcoind::{
+        interface::{utxos_label, LabeledUtxo},
+        BitcoindError,
+    },
     database::{
         interface::{
             db_cancel_transaction, db_emer_transaction, db_signed_emer_txs, db_signed_unemer_txs,
-            db_unvault_emer_transaction, db_unvault_transaction, db_vault_by_deposit, db_vaults,
+            db_unvault_emer_transaction, db_unvault_transaction, db_vault_by_deposit,
+            db_vault_labels, db_vaults,
         },
         schema::DbVault,
         DatabaseError,
@@ -30,24 +34,25 @@ use revault_tx::{
         hashes::hex::ToHex,
         secp256k1::{self, Signature},
         util::bip32::ChildNumber,
-        Address, Amount, OutPoint, PublicKey as BitcoinPubKey, SigHashType,
+        Address, Amount, BlockHash, OutPoint, PublicKey as BitcoinPubKey, SigHashType,
         Transaction as BitcoinTransaction, Txid,
     },
     miniscript::{descriptor::DescriptorPublicKey, DescriptorTrait},
     transactions::{
-        CancelTransaction, EmergencyTransaction, RevaultTransaction, SpendTransaction,
-        UnvaultEmergencyTransaction, UnvaultTransaction,
+        transaction_chain_manager, CancelTransaction, EmergencyTransaction, RevaultTransaction,
+        SpendTransaction, UnvaultEmergencyTransaction, UnvaultTransaction,
     },
 };
 
 use std::{
     collections::{BTreeMap, HashMap},
-    fmt, process,
+    fmt,
+    ops::RangeInclusive,
+    process,
     sync::{
         mpsc::{self, RecvError, SendError, Sender},
         Arc, RwLock,
     },
-    thread::JoinHandle,
 };
 
 use serde::{Deserialize, Serialize, Serializer};
@@ -58,6 +63,44 @@ pub struct VaultPresignedTransaction<T: RevaultTransaction> {
     pub psbt: T,
     #[serde(rename(serialize = "hex"), serialize_with = "serialize_option_tx_hex")]
     pub transaction: Option<BitcoinTransaction>,
+    /// Whether each stakeholder signed it, by master fingerprint of their xpub
+    pub signatures: BTreeMap<String, bool>,
+    /// Whether all the stakeholders signed it
+    pub is_signed: bool,
+}
+
+impl<T: RevaultTransaction + Clone> VaultPresignedTransaction<T> {
+    // All the presigned transactions spend a deposit or an Unvault output as their first input,
+    // which require the signature of all the stakeholders.
+    fn new(revaultd: &RevaultD, psbt: T, derivation_index: ChildNumber) -> Self {
+        let partial_sigs = &psbt.psbt().inputs[0].partial_sigs;
+        let signatures: BTreeMap<String, bool> = revaultd
+            .stakeholders_xpubs()
+            .iter()
+            .zip(revaultd.stakeholders_xpubs_at(derivation_index))
+            .map(|(xpub, pubkey)| {
+                (
+                    xpub.master_fingerprint().to_string(),
+                    partial_sigs.contains_key(&pubkey),
+                )
+            })
+            .collect();
+        let is_signed = signatures.values().all(|signed| *signed);
+
+        let mut finalized = psbt.clone();
+        let transaction = if finalized.finalize(&revaultd.secp_ctx).is_ok() {
+            Some(finalized.into_psbt().extract_tx())
+        } else {
+            None
+        };
+
+        VaultPresignedTransaction {
+            psbt,
+            transaction,
+            signatures,
+            is_signed,
+        }
+    }
 }
 
 /// Contains the presigned transactions (Unvault, Cancel, Emergency, UnvaultEmergency)
@@ -105,6 +148,10 @@ pub struct ListVaultsEntry {
     pub address: Address,
     pub received_at: u32,
     pub updated_at: u32,
+    pub excluded: bool,
+    /// The transaction that spent the Unvault, if it was spent by a Spend or an unknown one
+    pub spend_txid: Option<Txid>,
+    pub label: Option<String>,
 }
 
 fn serialize_tx_hex<S>(tx: &BitcoinTransaction, s: S) -> Result<S::Ok, S::Error>
@@ -134,6 +181,25 @@ pub enum ListSpendStatus {
     Broadcasted,
 }
 
+/// The field to sort the result of `listvaults` by
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ListVaultsSortKey {
+    Amount,
+    Status,
+    DerivationIndex,
+    UpdatedAt,
+}
+
+/// Where to get the result of `gethistory` from: a timestamp, or the cursor returned by a
+/// previous call to resume after its last event
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum HistoryStart {
+    Timestamp(u32),
+    Cursor(String),
+}
+
 #[derive(Debug, Serialize)]
 pub struct ServerStatus {
     pub host: String,
@@ -146,7 +212,7 @@ pub enum RpcControlError {
     InvalidStatus(VaultStatus, OutPoint),
     UnknownOutPoint(OutPoint),
     Database(DatabaseError),
-    Tx(revault_tx::Error),
+    Tx(Box<revault_tx::Error>),
     Bitcoind(BitcoindError),
     ThreadCommunication(String),
 }
@@ -159,7 +225,7 @@ impl From<DatabaseError> for RpcControlError {
 
 impl From<revault_tx::Error> for RpcControlError {
     fn from(e: revault_tx::Error) -> Self {
-        Self::Tx(e)
+        Self::Tx(Box::new(e))
     }
 }
 
@@ -198,16 +264,16 @@ impl fmt::Display for RpcControlError {
     }
 }
 
-// Ask bitcoind for a wallet transaction
-fn bitcoind_wallet_tx(
+// Ask bitcoind for these wallet transactions, all at once
+fn bitcoind_wallet_txs(
     bitcoind_tx: &Sender<BitcoindMessageOut>,
-    txid: Txid,
-) -> Result<Option<WalletTransaction>, RpcControlError> {
-    log::trace!("Sending WalletTx to bitcoind thread for {}", txid);
+    txids: Vec<Txid>,
+) -> Result<Vec<Option<WalletTransaction>>, RpcControlError> {
+    log::trace!("Sending WalletTxs to bitcoind thread for {:?}", txids);
 
     let (bitrep_tx, bitrep_rx) = mpsc::sync_channel(0);
-    bitcoind_tx.send(BitcoindMessageOut::WalletTransaction(txid, bitrep_tx))?;
-    bitrep_rx.recv().map_err(|e| e.into())
+    bitcoind_tx.send(BitcoindMessageOut::WalletTransactions(txids, bitrep_tx))?;
+    bitrep_rx.recv()?.map_err(|e| e.into())
 }
 
 /// Have bitcoind broadcast all these transactions
@@ -230,6 +296,59 @@ pub fn bitcoind_broadcast(
     Ok(())
 }
 
+/// Get the utxos of the watchonly wallet that belong to this category
+pub fn bitcoind_labeled_utxos(
+    bitcoind_tx: &Sender<BitcoindMessageOut>,
+    category: &str,
+) -> Result<Vec<LabeledUtxo>, RpcControlError> {
+    let (bitrep_tx, bitrep_rx) = mpsc::sync_channel(0);
+    bitcoind_tx.send(BitcoindMessageOut::LabeledUtxos(
+        utxos_label(category),
+        bitrep_tx,
+    ))?;
+    bitrep_rx.recv()?.map_err(|e| e.into())
+}
+
+/// Have bitcoind poll right away, returns the number of utxo changes it processed
+pub fn bitcoind_force_sync(
+    bitcoind_tx: &Sender<BitcoindMessageOut>,
+) -> Result<usize, RpcControlError> {
+    let (bitrep_tx, bitrep_rx) = mpsc::sync_channel(0);
+    bitcoind_tx.send(BitcoindMessageOut::ForceSync(bitrep_tx))?;
+    bitrep_rx.recv()?.map_err(|e| e.into())
+}
+
+/// Get the minimum feerate for a transaction to be relayed, in sat/vB
+pub fn bitcoind_relay_feerate(
+    bitcoind_tx: &Sender<BitcoindMessageOut>,
+) -> Result<u64, RpcControlError> {
+    let (bitrep_tx, bitrep_rx) = mpsc::sync_channel(0);
+    bitcoind_tx.send(BitcoindMessageOut::RelayFeerate(bitrep_tx))?;
+    bitrep_rx.recv()?.map_err(|e| e.into())
+}
+
+/// Get a feerate estimate in sat/vB for this confirmation target, if bitcoind has one
+pub fn bitcoind_estimate_feerate(
+    bitcoind_tx: &Sender<BitcoindMessageOut>,
+    conf_target: u32,
+) -> Result<Option<u64>, RpcControlError> {
+    let (bitrep_tx, bitrep_rx) = mpsc::sync_channel(0);
+    bitcoind_tx.send(BitcoindMessageOut::EstimateFeerate(conf_target, bitrep_tx))?;
+    bitrep_rx.recv()?.map_err(|e| e.into())
+}
+
+/// Get the hash of the block at this height along with the merkle proof that this transaction
+/// was included in it
+pub fn bitcoind_txout_proof(
+    bitcoind_tx: &Sender<BitcoindMessageOut>,
+    txid: Txid,
+    height: u32,
+) -> Result<(BlockHash, String), RpcControlError> {
+    let (bitrep_tx, bitrep_rx) = mpsc::sync_channel(0);
+    bitcoind_tx.send(BitcoindMessageOut::TxOutProof(txid, height, bitrep_tx))?;
+    bitrep_rx.recv()?.map_err(|e| e.into())
+}
+
 /// List the vaults from DB, and filter out the info the RPC wants
 // FIXME: we could make this more efficient with smarter SQL queries
 pub fn listvaults_from_db(
@@ -237,6 +356,7 @@ pub fn listvaults_from_db(
     statuses: Option<Vec<VaultStatus>>,
     outpoints: Option<Vec<OutPoint>>,
 ) -> Result<Vec<ListVaultsEntry>, DatabaseError> {
+    let mut labels = db_vault_labels(&revaultd.db_file())?;
     db_vaults(&revaultd.db_file()).map(|db_vaults| {
         db_vaults
             .into_iter()
@@ -254,6 +374,14 @@ pub fn listvaults_from_db(
                 }
 
                 let address = revaultd.vault_address(db_vault.derivation_index);
+                // It is not reset if the Spend gets evicted from the mempool, only report it for
+                // the vaults that are currently spent.
+                let spend_txid = match db_vault.status {
+                    VaultStatus::Spending | VaultStatus::Spent | VaultStatus::UnexpectedSpend => {
+                        db_vault.spend_txid
+                    }
+                    _ => None,
+                };
                 Some(ListVaultsEntry {
                     amount: db_vault.amount,
                     blockheight: db_vault.blockheight,
@@ -262,20 +390,138 @@ pub fn listvaults_from_db(
                     derivation_index: db_vault.derivation_index,
                     received_at: db_vault.received_at,
                     updated_at: db_vault.updated_at,
+                    excluded: db_vault.excluded,
+                    spend_txid,
                     address,
+                    label: labels.remove(&db_vault.id),
                 })
             })
             .collect()
     })
 }
 
+/// The deposit outpoints of the vaults whose Unvault was spent by an unexpected transaction
+pub fn unexpected_spends(revaultd: &RevaultD) -> Result<Vec<OutPoint>, DatabaseError> {
+    listvaults_from_db(revaultd, Some(vec![VaultStatus::UnexpectedSpend]), None).map(|entries| {
+        entries
+            .into_iter()
+            .map(|entry| entry.deposit_outpoint)
+            .collect()
+    })
+}
+
+/// Sort a list of vaults by the given field. Ties are broken by deposit outpoint so that the
+/// order is stable across calls.
+pub fn sort_vaults(vaults: &mut [ListVaultsEntry], key: ListVaultsSortKey, descending: bool) {
+    vaults.sort_by(|a, b| {
+        let ordering = match key {
+            ListVaultsSortKey::Amount => a.amount.cmp(&b.amount),
+            ListVaultsSortKey::Status => (a.status as u32).cmp(&(b.status as u32)),
+            ListVaultsSortKey::DerivationIndex => {
+                u32::from(a.derivation_index).cmp(&u32::from(b.derivation_index))
+            }
+            ListVaultsSortKey::UpdatedAt => a.updated_at.cmp(&b.updated_at),
+        }
+        .then_with(|| a.deposit_outpoint.cmp(&b.deposit_outpoint));
+
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
+
+/// The (inclusive) bounds on the amount and derivation index of the vaults to list
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ListVaultsBounds {
+    pub min_amount: Option<Amount>,
+    pub max_amount: Option<Amount>,
+    pub min_derivation_index: Option<ChildNumber>,
+    pub max_derivation_index: Option<ChildNumber>,
+}
+
+fn in_range<T: PartialOrd>(value: T, min: Option<T>, max: Option<T>) -> bool {
+    !matches!(min, Some(min) if value < min) && !matches!(max, Some(max) if value > max)
+}
+
+/// The optional parameters of `listvaults` past the statuses and outpoints, given as a single
+/// object: which other vaults to list, in what order, and which page of them
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ListVaultsOptions {
+    pub sort_by: Option<ListVaultsSortKey>,
+    pub descending: Option<bool>,
+    pub include_excluded: Option<bool>,
+    pub min_amount: Option<u64>,
+    pub max_amount: Option<u64>,
+    pub min_derivation_index: Option<ChildNumber>,
+    pub max_derivation_index: Option<ChildNumber>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+    pub label: Option<String>,
+}
+
+impl ListVaultsOptions {
+    pub fn bounds(&self) -> ListVaultsBounds {
+        ListVaultsBounds {
+            min_amount: self.min_amount.map(Amount::from_sat),
+            max_amount: self.max_amount.map(Amount::from_sat),
+            min_derivation_index: self.min_derivation_index,
+            max_derivation_index: self.max_derivation_index,
+        }
+    }
+}
+
+impl ListVaultsBounds {
+    /// Whether a bound is both set and lower than the other one, in which case nothing matches
+    pub fn is_empty(&self) -> bool {
+        matches!((self.min_amount, self.max_amount), (Some(min), Some(max)) if min > max)
+            || matches!(
+                (self.min_derivation_index, self.max_derivation_index),
+                (Some(min), Some(max)) if u32::from(min) > u32::from(max)
+            )
+    }
+
+    pub fn contains(&self, entry: &ListVaultsEntry) -> bool {
+        in_range(entry.amount, self.min_amount, self.max_amount)
+            && in_range(
+                u32::from(entry.derivation_index),
+                self.min_derivation_index.map(u32::from),
+                self.max_derivation_index.map(u32::from),
+            )
+    }
+}
+
+/// Format a list of vaults as CSV, one vault per line after a header line
+pub fn vaults_csv(vaults: &[ListVaultsEntry]) -> String {
+    let mut csv =
+        String::from("outpoint,amount,status,derivation_index,address,received_at,updated_at\n");
+
+    for entry in vaults {
+        let derivation_index: u32 = entry.derivation_index.into();
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            entry.deposit_outpoint,
+            entry.amount.as_sat(),
+            entry.status,
+            derivation_index,
+            entry.address,
+            entry.received_at,
+            entry.updated_at,
+        ));
+    }
+
+    csv
+}
+
 /// Get all vaults from a list of deposit outpoints, if they are not in a given status.
 ///
 /// # Errors
 /// If an outpoint does not refer to a known deposit, or if the status of the vault is
 /// part of `invalid_statuses`.
 pub fn vaults_from_deposits(
-    db_path: &std::path::PathBuf,
+    db_path: &std::path::Path,
     outpoints: &[OutPoint],
     invalid_statuses: &[VaultStatus],
 ) -> Result<Vec<DbVault>, RpcControlError> {
@@ -284,7 +530,7 @@ pub fn vaults_from_deposits(
     for outpoint in outpoints.iter() {
         // Note: being smarter with SQL queries implies enabling the 'table' feature of rusqlite
         // with a shit ton of dependencies.
-        if let Some(vault) = db_vault_by_deposit(db_path, &outpoint)? {
+        if let Some(vault) = db_vault_by_deposit(db_path, outpoint)? {
             if invalid_statuses.contains(&vault.status) {
                 return Err(RpcControlError::InvalidStatus(vault.status, *outpoint));
             }
@@ -308,59 +554,32 @@ pub fn presigned_txs(
     let mut tx_list = Vec::with_capacity(db_vaults.len());
     for db_vault in db_vaults {
         let outpoint = db_vault.deposit_outpoint;
+        let index = db_vault.derivation_index;
 
-        let (_, unvault_psbt) = db_unvault_transaction(db_path, db_vault.id)?;
-        let mut finalized_unvault = unvault_psbt.clone();
-        let unvault = VaultPresignedTransaction {
-            transaction: if finalized_unvault.finalize(&revaultd.secp_ctx).is_ok() {
-                Some(finalized_unvault.into_psbt().extract_tx())
-            } else {
-                None
-            },
-            psbt: unvault_psbt,
-        };
+        let (_, unvault_psbt) =
+            db_unvault_transaction(db_path, revaultd.db_key.as_ref(), db_vault.id)?;
+        let unvault = VaultPresignedTransaction::new(revaultd, unvault_psbt, index);
 
         // FIXME: this may not hold true in all cases, see https://github.com/revault/revaultd/issues/145
         let (_, cancel_psbt) =
-            db_cancel_transaction(db_path, db_vault.id)?.expect("Must be here post 'Funded' state");
-        let mut finalized_cancel = cancel_psbt.clone();
-        let cancel = VaultPresignedTransaction {
-            transaction: if finalized_cancel.finalize(&revaultd.secp_ctx).is_ok() {
-                Some(finalized_cancel.into_psbt().extract_tx())
-            } else {
-                None
-            },
-            psbt: cancel_psbt,
-        };
+            db_cancel_transaction(db_path, revaultd.db_key.as_ref(), db_vault.id)?
+                .expect("Must be here post 'Funded' state");
+        let cancel = VaultPresignedTransaction::new(revaultd, cancel_psbt, index);
 
         let mut emergency = None;
         let mut unvault_emergency = None;
         if revaultd.is_stakeholder() {
             // FIXME: this *might* not hold true in all cases, see https://github.com/revault/revaultd/issues/145
-            let (_, emer_psbt) = db_emer_transaction(db_path, db_vault.id)?
-                .expect("Must be here post 'Funded' state");
-            let mut finalized_emer = emer_psbt.clone();
-            emergency = Some(VaultPresignedTransaction {
-                transaction: if finalized_emer.finalize(&revaultd.secp_ctx).is_ok() {
-                    Some(finalized_emer.into_psbt().extract_tx())
-                } else {
-                    None
-                },
-                psbt: emer_psbt,
-            });
+            let (_, emer_psbt) =
+                db_emer_transaction(db_path, revaultd.db_key.as_ref(), db_vault.id)?
+                    .expect("Must be here post 'Funded' state");
+            emergency = Some(VaultPresignedTransaction::new(revaultd, emer_psbt, index));
 
             // FIXME: this *might* not hold true in all cases, see https://github.com/revault/revaultd/issues/145
-            let (_, unemer_psbt) = db_unvault_emer_transaction(db_path, db_vault.id)?
-                .expect("Must be here post 'Funded' state");
-            let mut finalized_unemer = unemer_psbt.clone();
-            unvault_emergency = Some(VaultPresignedTransaction {
-                transaction: if finalized_unemer.finalize(&revaultd.secp_ctx).is_ok() {
-                    Some(finalized_unemer.into_psbt().extract_tx())
-                } else {
-                    None
-                },
-                psbt: unemer_psbt,
-            });
+            let (_, unemer_psbt) =
+                db_unvault_emer_transaction(db_path, revaultd.db_key.as_ref(), db_vault.id)?
+                    .expect("Must be here post 'Funded' state");
+            unvault_emergency = Some(VaultPresignedTransaction::new(revaultd, unemer_psbt, index));
         }
 
         tx_list.push(VaultPresignedTransactions {
@@ -375,79 +594,104 @@ pub fn presigned_txs(
     Ok(tx_list)
 }
 
-/// List all the onchain transactions from these vaults.
+// The txids of the transactions that may have been broadcast for a vault, in this order:
+// Deposit, Unvault, Cancel, Emergency, UnvaultEmergency and Spend.
+fn vault_onchain_txids(
+    revaultd: &RevaultD,
+    db_vault: &DbVault,
+) -> Result<[Option<Txid>; 6], RpcControlError> {
+    let db_path = &revaultd.db_file();
+    let deposit = Some(db_vault.deposit_outpoint.txid);
+
+    // For the other transactions, it depends on the status of the vault. For the sake of
+    // simplicity bitcoind will tell us (but we could have some optimisation eventually here,
+    // eg returning None early on Funded vaults).
+    // We allow the unconfirmed status, for which we don't have any presigned tx in db! Nor
+    // could any of them be broadcast for an aborted vault, its deposit was spent by another one.
+    if matches!(
+        db_vault.status,
+        VaultStatus::Unconfirmed | VaultStatus::Aborted
+    ) {
+        return Ok([deposit, None, None, None, None, None]);
+    }
+
+    let (_, unvault) = db_unvault_transaction(db_path, revaultd.db_key.as_ref(), db_vault.id)?;
+    let unvault = Some(unvault.into_psbt().extract_tx().txid());
+    // FIXME: this may not hold true in all cases, see https://github.com/revault/revaultd/issues/145
+    let (_, cancel) = db_cancel_transaction(db_path, revaultd.db_key.as_ref(), db_vault.id)?
+        .expect("Must be here if not 'unconfirmed'");
+    let cancel = Some(cancel.into_psbt().extract_tx().txid());
+
+    // Emergencies are only for stakeholders!
+    let mut emergency = None;
+    let mut unvault_emergency = None;
+    if revaultd.is_stakeholder() {
+        // FIXME: this *might* not hold true in all cases, see https://github.com/revault/revaultd/issues/145
+        let emer = db_emer_transaction(db_path, revaultd.db_key.as_ref(), db_vault.id)?
+            .expect("Must be here post 'Funded' state")
+            .1;
+        emergency = Some(emer.into_psbt().extract_tx().txid());
+
+        // FIXME: this *might* not hold true in all cases, see https://github.com/revault/revaultd/issues/145
+        let unemer = db_unvault_emer_transaction(db_path, revaultd.db_key.as_ref(), db_vault.id)?
+            .expect("Must be here if not 'unconfirmed'")
+            .1;
+        unvault_emergency = Some(unemer.into_psbt().extract_tx().txid());
+    }
+
+    Ok([
+        deposit,
+        unvault,
+        cancel,
+        emergency,
+        unvault_emergency,
+        db_vault.spend_txid,
+    ])
+}
+
+/// List all the onchain transactions from these vaults. The transactions of all the vaults are
+/// fetched from bitcoind in a single batch request, so callers may want to split large lists of
+/// vaults in chunks.
 pub fn onchain_txs(
     revaultd: &RevaultD,
     bitcoind_tx: &Sender<BitcoindMessageOut>,
     db_vaults: Vec<DbVault>,
 ) -> Result<Vec<VaultOnchainTransactions>, RpcControlError> {
-    let db_path = &revaultd.db_file();
-
-    let mut tx_list = Vec::with_capacity(db_vaults.len());
-    for db_vault in db_vaults {
-        let outpoint = db_vault.deposit_outpoint;
-
-        // If the vault exist, there must always be a deposit transaction available.
-        let deposit = bitcoind_wallet_tx(bitcoind_tx, db_vault.deposit_outpoint.txid)?
-            .expect("Vault exists but not deposit tx?");
-
-        // For the other transactions, it depends on the status of the vault. For the sake of
-        // simplicity bitcoind will tell us (but we could have some optimisation eventually here,
-        // eg returning None early on Funded vaults).
-        let (unvault, cancel, emergency, unvault_emergency, spend) = match db_vault.status {
-            // We allow the unconfirmed status, for which we don't have any presigned tx in db!
-            VaultStatus::Unconfirmed => (None, None, None, None, None),
-            _ => {
-                let (_, unvault) = db_unvault_transaction(db_path, db_vault.id)?;
-                let unvault =
-                    bitcoind_wallet_tx(bitcoind_tx, unvault.into_psbt().extract_tx().txid())?;
-                // FIXME: this may not hold true in all cases, see https://github.com/revault/revaultd/issues/145
-                let (_, cancel) = db_cancel_transaction(db_path, db_vault.id)?
-                    .expect("Must be here if not 'unconfirmed'");
-                let cancel =
-                    bitcoind_wallet_tx(bitcoind_tx, cancel.into_psbt().extract_tx().txid())?;
-
-                // Emergencies are only for stakeholders!
-                let mut emergency = None;
-                let mut unvault_emergency = None;
-                if revaultd.is_stakeholder() {
-                    // FIXME: this *might* not hold true in all cases, see https://github.com/revault/revaultd/issues/145
-                    let emer = db_emer_transaction(db_path, db_vault.id)?
-                        .expect("Must be here post 'Funded' state")
-                        .1;
-                    emergency =
-                        bitcoind_wallet_tx(bitcoind_tx, emer.into_psbt().extract_tx().txid())?;
-
-                    // FIXME: this *might* not hold true in all cases, see https://github.com/revault/revaultd/issues/145
-                    let unemer = db_unvault_emer_transaction(db_path, db_vault.id)?
-                        .expect("Must be here if not 'unconfirmed'")
-                        .1;
-                    unvault_emergency =
-                        bitcoind_wallet_tx(bitcoind_tx, unemer.into_psbt().extract_tx().txid())?;
+    let vaults_txids = db_vaults
+        .iter()
+        .map(|db_vault| vault_onchain_txids(revaultd, db_vault))
+        .collect::<Result<Vec<[Option<Txid>; 6]>, RpcControlError>>()?;
+
+    // Several vaults may share the same Spend transaction, only ask for it once.
+    let mut txids: Vec<Txid> = vaults_txids.iter().flatten().flatten().copied().collect();
+    txids.sort_unstable();
+    txids.dedup();
+    let wallet_txs: HashMap<Txid, WalletTransaction> = txids
+        .iter()
+        .copied()
+        .zip(bitcoind_wallet_txs(bitcoind_tx, txids.clone())?)
+        .filter_map(|(txid, wallet_tx)| wallet_tx.map(|tx| (txid, tx)))
+        .collect();
+    let wallet_tx = |txid: Option<Txid>| txid.and_then(|txid| wallet_txs.get(&txid).cloned());
+
+    Ok(db_vaults
+        .into_iter()
+        .zip(vaults_txids)
+        .map(
+            |(db_vault, [deposit, unvault, cancel, emergency, unvault_emergency, spend])| {
+                VaultOnchainTransactions {
+                    outpoint: db_vault.deposit_outpoint,
+                    // If the vault exist, there must always be a deposit transaction available.
+                    deposit: wallet_tx(deposit).expect("Vault exists but not deposit tx?"),
+                    unvault: wallet_tx(unvault),
+                    cancel: wallet_tx(cancel),
+                    emergency: wallet_tx(emergency),
+                    unvault_emergency: wallet_tx(unvault_emergency),
+                    spend: wallet_tx(spend),
                 }
-
-                let spend = if let Some(spend_txid) = db_vault.spend_txid {
-                    bitcoind_wallet_tx(bitcoind_tx, spend_txid)?
-                } else {
-                    None
-                };
-
-                (unvault, cancel, emergency, unvault_emergency, spend)
-            }
-        };
-
-        tx_list.push(VaultOnchainTransactions {
-            outpoint,
-            deposit,
-            unvault,
-            cancel,
-            emergency,
-            unvault_emergency,
-            spend,
-        });
-    }
-
-    Ok(tx_list)
+            },
+        )
+        .collect())
 }
 
 /// Get all the finalized Emergency transactions for each vault, depending on wether the Unvault
@@ -455,19 +699,68 @@ pub fn onchain_txs(
 pub fn finalized_emer_txs(revaultd: &RevaultD) -> Result<Vec<BitcoinTransaction>, RpcControlError> {
     let db_path = revaultd.db_file();
 
-    let emer_iter = db_signed_emer_txs(&db_path)?.into_iter().map(|mut tx| {
-        tx.finalize(&revaultd.secp_ctx)?;
-        Ok(tx.into_psbt().extract_tx())
-    });
-    let unemer_iter = db_signed_unemer_txs(&db_path)?.into_iter().map(|mut tx| {
-        tx.finalize(&revaultd.secp_ctx)?;
-        Ok(tx.into_psbt().extract_tx())
-    });
+    let emer_iter = db_signed_emer_txs(&db_path, revaultd.db_key.as_ref())?
+        .into_iter()
+        .map(|mut tx| {
+            tx.finalize(&revaultd.secp_ctx)?;
+            Ok(tx.into_psbt().extract_tx())
+        });
+    let unemer_iter = db_signed_unemer_txs(&db_path, revaultd.db_key.as_ref())?
+        .into_iter()
+        .map(|mut tx| {
+            tx.finalize(&revaultd.secp_ctx)?;
+            Ok(tx.into_psbt().extract_tx())
+        });
 
     emer_iter
         .chain(unemer_iter)
-        .collect::<Result<Vec<BitcoinTransaction>, revault_tx::Error>>()
-        .map_err(|e| e.into())
+        .collect::<Result<Vec<BitcoinTransaction>, RpcControlError>>()
+}
+
+/// The fees it would take, at most, to bring the presigned transactions of the vaults we may have
+/// to Unvault or Cancel to this feerate. Returns the number of these vaults along with the total
+/// amount of fees missing, in sats.
+pub fn fee_reserve(revaultd: &RevaultD, feerate_vb: u64) -> Result<(usize, u64), DatabaseError> {
+    let db_path = revaultd.db_file();
+    let deposit_satisfaction_weight = revaultd.deposit_satisfaction_weight();
+    let unvault_satisfaction_weight = revaultd.unvault_satisfaction_weight();
+    let missing_fees = |tx: &BitcoinTransaction, fees: u64, satisfaction_weight: usize| {
+        (presigned_tx_vbytes(tx, satisfaction_weight) * feerate_vb).saturating_sub(fees)
+    };
+
+    let (mut n_vaults, mut reserve) = (0, 0);
+    for vault in db_vaults(&db_path)? {
+        if vault.excluded {
+            continue;
+        }
+        let unvault_pending = match vault.status {
+            VaultStatus::Active | VaultStatus::Unvaulting => true,
+            VaultStatus::Unvaulted => false,
+            _ => continue,
+        };
+
+        if unvault_pending {
+            let (_, unvault_tx) =
+                db_unvault_transaction(&db_path, revaultd.db_key.as_ref(), vault.id)?;
+            reserve += missing_fees(
+                unvault_tx.tx(),
+                unvault_tx.fees(),
+                deposit_satisfaction_weight,
+            );
+        }
+        if let Some((_, cancel_tx)) =
+            db_cancel_transaction(&db_path, revaultd.db_key.as_ref(), vault.id)?
+        {
+            reserve += missing_fees(
+                cancel_tx.tx(),
+                cancel_tx.fees(),
+                unvault_satisfaction_weight,
+            );
+        }
+        n_vaults += 1;
+    }
+
+    Ok((n_vaults, reserve))
 }
 
 /// An error thrown when the verification of a signature fails
@@ -477,9 +770,11 @@ pub enum SigError {
     InvalidSighash,
     VerifError(secp256k1::Error),
     NotEnoughSignatures(usize, usize),
+    /// The signature was made by a key which isn't expected to sign this transaction
+    UnknownKey(BitcoinPubKey),
     /// Transaction for which we check the sigs does not pass sanity checks
     InsaneTransaction,
-    Tx(revault_tx::Error),
+    Tx(Box<revault_tx::Error>),
 }
 
 impl std::fmt::Display for SigError {
@@ -495,6 +790,7 @@ impl std::fmt::Display for SigError {
                     needed, current
                 )
             }
+            Self::UnknownKey(pk) => write!(f, "Signature for unknown key '{}'", pk),
             Self::InsaneTransaction => write!(f, "Insane transaction"),
             Self::Tx(e) => write!(f, "Error in transaction management: '{}'", e),
         }
@@ -509,6 +805,110 @@ impl From<secp256k1::Error> for SigError {
     }
 }
 
+// Check that all the signatures were made by one of the expected keys
+fn check_signers(
+    sigs: &BTreeMap<BitcoinPubKey, Vec<u8>>,
+    expected_keys: &[BitcoinPubKey],
+) -> Result<(), SigError> {
+    if let Some(pubkey) = sigs.keys().find(|pk| !expected_keys.contains(pk)) {
+        return Err(SigError::UnknownKey(*pubkey));
+    }
+
+    Ok(())
+}
+
+// Check the presigned transactions of a vault as stored in database. The Unvault and Cancel must
+// be the ones we derive from our descriptors, and all the signatures must be valid.
+fn check_stored_presigned_txs(revaultd: &RevaultD, db_vault: &DbVault) -> Result<(), String> {
+    let db_path = revaultd.db_file();
+    let secp = &revaultd.secp_ctx;
+    // Only the stakeholders sign the presigned transactions
+    let stk_keys = revaultd.stakeholders_xpubs_at(db_vault.derivation_index);
+
+    let (unvault_tx, cancel_tx) = transaction_chain_manager(
+        db_vault.deposit_outpoint,
+        db_vault.amount,
+        &revaultd.deposit_descriptor,
+        &revaultd.unvault_descriptor,
+        &revaultd.cpfp_descriptor,
+        db_vault.derivation_index,
+        revaultd.lock_time,
+        secp,
+    )
+    .map_err(|e| format!("Deriving transactions: {}", e))?;
+
+    let (_, db_unvault) = db_unvault_transaction(&db_path, revaultd.db_key.as_ref(), db_vault.id)
+        .map_err(|e| e.to_string())?;
+    if db_unvault.txid() != unvault_tx.txid() {
+        return Err("Unvault transaction doesn't match our descriptors".to_string());
+    }
+    check_signers(&db_unvault.psbt().inputs[0].partial_sigs, &stk_keys)
+        .and_then(|_| check_unvault_signatures(secp, &db_unvault))
+        .map_err(|e| format!("Unvault: {}", e))?;
+
+    if let Some((_, db_cancel)) =
+        db_cancel_transaction(&db_path, revaultd.db_key.as_ref(), db_vault.id)
+            .map_err(|e| e.to_string())?
+    {
+        if db_cancel.txid() != cancel_tx.txid() {
+            return Err("Cancel transaction doesn't match our descriptors".to_string());
+        }
+        let sigs = &db_cancel.psbt().inputs[0].partial_sigs;
+        check_signers(sigs, &stk_keys)
+            .and_then(|_| check_revocation_signatures(secp, &db_cancel, sigs))
+            .map_err(|e| format!("Cancel: {}", e))?;
+    }
+    // The Emergency transactions pay to the Emergency address in use when the vault was
+    // created, which may not be the current one. Only check their signatures.
+    if let Some((_, db_emer)) = db_emer_transaction(&db_path, revaultd.db_key.as_ref(), db_vault.id)
+        .map_err(|e| e.to_string())?
+    {
+        let sigs = &db_emer.psbt().inputs[0].partial_sigs;
+        check_signers(sigs, &stk_keys)
+            .and_then(|_| check_revocation_signatures(secp, &db_emer, sigs))
+            .map_err(|e| format!("Emergency: {}", e))?;
+    }
+    if let Some((_, db_unemer)) =
+        db_unvault_emer_transaction(&db_path, revaultd.db_key.as_ref(), db_vault.id)
+            .map_err(|e| e.to_string())?
+    {
+        let sigs = &db_unemer.psbt().inputs[0].partial_sigs;
+        check_signers(sigs, &stk_keys)
+            .and_then(|_| check_revocation_signatures(secp, &db_unemer, sigs))
+            .map_err(|e| format!("Unvault Emergency: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// Check the presigned transactions of all the vaults in database, and the signatures they
+/// contain. The deposit outpoints of those that failed are recorded in `invalid_signatures`.
+/// Returns the number of vaults checked.
+pub fn verify_stored_signatures(revaultd: &mut RevaultD) -> Result<usize, DatabaseError> {
+    let mut checked = 0;
+    let mut invalid = Vec::new();
+
+    for db_vault in db_vaults(&revaultd.db_file())? {
+        // The presigned transactions are only stored once the deposit is confirmed
+        if matches!(db_vault.status, VaultStatus::Unconfirmed) {
+            continue;
+        }
+
+        checked += 1;
+        if let Err(e) = check_stored_presigned_txs(revaultd, &db_vault) {
+            log::error!(
+                "Presigned transactions of vault at '{}' failed verification: {}",
+                db_vault.deposit_outpoint,
+                e
+            );
+            invalid.push(db_vault.deposit_outpoint);
+        }
+    }
+    revaultd.invalid_signatures = invalid;
+
+    Ok(checked)
+}
+
 /// The signature hash of a presigned transaction (ie Unvault, Cancel, Emergency, or
 /// UnvaultEmergency)
 ///
@@ -535,10 +935,32 @@ pub fn presigned_tx_sighash(
 
     let sighash = tx
         .signature_hash(0, hashtype)
-        .map_err(|e| SigError::Tx(e.into()))?;
+        .map_err(|e| SigError::Tx(Box::new(e.into())))?;
     Ok(secp256k1::Message::from_slice(&sighash).expect("sighash is a 32 bytes hash"))
 }
 
+// The virtual size of a presigned transaction once its (single) input is satisfied
+fn presigned_tx_vbytes(tx: &BitcoinTransaction, satisfaction_weight: usize) -> u64 {
+    ((tx.get_weight() + satisfaction_weight + 3) / 4) as u64
+}
+
+/// Check the feerate, in sat/vB, of a revocation transaction (ie Cancel, Emergency, or
+/// UnvaultEmergency) spending an input with this maximum satisfaction weight is within the given
+/// range. Returns the feerate if it is not.
+pub fn check_revocation_feerate(
+    tx: &impl RevaultTransaction,
+    satisfaction_weight: usize,
+    feerate_range: &RangeInclusive<u64>,
+) -> Result<(), u64> {
+    let weight = (tx.tx().get_weight() + satisfaction_weight) as u64;
+    let feerate = tx.fees() * 4 / weight;
+    if !feerate_range.contains(&feerate) {
+        return Err(feerate);
+    }
+
+    Ok(())
+}
+
 /// Check all complete signatures for revocation transactions (ie Cancel, Emergency,
 /// or UnvaultEmergency)
 pub fn check_revocation_signatures(
@@ -555,7 +977,7 @@ pub fn check_revocation_signatures(
         if *sighash_type != SigHashType::AllPlusAnyoneCanPay as u8 {
             return Err(SigError::InvalidSighash);
         }
-        secp.verify(&sighash, &Signature::from_der(&sig)?, &pubkey.key)?;
+        secp.verify(&sighash, &Signature::from_der(sig)?, &pubkey.key)?;
     }
 
     Ok(())
@@ -571,7 +993,7 @@ pub fn check_unvault_signatures(
     let sigs = &tx
         .psbt()
         .inputs
-        .get(0)
+        .first()
         .ok_or(SigError::InsaneTransaction)?
         .partial_sigs;
 
@@ -580,7 +1002,7 @@ pub fn check_unvault_signatures(
         if *sighash_type != SigHashType::All as u8 {
             return Err(SigError::InvalidSighash);
         }
-        secp.verify(&sighash, &Signature::from_der(&sig)?, &pubkey.key)?;
+        secp.verify(&sighash, &Signature::from_der(sig)?, &pubkey.key)?;
     }
 
     Ok(())
@@ -630,7 +1052,7 @@ pub fn check_spend_signatures(
                     return Err(SigError::InvalidSighash);
                 }
 
-                secp.verify(&sighash, &Signature::from_der(&sig)?, &pubkey.key)?;
+                secp.verify(&sighash, &Signature::from_der(sig)?, &pubkey.key)?;
                 valid_sigs += 1;
             }
         }
@@ -659,6 +1081,8 @@ pub enum CommunicationError {
     CosigAlreadySigned,
     /// The Cosigning Server tried to fool us!
     CosigInsanePsbt,
+    /// The Watchtower did not acknowledge guarding the vault
+    WatchtowerNack,
 }
 
 impl fmt::Display for CommunicationError {
@@ -678,6 +1102,10 @@ impl fmt::Display for CommunicationError {
                     signed a Spend transaction spending one of these vaults."
             ),
             Self::CosigInsanePsbt => write!(f, "Cosigning server error: they sent an insane PSBT"),
+            Self::WatchtowerNack => write!(
+                f,
+                "Watchtower error: it did not acknowledge guarding the vault"
+            ),
         }
     }
 }
@@ -712,7 +1140,7 @@ fn send_sig_msg(
                 || *sigtype == SigHashType::All as u8
         );
 
-        let signature = Signature::from_der(&sig).expect("They must provide valid signatures");
+        let signature = Signature::from_der(sig).expect("They must provide valid signatures");
         let sig_msg = Sig {
             pubkey,
             signature,
@@ -768,7 +1196,7 @@ pub fn share_unvault_signatures(
     let sigs = &unvault_tx
         .psbt()
         .inputs
-        .get(0)
+        .first()
         .expect("Unvault has a single input")
         .partial_sigs;
     log::trace!("Sharing unvault sigs {:?}", sigs);
@@ -777,7 +1205,8 @@ pub fn share_unvault_signatures(
 }
 
 /// Make the cosigning servers sign this Spend transaction.
-pub fn fetch_cosigs_signatures(
+pub fn fetch_cosigs_signatures<C: secp256k1::Verification>(
+    secp: &secp256k1::Secp256k1<C>,
     noise_secret: &revault_net::noise::SecretKey,
     spend_tx: &mut SpendTransaction,
     cosigs: &[(std::net::SocketAddr, revault_net::noise::PublicKey)],
@@ -805,16 +1234,19 @@ pub fn fetch_cosigs_signatures(
         let sign_res: SignResult = transport.send_req(&msg.into())?;
         let signed_tx = sign_res.tx.ok_or(CommunicationError::CosigAlreadySigned)?;
         log::debug!("Cosigning server returned: '{}'", &signed_tx,);
+        if signed_tx.txid() != spend_tx.txid() {
+            return Err(CommunicationError::CosigInsanePsbt);
+        }
 
         for (i, psbtin) in signed_tx.into_psbt().inputs.into_iter().enumerate() {
             for (key, sig) in psbtin.partial_sigs {
                 let (_, rawsig) = sig
                     .split_last()
                     .ok_or(CommunicationError::CosigInsanePsbt)?;
-                let sig = secp256k1::Signature::from_der(&rawsig)
+                let sig = secp256k1::Signature::from_der(rawsig)
                     .map_err(|_| CommunicationError::CosigInsanePsbt)?;
                 spend_tx
-                    .add_signature(i, key.key, sig, &revaultd.secp_ctx)
+                    .add_signature(i, key.key, sig, secp)
                     .map_err(|_| CommunicationError::CosigInsanePsbt)?;
             }
         }
@@ -849,7 +1281,7 @@ pub fn check_spend_transaction_size(revaultd: &RevaultD, spend_tx: SpendTransact
         "transaction": tx_hex,
     }))
     .expect("JSON created inline");
-    return msg.len() <= revault_net::noise::NOISE_PLAINTEXT_MAX_SIZE;
+    msg.len() <= revault_net::noise::NOISE_PLAINTEXT_MAX_SIZE
 }
 
 /// Sends the spend transaction for a certain outpoint to the coordinator
@@ -881,8 +1313,7 @@ pub fn get_presigs(
     txid: Txid,
 ) -> Result<BTreeMap<secp256k1::PublicKey, secp256k1::Signature>, CommunicationError> {
     let getsigs_msg = GetSigs { id: txid };
-    let mut transport =
-        KKTransport::connect(coordinator_host, &noise_secret, &coordinator_noisekey)?;
+    let mut transport = KKTransport::connect(coordinator_host, noise_secret, coordinator_noisekey)?;
 
     log::debug!("Sending to sync server: '{:?}'", getsigs_msg,);
     let resp: Sigs = transport.send_req(&getsigs_msg.into())?;
@@ -909,7 +1340,7 @@ pub fn cosigners_status(revaultd: &RevaultD) -> Vec<ServerStatus> {
     let mut cosigners = Vec::new();
     if let Some(c) = &revaultd.cosigs {
         for (host, key) in c {
-            let reachable = KKTransport::connect(*host, &revaultd.noise_secret, &key).is_ok();
+            let reachable = KKTransport::connect(*host, &revaultd.noise_secret, key).is_ok();
 
             cosigners.push(ServerStatus {
                 host: host.to_string(),
@@ -924,7 +1355,7 @@ pub fn watchtowers_status(revaultd: &RevaultD) -> Vec<ServerStatus> {
     let mut watchtowers = Vec::new();
     if let Some(w) = &revaultd.watchtowers {
         for (host, key) in w {
-            let reachable = KKTransport::connect(*host, &revaultd.noise_secret, &key).is_ok();
+            let reachable = KKTransport::connect(*host, &revaultd.noise_secret, key).is_ok();
 
             watchtowers.push(ServerStatus {
                 host: host.to_string(),
@@ -940,9 +1371,7 @@ pub fn watchtowers_status(revaultd: &RevaultD) -> Vec<ServerStatus> {
 pub struct RpcUtils {
     pub revaultd: Arc<RwLock<RevaultD>>,
     pub bitcoind_tx: Sender<BitcoindMessageOut>,
-    pub bitcoind_thread: Arc<RwLock<JoinHandle<()>>>,
     pub sigfetcher_tx: Sender<SigFetcherMessageOut>,
-    pub sigfetcher_thread: Arc<RwLock<JoinHandle<()>>>,
 }
 
 #[cfg(test)]
@@ -951,8 +1380,8 @@ mod test {
         control::*,
         database::{
             actions::{
-                db_confirm_deposit, db_confirm_unvault, db_insert_new_unconfirmed_vault,
-                db_update_presigned_tx,
+                db_confirm_deposit, db_confirm_unvault, db_exclude_vault,
+                db_insert_new_unconfirmed_vault, db_label_vault, db_update_presigned_tx,
             },
             interface::{
                 db_cancel_transaction, db_emer_transaction, db_unvault_emer_transaction,
@@ -973,15 +1402,23 @@ mod test {
         bitcoin::{
             blockdata::transaction::OutPoint,
             hash_types::Txid,
+            hashes::hex::FromHex,
             network::constants::Network,
             secp256k1,
-            util::{amount::Amount, bip143::SigHashCache, bip32::ChildNumber},
+            util::{
+                amount::Amount,
+                bip143::SigHashCache,
+                bip32::{self, ChildNumber},
+            },
             PrivateKey as BitcoinPrivKey, PublicKey as BitcoinPubKey, SigHashType,
         },
-        miniscript::descriptor::{DescriptorPublicKey, DescriptorSinglePub},
+        miniscript::descriptor::{
+            DescriptorPublicKey, DescriptorSinglePub, DescriptorXKey, Wildcard,
+        },
+        scripts::{CpfpDescriptor, DepositDescriptor, UnvaultDescriptor},
         transactions::{
-            CancelTransaction, EmergencyTransaction, RevaultTransaction, SpendTransaction,
-            UnvaultEmergencyTransaction, UnvaultTransaction,
+            transaction_chain, CancelTransaction, EmergencyTransaction, RevaultTransaction,
+            SpendTransaction, UnvaultEmergencyTransaction, UnvaultTransaction,
         },
     };
     use std::{collections::BTreeMap, fs, net::TcpListener, str::FromStr, thread};
@@ -1098,7 +1535,7 @@ mod test {
             db_insert_new_unconfirmed_vault(
                 &db_file,
                 1,
-                &outpoint,
+                outpoint,
                 &Amount::ONE_BTC,
                 ChildNumber::from_normal_idx(0).unwrap(),
                 1,
@@ -1109,6 +1546,7 @@ mod test {
         // First vault: Funded
         db_confirm_deposit(
             &db_file,
+            revaultd.db_key.as_ref(),
             &outpoints[1],
             9, // blockheight
             &transactions[1].as_ref().unwrap().initial_unvault,
@@ -1128,6 +1566,7 @@ mod test {
         // Second vault: Secured
         db_confirm_deposit(
             &db_file,
+            revaultd.db_key.as_ref(),
             &outpoints[2],
             9, // blockheight
             &transactions[2].as_ref().unwrap().initial_unvault,
@@ -1139,14 +1578,15 @@ mod test {
 
         let vaults: Vec<_> = outpoints
             .iter()
-            .map(|o| db_vault_by_deposit(&db_file, &o).unwrap().unwrap())
+            .map(|o| db_vault_by_deposit(&db_file, o).unwrap().unwrap())
             .collect();
 
-        let (tx_db_id, _) = db_cancel_transaction(&db_file, vaults[2].id)
+        let (tx_db_id, _) = db_cancel_transaction(&db_file, revaultd.db_key.as_ref(), vaults[2].id)
             .unwrap()
             .unwrap();
         db_update_presigned_tx(
             &db_file,
+            revaultd.db_key.as_ref(),
             vaults[2].id,
             tx_db_id,
             transactions[2]
@@ -1163,11 +1603,12 @@ mod test {
         )
         .unwrap();
 
-        let (tx_db_id, _) = db_emer_transaction(&db_file, vaults[2].id)
+        let (tx_db_id, _) = db_emer_transaction(&db_file, revaultd.db_key.as_ref(), vaults[2].id)
             .unwrap()
             .unwrap();
         db_update_presigned_tx(
             &db_file,
+            revaultd.db_key.as_ref(),
             vaults[2].id,
             tx_db_id,
             transactions[2]
@@ -1184,11 +1625,13 @@ mod test {
         )
         .unwrap();
 
-        let (tx_db_id, _) = db_unvault_emer_transaction(&db_file, vaults[2].id)
-            .unwrap()
-            .unwrap();
+        let (tx_db_id, _) =
+            db_unvault_emer_transaction(&db_file, revaultd.db_key.as_ref(), vaults[2].id)
+                .unwrap()
+                .unwrap();
         db_update_presigned_tx(
             &db_file,
+            revaultd.db_key.as_ref(),
             vaults[2].id,
             tx_db_id,
             transactions[2]
@@ -1215,6 +1658,7 @@ mod test {
         // Third vault: Active
         db_confirm_deposit(
             &db_file,
+            revaultd.db_key.as_ref(),
             &vaults[3].deposit_outpoint,
             9, // blockheight
             &transactions[3].as_ref().unwrap().initial_unvault,
@@ -1224,11 +1668,12 @@ mod test {
         )
         .unwrap();
 
-        let (tx_db_id, _) = db_cancel_transaction(&db_file, vaults[3].id)
+        let (tx_db_id, _) = db_cancel_transaction(&db_file, revaultd.db_key.as_ref(), vaults[3].id)
             .unwrap()
             .unwrap();
         db_update_presigned_tx(
             &db_file,
+            revaultd.db_key.as_ref(),
             vaults[2].id,
             tx_db_id,
             transactions[3]
@@ -1245,11 +1690,12 @@ mod test {
         )
         .unwrap();
 
-        let (tx_db_id, _) = db_emer_transaction(&db_file, vaults[3].id)
+        let (tx_db_id, _) = db_emer_transaction(&db_file, revaultd.db_key.as_ref(), vaults[3].id)
             .unwrap()
             .unwrap();
         db_update_presigned_tx(
             &db_file,
+            revaultd.db_key.as_ref(),
             vaults[2].id,
             tx_db_id,
             transactions[3]
@@ -1266,11 +1712,13 @@ mod test {
         )
         .unwrap();
 
-        let (tx_db_id, _) = db_unvault_emer_transaction(&db_file, vaults[3].id)
-            .unwrap()
-            .unwrap();
+        let (tx_db_id, _) =
+            db_unvault_emer_transaction(&db_file, revaultd.db_key.as_ref(), vaults[3].id)
+                .unwrap()
+                .unwrap();
         db_update_presigned_tx(
             &db_file,
+            revaultd.db_key.as_ref(),
             vaults[2].id,
             tx_db_id,
             transactions[3]
@@ -1287,9 +1735,11 @@ mod test {
         )
         .unwrap();
 
-        let (tx_db_id, _) = db_unvault_transaction(&db_file, vaults[3].id).unwrap();
+        let (tx_db_id, _) =
+            db_unvault_transaction(&db_file, revaultd.db_key.as_ref(), vaults[3].id).unwrap();
         db_update_presigned_tx(
             &db_file,
+            revaultd.db_key.as_ref(),
             vaults[3].id,
             tx_db_id,
             transactions[3]
@@ -1411,7 +1861,166 @@ mod test {
             4
         );
 
-        fs::remove_dir_all(&datadir).unwrap_or_else(|_| ());
+        // Excluded vaults are still listed, it's up to the caller to filter them out
+        db_exclude_vault(&revaultd.db_file(), vaults[2].db_vault.id, true).unwrap();
+        let entries = listvaults_from_db(&revaultd, None, None).unwrap();
+        assert_eq!(entries.len(), 4);
+        for entry in entries {
+            assert_eq!(
+                entry.excluded,
+                entry.deposit_outpoint == vaults[2].db_vault.deposit_outpoint
+            );
+        }
+
+        // So are their labels
+        db_label_vault(&revaultd.db_file(), vaults[1].db_vault.id, Some("treasury")).unwrap();
+        for entry in listvaults_from_db(&revaultd, None, None).unwrap() {
+            if entry.deposit_outpoint == vaults[1].db_vault.deposit_outpoint {
+                assert_eq!(entry.label.as_deref(), Some("treasury"));
+            } else {
+                assert!(entry.label.is_none());
+            }
+        }
+
+        fs::remove_dir_all(&datadir).unwrap_or(());
+    }
+
+    #[test]
+    fn test_vaults_csv() {
+        let entry = ListVaultsEntry {
+            amount: Amount::from_sat(1_000_000),
+            blockheight: 100,
+            status: VaultStatus::Secured,
+            deposit_outpoint: OutPoint::from_str(
+                "4d799e993665149109682555ba482b386aea03c5dbd62c059b48eb8f40f2f040:0",
+            )
+            .unwrap(),
+            derivation_index: ChildNumber::from_normal_idx(3).unwrap(),
+            address: Address::from_str(
+                "bc1qwqdg6squsna38e46795at95yu9atm8azzmyvckulcc7kytlcckxswvvzej",
+            )
+            .unwrap(),
+            received_at: 1_600_000_000,
+            updated_at: 1_600_000_042,
+            excluded: false,
+            spend_txid: None,
+            label: None,
+        };
+
+        assert_eq!(
+            vaults_csv(&[]),
+            "outpoint,amount,status,derivation_index,address,received_at,updated_at\n"
+        );
+        assert_eq!(
+            vaults_csv(&[entry]),
+            "outpoint,amount,status,derivation_index,address,received_at,updated_at\n\
+             4d799e993665149109682555ba482b386aea03c5dbd62c059b48eb8f40f2f040:0,1000000,secured,3,\
+             bc1qwqdg6squsna38e46795at95yu9atm8azzmyvckulcc7kytlcckxswvvzej,1600000000,1600000042\n"
+        );
+    }
+
+    #[test]
+    fn test_sort_vaults() {
+        let entry = |amount: u64, status: VaultStatus, index: u32, updated_at: u32, vout: u32| {
+            ListVaultsEntry {
+                amount: Amount::from_sat(amount),
+                blockheight: 100,
+                status,
+                deposit_outpoint: OutPoint::new(
+                    Txid::from_str(
+                        "4d799e993665149109682555ba482b386aea03c5dbd62c059b48eb8f40f2f040",
+                    )
+                    .unwrap(),
+                    vout,
+                ),
+                derivation_index: ChildNumber::from_normal_idx(index).unwrap(),
+                address: Address::from_str(
+                    "bc1qwqdg6squsna38e46795at95yu9atm8azzmyvckulcc7kytlcckxswvvzej",
+                )
+                .unwrap(),
+                received_at: 1_600_000_000,
+                updated_at,
+                excluded: false,
+                spend_txid: None,
+                label: None,
+            }
+        };
+        let mut vaults = vec![
+            entry(3_000, VaultStatus::Active, 2, 12, 0),
+            entry(1_000, VaultStatus::Unconfirmed, 0, 13, 1),
+            entry(2_000, VaultStatus::Funded, 1, 11, 2),
+            entry(2_000, VaultStatus::Funded, 1, 11, 3),
+        ];
+        let vouts = |vaults: &[ListVaultsEntry]| {
+            vaults
+                .iter()
+                .map(|v| v.deposit_outpoint.vout)
+                .collect::<Vec<u32>>()
+        };
+
+        sort_vaults(&mut vaults, ListVaultsSortKey::DerivationIndex, false);
+        assert_eq!(vouts(&vaults), vec![1, 2, 3, 0]);
+        sort_vaults(&mut vaults, ListVaultsSortKey::Amount, true);
+        assert_eq!(vouts(&vaults), vec![0, 3, 2, 1]);
+        sort_vaults(&mut vaults, ListVaultsSortKey::Status, false);
+        assert_eq!(vouts(&vaults), vec![1, 2, 3, 0]);
+        sort_vaults(&mut vaults, ListVaultsSortKey::UpdatedAt, false);
+        assert_eq!(vouts(&vaults), vec![2, 3, 0, 1]);
+
+        // Filter them by amount and derivation index, bounds included
+        let matching = |bounds: ListVaultsBounds| {
+            let mut vouts: Vec<u32> = vaults
+                .iter()
+                .filter(|v| bounds.contains(v))
+                .map(|v| v.deposit_outpoint.vout)
+                .collect();
+            vouts.sort_unstable();
+            vouts
+        };
+        assert_eq!(matching(ListVaultsBounds::default()), vec![0, 1, 2, 3]);
+        let bounds = ListVaultsBounds {
+            min_amount: Some(Amount::from_sat(2_000)),
+            ..ListVaultsBounds::default()
+        };
+        assert_eq!(matching(bounds), vec![0, 2, 3]);
+        let bounds = ListVaultsBounds {
+            max_amount: Some(Amount::from_sat(2_000)),
+            min_derivation_index: Some(ChildNumber::from(1)),
+            ..ListVaultsBounds::default()
+        };
+        assert_eq!(matching(bounds), vec![2, 3]);
+        let bounds = ListVaultsBounds {
+            min_derivation_index: Some(ChildNumber::from(0)),
+            max_derivation_index: Some(ChildNumber::from(0)),
+            ..ListVaultsBounds::default()
+        };
+        assert!(!bounds.is_empty());
+        assert_eq!(matching(bounds), vec![1]);
+        let bounds = ListVaultsBounds {
+            min_amount: Some(Amount::from_sat(3_001)),
+            max_amount: Some(Amount::from_sat(3_000)),
+            ..ListVaultsBounds::default()
+        };
+        assert!(bounds.is_empty());
+        assert!(matching(bounds).is_empty());
+
+        // They are given as part of the options object of the command
+        let options: ListVaultsOptions = serde_json::from_value(serde_json::json!({
+            "sort_by": "amount",
+            "max_amount": 2_000,
+            "min_derivation_index": 1,
+            "limit": 1,
+            "label": "treasury",
+        }))
+        .unwrap();
+        assert_eq!(options.sort_by, Some(ListVaultsSortKey::Amount));
+        assert_eq!(options.limit, Some(1));
+        assert_eq!(options.label.as_deref(), Some("treasury"));
+        assert_eq!(matching(options.bounds()), vec![2, 3]);
+        assert!(
+            serde_json::from_value::<ListVaultsOptions>(serde_json::json!({"min_amnt": 1}))
+                .is_err()
+        );
     }
 
     #[test]
@@ -1424,13 +2033,13 @@ mod test {
         let outpoints: Vec<_> = vaults.iter().map(|v| v.db_vault.deposit_outpoint).collect();
 
         assert_eq!(
-            vaults_from_deposits(&db_file, &outpoints, &vec![],)
+            vaults_from_deposits(&db_file, &outpoints, &[],)
                 .unwrap()
                 .len(),
             4
         );
         assert_eq!(
-            vaults_from_deposits(&db_file, &outpoints[1..], &vec![VaultStatus::Unconfirmed],)
+            vaults_from_deposits(&db_file, &outpoints[1..], &[VaultStatus::Unconfirmed],)
                 .unwrap()
                 .len(),
             3
@@ -1438,7 +2047,7 @@ mod test {
 
         // Returning an error if one of the vaults has an invalid status
         assert!(
-            vaults_from_deposits(&db_file, &outpoints, &vec![VaultStatus::Unconfirmed])
+            vaults_from_deposits(&db_file, &outpoints, &[VaultStatus::Unconfirmed])
                 .unwrap_err()
                 .to_string()
                 .contains(
@@ -1453,14 +2062,12 @@ mod test {
                 .unwrap(),
             2,
         );
-        assert!(
-            vaults_from_deposits(&db_file, &vec![wrong_outpoint], &vec![],)
-                .unwrap_err()
-                .to_string()
-                .contains(&RpcControlError::UnknownOutPoint(wrong_outpoint).to_string())
-        );
+        assert!(vaults_from_deposits(&db_file, &[wrong_outpoint], &[],)
+            .unwrap_err()
+            .to_string()
+            .contains(&RpcControlError::UnknownOutPoint(wrong_outpoint).to_string()));
 
-        fs::remove_dir_all(&datadir).unwrap_or_else(|_| ());
+        fs::remove_dir_all(&datadir).unwrap_or(());
     }
 
     #[test]
@@ -1472,16 +2079,14 @@ mod test {
         let vaults = create_vaults(&stake_revaultd);
 
         // vault[0] is not confirmed, no presigned txs here!
-        assert!(
-            presigned_txs(&stake_revaultd, vec![vaults[0].db_vault.clone()])
-                .unwrap_err()
-                .to_string()
-                .contains("Database error: No unvault tx in db")
-        );
+        assert!(presigned_txs(&stake_revaultd, vec![vaults[0].db_vault])
+            .unwrap_err()
+            .to_string()
+            .contains("Database error: No unvault tx in db"));
 
         // vault[1] is funded, no txs is final
         // The stakeholder has all the txs
-        let stake_txs = presigned_txs(&stake_revaultd, vec![vaults[1].db_vault.clone()]).unwrap();
+        let stake_txs = presigned_txs(&stake_revaultd, vec![vaults[1].db_vault]).unwrap();
         assert_eq!(stake_txs.len(), 1);
         assert_eq!(stake_txs[0].outpoint, vaults[1].db_vault.deposit_outpoint);
         assert_eq!(
@@ -1518,9 +2123,16 @@ mod test {
             .unwrap()
             .transaction
             .is_none());
+        // None of the stakeholders signed yet
+        let n_stakeholders = stake_revaultd.stakeholders_xpubs().len();
+        assert_eq!(stake_txs[0].cancel.signatures.len(), n_stakeholders);
+        assert!(stake_txs[0].cancel.signatures.values().all(|s| !s));
+        assert!(!stake_txs[0].cancel.is_signed);
+        assert!(stake_txs[0].unvault.signatures.values().all(|s| !s));
+        assert!(!stake_txs[0].unvault.is_signed);
 
         // The manager has the same txs, but no emergency
-        let man_txs = presigned_txs(&man_revaultd, vec![vaults[1].db_vault.clone()]).unwrap();
+        let man_txs = presigned_txs(&man_revaultd, vec![vaults[1].db_vault]).unwrap();
         assert_eq!(man_txs.len(), 1);
         assert_eq!(man_txs[0].outpoint, vaults[1].db_vault.deposit_outpoint);
         assert_eq!(
@@ -1538,7 +2150,7 @@ mod test {
 
         // vault[2] is secured, the unvault tx is not final
         // The stakeholder has all the txs
-        let stake_txs = presigned_txs(&stake_revaultd, vec![vaults[2].db_vault.clone()]).unwrap();
+        let stake_txs = presigned_txs(&stake_revaultd, vec![vaults[2].db_vault]).unwrap();
         assert_eq!(stake_txs.len(), 1);
         assert_eq!(stake_txs[0].outpoint, vaults[2].db_vault.deposit_outpoint);
         assert_eq!(
@@ -1591,7 +2203,7 @@ mod test {
             .is_some());
 
         // The manager has the same txs, but no emergency
-        let man_txs = presigned_txs(&man_revaultd, vec![vaults[2].db_vault.clone()]).unwrap();
+        let man_txs = presigned_txs(&man_revaultd, vec![vaults[2].db_vault]).unwrap();
         assert_eq!(man_txs.len(), 1);
         assert_eq!(man_txs[0].outpoint, vaults[2].db_vault.deposit_outpoint);
         assert_eq!(
@@ -1615,7 +2227,7 @@ mod test {
 
         // vault[3] is active, every tx is final
         // The stakeholder has all the txs
-        let stake_txs = presigned_txs(&stake_revaultd, vec![vaults[3].db_vault.clone()]).unwrap();
+        let stake_txs = presigned_txs(&stake_revaultd, vec![vaults[3].db_vault]).unwrap();
         assert_eq!(stake_txs.len(), 1);
         assert_eq!(stake_txs[0].outpoint, vaults[3].db_vault.deposit_outpoint);
         assert_eq!(
@@ -1674,7 +2286,7 @@ mod test {
             .is_some());
 
         // The manager has the same txs, but no emergency
-        let man_txs = presigned_txs(&man_revaultd, vec![vaults[3].db_vault.clone()]).unwrap();
+        let man_txs = presigned_txs(&man_revaultd, vec![vaults[3].db_vault]).unwrap();
         assert_eq!(man_txs.len(), 1);
         assert_eq!(man_txs[0].outpoint, vaults[3].db_vault.deposit_outpoint);
         assert_eq!(
@@ -1702,7 +2314,7 @@ mod test {
         assert!(man_txs[0].emergency.is_none());
         assert!(man_txs[0].unvault_emergency.is_none());
 
-        fs::remove_dir_all(&datadir).unwrap_or_else(|_| ());
+        fs::remove_dir_all(&datadir).unwrap_or(());
     }
 
     #[test]
@@ -1806,6 +2418,35 @@ mod test {
         fs::remove_dir_all(&datadir).unwrap();
     }
 
+    #[test]
+    fn test_check_revocation_feerate() {
+        let datadir = test_datadir();
+        let mut revaultd = dummy_revaultd(datadir.clone(), UserRole::Stakeholder);
+        setup_db(&mut revaultd).unwrap();
+        let vaults = create_vaults(&revaultd);
+        let txs = vaults[1].transactions.as_ref().unwrap();
+        let deposit_weight = revaultd.deposit_satisfaction_weight();
+        let unvault_weight = revaultd.unvault_satisfaction_weight();
+
+        // They are all created at 22 sat/WU
+        let default_range = 44..=176;
+        check_revocation_feerate(&txs.initial_cancel, unvault_weight, &default_range).unwrap();
+        check_revocation_feerate(&txs.initial_emer, deposit_weight, &default_range).unwrap();
+        check_revocation_feerate(&txs.initial_unvault_emer, unvault_weight, &default_range)
+            .unwrap();
+        assert_eq!(
+            check_revocation_feerate(&txs.initial_cancel, unvault_weight, &(89..=176)),
+            Err(88)
+        );
+        assert_eq!(
+            check_revocation_feerate(&txs.initial_emer, deposit_weight, &(1..=87)),
+            Err(88)
+        );
+        check_revocation_feerate(&txs.initial_unvault_emer, unvault_weight, &(88..=88)).unwrap();
+
+        fs::remove_dir_all(&datadir).unwrap_or(());
+    }
+
     #[test]
     fn test_presigned_tx_sighash() {
         let datadir = test_datadir();
@@ -1848,7 +2489,7 @@ mod test {
             network: Network::Regtest,
             key: secret_key,
         };
-        let public_key = BitcoinPubKey::from_private_key(&ctx, &private_key);
+        let public_key = BitcoinPubKey::from_private_key(ctx, &private_key);
         (private_key, public_key)
     }
 
@@ -1872,12 +2513,8 @@ mod test {
         let mut cache = SigHashCache::new(&psbt.global.unsigned_tx);
         let prev_value = psbt.inputs[0].witness_utxo.as_ref().unwrap().value;
         let script_code = psbt.inputs[0].witness_script.as_ref().unwrap();
-        let sighash = cache.signature_hash(
-            0,
-            &script_code,
-            prev_value,
-            SigHashType::AllPlusAnyoneCanPay,
-        );
+        let sighash =
+            cache.signature_hash(0, script_code, prev_value, SigHashType::AllPlusAnyoneCanPay);
         let sighash = secp256k1::Message::from_slice(&sighash).unwrap();
         let mut sig = ctx
             .sign(&sighash, &private_key.key)
@@ -1938,7 +2575,7 @@ mod test {
         }
 
         // I signed with the wrong sighash_type but pushed the right one
-        let wrong_sighash = cache.signature_hash(0, &script_code, prev_value, SigHashType::All);
+        let wrong_sighash = cache.signature_hash(0, script_code, prev_value, SigHashType::All);
         let wrong_sighash = secp256k1::Message::from_slice(&wrong_sighash).unwrap();
         let mut wrong_sig = ctx
             .sign(&wrong_sighash, &private_key.key)
@@ -1989,6 +2626,180 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_verify_stored_signatures() {
+        let datadir = test_datadir();
+        let mut revaultd = dummy_revaultd(datadir.clone(), UserRole::Stakeholder);
+
+        // We need a ctx that can sign as well (revaultd context is verify only)
+        let ctx = secp256k1::Secp256k1::new();
+
+        // The presigned transactions can't be derived from the dummy configuration's Unvault
+        // descriptor, as it contains the same key twice. Use our own.
+        // Testnet, as a regtest tpub is read back from the database as a testnet one
+        let xpub = |seed: u8| {
+            let xpriv = bip32::ExtendedPrivKey::new_master(Network::Testnet, &[seed; 32]).unwrap();
+            DescriptorPublicKey::XPub(DescriptorXKey {
+                origin: None,
+                xkey: bip32::ExtendedPubKey::from_private(&ctx, &xpriv),
+                derivation_path: bip32::DerivationPath::from(vec![]),
+                wildcard: Wildcard::Unhardened,
+            })
+        };
+        let cosig = |seed: u8| {
+            let (_, key) = create_keys(&ctx, &[seed; secp256k1::constants::SECRET_KEY_SIZE]);
+            DescriptorPublicKey::SinglePub(DescriptorSinglePub { origin: None, key })
+        };
+        revaultd.deposit_descriptor = DepositDescriptor::new(vec![xpub(1), xpub(2)]).unwrap();
+        revaultd.unvault_descriptor = UnvaultDescriptor::new(
+            vec![xpub(1), xpub(2)],
+            vec![xpub(3)],
+            1,
+            vec![cosig(4), cosig(5)],
+            6,
+        )
+        .unwrap();
+        revaultd.cpfp_descriptor = CpfpDescriptor::new(vec![xpub(6)]).unwrap();
+        setup_db(&mut revaultd).unwrap();
+        let db_file = revaultd.db_file();
+
+        // Three vaults whose presigned transactions are the ones derived from our descriptors
+        let outpoints: Vec<OutPoint> = (0..3)
+            .map(|i| {
+                OutPoint::new(
+                    Txid::from_str(
+                        "fcb6ab963b654c773de786f4ac92c132b3d2e816ccea37af9592aa0b4aaec04b",
+                    )
+                    .unwrap(),
+                    i,
+                )
+            })
+            .collect();
+        let mut cancel_txs = Vec::with_capacity(outpoints.len());
+        for (i, outpoint) in outpoints.iter().enumerate() {
+            let index = ChildNumber::from_normal_idx(i as u32).unwrap();
+            db_insert_new_unconfirmed_vault(&db_file, 1, outpoint, &Amount::ONE_BTC, index, 1)
+                .unwrap();
+            let (unvault_tx, cancel_tx, emer_tx, unemer_tx) = transaction_chain(
+                *outpoint,
+                Amount::ONE_BTC,
+                &revaultd.deposit_descriptor,
+                &revaultd.unvault_descriptor,
+                &revaultd.cpfp_descriptor,
+                index,
+                revaultd.emergency_address.clone().unwrap(),
+                revaultd.lock_time,
+                &revaultd.secp_ctx,
+            )
+            .unwrap();
+            db_confirm_deposit(
+                &db_file,
+                None,
+                outpoint,
+                9,
+                &unvault_tx,
+                &cancel_tx,
+                Some(&emer_tx),
+                Some(&unemer_tx),
+            )
+            .unwrap();
+            cancel_txs.push(cancel_tx);
+        }
+        assert_eq!(verify_stored_signatures(&mut revaultd).unwrap(), 3);
+        assert!(revaultd.invalid_signatures.is_empty());
+
+        // The key of the stakeholder using this seed, at this vault's derivation index
+        let stk_keys = |seed: u8, index: u32| {
+            let xpriv = bip32::ExtendedPrivKey::new_master(Network::Testnet, &[seed; 32])
+                .unwrap()
+                .derive_priv(&ctx, &[ChildNumber::from_normal_idx(index).unwrap()])
+                .unwrap();
+            let public_key = BitcoinPubKey::from_private_key(&ctx, &xpriv.private_key);
+            (xpriv.private_key, public_key)
+        };
+
+        // A valid signature for the Cancel of each vault
+        let cancel_sig = |cancel_tx: &CancelTransaction, private_key: &BitcoinPrivKey| {
+            let psbt = cancel_tx.psbt();
+            let mut cache = SigHashCache::new(&psbt.global.unsigned_tx);
+            let prev_value = psbt.inputs[0].witness_utxo.as_ref().unwrap().value;
+            let script_code = psbt.inputs[0].witness_script.as_ref().unwrap();
+            let sighash =
+                cache.signature_hash(0, script_code, prev_value, SigHashType::AllPlusAnyoneCanPay);
+            let sighash = secp256k1::Message::from_slice(&sighash).unwrap();
+            let mut sig = ctx
+                .sign(&sighash, &private_key.key)
+                .serialize_der()
+                .to_vec();
+            sig.push(SigHashType::AllPlusAnyoneCanPay as u8);
+            sig
+        };
+        // Store the Cancel with these signatures as is, bypassing the checks made when merging
+        // them
+        let store_cancel = |vault_id: u32, sigs: BTreeMap<BitcoinPubKey, Vec<u8>>| {
+            let (tx_db_id, mut cancel_tx) = db_cancel_transaction(&db_file, None, vault_id)
+                .unwrap()
+                .unwrap();
+            cancel_tx.psbt_mut().inputs[0].partial_sigs = sigs;
+            rusqlite::Connection::open(&db_file)
+                .unwrap()
+                .execute(
+                    "UPDATE presigned_transactions SET psbt = (?1) WHERE id = (?2)",
+                    rusqlite::params![cancel_tx.as_psbt_serialized(), tx_db_id],
+                )
+                .unwrap();
+        };
+        let vaults: Vec<DbVault> = outpoints
+            .iter()
+            .map(|o| db_vault_by_deposit(&db_file, o).unwrap().unwrap())
+            .collect();
+
+        // The first one has a valid signature from a stakeholder
+        let (private_key, public_key) = stk_keys(1, 0);
+        let mut sigs = BTreeMap::new();
+        sigs.insert(public_key, cancel_sig(&cancel_txs[0], &private_key));
+        store_cancel(vaults[0].id, sigs);
+
+        // The second one's signature was tampered with
+        let (private_key, public_key) = stk_keys(1, 1);
+        let mut tampered_sig = cancel_sig(&cancel_txs[1], &private_key);
+        let last_byte = tampered_sig.len() - 2;
+        tampered_sig[last_byte] ^= 1;
+        let mut sigs = BTreeMap::new();
+        sigs.insert(public_key, tampered_sig);
+        store_cancel(vaults[1].id, sigs);
+
+        // The third one's is valid, but made by another stakeholder than the one it's stored for
+        let (private_key, _) = stk_keys(1, 2);
+        let (_, another_public_key) = stk_keys(2, 2);
+        let mut sigs = BTreeMap::new();
+        sigs.insert(another_public_key, cancel_sig(&cancel_txs[2], &private_key));
+        store_cancel(vaults[2].id, sigs);
+
+        assert_eq!(verify_stored_signatures(&mut revaultd).unwrap(), 3);
+        assert_eq!(revaultd.invalid_signatures.len(), 2);
+        assert!(revaultd.invalid_signatures.contains(&outpoints[1]));
+        assert!(revaultd.invalid_signatures.contains(&outpoints[2]));
+
+        // A valid signature, but from a key that isn't one of the stakeholders' for this vault
+        let (foreign_private_key, foreign_public_key) =
+            create_keys(&ctx, &[1; secp256k1::constants::SECRET_KEY_SIZE]);
+        let mut sigs = BTreeMap::new();
+        sigs.insert(
+            foreign_public_key,
+            cancel_sig(&cancel_txs[0], &foreign_private_key),
+        );
+        store_cancel(vaults[0].id, sigs);
+        assert!(check_stored_presigned_txs(&revaultd, &vaults[0])
+            .unwrap_err()
+            .contains(&SigError::UnknownKey(foreign_public_key).to_string()));
+        assert_eq!(verify_stored_signatures(&mut revaultd).unwrap(), 3);
+        assert_eq!(revaultd.invalid_signatures.len(), 3);
+        assert!(revaultd.invalid_signatures.contains(&outpoints[0]));
+
+        fs::remove_dir_all(&datadir).unwrap_or(());
+    }
+
     #[test]
     fn test_check_unvault_signatures() {
         let revaultds = [
@@ -2015,7 +2826,7 @@ mod test {
         let mut cache = SigHashCache::new(&psbt.global.unsigned_tx);
         let prev_value = psbt.inputs[0].witness_utxo.as_ref().unwrap().value;
         let script_code = psbt.inputs[0].witness_script.as_ref().unwrap();
-        let sighash = cache.signature_hash(0, &script_code, prev_value, SigHashType::All);
+        let sighash = cache.signature_hash(0, script_code, prev_value, SigHashType::All);
         let sighash = secp256k1::Message::from_slice(&sighash).unwrap();
         let mut sig = ctx
             .sign(&sighash, &private_key.key)
@@ -2082,7 +2893,7 @@ mod test {
         let mut cache = SigHashCache::new(&psbt.global.unsigned_tx);
         let prev_value = psbt.inputs[0].witness_utxo.as_ref().unwrap().value;
         let script_code = psbt.inputs[0].witness_script.as_ref().unwrap();
-        let sighash = cache.signature_hash(0, &script_code, prev_value, SigHashType::All);
+        let sighash = cache.signature_hash(0, script_code, prev_value, SigHashType::All);
         let sighash = secp256k1::Message::from_slice(&sighash).unwrap();
         let mut wrong_sig = ctx
             .sign(&sighash, &private_key.key)
@@ -2105,12 +2916,8 @@ mod test {
         let mut cache = SigHashCache::new(&psbt.global.unsigned_tx);
         let prev_value = psbt.inputs[0].witness_utxo.as_ref().unwrap().value;
         let script_code = psbt.inputs[0].witness_script.as_ref().unwrap();
-        let wrong_sighash = cache.signature_hash(
-            0,
-            &script_code,
-            prev_value,
-            SigHashType::AllPlusAnyoneCanPay,
-        );
+        let wrong_sighash =
+            cache.signature_hash(0, script_code, prev_value, SigHashType::AllPlusAnyoneCanPay);
         let wrong_sighash = secp256k1::Message::from_slice(&wrong_sighash).unwrap();
         let mut wrong_sig = ctx
             .sign(&wrong_sighash, &private_key.key)
@@ -2135,7 +2942,7 @@ mod test {
         let mut cache = SigHashCache::new(&psbt.global.unsigned_tx);
         let prev_value = psbt.inputs[0].witness_utxo.as_ref().unwrap().value;
         let script_code = psbt.inputs[0].witness_script.as_ref().unwrap();
-        let sighash = cache.signature_hash(0, &script_code, prev_value, SigHashType::All);
+        let sighash = cache.signature_hash(0, script_code, prev_value, SigHashType::All);
         let sighash = secp256k1::Message::from_slice(&sighash).unwrap();
         let mut sig = ctx
             .sign(&sighash, &private_key.key)
@@ -2188,7 +2995,6 @@ mod test {
         let ctx = secp256k1::Secp256k1::new();
 
         let manager_keychains: Vec<_> = (1..3)
-            .into_iter()
             .map(|i| create_keys(&ctx, &[i; secp256k1::constants::SECRET_KEY_SIZE]))
             .collect();
         let managers_pubkeys: Vec<_> = manager_keychains
@@ -2206,7 +3012,7 @@ mod test {
         for input in &mut psbt.inputs {
             let prev_value = input.witness_utxo.as_ref().unwrap().value;
             let script_code = input.witness_script.as_ref().unwrap();
-            let sighash = cache.signature_hash(0, &script_code, prev_value, SigHashType::All);
+            let sighash = cache.signature_hash(0, script_code, prev_value, SigHashType::All);
             let sighash = secp256k1::Message::from_slice(&sighash).unwrap();
             for keychain in &manager_keychains {
                 let mut sig = ctx.sign(&sighash, &keychain.0.key).serialize_der().to_vec();
@@ -2253,7 +3059,7 @@ mod test {
         let mut cache = SigHashCache::new(&psbt.global.unsigned_tx);
         let prev_value = psbt.inputs[0].witness_utxo.as_ref().unwrap().value;
         let script_code = psbt.inputs[0].witness_script.as_ref().unwrap();
-        let sighash = cache.signature_hash(0, &script_code, prev_value, SigHashType::All);
+        let sighash = cache.signature_hash(0, script_code, prev_value, SigHashType::All);
         let sighash = secp256k1::Message::from_slice(&sighash).unwrap();
         for keychain in &manager_keychains[1..] {
             let mut sig = ctx.sign(&sighash, &keychain.0.key).serialize_der().to_vec();
@@ -2301,7 +3107,7 @@ mod test {
         let mut cache = SigHashCache::new(&psbt.global.unsigned_tx);
         let prev_value = psbt.inputs[0].witness_utxo.as_ref().unwrap().value;
         let script_code = psbt.inputs[0].witness_script.as_ref().unwrap();
-        let wrong_sighash = cache.signature_hash(0, &script_code, prev_value, SigHashType::All);
+        let wrong_sighash = cache.signature_hash(0, script_code, prev_value, SigHashType::All);
         let wrong_sighash = secp256k1::Message::from_slice(&wrong_sighash).unwrap();
         for keychain in &manager_keychains {
             let mut wrong_sig = ctx
@@ -2330,12 +3136,8 @@ mod test {
         let mut cache = SigHashCache::new(&psbt.global.unsigned_tx);
         let prev_value = psbt.inputs[0].witness_utxo.as_ref().unwrap().value;
         let script_code = psbt.inputs[0].witness_script.as_ref().unwrap();
-        let wrong_sighash = cache.signature_hash(
-            0,
-            &script_code,
-            prev_value,
-            SigHashType::AllPlusAnyoneCanPay,
-        );
+        let wrong_sighash =
+            cache.signature_hash(0, script_code, prev_value, SigHashType::AllPlusAnyoneCanPay);
         let wrong_sighash = secp256k1::Message::from_slice(&wrong_sighash).unwrap();
         for keychain in &manager_keychains {
             let mut wrong_sig = ctx
@@ -2364,7 +3166,7 @@ mod test {
         let mut cache = SigHashCache::new(&psbt.global.unsigned_tx);
         let prev_value = psbt.inputs[0].witness_utxo.as_ref().unwrap().value;
         let script_code = psbt.inputs[0].witness_script.as_ref().unwrap();
-        let sighash = cache.signature_hash(0, &script_code, prev_value, SigHashType::All);
+        let sighash = cache.signature_hash(0, script_code, prev_value, SigHashType::All);
         let sighash = secp256k1::Message::from_slice(&sighash).unwrap();
         let mut sig = ctx
             .sign(&sighash, &manager_keychains[0].0.key)
@@ -2434,7 +3236,7 @@ mod test {
         let ctx = secp256k1::Secp256k1::new();
         let (_, public_key) = create_keys(&ctx, &[1; secp256k1::constants::SECRET_KEY_SIZE]);
         let mut sigs = BTreeMap::new();
-        let signature = Vec::<u8>::from_hex("304402201a3109a4a6445c1e56416bc39520aada5c8ad089e69ee4f1a40a0901de1a435302204b281ba97da2ab2e40eb65943ae414cc4307406c5eb177b1c646606839a2e99d01").unwrap();
+        let signature = Vec::<u8>::from_hex("304402207e9a0839ad5f8a91c4e6987e5f451844ddccee05eb56c2d9129a6933faa05f35022046a7348028e487939524a0d5c71645be11b1c2e2a96c1a152a25960ffd66051601").unwrap();
         sigs.insert(public_key, signature.clone());
 
         let ((client_pubkey, client_privkey), (server_pubkey, server_privkey)) =
@@ -2446,7 +3248,7 @@ mod test {
         let cli_thread = thread::spawn(move || {
             let mut cli_transport = KKTransport::connect(addr, &client_privkey, &server_pubkey)
                 .expect("Client channel connecting");
-            assert!(send_sig_msg(&mut cli_transport, txid.clone(), sigs.clone())
+            assert!(send_sig_msg(&mut cli_transport, txid, sigs.clone())
                 .unwrap_err()
                 .to_string()
                 .contains(&CommunicationError::SignatureStorage.to_string()));
@@ -2483,7 +3285,7 @@ mod test {
         let ctx = secp256k1::Secp256k1::new();
         let (_, public_key) = create_keys(&ctx, &[1; secp256k1::constants::SECRET_KEY_SIZE]);
         let mut sigs = BTreeMap::new();
-        let signature = Vec::<u8>::from_hex("304402201a3109a4a6445c1e56416bc39520aada5c8ad089e69ee4f1a40a0901de1a435302204b281ba97da2ab2e40eb65943ae414cc4307406c5eb177b1c646606839a2e99d01").unwrap();
+        let signature = Vec::<u8>::from_hex("304402207e9a0839ad5f8a91c4e6987e5f451844ddccee05eb56c2d9129a6933faa05f35022046a7348028e487939524a0d5c71645be11b1c2e2a96c1a152a25960ffd66051601").unwrap();
         sigs.insert(public_key, signature.clone());
 
         let ((client_pubkey, client_privkey), (server_pubkey, server_privkey)) =
@@ -2495,7 +3297,7 @@ mod test {
         let cli_thread = thread::spawn(move || {
             let mut cli_transport = KKTransport::connect(addr, &client_privkey, &server_pubkey)
                 .expect("Client channel connecting");
-            send_sig_msg(&mut cli_transport, txid.clone(), sigs.clone()).unwrap();
+            send_sig_msg(&mut cli_transport, txid, sigs.clone()).unwrap();
         });
 
         let mut server_transport =
@@ -2548,13 +3350,13 @@ mod test {
             .expect("Client channel connecting");
 
         // This call will panic because the signature has invalid lenght
-        send_sig_msg(&mut cli_transport, txid.clone(), sigs.clone()).unwrap();
+        send_sig_msg(&mut cli_transport, txid, sigs.clone()).unwrap();
         server_thread.join().unwrap();
     }
 
     #[test]
     #[should_panic(
-        expected = "assertion failed: *sigtype == SigHashType::AllPlusAnyoneCanPay as u8 ||\\n    *sigtype == SigHashType::All as u8"
+        expected = "assertion failed: *sigtype == SigHashType::AllPlusAnyoneCanPay as u8"
     )]
     fn test_send_sig_msg_invalid_sighash_type() {
         let txid =
@@ -2580,7 +3382,7 @@ mod test {
             .expect("Client channel connecting");
 
         // This call will fail as the signature has the wrong sighash
-        send_sig_msg(&mut cli_transport, txid.clone(), sigs.clone()).unwrap();
+        send_sig_msg(&mut cli_transport, txid, sigs.clone()).unwrap();
         server_thread.join().unwrap();
     }
 
@@ -2589,7 +3391,7 @@ mod test {
         let ctx = secp256k1::Secp256k1::new();
         let (_, public_key) = create_keys(&ctx, &[1; secp256k1::constants::SECRET_KEY_SIZE]);
         let mut sigs = BTreeMap::new();
-        let signature = Vec::<u8>::from_hex("304402201a3109a4a6445c1e56416bc39520aada5c8ad089e69ee4f1a40a0901de1a435302204b281ba97da2ab2e40eb65943ae414cc4307406c5eb177b1c646606839a2e99d01").unwrap();
+        let signature = Vec::<u8>::from_hex("304402207e9a0839ad5f8a91c4e6987e5f451844ddccee05eb56c2d9129a6933faa05f35022046a7348028e487939524a0d5c71645be11b1c2e2a96c1a152a25960ffd66051601").unwrap();
         sigs.insert(public_key, signature.clone());
         let cancel =
                 CancelTransaction::from_psbt_str("cHNidP8BAF4CAAAAASDOvhSZlTSEcEoUq/CT7Cg3ILtc6sqt5qJKvAMq+LbIAAAAAAD9////AXYfpDUAAAAAIgAg9AncsIZc8g7mJdfT9infAeWlqjtxBs93ireDGnQn/DYAAAAAAAEBK7hhpDUAAAAAIgAgFZlOQkpDkFSsLUfyeMGVAOT3T88jZM7L/XlVZoJ2jnABAwSBAAAAAQWpIQMVlEoh50lasMhcdwnrmnCp2ROlGY5CrH+HtxQmfZDZ06xRh2R2qRS/INUX1CaP7Pbn5GmtGYu2wgqjnIisa3apFO/kceq8yo9w69g4VVtlFAf739qTiKxsk1KHZ1IhAnddfXi3N38A+aEQ74sUdeuV7sg+2L3ijTjMHMEAfq3cIQLWP96FqjfC5qKQkC2WhYbbLJx1FbNSAjsnMfwDnK0jD1KvARKyaAABAUdSIQOO/iUanbfqJaBaLJWvYVlGFX+WECg27quCjtdyUuOSoCEDCnC1swAcW//WAUHvmyUVt796JvEtizTBlCkqeSvj0PtSrgA=").unwrap();
@@ -2648,7 +3450,7 @@ mod test {
         let ctx = secp256k1::Secp256k1::new();
         let (_, public_key) = create_keys(&ctx, &[1; secp256k1::constants::SECRET_KEY_SIZE]);
         let mut sigs = BTreeMap::new();
-        let signature = Vec::<u8>::from_hex("304402201a3109a4a6445c1e56416bc39520aada5c8ad089e69ee4f1a40a0901de1a435302204b281ba97da2ab2e40eb65943ae414cc4307406c5eb177b1c646606839a2e99d01").unwrap();
+        let signature = Vec::<u8>::from_hex("304402207e9a0839ad5f8a91c4e6987e5f451844ddccee05eb56c2d9129a6933faa05f35022046a7348028e487939524a0d5c71645be11b1c2e2a96c1a152a25960ffd66051601").unwrap();
         sigs.insert(public_key, signature.clone());
         let cancel =
                 CancelTransaction::from_psbt_str("cHNidP8BAF4CAAAAASDOvhSZlTSEcEoUq/CT7Cg3ILtc6sqt5qJKvAMq+LbIAAAAAAD9////AXYfpDUAAAAAIgAg9AncsIZc8g7mJdfT9infAeWlqjtxBs93ireDGnQn/DYAAAAAAAEBK7hhpDUAAAAAIgAgFZlOQkpDkFSsLUfyeMGVAOT3T88jZM7L/XlVZoJ2jnABAwSBAAAAAQWpIQMVlEoh50lasMhcdwnrmnCp2ROlGY5CrH+HtxQmfZDZ06xRh2R2qRS/INUX1CaP7Pbn5GmtGYu2wgqjnIisa3apFO/kceq8yo9w69g4VVtlFAf739qTiKxsk1KHZ1IhAnddfXi3N38A+aEQ74sUdeuV7sg+2L3ijTjMHMEAfq3cIQLWP96FqjfC5qKQkC2WhYbbLJx1FbNSAjsnMfwDnK0jD1KvARKyaAABAUdSIQOO/iUanbfqJaBaLJWvYVlGFX+WECg27quCjtdyUuOSoCEDCnC1swAcW//WAUHvmyUVt796JvEtizTBlCkqeSvj0PtSrgA=").unwrap();
@@ -2768,9 +3570,9 @@ mod test {
                 UnvaultTransaction::from_psbt_str("cHNidP8BAIkCAAAAAajRZE5yVgzG9McmOyy/WdcYdrGrK15bB5N/Hg8zhKOkAQAAAAD9////ArhhpDUAAAAAIgAgFZlOQkpDkFSsLUfyeMGVAOT3T88jZM7L/XlVZoJ2jnAwdQAAAAAAACIAILKCCA/RbV3QMPMrwwQmk4Ark4w1WyElM27WtBgftq6ZAAAAAAABASsA6aQ1AAAAACIAIPQJ3LCGXPIO5iXX0/Yp3wHlpao7cQbPd4q3gxp0J/w2AQMEAQAAAAEFR1IhA47+JRqdt+oloFosla9hWUYVf5YQKDbuq4KO13JS45KgIQMKcLWzABxb/9YBQe+bJRW3v3om8S2LNMGUKSp5K+PQ+1KuAAEBqSEDFZRKIedJWrDIXHcJ65pwqdkTpRmOQqx/h7cUJn2Q2dOsUYdkdqkUvyDVF9Qmj+z25+RprRmLtsIKo5yIrGt2qRTv5HHqvMqPcOvYOFVbZRQH+9/ak4isbJNSh2dSIQJ3XX14tzd/APmhEO+LFHXrle7IPti94o04zBzBAH6t3CEC1j/ehao3wuaikJAtloWG2yycdRWzUgI7JzH8A5ytIw9SrwESsmgAAQElIQOO/iUanbfqJaBaLJWvYVlGFX+WECg27quCjtdyUuOSoKxRhwA=").unwrap();
         let ctx = secp256k1::Secp256k1::new();
         let (_, public_key) = create_keys(&ctx, &[1; secp256k1::constants::SECRET_KEY_SIZE]);
-        let signature = Vec::<u8>::from_hex("304402201a3109a4a6445c1e56416bc39520aada5c8ad089e69ee4f1a40a0901de1a435302204b281ba97da2ab2e40eb65943ae414cc4307406c5eb177b1c646606839a2e99d01").unwrap();
+        let signature = Vec::<u8>::from_hex("304402207e9a0839ad5f8a91c4e6987e5f451844ddccee05eb56c2d9129a6933faa05f35022046a7348028e487939524a0d5c71645be11b1c2e2a96c1a152a25960ffd66051601").unwrap();
         unvault
-            .inner_tx_mut()
+            .psbt_mut()
             .inputs
             .get_mut(0)
             .unwrap()
@@ -2818,9 +3620,9 @@ mod test {
                 UnvaultTransaction::from_psbt_str("cHNidP8BAIkCAAAAAajRZE5yVgzG9McmOyy/WdcYdrGrK15bB5N/Hg8zhKOkAQAAAAD9////ArhhpDUAAAAAIgAgFZlOQkpDkFSsLUfyeMGVAOT3T88jZM7L/XlVZoJ2jnAwdQAAAAAAACIAILKCCA/RbV3QMPMrwwQmk4Ark4w1WyElM27WtBgftq6ZAAAAAAABASsA6aQ1AAAAACIAIPQJ3LCGXPIO5iXX0/Yp3wHlpao7cQbPd4q3gxp0J/w2AQMEAQAAAAEFR1IhA47+JRqdt+oloFosla9hWUYVf5YQKDbuq4KO13JS45KgIQMKcLWzABxb/9YBQe+bJRW3v3om8S2LNMGUKSp5K+PQ+1KuAAEBqSEDFZRKIedJWrDIXHcJ65pwqdkTpRmOQqx/h7cUJn2Q2dOsUYdkdqkUvyDVF9Qmj+z25+RprRmLtsIKo5yIrGt2qRTv5HHqvMqPcOvYOFVbZRQH+9/ak4isbJNSh2dSIQJ3XX14tzd/APmhEO+LFHXrle7IPti94o04zBzBAH6t3CEC1j/ehao3wuaikJAtloWG2yycdRWzUgI7JzH8A5ytIw9SrwESsmgAAQElIQOO/iUanbfqJaBaLJWvYVlGFX+WECg27quCjtdyUuOSoKxRhwA=").unwrap();
         let ctx = secp256k1::Secp256k1::new();
         let (_, public_key) = create_keys(&ctx, &[1; secp256k1::constants::SECRET_KEY_SIZE]);
-        let signature = Vec::<u8>::from_hex("304402201a3109a4a6445c1e56416bc39520aada5c8ad089e69ee4f1a40a0901de1a435302204b281ba97da2ab2e40eb65943ae414cc4307406c5eb177b1c646606839a2e99d01").unwrap();
+        let signature = Vec::<u8>::from_hex("304402207e9a0839ad5f8a91c4e6987e5f451844ddccee05eb56c2d9129a6933faa05f35022046a7348028e487939524a0d5c71645be11b1c2e2a96c1a152a25960ffd66051601").unwrap();
         unvault
-            .inner_tx_mut()
+            .psbt_mut()
             .inputs
             .get_mut(0)
             .unwrap()
@@ -2869,10 +3671,10 @@ mod test {
 
     #[test]
     fn test_fetch_cosigs_signatures() {
-        let mut spend = SpendTransaction::from_psbt_str("cHNidP8BAN0CAAAAAvvnQeptD/Ppkod15b290euvxLZ152fu+UG6SL6Sn/rKAwAAAAADAAAA++dB6m0P8+mSh3Xlvb3R66/EtnXnZ+75QbpIvpKf+soDAAAAAAMAAAADoEUAAAAAAAAiACDg+GlvXbP0TV3DYoQNm7MNXPm5oOuWroc/9w6DzxIO6ADh9QUAAAAAIgAg4Phpb12z9E1dw2KEDZuzDVz5uaDrlq6HP/cOg88SDujfcPMFAAAAACIAIKdjkv/h5NjyHOPSensxUoTK3V1lFzGvS6zsG04Xdfs8AAAAAAABASvQp+kLAAAAACIAIPEeGS8d2X/wO1TnGez7QM4Ui6OaJZKqmD/r6CwkRZ9kAQMEAQAAAAEFqCECBnMMBWVan9BFRWk1mJUJsUOKEwiJI/uSjrLQ6Btb4DmsUYdkdqkUb6EvZUC3JnDp5ob7670mID8QRt6IrGt2qRRFrmAKACpzZQe2b3NL6jaTgMGDHIisbJNSh2dSIQMPZLkiruL9WX8QS8bLO2cPHKLGxJsQcaGmwBBXXZT+WiECq+R1sZnsPWL6V2+u4WozT9uG/7JtznW+zrqu3zKKw/5Sr1OyaAABASvQp+kLAAAAACIAIPEeGS8d2X/wO1TnGez7QM4Ui6OaJZKqmD/r6CwkRZ9kAQMEAQAAAAEFqCECBnMMBWVan9BFRWk1mJUJsUOKEwiJI/uSjrLQ6Btb4DmsUYdkdqkUb6EvZUC3JnDp5ob7670mID8QRt6IrGt2qRRFrmAKACpzZQe2b3NL6jaTgMGDHIisbJNSh2dSIQMPZLkiruL9WX8QS8bLO2cPHKLGxJsQcaGmwBBXXZT+WiECq+R1sZnsPWL6V2+u4WozT9uG/7JtznW+zrqu3zKKw/5Sr1OyaAABASUhAlg43AlKAoXb47H4rxKCu40jBgz7l1svOSFK+N+gIKOdrFGHAAABAUdSIQL7lssn6CH7Mq08DaEfDfNM0gXXyaxn3/g/VygUS+2hBSECQxaOYH36+LE6Tj73COOcK91bVQk4f8sP0ogiWgiI4ldSrgA=").unwrap();
+        let mut spend = SpendTransaction::from_psbt_str("cHNidP8BAN0CAAAAAvvnQeptD/Ppkod15b290euvxLZ152fu+UG6SL6Sn/rKAwAAAAADAAAA++dB6m0P8+mSh3Xlvb3R66/EtnXnZ+75QbpIvpKf+soEAAAAAAMAAAADoEUAAAAAAAAiACDg+GlvXbP0TV3DYoQNm7MNXPm5oOuWroc/9w6DzxIO6ADh9QUAAAAAIgAg4Phpb12z9E1dw2KEDZuzDVz5uaDrlq6HP/cOg88SDujfcPMFAAAAACIAIKdjkv/h5NjyHOPSensxUoTK3V1lFzGvS6zsG04Xdfs8AAAAAAABASvQp+kLAAAAACIAIPEeGS8d2X/wO1TnGez7QM4Ui6OaJZKqmD/r6CwkRZ9kAQMEAQAAAAEFqCECBnMMBWVan9BFRWk1mJUJsUOKEwiJI/uSjrLQ6Btb4DmsUYdkdqkUb6EvZUC3JnDp5ob7670mID8QRt6IrGt2qRRFrmAKACpzZQe2b3NL6jaTgMGDHIisbJNSh2dSIQMPZLkiruL9WX8QS8bLO2cPHKLGxJsQcaGmwBBXXZT+WiECq+R1sZnsPWL6V2+u4WozT9uG/7JtznW+zrqu3zKKw/5Sr1OyaAABASvQp+kLAAAAACIAIPEeGS8d2X/wO1TnGez7QM4Ui6OaJZKqmD/r6CwkRZ9kAQMEAQAAAAEFqCECBnMMBWVan9BFRWk1mJUJsUOKEwiJI/uSjrLQ6Btb4DmsUYdkdqkUb6EvZUC3JnDp5ob7670mID8QRt6IrGt2qRRFrmAKACpzZQe2b3NL6jaTgMGDHIisbJNSh2dSIQMPZLkiruL9WX8QS8bLO2cPHKLGxJsQcaGmwBBXXZT+WiECq+R1sZnsPWL6V2+u4WozT9uG/7JtznW+zrqu3zKKw/5Sr1OyaAABASUhAlg43AlKAoXb47H4rxKCu40jBgz7l1svOSFK+N+gIKOdrFGHAAABAUdSIQL7lssn6CH7Mq08DaEfDfNM0gXXyaxn3/g/VygUS+2hBSECQxaOYH36+LE6Tj73COOcK91bVQk4f8sP0ogiWgiI4ldSrgA=").unwrap();
         let ctx = secp256k1::Secp256k1::new();
         let (_, public_key) = create_keys(&ctx, &[1; secp256k1::constants::SECRET_KEY_SIZE]);
-        let signature = Vec::<u8>::from_hex("304402201a3109a4a6445c1e56416bc39520aada5c8ad089e69ee4f1a40a0901de1a435302204b281ba97da2ab2e40eb65943ae414cc4307406c5eb177b1c646606839a2e99d01").unwrap();
+        let signature = Vec::<u8>::from_hex("304402207e9a0839ad5f8a91c4e6987e5f451844ddccee05eb56c2d9129a6933faa05f35022046a7348028e487939524a0d5c71645be11b1c2e2a96c1a152a25960ffd66051601").unwrap();
         // Rust newbie: I need the spend but it's moved inside the closure, so I'm cloning
         // it now
         let mut other_spend = spend.clone();
@@ -2885,16 +3687,10 @@ mod test {
         // client thread
         let cli_thread = thread::spawn(move || {
             // Our spend has no partial sigs...
-            assert_eq!(
-                spend.inner_tx().inputs.get(0).unwrap().partial_sigs.len(),
-                0
-            );
-            fetch_cosigs_signatures(&client_privkey, &mut spend, &cosigs).unwrap();
+            assert_eq!(spend.psbt().inputs.first().unwrap().partial_sigs.len(), 0);
+            fetch_cosigs_signatures(&ctx, &client_privkey, &mut spend, &cosigs).unwrap();
             // Now our spend has one :)
-            assert_eq!(
-                spend.inner_tx().inputs.get(0).unwrap().partial_sigs.len(),
-                1
-            );
+            assert_eq!(spend.psbt().inputs.first().unwrap().partial_sigs.len(), 1);
         });
 
         let mut server_transport =
@@ -2910,7 +3706,7 @@ mod test {
                     }),
                 );
                 other_spend
-                    .inner_tx_mut()
+                    .psbt_mut()
                     .inputs
                     .get_mut(0)
                     .unwrap()
@@ -2928,7 +3724,7 @@ mod test {
 
     #[test]
     fn test_fetch_cosigs_signatures_cosigner_already_signed() {
-        let mut spend = SpendTransaction::from_psbt_str("cHNidP8BAN0CAAAAAvvnQeptD/Ppkod15b290euvxLZ152fu+UG6SL6Sn/rKAwAAAAADAAAA++dB6m0P8+mSh3Xlvb3R66/EtnXnZ+75QbpIvpKf+soDAAAAAAMAAAADoEUAAAAAAAAiACDg+GlvXbP0TV3DYoQNm7MNXPm5oOuWroc/9w6DzxIO6ADh9QUAAAAAIgAg4Phpb12z9E1dw2KEDZuzDVz5uaDrlq6HP/cOg88SDujfcPMFAAAAACIAIKdjkv/h5NjyHOPSensxUoTK3V1lFzGvS6zsG04Xdfs8AAAAAAABASvQp+kLAAAAACIAIPEeGS8d2X/wO1TnGez7QM4Ui6OaJZKqmD/r6CwkRZ9kAQMEAQAAAAEFqCECBnMMBWVan9BFRWk1mJUJsUOKEwiJI/uSjrLQ6Btb4DmsUYdkdqkUb6EvZUC3JnDp5ob7670mID8QRt6IrGt2qRRFrmAKACpzZQe2b3NL6jaTgMGDHIisbJNSh2dSIQMPZLkiruL9WX8QS8bLO2cPHKLGxJsQcaGmwBBXXZT+WiECq+R1sZnsPWL6V2+u4WozT9uG/7JtznW+zrqu3zKKw/5Sr1OyaAABASvQp+kLAAAAACIAIPEeGS8d2X/wO1TnGez7QM4Ui6OaJZKqmD/r6CwkRZ9kAQMEAQAAAAEFqCECBnMMBWVan9BFRWk1mJUJsUOKEwiJI/uSjrLQ6Btb4DmsUYdkdqkUb6EvZUC3JnDp5ob7670mID8QRt6IrGt2qRRFrmAKACpzZQe2b3NL6jaTgMGDHIisbJNSh2dSIQMPZLkiruL9WX8QS8bLO2cPHKLGxJsQcaGmwBBXXZT+WiECq+R1sZnsPWL6V2+u4WozT9uG/7JtznW+zrqu3zKKw/5Sr1OyaAABASUhAlg43AlKAoXb47H4rxKCu40jBgz7l1svOSFK+N+gIKOdrFGHAAABAUdSIQL7lssn6CH7Mq08DaEfDfNM0gXXyaxn3/g/VygUS+2hBSECQxaOYH36+LE6Tj73COOcK91bVQk4f8sP0ogiWgiI4ldSrgA=").unwrap();
+        let mut spend = SpendTransaction::from_psbt_str("cHNidP8BAN0CAAAAAvvnQeptD/Ppkod15b290euvxLZ152fu+UG6SL6Sn/rKAwAAAAADAAAA++dB6m0P8+mSh3Xlvb3R66/EtnXnZ+75QbpIvpKf+soEAAAAAAMAAAADoEUAAAAAAAAiACDg+GlvXbP0TV3DYoQNm7MNXPm5oOuWroc/9w6DzxIO6ADh9QUAAAAAIgAg4Phpb12z9E1dw2KEDZuzDVz5uaDrlq6HP/cOg88SDujfcPMFAAAAACIAIKdjkv/h5NjyHOPSensxUoTK3V1lFzGvS6zsG04Xdfs8AAAAAAABASvQp+kLAAAAACIAIPEeGS8d2X/wO1TnGez7QM4Ui6OaJZKqmD/r6CwkRZ9kAQMEAQAAAAEFqCECBnMMBWVan9BFRWk1mJUJsUOKEwiJI/uSjrLQ6Btb4DmsUYdkdqkUb6EvZUC3JnDp5ob7670mID8QRt6IrGt2qRRFrmAKACpzZQe2b3NL6jaTgMGDHIisbJNSh2dSIQMPZLkiruL9WX8QS8bLO2cPHKLGxJsQcaGmwBBXXZT+WiECq+R1sZnsPWL6V2+u4WozT9uG/7JtznW+zrqu3zKKw/5Sr1OyaAABASvQp+kLAAAAACIAIPEeGS8d2X/wO1TnGez7QM4Ui6OaJZKqmD/r6CwkRZ9kAQMEAQAAAAEFqCECBnMMBWVan9BFRWk1mJUJsUOKEwiJI/uSjrLQ6Btb4DmsUYdkdqkUb6EvZUC3JnDp5ob7670mID8QRt6IrGt2qRRFrmAKACpzZQe2b3NL6jaTgMGDHIisbJNSh2dSIQMPZLkiruL9WX8QS8bLO2cPHKLGxJsQcaGmwBBXXZT+WiECq+R1sZnsPWL6V2+u4WozT9uG/7JtznW+zrqu3zKKw/5Sr1OyaAABASUhAlg43AlKAoXb47H4rxKCu40jBgz7l1svOSFK+N+gIKOdrFGHAAABAUdSIQL7lssn6CH7Mq08DaEfDfNM0gXXyaxn3/g/VygUS+2hBSECQxaOYH36+LE6Tj73COOcK91bVQk4f8sP0ogiWgiI4ldSrgA=").unwrap();
         // Rust newbie: I need the spend but it's moved inside the closure, so I'm cloning
         // it now
         let other_spend = spend.clone();
@@ -2937,11 +3733,12 @@ mod test {
         let listener = TcpListener::bind("127.0.0.1:0").unwrap();
         let addr = listener.local_addr().unwrap();
         let cosigs = vec![(addr, server_pubkey)];
+        let ctx = secp256k1::Secp256k1::verification_only();
 
         // client thread
         let cli_thread = thread::spawn(move || {
             assert!(
-                fetch_cosigs_signatures(&client_privkey, &mut spend, &cosigs)
+                fetch_cosigs_signatures(&ctx, &client_privkey, &mut spend, &cosigs)
                     .unwrap_err()
                     .to_string()
                     .contains(&CommunicationError::CosigAlreadySigned.to_string())
@@ -2977,11 +3774,12 @@ mod test {
         let listener = TcpListener::bind("127.0.0.1:0").unwrap();
         let addr = listener.local_addr().unwrap();
         let cosigs = vec![(addr, server_pubkey)];
+        let ctx = secp256k1::Secp256k1::verification_only();
 
         // client thread
         let cli_thread = thread::spawn(move || {
             assert!(
-                fetch_cosigs_signatures(&client_privkey, &mut spend, &cosigs)
+                fetch_cosigs_signatures(&ctx, &client_privkey, &mut spend, &cosigs)
                     .unwrap_err()
                     .to_string()
                     .contains(&CommunicationError::CosigInsanePsbt.to_string())
@@ -3200,7 +3998,7 @@ mod test {
         let txid =
             Txid::from_str("cafa9f92be48ba41f9ee67e775b6c4afebd1bdbde5758792e9f30f6dea41e7fb")
                 .unwrap();
-        let another_txid = txid.clone();
+        let another_txid = txid;
 
         // client thread
         let cli_thread = thread::spawn(move || {
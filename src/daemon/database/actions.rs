@@ -1,7 +1,9 @@
 use crate::{
     database::{
+        encryption::{decrypt_value, DbKey},
         interface::*,
-        schema::{DbTransaction, RevaultTx, TransactionType, SCHEMA},
+        migrations::{check_db_schema, create_schema, migrate_db},
+        schema::{DbTransaction, RevaultTx, TransactionType},
         DatabaseError, DB_VERSION,
     },
     revaultd::{BlockchainTip, RevaultD, VaultStatus},
@@ -10,7 +12,7 @@ use revault_tx::{
     bitcoin::{
         secp256k1, util::bip32::ChildNumber, Amount, OutPoint, PublicKey as BitcoinPubKey, Txid,
     },
-    miniscript::descriptor::DescriptorTrait,
+    scripts::EmergencyAddress,
     transactions::{
         CancelTransaction, EmergencyTransaction, RevaultTransaction, SpendTransaction,
         UnvaultEmergencyTransaction, UnvaultTransaction,
@@ -21,8 +23,10 @@ use std::{
     collections::BTreeMap,
     convert::TryInto,
     fs,
-    path::Path,
-    time::{SystemTime, UNIX_EPOCH},
+    path::{Path, PathBuf},
+    sync::atomic,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use rusqlite::params;
@@ -73,7 +77,7 @@ fn create_db(revaultd: &RevaultD) -> Result<(), DatabaseError> {
     let timestamp = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .map(|dur| timestamp_to_u32(dur.as_secs()))
-        .map_err(|e| DatabaseError(format!("Computing time since epoch: {}", e.to_string())))?;
+        .map_err(|e| DatabaseError::new(format!("Computing time since epoch: {}", e)))?;
     let deposit_descriptor = revaultd.deposit_descriptor.to_string();
     let unvault_descriptor = revaultd.unvault_descriptor.to_string();
     let cpfp_descriptor = revaultd.cpfp_descriptor.to_string();
@@ -82,17 +86,15 @@ fn create_db(revaultd: &RevaultD) -> Result<(), DatabaseError> {
     let raw_unused_index: u32 = revaultd.current_unused_index.into();
 
     // Rusqlite could create it for us, but we want custom permissions
-    create_db_file(&db_path)
-        .map_err(|e| DatabaseError(format!("Creating db file: {}", e.to_string())))?;
+    create_db_file(&db_path).map_err(|e| DatabaseError::new(format!("Creating db file: {}", e)))?;
 
     db_exec(&db_path, |tx| {
-        tx.execute_batch(&SCHEMA)
-            .map_err(|e| DatabaseError(format!("Creating database: {}", e.to_string())))?;
+        create_schema(tx).map_err(|e| DatabaseError::sqlite("Creating database", e))?;
         tx.execute(
             "INSERT INTO version (version) VALUES (?1)",
             params![DB_VERSION],
         )
-        .map_err(|e| DatabaseError(format!("Inserting version: {}", e.to_string())))?;
+        .map_err(|e| DatabaseError::sqlite("Inserting version", e))?;
         tx.execute(
             "INSERT INTO tip (network, blockheight, blockhash) VALUES (?1, ?2, ?3)",
             params![
@@ -101,7 +103,7 @@ fn create_db(revaultd: &RevaultD) -> Result<(), DatabaseError> {
                 vec![0u8; 32]
             ],
         )
-        .map_err(|e| DatabaseError(format!("Inserting version: {}", e.to_string())))?;
+        .map_err(|e| DatabaseError::sqlite("Inserting version", e))?;
         tx.execute(
             "INSERT INTO wallets (timestamp, deposit_descriptor, unvault_descriptor,\
             cpfp_descriptor, our_manager_xpub, our_stakeholder_xpub, deposit_derivation_index) \
@@ -116,7 +118,7 @@ fn create_db(revaultd: &RevaultD) -> Result<(), DatabaseError> {
                 raw_unused_index,
             ],
         )
-        .map_err(|e| DatabaseError(format!("Inserting wallet: {}", e.to_string())))?;
+        .map_err(|e| DatabaseError::sqlite("Inserting wallet", e))?;
 
         Ok(())
     })
@@ -125,22 +127,24 @@ fn create_db(revaultd: &RevaultD) -> Result<(), DatabaseError> {
 // Called on startup to check database integrity
 fn check_db(revaultd: &RevaultD) -> Result<(), DatabaseError> {
     let db_path = revaultd.db_file();
-    let wallet = db_wallet(&db_path)?;
 
-    // Check if their database is not from the future.
-    // We'll eventually do migration here if version < VERSION, but be strict until then.
+    // Check if their database is not from the future, and upgrade it if it's from the past.
     let version = db_version(&db_path)?;
-    if version != DB_VERSION {
-        return Err(DatabaseError(format!(
+    if version > DB_VERSION {
+        return Err(DatabaseError::new(format!(
             "Unexpected database version: got '{}', expected '{}'",
             version, DB_VERSION
         )));
     }
+    if version < DB_VERSION {
+        migrate_db(&db_path, version)?;
+    }
+    let wallet = db_wallet(&db_path)?;
 
     // Then that we are on the right network..
     let db_net = db_network(&db_path)?;
     if db_net != revaultd.bitcoind_config.network {
-        return Err(DatabaseError(format!(
+        return Err(DatabaseError::new(format!(
             "Invalid network. Database is on '{}' but config says '{}'.",
             db_net, revaultd.bitcoind_config.network
         )));
@@ -148,19 +152,19 @@ fn check_db(revaultd: &RevaultD) -> Result<(), DatabaseError> {
 
     // .. And managing the same Scripts!
     if revaultd.deposit_descriptor != wallet.deposit_descriptor {
-        return Err(DatabaseError(format!(
+        return Err(DatabaseError::new(format!(
             "Database Deposit descriptor mismatch: '{}' (config) vs '{}' (database)",
             revaultd.deposit_descriptor, wallet.deposit_descriptor
         )));
     }
     if revaultd.unvault_descriptor != wallet.unvault_descriptor {
-        return Err(DatabaseError(format!(
+        return Err(DatabaseError::new(format!(
             "Database Unvault descriptor mismatch: '{}' (config) vs '{}' (database)",
             revaultd.unvault_descriptor, wallet.unvault_descriptor
         )));
     }
     if revaultd.cpfp_descriptor != wallet.cpfp_descriptor {
-        return Err(DatabaseError(format!(
+        return Err(DatabaseError::new(format!(
             "Database Cpfp descriptor mismatch: '{}' (config) vs '{}' (database)",
             revaultd.cpfp_descriptor, wallet.cpfp_descriptor
         )));
@@ -179,43 +183,339 @@ fn state_from_db(revaultd: &mut RevaultD) -> Result<(), DatabaseError> {
     revaultd.current_unused_index = wallet.deposit_derivation_index;
     // Of course, it's no good... Miniscript on bitcoind soon :tm:
     // FIXME: in the meantime, reversed gap limit?
-    let raw_index: u32 = revaultd.current_unused_index.into();
-    (0..raw_index + revaultd.gap_limit()).for_each(|i| {
-        // FIXME: this should fail instead of creating a hardened index
-        let index = ChildNumber::from(i);
-        revaultd.derivation_index_map.insert(
-            revaultd
-                .deposit_descriptor
-                .derive(index, &revaultd.secp_ctx)
-                .inner()
-                .address(revaultd.bitcoind_config.network)
-                .expect("deposit_descriptor is a wsh")
-                .script_pubkey(),
-            index,
-        );
-    });
+    let indexes: Vec<ChildNumber> = (0..revaultd.watched_range_end())
+        .map(ChildNumber::from)
+        .collect();
+    revaultd.watch_deposit_indexes(&indexes);
     revaultd.wallet_id = Some(wallet.id);
+    revaultd
+        .maintenance
+        .store(wallet.maintenance, atomic::Ordering::Relaxed);
+
+    // A rotated Emergency address supersedes the one from the config
+    if let Some(emer_address) = wallet.emergency_address {
+        if revaultd.is_stakeholder() {
+            if revaultd.emergency_address.as_ref() != Some(&emer_address) {
+                log::info!(
+                    "Using the rotated Emergency address '{}' instead of the configured one",
+                    emer_address
+                );
+            }
+            revaultd.emergency_address = Some(emer_address);
+        }
+    }
+
+    Ok(())
+}
+
+// Get the value to store for this PSBT, encrypted if the database is. We'd rather fail than
+// store it in clear into an encrypted database if we weren't given its key.
+fn db_psbt_value(
+    db_tx: &rusqlite::Transaction,
+    db_key: Option<&DbKey>,
+    psbt: Vec<u8>,
+) -> Result<Vec<u8>, DatabaseError> {
+    if let Some(key) = db_key {
+        return Ok(key.encrypt(&psbt));
+    }
+
+    let is_encrypted: bool = db_tx.query_row(
+        "SELECT encryption_salt IS NOT NULL FROM wallets",
+        rusqlite::NO_PARAMS,
+        |row| row.get(0),
+    )?;
+    if is_encrypted {
+        return Err(DatabaseError::new(
+            "Database is encrypted but we have no key to encrypt the transaction with".to_string(),
+        ));
+    }
+
+    Ok(psbt)
+}
+
+// Rebuild the database file and empty the WAL, so that no copy of the values we overwrote is
+// left in the free pages or in the journal.
+fn db_vacuum(db_path: &Path) -> Result<(), DatabaseError> {
+    let conn = rusqlite::Connection::open(db_path)
+        .map_err(|e| DatabaseError::sqlite("Opening database", e))?;
+    conn.busy_timeout(Duration::from_secs(60))?;
+    conn.execute_batch("VACUUM;")
+        .map_err(|e| DatabaseError::sqlite("Vacuuming database", e))?;
+    conn.query_row(
+        "PRAGMA wal_checkpoint(TRUNCATE)",
+        rusqlite::NO_PARAMS,
+        |_| Ok(()),
+    )
+    .map_err(|e| DatabaseError::sqlite("Checkpointing database", e))?;
+
+    Ok(())
+}
+
+// Re-encrypt all the transactions of the database with this key, and store its salt and check
+// value in the wallet. The transactions may be in clear or encrypted with the current key.
+fn db_encrypt_transactions(
+    db_path: &Path,
+    current_key: Option<&DbKey>,
+    key: &DbKey,
+    salt: &[u8],
+) -> Result<(), DatabaseError> {
+    db_exec(db_path, |db_tx| {
+        for table in &["presigned_transactions", "spend_transactions"] {
+            let rows = db_tx
+                .prepare(&format!("SELECT id, psbt FROM {}", table))?
+                .query_map(rusqlite::NO_PARAMS, |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, Vec<u8>>(1)?))
+                })?
+                .collect::<rusqlite::Result<Vec<(i64, Vec<u8>)>>>()?;
+            for (id, psbt) in rows {
+                let psbt = key.encrypt(&decrypt_value(current_key, psbt)?);
+                db_tx.execute(
+                    &format!("UPDATE {} SET psbt = (?1) WHERE id = (?2)", table),
+                    params![psbt, id],
+                )?;
+            }
+        }
+
+        db_tx
+            .execute(
+                "UPDATE wallets SET encryption_salt = (?1), encryption_check = (?2)",
+                params![salt, key.check_value()],
+            )
+            .map_err(|e| DatabaseError::sqlite("Storing encryption salt", e))?;
+
+        Ok(())
+    })?;
+
+    db_vacuum(db_path)
+}
+
+// Called on startup to get the key to encrypt and decrypt the transactions with, if the database
+// is encrypted. An unencrypted database is encrypted on the first startup with 'db_encryption'.
+fn setup_db_encryption(revaultd: &mut RevaultD) -> Result<(), DatabaseError> {
+    let db_path = revaultd.db_file();
+    let wallet = db_wallet(&db_path)?;
+
+    match (revaultd.db_encryption, wallet.encryption) {
+        (false, None) => revaultd.db_key = None,
+        (false, Some(_)) => {
+            return Err(DatabaseError::new(
+                "Database is encrypted but 'db_encryption' is not set".to_string(),
+            ))
+        }
+        (true, encryption) => {
+            let passphrase = revaultd.db_passphrase.as_ref().ok_or_else(|| {
+                DatabaseError::new("No passphrase to decrypt the database with".to_string())
+            })?;
+            if let Some((salt, check)) = encryption {
+                let key = DbKey::derive(passphrase, &salt)?;
+                if !key.verify(&check) {
+                    return Err(DatabaseError::new(
+                        "Invalid database passphrase".to_string(),
+                    ));
+                }
+                revaultd.db_key = Some(key);
+            } else {
+                log::info!("Encrypting the transactions stored in the database");
+                let (key, salt) = DbKey::generate(passphrase)?;
+                db_encrypt_transactions(&db_path, None, &key, &salt)?;
+                revaultd.db_key = Some(key);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Re-encrypt the transactions stored in this database with a key derived from a new passphrase.
+/// The database must have been set up with the current passphrase.
+pub fn db_rotate_passphrase(
+    revaultd: &mut RevaultD,
+    new_passphrase: &str,
+) -> Result<(), DatabaseError> {
+    let db_path = revaultd.db_file();
+    if db_wallet(&db_path)?.encryption.is_none() {
+        return Err(DatabaseError::new("Database is not encrypted".to_string()));
+    }
+
+    let (key, salt) = DbKey::generate(new_passphrase)?;
+    db_encrypt_transactions(&db_path, revaultd.db_key.as_ref(), &key, &salt)?;
+    revaultd.db_key = Some(key);
 
     Ok(())
 }
 
+// How many times to retry setting up the database if it is locked by another process, and how
+// long to wait before the first retry. The delay is doubled at each subsequent retry.
+const SETUP_DB_RETRIES: u32 = 5;
+const SETUP_DB_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
 /// This integrity checks the database, creates it if it doesn't exist, and populates miniscript
 /// descriptors in the global state. They are already parsed at compile time in order to be able
 /// to populate the wallets table if the database does not exist and are always replaced here by
 /// the one from the database (compilation from config policy is non-deterministic!)
-pub fn setup_db(revaultd: &mut RevaultD) -> Result<(), DatabaseError> {
+fn try_setup_db(revaultd: &mut RevaultD) -> Result<(), DatabaseError> {
     let db_path = revaultd.db_file();
     if !db_path.exists() {
         log::info!("No database at {:?}, creating a new one.", db_path);
-        create_db(&revaultd)?;
+        create_db(revaultd)?;
     }
 
-    check_db(&revaultd)?;
+    db_set_wal_mode(&db_path, revaultd.sqlite_wal_mode)?;
+    check_db(revaultd)?;
+    setup_db_encryption(revaultd)?;
     state_from_db(revaultd)?;
 
     Ok(())
 }
 
+// The journal mode is persisted in the database file, so we need to set it back if WAL was
+// disabled in the configuration.
+fn db_set_wal_mode(db_path: &Path, wal: bool) -> Result<(), DatabaseError> {
+    let mode = if wal { "wal" } else { "delete" };
+    let conn = rusqlite::Connection::open(db_path)
+        .map_err(|e| DatabaseError::sqlite("Opening database", e))?;
+    conn.busy_timeout(Duration::from_secs(60))?;
+    let new_mode: String = conn
+        .query_row(
+            &format!("PRAGMA journal_mode = {}", mode),
+            rusqlite::NO_PARAMS,
+            |row| row.get(0),
+        )
+        .map_err(|e| DatabaseError::sqlite("Setting journal mode", e))?;
+    if !new_mode.eq_ignore_ascii_case(mode) {
+        return Err(DatabaseError::new(format!(
+            "Could not set the journal mode to '{}', it is '{}'",
+            mode, new_mode
+        )));
+    }
+
+    Ok(())
+}
+
+/// Create the database if needed, check it and load our state from it. If another process
+/// holds a lock on the database we retry a few times before giving up, any other error
+/// (corrupted or incompatible database) is returned right away.
+pub fn setup_db(revaultd: &mut RevaultD) -> Result<(), DatabaseError> {
+    let mut delay = SETUP_DB_RETRY_INTERVAL;
+    let mut retries = 0;
+    loop {
+        match try_setup_db(revaultd) {
+            Err(e) if retries < SETUP_DB_RETRIES && e.is_locked() => {
+                retries += 1;
+                log::warn!(
+                    "Database is locked: '{}'. Retrying in {:?} ({}/{}).",
+                    e,
+                    delay,
+                    retries,
+                    SETUP_DB_RETRIES
+                );
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            res => return res,
+        }
+    }
+}
+
+// How many pages to copy at once during a backup, and how long to wait in between. The source
+// database is only locked while copying a batch, so we don't block the daemon's writes.
+const BACKUP_PAGES_PER_STEP: i32 = 100;
+const BACKUP_STEP_PAUSE: Duration = Duration::from_millis(10);
+
+// Copy the database at 'src' over the one at 'dst' using SQLite's online backup API
+fn db_copy(src: &Path, dst: &Path) -> Result<(), DatabaseError> {
+    let src_conn =
+        rusqlite::Connection::open_with_flags(src, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| DatabaseError::sqlite(&format!("Opening database at {:?}", src), e))?;
+    src_conn.busy_timeout(Duration::from_secs(60))?;
+    let mut dst_conn = rusqlite::Connection::open(dst)
+        .map_err(|e| DatabaseError::sqlite(&format!("Opening database at {:?}", dst), e))?;
+    dst_conn.busy_timeout(Duration::from_secs(60))?;
+
+    let backup = rusqlite::backup::Backup::new(&src_conn, &mut dst_conn)?;
+    backup
+        .run_to_completion(BACKUP_PAGES_PER_STEP, BACKUP_STEP_PAUSE, None)
+        .map_err(|e| DatabaseError::sqlite(&format!("Copying {:?} to {:?}", src, dst), e))?;
+
+    Ok(())
+}
+
+/// Check the database file at this path is not corrupted and could be used by this version.
+/// Returns its version.
+pub fn db_verify_file(db_path: &Path) -> Result<u32, DatabaseError> {
+    if !db_path.exists() {
+        return Err(DatabaseError::new(format!("No database at {:?}", db_path)));
+    }
+    let conn =
+        rusqlite::Connection::open_with_flags(db_path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| DatabaseError::sqlite("Opening database", e))?;
+    let errors = conn
+        .prepare("PRAGMA integrity_check")?
+        .query_map(rusqlite::NO_PARAMS, |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    if errors != ["ok"] {
+        return Err(DatabaseError::new(format!(
+            "Integrity check failed: {}",
+            errors.join(", ")
+        )));
+    }
+    drop(conn);
+
+    check_db_schema(db_path)
+}
+
+/// Snapshot the database to a new file while it is in use, and check the snapshot.
+pub fn db_backup(db_path: &Path, backup_path: &Path) -> Result<(), DatabaseError> {
+    if backup_path.exists() {
+        return Err(DatabaseError::new(format!(
+            "There is already a file at {:?}",
+            backup_path
+        )));
+    }
+
+    // Don't leave a snapshot we couldn't check behind
+    if let Err(e) = db_copy(db_path, backup_path).and_then(|_| db_verify_file(backup_path)) {
+        fs::remove_file(backup_path).unwrap_or(());
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// Replace the database with a backup, after checking it can be used with this configuration.
+/// The previous database is kept in the data directory, and its path is returned. Must not be
+/// called while another revaultd is using the database.
+pub fn db_restore(
+    revaultd: &RevaultD,
+    backup_path: &Path,
+) -> Result<Option<PathBuf>, DatabaseError> {
+    db_verify_file(backup_path)?;
+
+    let db_path = revaultd.db_file();
+    let previous_db = if db_path.exists() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let previous_path = db_path.with_extension(format!("pre-restore-{}.sqlite3", now));
+        db_copy(&db_path, &previous_path)?;
+        Some(previous_path)
+    } else {
+        None
+    };
+
+    // Put the previous database back if the backup is for another network or wallet
+    if let Err(e) = db_copy(backup_path, &db_path).and_then(|_| check_db(revaultd)) {
+        if let Some(ref previous_path) = previous_db {
+            db_copy(previous_path, &db_path)?;
+            fs::remove_file(previous_path).unwrap_or(());
+        }
+        return Err(e);
+    }
+
+    Ok(previous_db)
+}
+
 pub fn db_update_tip_dbtx(
     db_tx: &rusqlite::Transaction,
     tip: &BlockchainTip,
@@ -225,7 +525,7 @@ pub fn db_update_tip_dbtx(
             "UPDATE tip SET blockheight = (?1), blockhash = (?2)",
             params![tip.height, tip.hash.to_vec()],
         )
-        .map_err(|e| DatabaseError(format!("Inserting new tip: {}", e.to_string())))
+        .map_err(|e| DatabaseError::sqlite("Inserting new tip", e))
         .map(|_| ())
 }
 
@@ -244,7 +544,119 @@ pub fn db_update_deposit_index(
             "UPDATE wallets SET deposit_derivation_index = (?1)",
             params![new_index],
         )
-        .map_err(|e| DatabaseError(format!("Inserting new derivation index: {}", e.to_string())))?;
+        .map_err(|e| DatabaseError::sqlite("Inserting new derivation index", e))?;
+
+        Ok(())
+    })
+}
+
+/// Mark a vault as excluded (or not anymore) from the vaults we manage
+pub fn db_exclude_vault(
+    db_path: &Path,
+    vault_id: u32,
+    excluded: bool,
+) -> Result<(), DatabaseError> {
+    db_exec(db_path, |tx| {
+        tx.execute(
+            "UPDATE vaults SET excluded = (?1) WHERE id = (?2)",
+            params![excluded, vault_id],
+        )
+        .map_err(|e| DatabaseError::sqlite("Updating vault exclusion", e))?;
+
+        Ok(())
+    })
+}
+
+/// Label the deposit address at this derivation index, replacing any previous label
+pub fn db_label_deposit_address(
+    db_path: &Path,
+    wallet_id: u32,
+    derivation_index: ChildNumber,
+    label: &str,
+) -> Result<(), DatabaseError> {
+    let derivation_index: u32 = derivation_index.into();
+    db_exec(db_path, |tx| {
+        tx.execute(
+            "INSERT OR REPLACE INTO deposit_labels (wallet_id, derivation_index, label) \
+             VALUES (?1, ?2, ?3)",
+            params![wallet_id, derivation_index, label],
+        )
+        .map_err(|e| DatabaseError::sqlite("Inserting deposit label", e))?;
+
+        Ok(())
+    })
+}
+
+/// Label a vault, replacing any previous label, or remove its label if None
+pub fn db_label_vault(
+    db_path: &Path,
+    vault_id: u32,
+    label: Option<&str>,
+) -> Result<(), DatabaseError> {
+    db_exec(db_path, |tx| {
+        match label {
+            Some(label) => tx.execute(
+                "INSERT OR REPLACE INTO vault_labels (vault_id, label) VALUES (?1, ?2)",
+                params![vault_id, label],
+            ),
+            None => tx.execute(
+                "DELETE FROM vault_labels WHERE vault_id = (?1)",
+                params![vault_id],
+            ),
+        }
+        .map_err(|e| DatabaseError::sqlite("Updating vault label", e))?;
+
+        Ok(())
+    })
+}
+
+/// Set the Emergency address to use for the presigned transactions of future vaults
+pub fn db_update_emergency_address(
+    db_path: &Path,
+    wallet_id: u32,
+    emer_address: &EmergencyAddress,
+) -> Result<(), DatabaseError> {
+    db_exec(db_path, |tx| {
+        tx.execute(
+            "UPDATE wallets SET emergency_address = (?1) WHERE id = (?2)",
+            params![emer_address.to_string(), wallet_id],
+        )
+        .map_err(|e| DatabaseError::sqlite("Updating emergency address", e))?;
+
+        Ok(())
+    })
+}
+
+/// Set whether we must refuse to broadcast any transaction
+pub fn db_set_maintenance(
+    db_path: &Path,
+    wallet_id: u32,
+    maintenance: bool,
+) -> Result<(), DatabaseError> {
+    db_exec(db_path, |tx| {
+        tx.execute(
+            "UPDATE wallets SET maintenance = (?1) WHERE id = (?2)",
+            params![maintenance, wallet_id],
+        )
+        .map_err(|e| DatabaseError::sqlite("Updating maintenance mode", e))?;
+
+        Ok(())
+    })
+}
+
+/// Record that this watchtower acknowledged it is guarding this vault
+pub fn db_mark_watchtower_ack(
+    db_path: &Path,
+    vault_id: u32,
+    watchtower_key: &[u8; 32],
+) -> Result<(), DatabaseError> {
+    db_exec(db_path, |tx| {
+        tx.execute(
+            "INSERT OR IGNORE INTO watchtower_acks (vault_id, watchtower_key, acked_at) \
+             VALUES (?1, ?2, strftime('%s','now'))",
+            params![vault_id, watchtower_key.to_vec()],
+        )
+        .map_err(|e| DatabaseError::sqlite("Inserting watchtower ack", e))?;
 
         Ok(())
     })
@@ -280,14 +692,14 @@ pub fn db_insert_new_unconfirmed_vault(
                 received_at,
             ],
         )
-        .map_err(|e| DatabaseError(format!("Inserting vault: {}", e.to_string())))?;
+        .map_err(|e| DatabaseError::sqlite("Inserting vault", e))?;
 
         Ok(())
     })
 }
 
 macro_rules! db_store_unsigned_transactions {
-    ($db_tx:ident, $vault_id:ident, [$( $tx:ident ),*]) => {
+    ($db_key:ident, $db_tx:ident, $vault_id:ident, [$( $tx:ident ),*]) => {
             $(
                 // We store the transactions without any feebump input. Note that this assertion
                 // would fail if/when we implement multi-inputs Unvaults.
@@ -297,13 +709,14 @@ macro_rules! db_store_unsigned_transactions {
 
                 let tx_type = TransactionType::from($tx);
                 let txid = $tx.txid();
+                let psbt = db_psbt_value($db_tx, $db_key, $tx.as_psbt_serialized())?;
                 $db_tx
                     .execute(
                         "INSERT INTO presigned_transactions (vault_id, type, psbt, txid, fullysigned) VALUES (?1, ?2, ?3 , ?4, ?5)",
-                        params![$vault_id, tx_type as u32, $tx.as_psbt_serialized(), txid.to_vec(), false as u32],
+                        params![$vault_id, tx_type as u32, psbt, txid.to_vec(), false as u32],
                     )
                     .map_err(|e| {
-                        DatabaseError(format!("Inserting psbt in vault '{}': {}", $vault_id, e))
+                        DatabaseError::sqlite(&format!("Inserting psbt in vault '{}'", $vault_id), e)
                     })?;
             )*
     };
@@ -312,8 +725,10 @@ macro_rules! db_store_unsigned_transactions {
 /// Mark an unconfirmed deposit as being in 'Funded' state (confirmed), as well as storing the
 /// unsigned "presigned-transactions".
 /// The `emer_tx` and `unemer_tx` may only be passed for stakeholders.
+#[allow(clippy::too_many_arguments)]
 pub fn db_confirm_deposit(
     db_path: &Path,
+    db_key: Option<&DbKey>,
     outpoint: &OutPoint,
     blockheight: u32,
     unvault_tx: &UnvaultTransaction,
@@ -323,7 +738,7 @@ pub fn db_confirm_deposit(
 ) -> Result<(), DatabaseError> {
     let vault_id = db_vault_by_deposit(db_path, outpoint)?
         .ok_or_else(|| {
-            DatabaseError(format!(
+            DatabaseError::new(format!(
                 "Confirming '{}' but it does not exist in db?",
                 outpoint
             ))
@@ -336,18 +751,19 @@ pub fn db_confirm_deposit(
                 "UPDATE vaults SET status = (?1), blockheight = (?2), updated_at = strftime('%s','now') WHERE id = (?3)",
                 params![VaultStatus::Funded as u32, blockheight, vault_id,],
             )
-            .map_err(|e| DatabaseError(format!("Updating vault to 'funded': {}", e.to_string())))?;
+            .map_err(|e| DatabaseError::sqlite("Updating vault to 'funded'", e))?;
 
         match (emer_tx, unemer_tx) {
             (Some(emer_tx), Some(unemer_tx)) => {
                 db_store_unsigned_transactions!(
+                    db_key,
                     db_tx,
                     vault_id,
                     [unvault_tx, cancel_tx, emer_tx, unemer_tx]
                 );
             }
             (None, None) => {
-                db_store_unsigned_transactions!(db_tx, vault_id, [unvault_tx, cancel_tx]);
+                db_store_unsigned_transactions!(db_key, db_tx, vault_id, [unvault_tx, cancel_tx]);
             }
             _ => unreachable!(),
         }
@@ -450,7 +866,7 @@ fn db_status_from_unvault_txid(
              WHERE vaults.id IN (SELECT vault_id FROM presigned_transactions WHERE txid = (?2))",
             params![status as u32, unvault_txid.to_vec(),],
         )
-        .map_err(|e| DatabaseError(format!("Updating vault to '{}': {}", status, e.to_string())))?;
+        .map_err(|e| DatabaseError::sqlite(&format!("Updating vault to '{}'", status), e))?;
 
         Ok(())
     })
@@ -461,6 +877,12 @@ pub fn db_unvault_deposit(db_path: &Path, unvault_txid: &Txid) -> Result<(), Dat
     db_status_from_unvault_txid(db_path, unvault_txid, VaultStatus::Unvaulting)
 }
 
+/// Mark an 'unvaulting' vault as 'active' again, out of the Unvault txid. This is used when the
+/// Unvault transaction left the mempool without being mined.
+pub fn db_revert_unvault(db_path: &Path, unvault_txid: &Txid) -> Result<(), DatabaseError> {
+    db_status_from_unvault_txid(db_path, unvault_txid, VaultStatus::Active)
+}
+
 /// Mark a vault as being in the 'unvaulted' state, out of the Unvault txid
 pub fn db_confirm_unvault(db_path: &Path, unvault_txid: &Txid) -> Result<(), DatabaseError> {
     db_status_from_unvault_txid(db_path, unvault_txid, VaultStatus::Unvaulted)
@@ -476,14 +898,38 @@ pub fn db_spend_unvault(
     db_path: &Path,
     unvault_txid: &Txid,
     spend_txid: &Txid,
+) -> Result<(), DatabaseError> {
+    db_spent_unvault_as(db_path, unvault_txid, spend_txid, VaultStatus::Spending)
+}
+
+/// Mark a vault as having its Unvault spent by an unexpected transaction, out of the Unvault
+/// txid. We record the txid of the offending transaction.
+pub fn db_unexpected_spend_unvault(
+    db_path: &Path,
+    unvault_txid: &Txid,
+    spender_txid: &Txid,
+) -> Result<(), DatabaseError> {
+    db_spent_unvault_as(
+        db_path,
+        unvault_txid,
+        spender_txid,
+        VaultStatus::UnexpectedSpend,
+    )
+}
+
+fn db_spent_unvault_as(
+    db_path: &Path,
+    unvault_txid: &Txid,
+    spender_txid: &Txid,
+    status: VaultStatus,
 ) -> Result<(), DatabaseError> {
     db_exec(db_path, |tx| {
         tx.execute(
             "UPDATE vaults SET status = (?1), updated_at = strftime('%s','now'), spend_txid = (?2) \
              WHERE vaults.id IN (SELECT vault_id FROM presigned_transactions WHERE txid = (?3))",
-            params![VaultStatus::Spending as u32, spend_txid.to_vec(), unvault_txid.to_vec(),],
+            params![status as u32, spender_txid.to_vec(), unvault_txid.to_vec(),],
         )
-        .map_err(|e| DatabaseError(format!("Updating vault to 'spending': {}", e.to_string())))?;
+        .map_err(|e| DatabaseError::sqlite(&format!("Updating vault to '{}'", status), e))?;
 
         Ok(())
     })
@@ -505,30 +951,72 @@ fn db_mark_vault_as(
              WHERE vaults.id = (?2)",
             params![status as u32, vault_id,],
         )
-        .map_err(|e| DatabaseError(format!("Updating vault to '{}': {}", status, e.to_string())))?;
+        .map_err(|e| DatabaseError::sqlite(&format!("Updating vault to '{}'", status), e))?;
 
         Ok(())
     })
 }
 
 pub fn db_mark_spent_unvault(db_path: &Path, vault_id: u32) -> Result<(), DatabaseError> {
-    db_mark_vault_as(&db_path, vault_id, VaultStatus::Spent)
+    db_mark_vault_as(db_path, vault_id, VaultStatus::Spent)
 }
 
 pub fn db_mark_canceled_unvault(db_path: &Path, vault_id: u32) -> Result<(), DatabaseError> {
-    db_mark_vault_as(&db_path, vault_id, VaultStatus::Canceled)
+    db_mark_vault_as(db_path, vault_id, VaultStatus::Canceled)
 }
 
 pub fn db_mark_emergencied_unvault(db_path: &Path, vault_id: u32) -> Result<(), DatabaseError> {
-    db_mark_vault_as(&db_path, vault_id, VaultStatus::UnvaultEmergencyVaulted)
+    db_mark_vault_as(db_path, vault_id, VaultStatus::UnvaultEmergencyVaulted)
 }
 
 pub fn db_mark_emergencying_vault(db_path: &Path, vault_id: u32) -> Result<(), DatabaseError> {
-    db_mark_vault_as(&db_path, vault_id, VaultStatus::EmergencyVaulting)
+    db_mark_vault_as(db_path, vault_id, VaultStatus::EmergencyVaulting)
 }
 
 pub fn db_mark_emergencied_vault(db_path: &Path, vault_id: u32) -> Result<(), DatabaseError> {
-    db_mark_vault_as(&db_path, vault_id, VaultStatus::EmergencyVaulted)
+    db_mark_vault_as(db_path, vault_id, VaultStatus::EmergencyVaulted)
+}
+
+/// Mark a vault whose deposit was spent before it got secured.
+pub fn db_mark_aborted_vault(db_path: &Path, vault_id: u32) -> Result<(), DatabaseError> {
+    db_mark_vault_as(db_path, vault_id, VaultStatus::Aborted)
+}
+
+/// Restore the status a vault had before it got marked as aborted, for instance because the
+/// transaction spending its deposit was reorged out. Returns the restored status.
+pub fn db_unabort_vault_dbtx(
+    db_tx: &rusqlite::Transaction,
+    vault_id: u32,
+) -> Result<VaultStatus, DatabaseError> {
+    let status: u32 = db_tx.query_row(
+        "SELECT status FROM vault_status_history WHERE vault_id = (?1) AND status != (?2) \
+         ORDER BY id DESC LIMIT 1",
+        params![vault_id, VaultStatus::Aborted as u32],
+        |row| row.get(0),
+    )?;
+    let status: VaultStatus = status
+        .try_into()
+        .map_err(|_| DatabaseError::new(format!("Invalid vault status '{}' in history", status)))?;
+
+    if status == VaultStatus::Unconfirmed {
+        db_unconfirm_deposit_dbtx(db_tx, vault_id)?;
+    } else {
+        dbtx_downgrade(db_tx, vault_id, status)?;
+    }
+
+    Ok(status)
+}
+
+/// Restore the status a vault had before it got marked as aborted, for instance because the
+/// transaction spending its deposit was evicted from the mempool. Returns the restored status.
+pub fn db_unabort_vault(db_path: &Path, vault_id: u32) -> Result<VaultStatus, DatabaseError> {
+    let mut status = VaultStatus::Aborted;
+    db_exec(db_path, |db_tx| {
+        status = db_unabort_vault_dbtx(db_tx, vault_id)?;
+        Ok(())
+    })?;
+
+    Ok(status)
 }
 
 /// Mark that we actually signed this vault's revocation txs, and stored the signatures for it.
@@ -543,7 +1031,7 @@ pub fn db_mark_securing_vault(db_path: &Path, vault_id: u32) -> Result<(), Datab
                 VaultStatus::Funded as u32
             ],
         )
-        .map_err(|e| DatabaseError(format!("Updating vault to 'securing': {}", e.to_string())))?;
+        .map_err(|e| DatabaseError::sqlite("Updating vault to 'securing'", e))?;
 
         Ok(())
     })
@@ -561,7 +1049,7 @@ pub fn db_mark_activating_vault(db_path: &Path, vault_id: u32) -> Result<(), Dat
                 VaultStatus::Secured as u32
             ],
         )
-        .map_err(|e| DatabaseError(format!("Updating vault to 'securing': {}", e.to_string())))?;
+        .map_err(|e| DatabaseError::sqlite("Updating vault to 'securing'", e))?;
 
         Ok(())
     })
@@ -583,6 +1071,7 @@ fn revault_tx_merge_sigs(
 /// the same database transaction.
 pub fn db_update_presigned_tx(
     db_path: &Path,
+    db_key: Option<&DbKey>,
     vault_id: u32,
     tx_db_id: u32,
     sigs: BTreeMap<BitcoinPubKey, Vec<u8>>,
@@ -592,17 +1081,19 @@ pub fn db_update_presigned_tx(
         let mut is_unvault = false;
 
         // Fetch the PSBT in the transaction, to avoid someone else to modify it under our feet..
-        let presigned_tx: DbTransaction = db_tx
-            .prepare("SELECT * FROM presigned_transactions WHERE id = (?1)")?
-            .query(params![tx_db_id])?
-            .next()?
-            .ok_or_else(|| {
-                DatabaseError(format!(
-                    "Transaction with id '{}' (vault id '{}') not found in db",
-                    tx_db_id, vault_id
-                ))
-            })?
-            .try_into()?;
+        let presigned_tx = db_transaction_from_row(
+            db_tx
+                .prepare("SELECT * FROM presigned_transactions WHERE id = (?1)")?
+                .query(params![tx_db_id])?
+                .next()?
+                .ok_or_else(|| {
+                    DatabaseError::new(format!(
+                        "Transaction with id '{}' (vault id '{}') not found in db",
+                        tx_db_id, vault_id
+                    ))
+                })?,
+            db_key,
+        )?;
         // Now we are safe merging the signatures on what is the latest version of the PSBT
         let (fully_signed, raw_psbt) = match presigned_tx.psbt {
             RevaultTx::Cancel(mut tx) => revault_tx_merge_sigs(&mut tx, sigs, secp_ctx)?,
@@ -615,6 +1106,7 @@ pub fn db_update_presigned_tx(
             }
         };
 
+        let raw_psbt = db_psbt_value(db_tx, db_key, raw_psbt)?;
         db_tx.execute(
             "UPDATE presigned_transactions SET psbt = (?1), fullysigned = (?2) WHERE id = (?3)",
             params![raw_psbt, fully_signed, tx_db_id],
@@ -638,7 +1130,7 @@ pub fn db_update_presigned_tx(
                         params![VaultStatus::Secured as u32, vault_id],
                     )
                     .map_err(|e| {
-                        DatabaseError(format!("Updating vault to 'secured': {}", e.to_string()))
+                        DatabaseError::sqlite("Updating vault to 'secured'", e)
                     })?;
             }
 
@@ -650,7 +1142,7 @@ pub fn db_update_presigned_tx(
                         params![VaultStatus::Active as u32, vault_id],
                     )
                     .map_err(|e| {
-                        DatabaseError(format!("Updating vault to 'active': {}", e.to_string()))
+                        DatabaseError::sqlite("Updating vault to 'active'", e)
                     })?;
             }
         }
@@ -662,6 +1154,7 @@ pub fn db_update_presigned_tx(
 /// Insert a new Spend transaction in the database
 pub fn db_insert_spend(
     db_path: &Path,
+    db_key: Option<&DbKey>,
     // FIXME: Rust newbie: i *don't* need to be moving this. So i want to take &[&T] but i can't
     // have it working in a generic manner (eg once by passing a slice the second time by passing a
     // Vec<T> somehow)
@@ -669,16 +1162,16 @@ pub fn db_insert_spend(
     spend_tx: &SpendTransaction,
 ) -> Result<(), DatabaseError> {
     let spend_txid = spend_tx.txid();
-    let spend_psbt = spend_tx.as_psbt_serialized();
 
     db_exec(db_path, |db_tx| {
+        let spend_psbt = db_psbt_value(db_tx, db_key, spend_tx.as_psbt_serialized())?;
         db_tx.execute(
             "INSERT INTO spend_transactions (psbt, txid, broadcasted) VALUES (?1, ?2, NULL)",
             params![spend_psbt, spend_txid.to_vec()],
         )?;
         let spend_id = db_tx.last_insert_rowid();
 
-        for unvault_tx in unvault_txs.into_iter() {
+        for unvault_tx in unvault_txs.iter() {
             db_tx.execute(
                 "INSERT INTO spend_inputs (unvault_id, spend_id) VALUES (?1, ?2)",
                 params![unvault_tx.id, spend_id],
@@ -689,11 +1182,15 @@ pub fn db_insert_spend(
     })
 }
 
-pub fn db_update_spend(db_path: &Path, spend_tx: &SpendTransaction) -> Result<(), DatabaseError> {
+pub fn db_update_spend(
+    db_path: &Path,
+    db_key: Option<&DbKey>,
+    spend_tx: &SpendTransaction,
+) -> Result<(), DatabaseError> {
     let spend_txid = spend_tx.txid();
-    let spend_psbt = spend_tx.as_psbt_serialized();
 
     db_exec(db_path, |db_tx| {
+        let spend_psbt = db_psbt_value(db_tx, db_key, spend_tx.as_psbt_serialized())?;
         db_tx.execute(
             "UPDATE spend_transactions SET psbt = (?1) WHERE txid = (?2)",
             params![spend_psbt, spend_txid.to_vec()],
@@ -758,15 +1255,18 @@ pub fn db_mark_rebroadcastable_spend(
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::database::schema::DbSpendTransaction;
+    use crate::database::{
+        migrations::test::downgrade_to_v0,
+        schema::{DbSpendTransaction, DbVault},
+    };
     use crate::jsonrpc::UserRole;
     use crate::utils::test_utils::{dummy_revaultd, test_datadir};
     use revault_tx::{
-        bitcoin::{Network, OutPoint, PublicKey},
+        bitcoin::{Address, Network, OutPoint, PublicKey},
         transactions::{CancelTransaction, EmergencyTransaction, UnvaultEmergencyTransaction},
     };
 
-    use std::{fs, str::FromStr};
+    use std::{collections::HashSet, fs, str::FromStr};
 
     fn revault_tx_add_dummy_sig(tx: &mut impl RevaultTransaction, input_index: usize) {
         let pubkey = PublicKey::from_str(
@@ -788,17 +1288,17 @@ mod test {
         let datadir = test_datadir();
         let mut revaultd = dummy_revaultd(datadir.clone(), UserRole::ManagerStakeholder);
 
-        create_db(&mut revaultd).unwrap();
+        create_db(&revaultd).unwrap();
         // There must be a wallet entry now, and there is only one so its id must
         // be 0.
         assert_eq!(db_wallet(&revaultd.db_file()).unwrap().id, 1);
         // We can't create it twice
-        create_db(&mut revaultd).unwrap_err();
+        create_db(&revaultd).unwrap_err();
         // The version is right
-        check_db(&mut revaultd).unwrap();
+        check_db(&revaultd).unwrap();
         // But it would not open a database created for a different network
         revaultd.bitcoind_config.network = Network::Testnet;
-        check_db(&mut revaultd).unwrap_err();
+        check_db(&revaultd).unwrap_err();
         revaultd.bitcoind_config.network = Network::Bitcoin;
         // Neither would it accept to open a database from the future!
         db_exec(&revaultd.db_file(), |tx| {
@@ -807,9 +1307,88 @@ mod test {
             Ok(())
         })
         .unwrap();
-        check_db(&mut revaultd).unwrap_err();
+        check_db(&revaultd).unwrap_err();
+
+        fs::remove_dir_all(&datadir).unwrap_or(());
+    }
+
+    fn test_db_migration() {
+        let datadir = test_datadir();
+        let mut revaultd = dummy_revaultd(datadir.clone(), UserRole::ManagerStakeholder);
+        let db_path = revaultd.db_file();
+
+        // Create a database as it was in version 0, without the 'excluded', 'emergency_address'
+        // and 'maintenance' columns
+        create_db(&revaultd).unwrap();
+        downgrade_to_v0(&db_path);
+
+        // It gets upgraded at startup
+        setup_db(&mut revaultd).unwrap();
+        assert_eq!(db_version(&db_path).unwrap(), DB_VERSION);
+
+        // And we can exclude vaults
+        let outpoint = OutPoint::from_str(
+            "4d799e993665149109682555ba482b386aea03c5dbd62c059b48eb8f40f2f040:0",
+        )
+        .unwrap();
+        db_insert_new_unconfirmed_vault(
+            &db_path,
+            1,
+            &outpoint,
+            &Amount::from_sat(123456),
+            ChildNumber::from(3),
+            1615297315,
+        )
+        .unwrap();
+        let db_vault = db_vault_by_deposit(&db_path, &outpoint).unwrap().unwrap();
+        assert!(!db_vault.excluded);
+
+        // Its status history is recorded from now on
+        let history = db_vault_status_history(&db_path, db_vault.id).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].status, VaultStatus::Unconfirmed);
+        db_exclude_vault(&db_path, db_vault.id, true).unwrap();
+        assert!(
+            db_vault_by_deposit(&db_path, &outpoint)
+                .unwrap()
+                .unwrap()
+                .excluded
+        );
+        db_exclude_vault(&db_path, db_vault.id, false).unwrap();
+        assert!(
+            !db_vault_by_deposit(&db_path, &outpoint)
+                .unwrap()
+                .unwrap()
+                .excluded
+        );
+
+        // And rotate the Emergency address, which is then used over the configured one
+        assert!(db_wallet(&db_path).unwrap().emergency_address.is_none());
+        let new_emer_address = EmergencyAddress::from(
+            Address::from_str("bc1qwqdg6squsna38e46795at95yu9atm8azzmyvckulcc7kytlcckxswvvzej")
+                .unwrap(),
+        )
+        .unwrap();
+        assert_ne!(revaultd.emergency_address, Some(new_emer_address.clone()));
+        db_update_emergency_address(&db_path, 1, &new_emer_address).unwrap();
+        assert_eq!(
+            db_wallet(&db_path).unwrap().emergency_address,
+            Some(new_emer_address.clone())
+        );
+        setup_db(&mut revaultd).unwrap();
+        assert_eq!(revaultd.emergency_address, Some(new_emer_address));
+
+        // And set the maintenance mode, which is loaded at startup
+        assert!(!db_wallet(&db_path).unwrap().maintenance);
+        db_set_maintenance(&db_path, 1, true).unwrap();
+        assert!(db_wallet(&db_path).unwrap().maintenance);
+        setup_db(&mut revaultd).unwrap();
+        assert!(revaultd.is_maintenance());
+        db_set_maintenance(&db_path, 1, false).unwrap();
+        setup_db(&mut revaultd).unwrap();
+        assert!(!revaultd.is_maintenance());
 
-        fs::remove_dir_all(&datadir).unwrap_or_else(|_| ());
+        fs::remove_dir_all(&datadir).unwrap_or(());
     }
 
     fn test_db_fetch_deposits() {
@@ -921,7 +1500,162 @@ mod test {
         assert!(deposit_outpoints.contains(&second_deposit_outpoint));
         assert!(deposit_outpoints.contains(&third_deposit_outpoint));
 
-        fs::remove_dir_all(&datadir).unwrap_or_else(|_| ());
+        // A second deposit to the same address is a distinct vault
+        let reused_deposit_outpoint = OutPoint::from_str(
+            "616efc37747c8cafc2f99692177a5400bad81b671d8d35ffa347d84b246e9a83:1",
+        )
+        .unwrap();
+        db_insert_new_unconfirmed_vault(
+            &db_path,
+            wallet_id,
+            &reused_deposit_outpoint,
+            &Amount::from_sat(1_000_000),
+            derivation_index,
+            received_at,
+        )
+        .unwrap();
+        let reused_outpoints: Vec<OutPoint> =
+            db_vaults_by_derivation_index(&db_path, derivation_index)
+                .unwrap()
+                .into_iter()
+                .map(|db_vault| db_vault.deposit_outpoint)
+                .collect();
+        assert_eq!(reused_outpoints.len(), 2);
+        assert!(reused_outpoints.contains(&third_deposit_outpoint));
+        assert!(reused_outpoints.contains(&reused_deposit_outpoint));
+        let deposit_outpoints: Vec<OutPoint> = db_deposits(&db_path)
+            .unwrap()
+            .into_iter()
+            .map(|db_vault| db_vault.deposit_outpoint)
+            .collect();
+        assert_eq!(deposit_outpoints.len(), 3);
+        assert!(deposit_outpoints.contains(&reused_deposit_outpoint));
+
+        // The watchtowers should only be guarding the Unvaulting one, until they ack it
+        let to_guard = db_vaults_to_guard(&db_path).unwrap();
+        assert_eq!(to_guard.len(), 1);
+        assert_eq!(to_guard[0].deposit_outpoint, first_deposit_outpoint);
+        let (wt_key_a, wt_key_b) = ([1; 32], [2; 32]);
+        db_mark_watchtower_ack(&db_path, to_guard[0].id, &wt_key_a).unwrap();
+        db_mark_watchtower_ack(&db_path, to_guard[0].id, &wt_key_a).unwrap();
+        assert_eq!(
+            db_watchtower_acked_vaults(&db_path, &wt_key_a).unwrap(),
+            vec![to_guard[0].id]
+        );
+        assert!(db_watchtower_acked_vaults(&db_path, &wt_key_b)
+            .unwrap()
+            .is_empty());
+
+        // Only the first one is confirmed, and its address isn't labeled yet
+        assert_eq!(
+            db_deposit_totals(&db_path).unwrap(),
+            vec![(None, Amount::from_sat(123456))]
+        );
+
+        // Labeling the addresses afterwards doesn't change the attribution of the past deposits
+        db_label_deposit_address(&db_path, wallet_id, ChildNumber::from(3), "alice").unwrap();
+        db_label_deposit_address(&db_path, wallet_id, derivation_index, "alice").unwrap();
+        db_label_deposit_address(&db_path, wallet_id, derivation_index, "bob").unwrap();
+        assert_eq!(
+            db_deposit_totals(&db_path).unwrap(),
+            vec![(None, Amount::from_sat(123456))]
+        );
+
+        // The new deposits are accounted under the label of the address when we received them,
+        // even if it's relabeled since then. Unconfirmed deposits aren't accounted.
+        let bob_outpoint = OutPoint::from_str(
+            "616efc37747c8cafc2f99692177a5400bad81b671d8d35ffa347d84b246e9a83:2",
+        )
+        .unwrap();
+        db_insert_new_unconfirmed_vault(
+            &db_path,
+            wallet_id,
+            &bob_outpoint,
+            &Amount::from_sat(500_000),
+            derivation_index,
+            received_at,
+        )
+        .unwrap();
+        db_label_deposit_address(&db_path, wallet_id, derivation_index, "carol").unwrap();
+        let carol_outpoint = OutPoint::from_str(
+            "616efc37747c8cafc2f99692177a5400bad81b671d8d35ffa347d84b246e9a83:3",
+        )
+        .unwrap();
+        db_insert_new_unconfirmed_vault(
+            &db_path,
+            wallet_id,
+            &carol_outpoint,
+            &Amount::from_sat(200_000),
+            derivation_index,
+            received_at,
+        )
+        .unwrap();
+        assert_eq!(
+            db_deposit_totals(&db_path).unwrap(),
+            vec![(None, Amount::from_sat(123456))]
+        );
+
+        // Once confirmed, they are accounted
+        db_exec(&db_path, |tx| {
+            tx.execute(
+                "UPDATE vaults SET status = (?1) WHERE status = (?2)",
+                params![VaultStatus::Funded as u32, VaultStatus::Unconfirmed as u32],
+            )
+            .unwrap();
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            db_deposit_totals(&db_path).unwrap(),
+            vec![
+                (None, Amount::from_sat(123456 + 456789 + 1_428_000)),
+                (Some("bob".to_string()), Amount::from_sat(500_000)),
+                (Some("carol".to_string()), Amount::from_sat(200_000)),
+            ]
+        );
+
+        // Once spent, a deposit isn't accounted anymore
+        db_exec(&db_path, |tx| {
+            tx.execute(
+                "UPDATE vaults SET status = (?1) WHERE amount = (?2)",
+                params![VaultStatus::Spent as u32, 456789],
+            )
+            .unwrap();
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            db_deposit_totals(&db_path).unwrap(),
+            vec![
+                (None, Amount::from_sat(123456 + 1_428_000)),
+                (Some("bob".to_string()), Amount::from_sat(500_000)),
+                (Some("carol".to_string()), Amount::from_sat(200_000)),
+            ]
+        );
+
+        // We can query the vaults by status, skipping some derivation indexes
+        let indexes = |vaults: Vec<DbVault>| -> Vec<u32> {
+            vaults.iter().map(|v| v.derivation_index.into()).collect()
+        };
+        let statuses = [VaultStatus::Unvaulting, VaultStatus::Spent];
+        assert_eq!(
+            indexes(db_vaults_with_status(&db_path, &statuses, &HashSet::new()).unwrap()),
+            vec![3, 12]
+        );
+        let skipped = vec![ChildNumber::from(12)].into_iter().collect();
+        assert_eq!(
+            indexes(db_vaults_with_status(&db_path, &statuses, &skipped).unwrap()),
+            vec![3]
+        );
+        assert_eq!(
+            indexes(db_vaults_with_status(&db_path, &[VaultStatus::Funded], &skipped).unwrap()),
+            vec![15; 4]
+        );
+        assert!(db_vaults_with_status(&db_path, &[], &HashSet::new())
+            .unwrap()
+            .is_empty());
+
+        fs::remove_dir_all(&datadir).unwrap_or(());
     }
 
     fn test_db_store_presigned_txs() {
@@ -932,8 +1666,8 @@ mod test {
         setup_db(&mut revaultd).unwrap();
 
         // There is no fully signed Emergency transaction at this point.
-        assert!(db_signed_emer_txs(&db_path).unwrap().is_empty());
-        assert!(db_signed_unemer_txs(&db_path).unwrap().is_empty());
+        assert!(db_signed_emer_txs(&db_path, None).unwrap().is_empty());
+        assert!(db_signed_unemer_txs(&db_path, None).unwrap().is_empty());
 
         // Let's insert a deposit
         let wallet_id = 1;
@@ -964,6 +1698,7 @@ mod test {
         let blockheight = 700000;
         db_confirm_deposit(
             &db_path,
+            None,
             &outpoint,
             blockheight,
             &fresh_unvault_tx,
@@ -974,11 +1709,11 @@ mod test {
         .unwrap();
 
         // There is still no *fully signed* Emergency transaction at this point!
-        assert!(db_signed_emer_txs(&db_path).unwrap().is_empty());
-        assert!(db_signed_unemer_txs(&db_path).unwrap().is_empty());
+        assert!(db_signed_emer_txs(&db_path, None).unwrap().is_empty());
+        assert!(db_signed_unemer_txs(&db_path, None).unwrap().is_empty());
 
         // Sanity check we can add sigs to them now
-        let (tx_db_id, stored_cancel_tx) = db_cancel_transaction(&db_path, db_vault.id)
+        let (tx_db_id, stored_cancel_tx) = db_cancel_transaction(&db_path, None, db_vault.id)
             .unwrap()
             .unwrap();
         assert_eq!(stored_cancel_tx.psbt().inputs[0].partial_sigs.len(), 0);
@@ -986,34 +1721,39 @@ mod test {
         revault_tx_add_dummy_sig(&mut cancel_tx, 0);
         db_update_presigned_tx(
             &db_path,
+            None,
             db_vault.id,
             tx_db_id,
             cancel_tx.psbt().inputs[0].partial_sigs.clone(),
             &revaultd.secp_ctx,
         )
         .unwrap();
-        let (_, stored_cancel_tx) = db_cancel_transaction(&db_path, db_vault.id)
+        let (_, stored_cancel_tx) = db_cancel_transaction(&db_path, None, db_vault.id)
             .unwrap()
             .unwrap();
         assert_eq!(stored_cancel_tx.psbt().inputs[0].partial_sigs.len(), 1);
 
-        let (tx_db_id, stored_emer_tx) =
-            db_emer_transaction(&db_path, db_vault.id).unwrap().unwrap();
+        let (tx_db_id, stored_emer_tx) = db_emer_transaction(&db_path, None, db_vault.id)
+            .unwrap()
+            .unwrap();
         assert_eq!(stored_emer_tx.psbt().inputs[0].partial_sigs.len(), 0);
         let mut emer_tx = fresh_emer_tx.clone();
         revault_tx_add_dummy_sig(&mut emer_tx, 0);
         db_update_presigned_tx(
             &db_path,
+            None,
             db_vault.id,
             tx_db_id,
             emer_tx.psbt().inputs[0].partial_sigs.clone(),
             &revaultd.secp_ctx,
         )
         .unwrap();
-        let (_, stored_emer_tx) = db_emer_transaction(&db_path, db_vault.id).unwrap().unwrap();
+        let (_, stored_emer_tx) = db_emer_transaction(&db_path, None, db_vault.id)
+            .unwrap()
+            .unwrap();
         assert_eq!(stored_emer_tx.psbt().inputs[0].partial_sigs.len(), 1);
 
-        let (tx_db_id, stored_unemer_tx) = db_unvault_emer_transaction(&db_path, db_vault.id)
+        let (tx_db_id, stored_unemer_tx) = db_unvault_emer_transaction(&db_path, None, db_vault.id)
             .unwrap()
             .unwrap();
         assert_eq!(stored_unemer_tx.psbt().inputs[0].partial_sigs.len(), 0);
@@ -1021,79 +1761,85 @@ mod test {
         revault_tx_add_dummy_sig(&mut unemer_tx, 0);
         db_update_presigned_tx(
             &db_path,
+            None,
             db_vault.id,
             tx_db_id,
             unemer_tx.psbt().inputs[0].partial_sigs.clone(),
             &revaultd.secp_ctx,
         )
         .unwrap();
-        let (_, stored_unemer_tx) = db_unvault_emer_transaction(&db_path, db_vault.id)
+        let (_, stored_unemer_tx) = db_unvault_emer_transaction(&db_path, None, db_vault.id)
             .unwrap()
             .unwrap();
         assert_eq!(stored_unemer_tx.psbt().inputs[0].partial_sigs.len(), 1);
 
-        let (tx_db_id, stored_unvault_tx) = db_unvault_transaction(&db_path, db_vault.id).unwrap();
+        let (tx_db_id, stored_unvault_tx) =
+            db_unvault_transaction(&db_path, None, db_vault.id).unwrap();
         assert_eq!(stored_unvault_tx.psbt().inputs[0].partial_sigs.len(), 0);
         let mut unvault_tx = fresh_unvault_tx.clone();
         revault_tx_add_dummy_sig(&mut unvault_tx, 0);
         db_update_presigned_tx(
             &db_path,
+            None,
             db_vault.id,
             tx_db_id,
             unvault_tx.psbt().inputs[0].partial_sigs.clone(),
             &revaultd.secp_ctx,
         )
         .unwrap();
-        let (_, stored_unvault_tx) = db_unvault_transaction(&db_path, db_vault.id).unwrap();
+        let (_, stored_unvault_tx) = db_unvault_transaction(&db_path, None, db_vault.id).unwrap();
         assert_eq!(stored_unvault_tx.psbt().inputs[0].partial_sigs.len(), 1);
 
         // They can also be queried
         assert_eq!(
             emer_tx,
-            db_emer_transaction(&db_path, db_vault.id)
+            db_emer_transaction(&db_path, None, db_vault.id)
                 .unwrap()
                 .unwrap()
                 .1
         );
         assert_eq!(
             cancel_tx,
-            db_cancel_transaction(&db_path, db_vault.id)
+            db_cancel_transaction(&db_path, None, db_vault.id)
                 .unwrap()
                 .unwrap()
                 .1
         );
         assert_eq!(
             unemer_tx,
-            db_unvault_emer_transaction(&db_path, db_vault.id)
+            db_unvault_emer_transaction(&db_path, None, db_vault.id)
                 .unwrap()
                 .unwrap()
                 .1
         );
         assert_eq!(
             unvault_tx,
-            db_unvault_transaction(&db_path, db_vault.id).unwrap().1
+            db_unvault_transaction(&db_path, None, db_vault.id)
+                .unwrap()
+                .1
         );
 
         // And removed, if there is eg a reorg.
         db_exec(&db_path, |db_tx| {
-            db_unconfirm_deposit_dbtx(&db_tx, db_vault.id).unwrap();
+            db_unconfirm_deposit_dbtx(db_tx, db_vault.id).unwrap();
             Ok(())
         })
         .unwrap();
-        assert!(db_emer_transaction(&db_path, db_vault.id)
+        assert!(db_emer_transaction(&db_path, None, db_vault.id)
             .unwrap()
             .is_none());
-        assert!(db_cancel_transaction(&db_path, db_vault.id)
+        assert!(db_cancel_transaction(&db_path, None, db_vault.id)
             .unwrap()
             .is_none());
-        assert!(db_unvault_emer_transaction(&db_path, db_vault.id)
+        assert!(db_unvault_emer_transaction(&db_path, None, db_vault.id)
             .unwrap()
             .is_none());
-        db_unvault_transaction(&db_path, db_vault.id).unwrap_err();
+        db_unvault_transaction(&db_path, None, db_vault.id).unwrap_err();
 
         // And re-added of course
         db_confirm_deposit(
             &db_path,
+            None,
             &outpoint,
             blockheight,
             &fresh_unvault_tx,
@@ -1105,6 +1851,7 @@ mod test {
         // But not twice! (UNIQUE on the psbt field)
         db_confirm_deposit(
             &db_path,
+            None,
             &outpoint,
             blockheight,
             &fresh_unvault_tx,
@@ -1124,8 +1871,8 @@ mod test {
             Ok(())
         })
         .unwrap();
-        assert_eq!(db_signed_emer_txs(&db_path).unwrap().len(), 1);
-        assert!(db_signed_unemer_txs(&db_path).unwrap().is_empty());
+        assert_eq!(db_signed_emer_txs(&db_path, None).unwrap().len(), 1);
+        assert!(db_signed_unemer_txs(&db_path, None).unwrap().is_empty());
         // If we mark the UnvaultEmergency transaction as fully signed and the vault as
         // Unvaulting, it'll get returned by the unemer fetcher instead.
         db_unvault_deposit(&db_path, &fresh_unvault_tx.txid()).unwrap();
@@ -1137,10 +1884,203 @@ mod test {
             Ok(())
         })
         .unwrap();
-        assert!(db_signed_emer_txs(&db_path).unwrap().is_empty());
-        assert_eq!(db_signed_unemer_txs(&db_path).unwrap().len(), 1);
+        assert!(db_signed_emer_txs(&db_path, None).unwrap().is_empty());
+        assert_eq!(db_signed_unemer_txs(&db_path, None).unwrap().len(), 1);
+
+        // If the Unvault leaves the mempool, the vault is active again
+        db_revert_unvault(&db_path, &fresh_unvault_tx.txid()).unwrap();
+        let db_vault = db_vault_by_deposit(&db_path, &outpoint).unwrap().unwrap();
+        assert_eq!(db_vault.status, VaultStatus::Active);
+        db_unvault_deposit(&db_path, &fresh_unvault_tx.txid()).unwrap();
+
+        // An unexpected transaction spends the Unvault, we record it
+        let spender_txid =
+            Txid::from_str("e56808d17a866de5a1d0874894c84a759a7cabc8763694966cc6423f4c597a7f")
+                .unwrap();
+        db_unexpected_spend_unvault(&db_path, &fresh_unvault_tx.txid(), &spender_txid).unwrap();
+        let db_vault = db_vault_by_deposit(&db_path, &outpoint).unwrap().unwrap();
+        assert_eq!(db_vault.status, VaultStatus::UnexpectedSpend);
+        assert_eq!(db_vault.spend_txid, Some(spender_txid));
+
+        // A vault can be marked as aborted, and it's reported as such
+        db_mark_aborted_vault(&db_path, db_vault.id).unwrap();
+        let db_vault = db_vault_by_deposit(&db_path, &outpoint).unwrap().unwrap();
+        assert_eq!(db_vault.status, VaultStatus::Aborted);
+        assert_eq!(
+            VaultStatus::from_str(&db_vault.status.to_string()),
+            Ok(VaultStatus::Aborted)
+        );
+
+        // All the status transitions were recorded, in order
+        let history = db_vault_status_history(&db_path, db_vault.id).unwrap();
+        assert_eq!(history[0].status, VaultStatus::Unconfirmed);
+        let last_statuses: Vec<VaultStatus> = history[history.len() - 2..]
+            .iter()
+            .map(|change| change.status)
+            .collect();
+        assert_eq!(
+            last_statuses,
+            vec![VaultStatus::UnexpectedSpend, VaultStatus::Aborted]
+        );
+        assert!(history
+            .windows(2)
+            .all(|changes| changes[0].status != changes[1].status));
+
+        // Only the accounting events are part of the wallet history
+        let accounting_statuses = [
+            VaultStatus::Funded,
+            VaultStatus::Secured,
+            VaultStatus::Unvaulting,
+            VaultStatus::Spent,
+            VaultStatus::Canceled,
+            VaultStatus::EmergencyVaulting,
+            VaultStatus::UnvaultEmergencyVaulting,
+        ];
+        let events = db_history_events(
+            &db_path,
+            &accounting_statuses,
+            0,
+            u32::MAX,
+            1_000,
+            None,
+            None,
+        )
+        .unwrap();
+        assert!(events
+            .iter()
+            .any(|event| event.change.status == VaultStatus::Funded
+                && event.deposit_outpoint == outpoint
+                && event.amount == db_vault.amount));
+        assert!(events
+            .iter()
+            .all(|event| accounting_statuses.contains(&event.change.status)));
+        assert!(events
+            .windows(2)
+            .all(|events| events[0].change.timestamp <= events[1].change.timestamp));
+        assert_eq!(
+            db_history_events(&db_path, &accounting_statuses, 0, u32::MAX, 1, None, None)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(
+            db_history_events(&db_path, &accounting_statuses, 0, 1, 1_000, None, None)
+                .unwrap()
+                .is_empty()
+        );
+        // The Unvault was reverted and broadcast again, but it's a single event
+        assert!(
+            history
+                .iter()
+                .filter(|change| change.status == VaultStatus::Unvaulting)
+                .count()
+                > 1
+        );
+        assert_eq!(
+            events
+                .iter()
+                .filter(|event| event.vault_id == db_vault.id
+                    && event.change.status == VaultStatus::Unvaulting)
+                .count(),
+            1
+        );
+        // We can query only some kinds of events
+        let unvault_events = db_history_events(
+            &db_path,
+            &[VaultStatus::Unvaulting],
+            0,
+            u32::MAX,
+            1_000,
+            None,
+            None,
+        )
+        .unwrap();
+        assert!(!unvault_events.is_empty());
+        assert!(unvault_events
+            .iter()
+            .all(|event| event.change.status == VaultStatus::Unvaulting));
+        assert!(
+            db_history_events(&db_path, &[], 0, u32::MAX, 1_000, None, None)
+                .unwrap()
+                .is_empty()
+        );
+        // We can page through the events one by one, even if they happened the same second
+        let mut paged_events = Vec::with_capacity(events.len());
+        let mut after = None;
+        loop {
+            let page =
+                db_history_events(&db_path, &accounting_statuses, 0, u32::MAX, 1, None, after)
+                    .unwrap();
+            match page.last() {
+                Some(event) => after = Some((event.change.timestamp, event.id)),
+                None => break,
+            }
+            paged_events.extend(page);
+        }
+        assert_eq!(
+            paged_events.iter().map(|e| e.id).collect::<Vec<u32>>(),
+            events.iter().map(|e| e.id).collect::<Vec<u32>>()
+        );
+
+        // An aborted vault can be restored to the status it had before
+        assert_eq!(
+            db_unabort_vault(&db_path, db_vault.id).unwrap(),
+            VaultStatus::UnexpectedSpend
+        );
+        let db_vault = db_vault_by_deposit(&db_path, &outpoint).unwrap().unwrap();
+        assert_eq!(db_vault.status, VaultStatus::UnexpectedSpend);
+        db_mark_aborted_vault(&db_path, db_vault.id).unwrap();
+
+        // The vault can be labeled, relabeled and unlabeled. Its events carry its label and can
+        // be filtered by it.
+        assert!(db_vault_labels(&db_path).unwrap().is_empty());
+        assert!(events.iter().all(|event| event.label.is_none()));
+        db_label_vault(&db_path, db_vault.id, Some("treasury")).unwrap();
+        db_label_vault(&db_path, db_vault.id, Some("client escrow")).unwrap();
+        assert_eq!(
+            db_vault_labels(&db_path).unwrap().get(&db_vault.id),
+            Some(&"client escrow".to_string())
+        );
+        let labeled_events = db_history_events(
+            &db_path,
+            &accounting_statuses,
+            0,
+            u32::MAX,
+            1_000,
+            Some("client escrow"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(labeled_events.len(), events.len());
+        assert!(labeled_events
+            .iter()
+            .all(|event| event.label.as_deref() == Some("client escrow")));
+        assert!(db_history_events(
+            &db_path,
+            &accounting_statuses,
+            0,
+            u32::MAX,
+            1_000,
+            Some("treasury"),
+            None
+        )
+        .unwrap()
+        .is_empty());
+        db_label_vault(&db_path, db_vault.id, None).unwrap();
+        assert!(db_vault_labels(&db_path).unwrap().is_empty());
+        assert!(db_history_events(
+            &db_path,
+            &accounting_statuses,
+            0,
+            u32::MAX,
+            1_000,
+            Some("client escrow"),
+            None
+        )
+        .unwrap()
+        .is_empty());
 
-        fs::remove_dir_all(&datadir).unwrap_or_else(|_| ());
+        fs::remove_dir_all(&datadir).unwrap_or(());
     }
 
     // There we trigger a concurrent write access to the database by inserting a deposit and
@@ -1179,6 +2119,7 @@ mod test {
         let blockheight = 2300000;
         db_confirm_deposit(
             &db_path,
+            None,
             &outpoint,
             blockheight,
             &fresh_unvault_tx,
@@ -1188,7 +2129,7 @@ mod test {
         )
         .unwrap();
 
-        let (tx_db_id, _) = db_cancel_transaction(&db_path, db_vault.id)
+        let (tx_db_id, _) = db_cancel_transaction(&db_path, None, db_vault.id)
             .unwrap()
             .unwrap();
         let mut cancel_tx = fresh_cancel_tx.clone();
@@ -1201,6 +2142,7 @@ mod test {
                 for _ in 0..10 {
                     db_update_presigned_tx(
                         &db_path,
+                        None,
                         db_vault.id,
                         tx_db_id,
                         cancel_tx.psbt().inputs[0].partial_sigs.clone(),
@@ -1213,6 +2155,7 @@ mod test {
         for _ in 0..10 {
             db_update_presigned_tx(
                 &db_path,
+                None,
                 db_vault.id,
                 tx_db_id,
                 cancel_tx.psbt().inputs[0].partial_sigs.clone(),
@@ -1221,7 +2164,7 @@ mod test {
             .unwrap();
         }
         handle.join().unwrap();
-        fs::remove_dir_all(&datadir).unwrap_or_else(|_| ());
+        fs::remove_dir_all(&datadir).unwrap_or(());
     }
 
     fn test_db_spend_storage() {
@@ -1257,6 +2200,7 @@ mod test {
         // Have the Unvault tx fully signed
         db_confirm_deposit(
             &db_path,
+            None,
             &outpoint,
             9,
             &fresh_unvault_tx,
@@ -1267,8 +2211,11 @@ mod test {
         .unwrap();
         db_update_presigned_tx(
             &db_path,
+            None,
             db_vault.id,
-            db_unvault_transaction(&db_path, db_vault.id).unwrap().0,
+            db_unvault_transaction(&db_path, None, db_vault.id)
+                .unwrap()
+                .0,
             fullysigned_unvault_tx.psbt().inputs[0].partial_sigs.clone(),
             &revaultd.secp_ctx,
         )
@@ -1279,13 +2226,13 @@ mod test {
         let spend_tx_inputs = &spend_tx.tx().input;
         assert_eq!(spend_tx_inputs.len(), 1);
         let (_, db_unvault) =
-            db_vault_by_unvault_txid(&db_path, &spend_tx_inputs[0].previous_output.txid)
+            db_vault_by_unvault_txid(&db_path, None, &spend_tx_inputs[0].previous_output.txid)
                 .unwrap()
                 .unwrap();
-        db_insert_spend(&db_path, &[db_unvault.clone()], &spend_tx).unwrap();
+        db_insert_spend(&db_path, None, std::slice::from_ref(&db_unvault), &spend_tx).unwrap();
         let spend_txid = spend_tx.txid();
         assert_eq!(
-            db_list_spends(&db_path).unwrap().get(&spend_txid),
+            db_list_spends(&db_path, None).unwrap().get(&spend_txid),
             Some(&(
                 DbSpendTransaction {
                     id: 1,
@@ -1298,10 +2245,10 @@ mod test {
 
         // We can update it, eg with a Spend with more sigs
         let spend_tx = SpendTransaction::from_psbt_str("cHNidP8BAGcCAAAAAciTbKS43sH49TJWX6xJ+MxqWfNQhRl+vkttRZ9sLUkHAAAAAAClAQAAAoAyAAAAAAAAIgAggxumgjPgMj5oHWn8QkvKqPIN0N5nuAbyQ+FEgOJZpjygjAIAAAAAAAAAAAAAAAEBK0ANAwAAAAAAIgAgKb0SdnuqeHAJpRuZTbk3r81qbXpuHrMEmxT9Kph47HQiAgKl21mZX7WAQhRvdhhwqUAuQfIemg9zkTCCyMQ+Q8CVFUgwRQIhAJynJJuu8tq0mN1SEeWUZRN67KlKL0zHOyrWuPRUp6UjAiAjYDl5/pwMHns9XUYHzrHfLaxjHFg419NFQPCX2wfHrQEiAgMfu47eLiYeHN6Y3C1Vk0ckgmWifMy5IUhaPHbNELV93UcwRAIgF4HaNIfFLQ537aR9opqlY4SN+v3dt7GnSKR2kIGp8n4CIBQQQg13scqRYVQHJf1oS4N8cb6PHmyzGpcDCp6rIbMNASICAzTPPnjrvzPFmi+raNR6sY8WTt1KNusVwp82uWebzWDwRzBEAiBuu/TH4/aBrZPy/+TtpJLxztEJQWcYxjEpPe2s6iChCAIgfE09pqQAcDhYaoEVG7tPOUsc3B/HuOrHOyDfCzSz5kABAQMEAQAAAAEFqiEDH7uO3i4mHhzemNwtVZNHJIJlonzMuSFIWjx2zRC1fd2sUYdkdqkU7YhsQQ+SqzEEFOlBsds7CjDH+pyIrGt2qRQgyrXvSLg3hdA+BgPyUVDV+MYLfoisbJNSh2dSIQM0zz54678zxZovq2jUerGPFk7dSjbrFcKfNrlnm81g8CECpdtZmV+1gEIUb3YYcKlALkHyHpoPc5EwgsjEPkPAlRVSrwKlAbJoAAEBJSEDH7uO3i4mHhzemNwtVZNHJIJlonzMuSFIWjx2zRC1fd2sUYcAAA==").unwrap();
-        db_update_spend(&db_path, &spend_tx).unwrap();
+        db_update_spend(&db_path, None, &spend_tx).unwrap();
         let spend_txid = spend_tx.txid();
         assert_eq!(
-            db_list_spends(&db_path).unwrap().get(&spend_txid),
+            db_list_spends(&db_path, None).unwrap().get(&spend_txid),
             Some(&(
                 DbSpendTransaction {
                     id: 1,
@@ -1314,12 +2261,14 @@ mod test {
 
         // And delete it
         db_delete_spend(&db_path, &spend_tx.txid()).unwrap();
-        assert!(db_list_spends(&db_path).unwrap().get(&spend_txid).is_none());
+        assert!(!db_list_spends(&db_path, None)
+            .unwrap()
+            .contains_key(&spend_txid));
 
         // And this works with multiple unvaults too
 
         // Re-insert the previous one so we have many references to the first Unvault
-        db_insert_spend(&db_path, &[db_unvault.clone()], &spend_tx).unwrap();
+        db_insert_spend(&db_path, None, std::slice::from_ref(&db_unvault), &spend_tx).unwrap();
 
         // Same as above with a new vault
         let cancel_tx = CancelTransaction::from_psbt_str("cHNidP8BAF4CAAAAAc+BIbsSvYK/BWRNOAjazIlLfjlVzCCtXvoyN5/bydgEAAAAAAD9////AdLKAgAAAAAAIgAgFy2HNuxbT516bQQBY3R04IkEja348wJveLmF73Tj/owAAAAAAAEBK0ANAwAAAAAAIgAgZw+cwq8wJzworIDuy6s8cpOo3uF8fYyL5pECqg0UVagBAwSBAAAAAQWrIQLDtCYN0BlQw/h5zAcF0yXft2G7vAjkRsD9B9uoiyr1x6xRh2R2qRTGFACwvLOTrJHUPKb3ifnio7mt0Yisa3apFOZTIiKdGP+9rilwd09H1kOsfB/PiKxsk1KHZ1IhAtGKwcs21FeGy2qY+fzQ9uvI4X5ThtCqkwHsGtKQx0jYIQP93zm1sGAtxTNxsYQTkoXt26FoyKWNh1sx6hmk1yVzYlKvA8aOALJoAAEBR1IhA8HKPHwUwdE4CMkbosklbbI6mPPzzVnOom7LFxQbvCfYIQJ358C4w7CQrcz3UUcpo8eqsRn5JTM0Y0ge5Fz3CApS7lKuAA==").unwrap();
@@ -1345,6 +2294,7 @@ mod test {
         let db_vault = db_vault_by_deposit(&db_path, &outpoint_b).unwrap().unwrap();
         db_confirm_deposit(
             &db_path,
+            None,
             &outpoint_b,
             9,
             &fresh_unvault_tx,
@@ -1355,8 +2305,11 @@ mod test {
         .unwrap();
         db_update_presigned_tx(
             &db_path,
+            None,
             db_vault.id,
-            db_unvault_transaction(&db_path, db_vault.id).unwrap().0,
+            db_unvault_transaction(&db_path, None, db_vault.id)
+                .unwrap()
+                .0,
             fullysigned_unvault_tx.psbt().inputs[0].partial_sigs.clone(),
             &revaultd.secp_ctx,
         )
@@ -1366,13 +2319,13 @@ mod test {
         let spend_tx_b_inputs = &spend_tx_b.tx().input;
         assert_eq!(spend_tx_b_inputs.len(), 1);
         let (_, db_unvault_b) =
-            db_vault_by_unvault_txid(&db_path, &spend_tx_b_inputs[0].previous_output.txid)
+            db_vault_by_unvault_txid(&db_path, None, &spend_tx_b_inputs[0].previous_output.txid)
                 .unwrap()
                 .unwrap();
-        db_insert_spend(&db_path, &[db_unvault, db_unvault_b], &spend_tx_b).unwrap();
+        db_insert_spend(&db_path, None, &[db_unvault, db_unvault_b], &spend_tx_b).unwrap();
         let spend_txid = spend_tx.txid();
         assert_eq!(
-            db_list_spends(&db_path).unwrap().get(&spend_txid),
+            db_list_spends(&db_path, None).unwrap().get(&spend_txid),
             Some(&(
                 DbSpendTransaction {
                     id: 1,
@@ -1384,7 +2337,7 @@ mod test {
         );
         let spend_txid_b = spend_tx_b.txid();
         assert_eq!(
-            db_list_spends(&db_path).unwrap().get(&spend_txid_b),
+            db_list_spends(&db_path, None).unwrap().get(&spend_txid_b),
             Some(&(
                 DbSpendTransaction {
                     id: 2,
@@ -1396,10 +2349,10 @@ mod test {
         );
 
         let spend_tx_b = SpendTransaction::from_psbt_str("cHNidP8BAGcCAAAAAXHqOcTAJnPyXEF1cxFATe4S6yHLGZm+s0aj9mUTtKgVAAAAAACHGwAAAoAyAAAAAAAAIgAgAYLPNnsrzQaSg7aZR0JUgXHtO6bnZRehvxqxyzW5m5ygjAIAAAAAAAAAAAAAAAEBK0ANAwAAAAAAIgAgdS3fC7QX+PKWZBful8J229uixPOW012CYpKMH7rU8T4iAgKkxJmDMXYy1OdMI/x8PV9j3+1kQ0gpzuD+KqSeYfjzTkgwRQIhAMwdbbLXqH49pRfZR6PtSzNg/MB+DuVo1xs7rPTZQ12RAiBDSHEGyQaE1K+wknL2IFnhWXKn+/YSfSMtMg9u4zepNwEiAgO2p8sldVsHhhEimy+ZW0E1L3vX5d9mqQ0d01XVdx3DWUcwRAIgRhhHxuXx5X2eniy4tMP4wP2xoBD+XZlxMQiF9HoXIDYCIEfKdXOOILXSFKeOZ2v6nomllEQOyjuBUk+0LhK7+55mASICAtutlRTEdM0EvJJfteMEFrlq7FN+O5RIfETe8nUld92VSDBFAiEAzSxWF19m2/1Sh92jahJ/A6pMvmCa95USVSXzPEOBn3ACIHzYQdjjDJIhZ5z1xkduaEtjvYtLDIauoMA00xO6fok3AQEDBAEAAAABBaohAtutlRTEdM0EvJJfteMEFrlq7FN+O5RIfETe8nUld92VrFGHZHapFLyK7KRbyUNl1FZeArhRjSAsCSNfiKxrdqkUn6VDRhuPgSKDvf3VMS3GKSA/gMOIrGyTUodnUiECpMSZgzF2MtTnTCP8fD1fY9/tZENIKc7g/iqknmH4804hA7anyyV1WweGESKbL5lbQTUve9fl32apDR3TVdV3HcNZUq8ChxuyaAABASUhAtutlRTEdM0EvJJfteMEFrlq7FN+O5RIfETe8nUld92VrFGHAAA=").unwrap();
-        db_update_spend(&db_path, &spend_tx_b).unwrap();
+        db_update_spend(&db_path, None, &spend_tx_b).unwrap();
 
         // There are 2 Unvaults referenced by this Spend
-        let db_spend = db_spend_transaction(&db_path, &spend_tx_b.txid())
+        let db_spend = db_spend_transaction(&db_path, None, &spend_tx_b.txid())
             .unwrap()
             .unwrap();
         let conn = rusqlite::Connection::open(&db_path).unwrap();
@@ -1425,39 +2378,45 @@ mod test {
         // Thus there are 2 vaults too
         let spent_outpoints: Vec<OutPoint> = db_vaults_from_spend(&db_path, &spend_tx_b.txid())
             .unwrap()
-            .into_iter()
-            .map(|(_, db_vault)| db_vault.deposit_outpoint)
+            .values()
+            .map(|db_vault| db_vault.deposit_outpoint)
             .collect();
         assert_eq!(spent_outpoints.len(), 2);
         assert!(spent_outpoints.contains(&outpoint));
         assert!(spent_outpoints.contains(&outpoint_b));
 
         let spend_txid = spend_tx.txid();
-        assert!(db_spend_transaction(&db_path, &spend_txid)
+        assert!(db_spend_transaction(&db_path, None, &spend_txid)
             .unwrap()
             .unwrap()
             .broadcasted
             .is_none());
         assert_eq!(
-            db_broadcastable_spend_transactions(&db_path).unwrap().len(),
+            db_broadcastable_spend_transactions(&db_path, None)
+                .unwrap()
+                .len(),
             0
         );
         db_mark_broadcastable_spend(&db_path, &spend_txid).unwrap();
         assert_eq!(
-            db_broadcastable_spend_transactions(&db_path).unwrap().len(),
+            db_broadcastable_spend_transactions(&db_path, None)
+                .unwrap()
+                .len(),
             1
         );
-        assert!(!db_spend_transaction(&db_path, &spend_txid)
+        assert!(!db_spend_transaction(&db_path, None, &spend_txid)
             .unwrap()
             .unwrap()
             .broadcasted
             .unwrap(),);
         db_mark_broadcasted_spend(&db_path, &spend_txid).unwrap();
         assert_eq!(
-            db_broadcastable_spend_transactions(&db_path).unwrap().len(),
+            db_broadcastable_spend_transactions(&db_path, None)
+                .unwrap()
+                .len(),
             0
         );
-        assert!(db_spend_transaction(&db_path, &spend_txid)
+        assert!(db_spend_transaction(&db_path, None, &spend_txid)
             .unwrap()
             .unwrap()
             .broadcasted
@@ -1465,19 +2424,330 @@ mod test {
 
         // And we can delete the transaction
         db_delete_spend(&db_path, &spend_txid).unwrap();
-        assert!(db_spend_transaction(&db_path, &spend_txid)
+        assert!(db_spend_transaction(&db_path, None, &spend_txid)
             .unwrap()
             .is_none());
 
         // And if we unconfirm the vault, it'll delete the last remaining transaction
         let txid_b = spend_tx_b.txid();
         db_exec(&db_path, |db_tx| {
-            db_unconfirm_deposit_dbtx(&db_tx, db_vault.id).unwrap();
+            db_unconfirm_deposit_dbtx(db_tx, db_vault.id).unwrap();
             Ok(())
         })
         .unwrap();
-        assert!(db_spend_transaction(&db_path, &txid_b).unwrap().is_none());
-        fs::remove_dir_all(&datadir).unwrap_or_else(|_| ());
+        assert!(db_spend_transaction(&db_path, None, &txid_b)
+            .unwrap()
+            .is_none());
+        fs::remove_dir_all(&datadir).unwrap_or(());
+    }
+
+    #[test]
+    fn locked_db_error() {
+        let busy: DatabaseError = rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            None,
+        )
+        .into();
+        assert!(busy.is_locked());
+        let locked = DatabaseError::sqlite(
+            "Creating transaction",
+            rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_LOCKED),
+                None,
+            ),
+        );
+        assert!(locked.is_locked());
+        let busy_msg: DatabaseError = rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            Some("database is locked".to_string()),
+        )
+        .into();
+        assert!(busy_msg.is_locked());
+
+        // Anything else is fatal
+        let corrupt: DatabaseError = rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CORRUPT),
+            None,
+        )
+        .into();
+        assert!(!corrupt.is_locked());
+        assert!(!DatabaseError::new(
+            "Unexpected database version: got '4', expected '3'".to_string()
+        )
+        .is_locked());
+        // Even if the message looks like it
+        let corrupt_msg: DatabaseError = rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CORRUPT),
+            Some("database is locked".to_string()),
+        )
+        .into();
+        assert!(!corrupt_msg.is_locked());
+    }
+
+    fn test_db_wal_mode() {
+        let datadir = test_datadir();
+        let mut revaultd = dummy_revaultd(datadir.clone(), UserRole::ManagerStakeholder);
+        let db_path = revaultd.db_file();
+        let journal_mode = |db_path: &Path| -> String {
+            rusqlite::Connection::open(db_path)
+                .unwrap()
+                .query_row("PRAGMA journal_mode", rusqlite::NO_PARAMS, |row| row.get(0))
+                .unwrap()
+        };
+
+        revaultd.sqlite_wal_mode = true;
+        setup_db(&mut revaultd).unwrap();
+        assert_eq!(journal_mode(&db_path), "wal");
+        let tip = db_tip(&db_path).unwrap();
+
+        // A writer holding an exclusive lock doesn't prevent us from reading the committed state
+        let mut conn = rusqlite::Connection::open(&db_path).unwrap();
+        let db_tx = conn
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Exclusive)
+            .unwrap();
+        let new_tip = BlockchainTip {
+            height: tip.height + 1,
+            hash: tip.hash,
+        };
+        db_update_tip_dbtx(&db_tx, &new_tip).unwrap();
+        assert_eq!(db_tip(&db_path).unwrap(), tip);
+        db_tx.commit().unwrap();
+        assert_eq!(db_tip(&db_path).unwrap(), new_tip);
+        drop(conn);
+
+        // It's set back if disabled
+        revaultd.sqlite_wal_mode = false;
+        setup_db(&mut revaultd).unwrap();
+        assert_eq!(journal_mode(&db_path), "delete");
+        assert_eq!(db_tip(&db_path).unwrap(), new_tip);
+
+        fs::remove_dir_all(&datadir).unwrap_or(());
+    }
+
+    fn test_db_encryption() {
+        let datadir = test_datadir();
+        let mut revaultd = dummy_revaultd(datadir.clone(), UserRole::ManagerStakeholder);
+        let db_path = revaultd.db_file();
+        let stored_psbts = |db_path: &Path| -> Vec<Vec<u8>> {
+            rusqlite::Connection::open(db_path)
+                .unwrap()
+                .prepare("SELECT psbt FROM presigned_transactions")
+                .unwrap()
+                .query_map(rusqlite::NO_PARAMS, |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<Vec<Vec<u8>>>>()
+                .unwrap()
+        };
+        // Whether these bytes can be found anywhere in the database files
+        let in_db_files = |db_path: &Path, needle: &[u8]| -> bool {
+            let mut wal_path = db_path.as_os_str().to_owned();
+            wal_path.push("-wal");
+            [db_path.to_path_buf(), PathBuf::from(wal_path)]
+                .iter()
+                .filter_map(|path| fs::read(path).ok())
+                .any(|content| content.windows(needle.len()).any(|w| w == needle))
+        };
+
+        // Start with an unencrypted database with some presigned transactions
+        setup_db(&mut revaultd).unwrap();
+        let outpoint = OutPoint::from_str(
+            "4d799e993665149109682555ba482b386aea03c5dbd62c059b48eb8f40f2f040:0",
+        )
+        .unwrap();
+        db_insert_new_unconfirmed_vault(
+            &db_path,
+            1,
+            &outpoint,
+            &Amount::from_sat(123456),
+            ChildNumber::from(33334),
+            1615297315,
+        )
+        .unwrap();
+        let db_vault = db_vault_by_deposit(&db_path, &outpoint).unwrap().unwrap();
+        let cancel_tx = CancelTransaction::from_psbt_str("cHNidP8BAF4CAAAAARoHs0elD2sCfWV4+b7PH3aRA+BkRVNf3m/P+Epjx2fNAAAAAAD9////AdLKAgAAAAAAIgAgB6abzQJ4vo5CO9XW3r3JnNumTwlpQbZm9FVICsLHPYQAAAAAAAEBK0ANAwAAAAAAIgAglEs6phQpv+twnAQSdjDvAEic65OtUIijeePBzAAqr50BAwSBAAAAAQWrIQO4lrAuffeRLuEEuwp2hAMZIPmqaHMTUySM3OwdA2hIW6xRh2R2qRTflccImFIy5NdTqwPuPZFB7g1pvYisa3apFOQxXoLeQv/aDFfav/l6YnYRKt+1iKxsk1KHZ1IhA32Q1DEqQ/kUP2MvQYFW46RCexZ5aYk17Arhp01th+37IQNrXQtfIXQdrv+RyyHLilJsb4ujlUMddG9X2jYkeXiWoFKvA3nxALJoAAEBR1IhA9+bpoeRoYk6Fehku5U6JFn6v0b8vq0SPVzELn/n6DqBIQPRrV6R4VL8XI/QyVm2kb8+fQjbDMB9jRL5kWvIHNlkZFKuAA==").unwrap();
+        let unvault_tx = UnvaultTransaction::from_psbt_str("cHNidP8BAIkCAAAAAcRWqIPG85zGye1nuRlbwWKkko4g91Vd/508Ff6vKklpAAAAAAD9////AkANAwAAAAAAIgAgsT7u0Lo8o2WEfxS1nXWtQzsdJTMJnnOC5fwg0nYPvpowdQAAAAAAACIAIAx0DegrXfBr4D0XdetrGgAT2Q3AZANYm0rJL8L/Epp/AAAAAAABASuIlAMAAAAAACIAIGaHQ5brMNbT+WCtfE/WPW8gkmMir5NXAKRsQZAs9cT2AQMEAQAAAAEFR1IhAwYSJ4FeXdf/XPw6lFHpeMFeGvh88f+rWN2VtnaW75TNIQOn5Sg6nytLwT5FT9z5KmV/LMN1pZRsqbworUMwRdRN0lKuAAEBqiEDdDY+WLVpanVLROFc6wsvXyFG4FUgYknnTic2GPQNIy6sUYdkdqkUNlKGE2FxZM1sR08UC7GJfzRqXlSIrGt2qRQoTG+3hS6ElXzBw+21PRDtEJ9sKoisbJNSh2dSIQNiqGzCWTbNvmnTm7l6YNTctgzoP5xaOW6hiXSWVkoClCEC/w0jRRlaB3Oa5c0OPrRAxbxE1kdfzV24OWsaSCGLgIVSrwLWNLJoAAEBJSEDdDY+WLVpanVLROFc6wsvXyFG4FUgYknnTic2GPQNIy6sUYcA").unwrap();
+        db_confirm_deposit(
+            &db_path,
+            None,
+            &outpoint,
+            700000,
+            &unvault_tx,
+            &cancel_tx,
+            None,
+            None,
+        )
+        .unwrap();
+        assert!(stored_psbts(&db_path)
+            .iter()
+            .all(|psbt| psbt.starts_with(b"psbt")));
+        assert!(in_db_files(&db_path, &cancel_tx.as_psbt_serialized()));
+
+        // We need a passphrase to encrypt it
+        revaultd.db_encryption = true;
+        setup_db(&mut revaultd).unwrap_err();
+        revaultd.db_passphrase = Some("correct horse battery staple".to_string());
+        setup_db(&mut revaultd).unwrap();
+        assert!(stored_psbts(&db_path)
+            .iter()
+            .all(|psbt| !psbt.starts_with(b"psbt")));
+        // No clear copy of the transactions is left behind in the free pages or in the WAL
+        assert!(!in_db_files(&db_path, &cancel_tx.as_psbt_serialized()));
+        assert!(!in_db_files(&db_path, &unvault_tx.as_psbt_serialized()));
+        // We can't read them without the key
+        db_cancel_transaction(&db_path, None, db_vault.id).unwrap_err();
+        let (tx_db_id, stored_cancel_tx) =
+            db_cancel_transaction(&db_path, revaultd.db_key.as_ref(), db_vault.id)
+                .unwrap()
+                .unwrap();
+        assert_eq!(stored_cancel_tx, cancel_tx);
+
+        // The transactions we write from now on are encrypted too
+        let mut signed_cancel_tx = cancel_tx.clone();
+        revault_tx_add_dummy_sig(&mut signed_cancel_tx, 0);
+        db_update_presigned_tx(
+            &db_path,
+            revaultd.db_key.as_ref(),
+            db_vault.id,
+            tx_db_id,
+            signed_cancel_tx.psbt().inputs[0].partial_sigs.clone(),
+            &revaultd.secp_ctx,
+        )
+        .unwrap();
+        assert!(stored_psbts(&db_path)
+            .iter()
+            .all(|psbt| !psbt.starts_with(b"psbt")));
+        let (_, stored_cancel_tx) =
+            db_cancel_transaction(&db_path, revaultd.db_key.as_ref(), db_vault.id)
+                .unwrap()
+                .unwrap();
+        assert_eq!(stored_cancel_tx, signed_cancel_tx);
+
+        // We refuse to write a transaction in clear to an encrypted database
+        let outpoint_b = OutPoint::from_str(
+            "4d799e993665149109682555ba482b386aea03c5dbd62c059b48eb8f40f2f040:1",
+        )
+        .unwrap();
+        db_insert_new_unconfirmed_vault(
+            &db_path,
+            1,
+            &outpoint_b,
+            &Amount::from_sat(123456),
+            ChildNumber::from(33335),
+            1615297315,
+        )
+        .unwrap();
+        let err = db_confirm_deposit(
+            &db_path,
+            None,
+            &outpoint_b,
+            700000,
+            &unvault_tx,
+            &cancel_tx,
+            None,
+            None,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("no key"), "{}", err);
+
+        // We can't open it with the wrong passphrase, or without encryption
+        revaultd.db_passphrase = Some("correct horse battery stapler".to_string());
+        setup_db(&mut revaultd).unwrap_err();
+        revaultd.db_encryption = false;
+        setup_db(&mut revaultd).unwrap_err();
+
+        // Rotate the passphrase. The old one isn't valid anymore.
+        revaultd.db_encryption = true;
+        revaultd.db_passphrase = Some("correct horse battery staple".to_string());
+        setup_db(&mut revaultd).unwrap();
+        let encrypted_psbts = stored_psbts(&db_path);
+        db_rotate_passphrase(&mut revaultd, "").unwrap_err();
+        db_rotate_passphrase(&mut revaultd, "Tr0ub4dor&3").unwrap();
+        let rotated_psbts = stored_psbts(&db_path);
+        assert!(rotated_psbts
+            .iter()
+            .all(|psbt| !encrypted_psbts.contains(psbt)));
+        setup_db(&mut revaultd).unwrap_err();
+        revaultd.db_passphrase = Some("Tr0ub4dor&3".to_string());
+        setup_db(&mut revaultd).unwrap();
+        let (_, stored_cancel_tx) =
+            db_cancel_transaction(&db_path, revaultd.db_key.as_ref(), db_vault.id)
+                .unwrap()
+                .unwrap();
+        assert_eq!(stored_cancel_tx, signed_cancel_tx);
+
+        fs::remove_dir_all(&datadir).unwrap_or(());
+    }
+
+    fn test_db_backup_restore() {
+        let datadir = test_datadir();
+        let mut revaultd = dummy_revaultd(datadir.clone(), UserRole::ManagerStakeholder);
+        let db_path = revaultd.db_file();
+        let backup_path = datadir.join("backup.sqlite3");
+        let insert_vault = |db_path: &Path, vout: u32| {
+            let outpoint = OutPoint::from_str(&format!(
+                "4d799e993665149109682555ba482b386aea03c5dbd62c059b48eb8f40f2f040:{}",
+                vout
+            ))
+            .unwrap();
+            db_insert_new_unconfirmed_vault(
+                db_path,
+                1,
+                &outpoint,
+                &Amount::from_sat(123456),
+                ChildNumber::from(vout),
+                1615297315,
+            )
+            .unwrap();
+        };
+
+        setup_db(&mut revaultd).unwrap();
+        insert_vault(&db_path, 0);
+
+        // We can take a backup while the database is being used
+        let conn = rusqlite::Connection::open(&db_path).unwrap();
+        let reader = conn.unchecked_transaction().unwrap();
+        reader
+            .query_row("SELECT COUNT(*) FROM vaults", rusqlite::NO_PARAMS, |row| {
+                row.get::<_, i64>(0)
+            })
+            .unwrap();
+        db_backup(&db_path, &backup_path).unwrap();
+        drop(reader);
+        drop(conn);
+        assert_eq!(db_verify_file(&backup_path).unwrap(), DB_VERSION);
+        assert_eq!(db_vaults(&backup_path).unwrap().len(), 1);
+        // But never overwrite a file
+        db_backup(&db_path, &backup_path).unwrap_err();
+
+        // Restoring it brings us back to the state at the time of the backup, and keeps the
+        // previous database around
+        insert_vault(&db_path, 1);
+        assert_eq!(db_vaults(&db_path).unwrap().len(), 2);
+        let previous_db = db_restore(&revaultd, &backup_path).unwrap().unwrap();
+        assert_eq!(db_vaults(&db_path).unwrap().len(), 1);
+        assert_eq!(db_vaults(&previous_db).unwrap().len(), 2);
+        setup_db(&mut revaultd).unwrap();
+
+        // A corrupted backup is rejected
+        let corrupted_path = datadir.join("corrupted.sqlite3");
+        let mut content = fs::read(&backup_path).unwrap();
+        let len = content.len();
+        content[len / 2..].iter_mut().for_each(|b| *b = 0xff);
+        fs::write(&corrupted_path, content).unwrap();
+        db_verify_file(&corrupted_path).unwrap_err();
+        db_restore(&revaultd, &corrupted_path).unwrap_err();
+        db_restore(&revaultd, &datadir.join("nonexistent.sqlite3")).unwrap_err();
+
+        // So is a backup of another wallet, and we keep using the current database
+        insert_vault(&db_path, 2);
+        let network = revaultd.bitcoind_config.network;
+        revaultd.bitcoind_config.network = Network::Testnet;
+        db_restore(&revaultd, &backup_path).unwrap_err();
+        revaultd.bitcoind_config.network = network;
+        assert_eq!(db_vaults(&db_path).unwrap().len(), 2);
+        setup_db(&mut revaultd).unwrap();
+
+        fs::remove_dir_all(&datadir).unwrap_or(());
     }
 
     // We disabled #[test] for the above, as they may erase the db concurrently.
@@ -1485,9 +2755,13 @@ mod test {
     #[test]
     fn db_sequential_test_runner() {
         test_db_creation();
+        test_db_migration();
         test_db_fetch_deposits();
         test_db_store_presigned_txs();
         test_db_concurrent_write();
         test_db_spend_storage();
+        test_db_wal_mode();
+        test_db_encryption();
+        test_db_backup_restore();
     }
 }
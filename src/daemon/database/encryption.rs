@@ -0,0 +1,178 @@
+//! Encryption at rest of the transactions we store in the database.
+//!
+//! The presigned and Spend transactions contain the signatures of the participants. If
+//! `db_encryption` is set they are encrypted with a key derived from a passphrase, using a salt
+//! stored in the wallet. The rest of the database (vaults outpoints and amounts, descriptors,
+//! ..) is left in clear as it is already known to the bitcoind watchonly wallet.
+//!
+//! The key is derived at startup and kept in the global state. The database functions reading
+//! or writing transactions take it as parameter, and refuse to write a transaction in clear to
+//! an encrypted database.
+//!
+//! An encrypted value is prefixed with a magic and the identifier of the key it was encrypted
+//! with. This lets us read the values written before the database was encrypted, and tell a
+//! value encrypted with another key apart from a corrupted one.
+
+use crate::database::DatabaseError;
+use revault_net::sodiumoxide::crypto::{pwhash::argon2id13, secretbox};
+
+use std::fmt;
+
+const MAGIC: &[u8] = b"rvenc";
+const KEY_ID_LEN: usize = 8;
+// Stored encrypted in the wallet, to tell whether a passphrase is the right one
+const CHECK_PLAINTEXT: &[u8] = b"revaultd database key";
+
+/// A key to encrypt the transactions of a database with, derived from a passphrase
+#[derive(Clone)]
+pub struct DbKey {
+    id: [u8; KEY_ID_LEN],
+    key: secretbox::Key,
+}
+
+// Don't print the key itself
+impl fmt::Debug for DbKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DbKey {{ id: {:?} }}", self.id)
+    }
+}
+
+impl DbKey {
+    /// Derive the key from the passphrase and the salt stored in the database
+    pub fn derive(passphrase: &str, salt: &[u8]) -> Result<DbKey, DatabaseError> {
+        if passphrase.is_empty() {
+            return Err(DatabaseError::new("Empty database passphrase".to_string()));
+        }
+        let salt = argon2id13::Salt::from_slice(salt)
+            .ok_or_else(|| DatabaseError::new("Invalid database encryption salt".to_string()))?;
+
+        let mut key = secretbox::Key([0; secretbox::KEYBYTES]);
+        argon2id13::derive_key(
+            &mut key.0,
+            passphrase.as_bytes(),
+            &salt,
+            argon2id13::OPSLIMIT_INTERACTIVE,
+            argon2id13::MEMLIMIT_INTERACTIVE,
+        )
+        .map_err(|_| DatabaseError::new("Deriving the database key".to_string()))?;
+        // The salt is random, and a new one is drawn for each new passphrase
+        let mut id = [0; KEY_ID_LEN];
+        id.copy_from_slice(&salt.0[..KEY_ID_LEN]);
+
+        Ok(DbKey { id, key })
+    }
+
+    /// Derive a key from a new passphrase, along with the (new) salt to store
+    pub fn generate(passphrase: &str) -> Result<(DbKey, Vec<u8>), DatabaseError> {
+        let salt = argon2id13::gen_salt().0.to_vec();
+        Ok((DbKey::derive(passphrase, &salt)?, salt))
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = secretbox::gen_nonce();
+        let ciphertext = secretbox::seal(plaintext, &nonce, &self.key);
+
+        let mut value =
+            Vec::with_capacity(MAGIC.len() + KEY_ID_LEN + nonce.0.len() + ciphertext.len());
+        value.extend_from_slice(MAGIC);
+        value.extend_from_slice(&self.id);
+        value.extend_from_slice(&nonce.0);
+        value.extend_from_slice(&ciphertext);
+        value
+    }
+
+    fn decrypt(&self, value: &[u8]) -> Result<Vec<u8>, DatabaseError> {
+        let value = &value[MAGIC.len() + KEY_ID_LEN..];
+        if value.len() < secretbox::NONCEBYTES {
+            return Err(DatabaseError::new("Truncated encrypted value".to_string()));
+        }
+        let nonce = secretbox::Nonce::from_slice(&value[..secretbox::NONCEBYTES])
+            .expect("Checked the length above");
+        secretbox::open(&value[secretbox::NONCEBYTES..], &nonce, &self.key)
+            .map_err(|_| DatabaseError::new("Could not decrypt value".to_string()))
+    }
+
+    /// The value to store along with the salt, for `verify` to check a passphrase against
+    pub fn check_value(&self) -> Vec<u8> {
+        self.encrypt(CHECK_PLAINTEXT)
+    }
+
+    /// Whether this key is the one the check value was created with
+    pub fn verify(&self, check_value: &[u8]) -> bool {
+        key_id(check_value) == Some(self.id)
+            && self.decrypt(check_value).ok().as_deref() == Some(CHECK_PLAINTEXT)
+    }
+}
+
+fn key_id(value: &[u8]) -> Option<[u8; KEY_ID_LEN]> {
+    if !value.starts_with(MAGIC) || value.len() < MAGIC.len() + KEY_ID_LEN {
+        return None;
+    }
+    let mut id = [0; KEY_ID_LEN];
+    id.copy_from_slice(&value[MAGIC.len()..MAGIC.len() + KEY_ID_LEN]);
+    Some(id)
+}
+
+/// Decrypt this value read from the database, if it was encrypted
+pub fn decrypt_value(db_key: Option<&DbKey>, value: Vec<u8>) -> Result<Vec<u8>, DatabaseError> {
+    let id = match key_id(&value) {
+        Some(id) => id,
+        // A PSBT starts with its own magic, so it was stored in clear
+        None => return Ok(value),
+    };
+
+    match db_key {
+        Some(key) if key.id == id => key.decrypt(&value),
+        Some(_) => Err(DatabaseError::new(
+            "Value is encrypted with a different key".to_string(),
+        )),
+        None => Err(DatabaseError::new(
+            "Value is encrypted but we have no database key. Is 'db_encryption' set?".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn db_key_encryption() {
+        let (key, salt) = DbKey::generate("correct horse battery staple").unwrap();
+        DbKey::generate("").unwrap_err();
+
+        // The same passphrase and salt give the same key
+        let check = key.check_value();
+        assert!(DbKey::derive("correct horse battery staple", &salt)
+            .unwrap()
+            .verify(&check));
+        assert!(!DbKey::derive("correct horse battery stapler", &salt)
+            .unwrap()
+            .verify(&check));
+        let (other_key, _) = DbKey::generate("correct horse battery staple").unwrap();
+        assert!(!other_key.verify(&check));
+
+        // Values stored in clear are read as is
+        let psbt = b"psbt\xff some PSBT".to_vec();
+        assert_eq!(decrypt_value(None, psbt.clone()).unwrap(), psbt);
+        assert_eq!(decrypt_value(Some(&key), psbt.clone()).unwrap(), psbt);
+
+        let encrypted = key.encrypt(&psbt);
+        assert!(encrypted.starts_with(MAGIC));
+        assert_ne!(encrypted, key.encrypt(&psbt), "The nonce must be random");
+        assert_eq!(decrypt_value(Some(&key), encrypted.clone()).unwrap(), psbt);
+        // But not tampered ones
+        let mut tampered = encrypted.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        decrypt_value(Some(&key), tampered).unwrap_err();
+        decrypt_value(
+            Some(&key),
+            encrypted[..MAGIC.len() + KEY_ID_LEN + 3].to_vec(),
+        )
+        .unwrap_err();
+
+        // Nor those encrypted with a key we don't have
+        decrypt_value(Some(&other_key), encrypted.clone()).unwrap_err();
+        decrypt_value(None, encrypted).unwrap_err();
+    }
+}
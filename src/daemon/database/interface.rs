@@ -1,8 +1,10 @@
 use crate::{
     assert_tx_type,
     database::{
+        encryption::{decrypt_value, DbKey},
         schema::{
-            DbSpendTransaction, DbTransaction, DbVault, DbWallet, RevaultTx, TransactionType,
+            DbSpendTransaction, DbTransaction, DbVault, DbVaultStatusChange, DbWallet, RevaultTx,
+            TransactionType,
         },
         DatabaseError,
     },
@@ -12,9 +14,9 @@ use revault_tx::{
     bitcoin::{
         consensus::encode,
         util::bip32::{ChildNumber, ExtendedPubKey},
-        Amount, BlockHash, Network, OutPoint, Txid,
+        Address, Amount, BlockHash, Network, OutPoint, Txid,
     },
-    scripts::{CpfpDescriptor, DepositDescriptor, UnvaultDescriptor},
+    scripts::{CpfpDescriptor, DepositDescriptor, EmergencyAddress, UnvaultDescriptor},
     transactions::{
         CancelTransaction, EmergencyTransaction, RevaultTransaction, SpendTransaction,
         UnvaultEmergencyTransaction, UnvaultTransaction,
@@ -23,15 +25,15 @@ use revault_tx::{
 
 use std::{
     boxed::Box,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     convert::{TryFrom, TryInto},
     path::Path,
     str::FromStr,
 };
 
 use rusqlite::{
-    params, types::FromSqlError, Connection, Row, ToSql, Transaction, TransactionBehavior,
-    NO_PARAMS,
+    params, types::FromSqlError, Connection, OpenFlags, Row, ToSql, Transaction,
+    TransactionBehavior, NO_PARAMS,
 };
 
 // As the bundled sqlite is compiled with SQLITE_THREADSAFE, quoting sqlite.org:
@@ -39,58 +41,64 @@ use rusqlite::{
 // > no single database connection is used simultaneously in two or more threads.
 // Therefore the below routines for now create a new connection and can be used from any thread.
 // For concurrent write accesses, we rely on the 'unlock_notify' feature of SQLite: https://sqlite.org/unlock_notify.html
+// Queries use a separate, read-only, connection. If the database is in WAL mode it reads the last
+// committed state without ever waiting for a writer.
 
 /// Perform a set of modifications to the database inside a single transaction
 pub fn db_exec<F>(path: &Path, modifications: F) -> Result<(), DatabaseError>
 where
     F: FnOnce(&Transaction) -> Result<(), DatabaseError>,
 {
-    let mut conn = Connection::open(path)
-        .map_err(|e| DatabaseError(format!("Opening database: {}", e.to_string())))?;
+    let mut conn =
+        Connection::open(path).map_err(|e| DatabaseError::sqlite("Opening database", e))?;
     conn.busy_timeout(std::time::Duration::from_secs(60))?;
     let tx = conn
         .transaction_with_behavior(TransactionBehavior::Immediate)
-        .map_err(|e| DatabaseError(format!("Creating transaction: {}", e.to_string())))?;
+        .map_err(|e| DatabaseError::sqlite("Creating transaction", e))?;
 
     modifications(&tx)?;
     tx.commit()
-        .map_err(|e| DatabaseError(format!("Comitting transaction: {}", e.to_string())))?;
+        .map_err(|e| DatabaseError::sqlite("Comitting transaction", e))?;
 
     Ok(())
 }
 
+// Get a PSBT column, decrypting it if the database is encrypted
+fn row_psbt(row: &Row, index: usize, db_key: Option<&DbKey>) -> rusqlite::Result<Vec<u8>> {
+    let value: Vec<u8> = row.get(index)?;
+    Ok(decrypt_value(db_key, value).map_err(|e| FromSqlError::Other(Box::new(e)))?)
+}
+
 // Internal helper for queries boilerplate
-fn db_query<'a, P, F, T>(
-    path: &Path,
-    stmt_str: &'a str,
-    params: P,
-    f: F,
-) -> Result<Vec<T>, DatabaseError>
+fn db_query<P, F, T>(path: &Path, stmt_str: &str, params: P, f: F) -> Result<Vec<T>, DatabaseError>
 where
     P: IntoIterator,
     P::Item: ToSql,
     F: FnMut(&Row<'_>) -> rusqlite::Result<T>,
 {
-    let conn = Connection::open(path)
-        .map_err(|e| DatabaseError(format!("Opening database for query: {}", e.to_string())))?;
+    let conn = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(|e| DatabaseError::sqlite("Opening database for query", e))?;
 
     conn.busy_timeout(std::time::Duration::from_secs(60))?;
 
     // rustc says 'borrowed value does not live long enough'
     let x = conn
         .prepare(stmt_str)
-        .map_err(|e| DatabaseError(format!("Preparing query: '{}'", e.to_string())))?
+        .map_err(|e| DatabaseError::sqlite("Preparing query", e))?
         .query_map(params, f)
-        .map_err(|e| DatabaseError(format!("Mapping query: '{}'", e.to_string())))?
+        .map_err(|e| DatabaseError::sqlite("Mapping query", e))?
         .collect::<rusqlite::Result<Vec<T>>>()
-        .map_err(|e| DatabaseError(format!("Executing query: '{}'", e.to_string())));
+        .map_err(|e| DatabaseError::sqlite("Executing query", e));
 
     x
 }
 
-fn db_query_tx<'a, P, F, T>(
+fn db_query_tx<P, F, T>(
     db_tx: &Transaction,
-    stmt_str: &'a str,
+    stmt_str: &str,
     params: P,
     f: F,
 ) -> Result<Vec<T>, DatabaseError>
@@ -102,11 +110,11 @@ where
     // rustc says 'borrowed value does not live long enough'
     db_tx
         .prepare(stmt_str)
-        .map_err(|e| DatabaseError(format!("Preparing query: '{}'", e.to_string())))?
+        .map_err(|e| DatabaseError::sqlite("Preparing query", e))?
         .query_map(params, f)
-        .map_err(|e| DatabaseError(format!("Mapping query: '{}'", e.to_string())))?
+        .map_err(|e| DatabaseError::sqlite("Mapping query", e))?
         .collect::<rusqlite::Result<Vec<T>>>()
-        .map_err(|e| DatabaseError(format!("Executing query: '{}'", e.to_string())))
+        .map_err(|e| DatabaseError::sqlite("Executing query", e))
 }
 
 /// Get the database version
@@ -116,7 +124,7 @@ pub fn db_version(db_path: &Path) -> Result<u32, DatabaseError> {
     })?;
 
     rows.pop()
-        .ok_or_else(|| DatabaseError("No row in version table?".to_string()))
+        .ok_or_else(|| DatabaseError::new("No row in version table?".to_string()))
 }
 
 /// Get our tip from the database
@@ -135,7 +143,7 @@ pub fn db_tip(db_path: &Path) -> Result<BlockchainTip, DatabaseError> {
     )?;
 
     rows.pop()
-        .ok_or_else(|| DatabaseError("No row in tip table?".to_string()))
+        .ok_or_else(|| DatabaseError::new("No row in tip table?".to_string()))
 }
 
 /// Get the network this DB was created on
@@ -146,7 +154,7 @@ pub fn db_network(db_path: &Path) -> Result<Network, DatabaseError> {
     })?;
 
     rows.pop()
-        .ok_or_else(|| DatabaseError("No row in tip table?".to_string()))
+        .ok_or_else(|| DatabaseError::new("No row in tip table?".to_string()))
 }
 
 /// Get the database wallet. We only support single wallet, so this always return the first row.
@@ -157,21 +165,21 @@ pub fn db_wallet(db_path: &Path) -> Result<DbWallet, DatabaseError> {
 
         let deposit_desc_str: String = row.get(2)?;
         let deposit_descriptor = DepositDescriptor::from_str(&deposit_desc_str).map_err(|e| {
-            FromSqlError::Other(Box::new(DatabaseError(format!(
+            FromSqlError::Other(Box::new(DatabaseError::new(format!(
                 "Parsing database Deposit descriptor '{}': {}",
                 deposit_desc_str, e
             ))))
         })?;
         let unvault_desc_str: String = row.get(3)?;
         let unvault_descriptor = UnvaultDescriptor::from_str(&unvault_desc_str).map_err(|e| {
-            FromSqlError::Other(Box::new(DatabaseError(format!(
+            FromSqlError::Other(Box::new(DatabaseError::new(format!(
                 "Parsing database Unvault descriptor '{}': {}",
                 unvault_desc_str, e
             ))))
         })?;
         let cpfp_desc_str: String = row.get(4)?;
         let cpfp_descriptor = CpfpDescriptor::from_str(&cpfp_desc_str).map_err(|e| {
-            FromSqlError::Other(Box::new(DatabaseError(format!(
+            FromSqlError::Other(Box::new(DatabaseError::new(format!(
                 "Parsing database Cpfp descriptor '{}': {}",
                 cpfp_desc_str, e
             ))))
@@ -179,20 +187,14 @@ pub fn db_wallet(db_path: &Path) -> Result<DbWallet, DatabaseError> {
 
         let our_man_xpub_str = row.get::<_, Option<String>>(5)?;
         let our_man_xpub = if let Some(ref xpub_str) = our_man_xpub_str {
-            Some(
-                ExtendedPubKey::from_str(&xpub_str)
-                    .map_err(|e| FromSqlError::Other(Box::new(e)))?,
-            )
+            Some(ExtendedPubKey::from_str(xpub_str).map_err(|e| FromSqlError::Other(Box::new(e)))?)
         } else {
             None
         };
 
         let our_stk_xpub_str = row.get::<_, Option<String>>(6)?;
         let our_stk_xpub = if let Some(ref xpub_str) = our_stk_xpub_str {
-            Some(
-                ExtendedPubKey::from_str(&xpub_str)
-                    .map_err(|e| FromSqlError::Other(Box::new(e)))?,
-            )
+            Some(ExtendedPubKey::from_str(xpub_str).map_err(|e| FromSqlError::Other(Box::new(e)))?)
         } else {
             None
         };
@@ -205,29 +207,51 @@ pub fn db_wallet(db_path: &Path) -> Result<DbWallet, DatabaseError> {
         let deposit_derivation_index: u32 = row.get(7)?;
         let deposit_derivation_index: ChildNumber = deposit_derivation_index.into();
 
+        let emer_addr_str = row.get::<_, Option<String>>(8)?;
+        let emergency_address = if let Some(ref addr_str) = emer_addr_str {
+            let address =
+                Address::from_str(addr_str).map_err(|e| FromSqlError::Other(Box::new(e)))?;
+            Some(EmergencyAddress::from(address).map_err(|e| {
+                FromSqlError::Other(Box::new(DatabaseError::new(format!(
+                    "Parsing database Emergency address '{}': {}",
+                    addr_str, e
+                ))))
+            })?)
+        } else {
+            None
+        };
+        let maintenance = row.get(9)?;
+        let encryption_salt: Option<Vec<u8>> = row.get(10)?;
+        let encryption_check: Option<Vec<u8>> = row.get(11)?;
+        let encryption = match (encryption_salt, encryption_check) {
+            (Some(salt), Some(check)) => Some((salt, check)),
+            _ => None,
+        };
+
         Ok(DbWallet {
             id,
             timestamp,
             deposit_descriptor,
             unvault_descriptor,
             cpfp_descriptor,
-            our_man_xpub,
-            our_stk_xpub,
             deposit_derivation_index,
+            emergency_address,
+            maintenance,
+            encryption,
         })
     })?;
 
     rows.pop()
-        .ok_or_else(|| DatabaseError("No row in wallet table?".to_string()))
+        .ok_or_else(|| DatabaseError::new("No row in wallet table?".to_string()))
 }
 
 impl TryFrom<&Row<'_>> for DbVault {
     type Error = rusqlite::Error;
 
     fn try_from(row: &Row) -> Result<Self, Self::Error> {
-        let (id, wallet_id) = (row.get(0)?, row.get(1)?);
+        let id = row.get(0)?;
         let status: VaultStatus = row.get::<_, u32>(2)?.try_into().map_err(|_| {
-            FromSqlError::Other(Box::new(DatabaseError(format!(
+            FromSqlError::Other(Box::new(DatabaseError::new(format!(
                 "Unknown status for vault id '{}'",
                 id
             ))))
@@ -246,10 +270,10 @@ impl TryFrom<&Row<'_>> for DbVault {
         let spend_txid = row
             .get::<_, Option<Vec<u8>>>(10)?
             .map(|raw_txid| encode::deserialize(&raw_txid).expect("We only store valid txids"));
+        let excluded = row.get(11)?;
 
         Ok(DbVault {
             id,
-            wallet_id,
             status,
             blockheight,
             deposit_outpoint,
@@ -258,6 +282,7 @@ impl TryFrom<&Row<'_>> for DbVault {
             received_at,
             updated_at,
             spend_txid,
+            excluded,
         })
     }
 }
@@ -296,6 +321,65 @@ pub fn db_vaults_min_status(
     )
 }
 
+/// Get the vaults in any of these `statuses`, except the ones at one of the `skipped`
+/// derivation indexes
+pub fn db_vaults_with_status(
+    db_path: &Path,
+    statuses: &[VaultStatus],
+    skipped: &HashSet<ChildNumber>,
+) -> Result<Vec<DbVault>, DatabaseError> {
+    if statuses.is_empty() {
+        return Ok(Vec::new());
+    }
+    // They are integers we control, no need to bind them
+    let statuses = statuses
+        .iter()
+        .map(|status| (*status as u32).to_string())
+        .collect::<Vec<String>>()
+        .join(", ");
+    let skipped = skipped
+        .iter()
+        .map(|index| u32::from(*index).to_string())
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    db_query::<_, _, DbVault>(
+        db_path,
+        &format!(
+            "SELECT * FROM vaults WHERE status IN ({}) AND derivation_index NOT IN ({}) \
+             ORDER BY id ASC",
+            statuses, skipped
+        ),
+        NO_PARAMS,
+        |row| row.try_into(),
+    )
+}
+
+/// Get the vaults we manage whose revocation transactions a watchtower should be guarding,
+/// that is the ones from 'secured' up to 'unvaulted'.
+pub fn db_vaults_to_guard(db_path: &Path) -> Result<Vec<DbVault>, DatabaseError> {
+    db_query::<_, _, DbVault>(
+        db_path,
+        "SELECT * FROM vaults WHERE status >= (?1) AND status <= (?2) AND excluded = 0 \
+         ORDER BY id ASC",
+        params![VaultStatus::Secured as u32, VaultStatus::Unvaulted as u32],
+        |row| row.try_into(),
+    )
+}
+
+/// Get the ids of the vaults this watchtower acknowledged it is guarding
+pub fn db_watchtower_acked_vaults(
+    db_path: &Path,
+    watchtower_key: &[u8; 32],
+) -> Result<Vec<u32>, DatabaseError> {
+    db_query(
+        db_path,
+        "SELECT vault_id FROM watchtower_acks WHERE watchtower_key = (?1)",
+        params![watchtower_key.to_vec()],
+        |row| row.get(0),
+    )
+}
+
 /// Get all the vaults we know about from an already-created transaction
 pub fn db_vaults_dbtx(db_tx: &Transaction) -> Result<Vec<DbVault>, DatabaseError> {
     db_query_tx(db_tx, "SELECT * FROM vaults", params![], |row| {
@@ -308,7 +392,7 @@ pub fn db_deposits(db_path: &Path) -> Result<Vec<DbVault>, DatabaseError> {
     db_query(
         db_path,
         "SELECT * FROM vaults WHERE status <= (?1) ORDER BY updated_at DESC",
-        &[VaultStatus::Active as u32],
+        [VaultStatus::Active as u32],
         |row| row.try_into(),
     )
 }
@@ -327,23 +411,194 @@ pub fn db_vault_by_deposit(
     .map(|mut vault_list| vault_list.pop())
 }
 
+impl TryFrom<&Row<'_>> for DbVaultStatusChange {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        let vault_id: u32 = row.get(1)?;
+        let status: VaultStatus = row.get::<_, u32>(2)?.try_into().map_err(|_| {
+            FromSqlError::Other(Box::new(DatabaseError::new(format!(
+                "Unknown status in history of vault id '{}'",
+                vault_id
+            ))))
+        })?;
+
+        Ok(DbVaultStatusChange {
+            status,
+            blockheight: row.get(3)?,
+            timestamp: row.get(4)?,
+        })
+    }
+}
+
+/// Get all the statuses a vault went through, from the oldest to the most recent.
+pub fn db_vault_status_history(
+    db_path: &Path,
+    vault_id: u32,
+) -> Result<Vec<DbVaultStatusChange>, DatabaseError> {
+    db_query(
+        db_path,
+        "SELECT * FROM vault_status_history WHERE vault_id = (?1) ORDER BY id ASC",
+        params![vault_id],
+        |row| row.try_into(),
+    )
+}
+
+/// A status change, along with the vault it happened to
+#[derive(Debug, Clone)]
+pub struct HistoryEvent {
+    /// The id of the status change, which orders the events of the same second
+    pub id: u32,
+    pub vault_id: u32,
+    pub change: DbVaultStatusChange,
+    pub deposit_outpoint: OutPoint,
+    pub amount: Amount,
+    pub spend_txid: Option<Txid>,
+    pub label: Option<String>,
+}
+
+/// Get the status changes to any of these `statuses` between these two timestamps (included),
+/// from the oldest to the most recent. Only those of the vaults with this label if one is given.
+/// If `after` is set to the `(timestamp, id)` of an event, only the events past this one are
+/// returned. Unlike the timestamp, it allows to page through many events of the same second.
+///
+/// A vault may reach the same status more than once, for instance if its Unvault was reorged
+/// out and confirmed again. Only its last transition to each status is returned.
+pub fn db_history_events(
+    db_path: &Path,
+    statuses: &[VaultStatus],
+    start: u32,
+    end: u32,
+    limit: usize,
+    label: Option<&str>,
+    after: Option<(u32, u32)>,
+) -> Result<Vec<HistoryEvent>, DatabaseError> {
+    if statuses.is_empty() {
+        return Ok(Vec::new());
+    }
+    // They are integers we control, no need to bind them
+    let statuses = statuses
+        .iter()
+        .map(|status| (*status as u32).to_string())
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    db_query(
+        db_path,
+        &format!(
+            "SELECT vault_status_history.*, vaults.deposit_txid, vaults.deposit_vout, \
+             vaults.amount, vaults.spend_txid, vault_labels.label \
+             FROM vault_status_history \
+             INNER JOIN vaults ON vaults.id = vault_status_history.vault_id \
+             LEFT JOIN vault_labels ON vault_labels.vault_id = vaults.id \
+             WHERE vault_status_history.timestamp >= (?1) \
+             AND vault_status_history.timestamp <= (?2) \
+             AND vault_status_history.status IN ({}) \
+             AND vault_status_history.id IN \
+             (SELECT MAX(id) FROM vault_status_history GROUP BY vault_id, status) \
+             AND vaults.excluded = 0 AND ((?4) IS NULL OR vault_labels.label = (?4)) \
+             AND ((?5) IS NULL OR vault_status_history.timestamp > (?5) \
+                  OR (vault_status_history.timestamp = (?5) AND vault_status_history.id > (?6))) \
+             ORDER BY vault_status_history.timestamp ASC, vault_status_history.id ASC \
+             LIMIT (?3)",
+            statuses
+        ),
+        params![
+            start,
+            end,
+            limit as i64,
+            label,
+            after.map(|(timestamp, _)| timestamp),
+            after.map(|(_, id)| id)
+        ],
+        |row| {
+            let change = DbVaultStatusChange::try_from(row)?;
+            let txid: Txid = encode::deserialize(&row.get::<_, Vec<u8>>(5)?)
+                .map_err(|e| FromSqlError::Other(Box::new(e)))?;
+            let deposit_outpoint = OutPoint {
+                txid,
+                vout: row.get(6)?,
+            };
+            let amount = Amount::from_sat(row.get::<_, i64>(7)? as u64);
+            let spend_txid = row
+                .get::<_, Option<Vec<u8>>>(8)?
+                .map(|raw_txid| encode::deserialize(&raw_txid).expect("We only store valid txids"));
+            Ok(HistoryEvent {
+                id: row.get(0)?,
+                vault_id: row.get(1)?,
+                change,
+                deposit_outpoint,
+                amount,
+                spend_txid,
+                label: row.get(9)?,
+            })
+        },
+    )
+}
+
+/// Get the labels of all the labeled vaults, by vault id
+pub fn db_vault_labels(db_path: &Path) -> Result<HashMap<u32, String>, DatabaseError> {
+    db_query(
+        db_path,
+        "SELECT vault_id, label FROM vault_labels",
+        NO_PARAMS,
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .map(|labels| labels.into_iter().collect())
+}
+
+/// Get the total amount of the confirmed deposits of the vaults we manage whose funds are still
+/// held (from 'funded' up to 'unvaulted'), by the label of the deposit address at the time we
+/// received them. Deposits to addresses without a label are accounted under `None`.
+pub fn db_deposit_totals(db_path: &Path) -> Result<Vec<(Option<String>, Amount)>, DatabaseError> {
+    db_query(
+        db_path,
+        "SELECT vault_deposit_labels.label, SUM(vaults.amount) FROM vaults \
+         LEFT JOIN vault_deposit_labels ON vault_deposit_labels.vault_id = vaults.id \
+         WHERE vaults.status >= (?1) AND vaults.status <= (?2) AND vaults.excluded = 0 \
+         GROUP BY vault_deposit_labels.label ORDER BY vault_deposit_labels.label",
+        params![VaultStatus::Funded as u32, VaultStatus::Unvaulted as u32],
+        |row| {
+            let label: Option<String> = row.get(0)?;
+            let amount = Amount::from_sat(row.get::<_, i64>(1)? as u64);
+            Ok((label, amount))
+        },
+    )
+}
+
+/// Get all the vaults paying to the deposit address at this derivation index. There may be more
+/// than one if the address was reused.
+pub fn db_vaults_by_derivation_index(
+    db_path: &Path,
+    derivation_index: ChildNumber,
+) -> Result<Vec<DbVault>, DatabaseError> {
+    let derivation_index: u32 = derivation_index.into();
+    db_query(
+        db_path,
+        "SELECT * FROM vaults WHERE derivation_index = (?1)",
+        params![derivation_index],
+        |row| row.try_into(),
+    )
+}
+
 /// Get the vaults that were unvaulted but for which the Unvault was not spent yet from the DB.
 pub fn db_unvaulted_vaults(
     db_path: &Path,
+    db_key: Option<&DbKey>,
 ) -> Result<Vec<(DbVault, UnvaultTransaction)>, DatabaseError> {
     db_query(
         db_path,
         "SELECT vaults.*, ptx.psbt FROM vaults INNER JOIN presigned_transactions as ptx \
          ON ptx.vault_id = vaults.id \
          WHERE ptx.type = (?1) AND vaults.status IN ((?2), (?3))",
-        &[
+        [
             TransactionType::Unvault as u32,
             VaultStatus::Unvaulted as u32,
             VaultStatus::Unvaulting as u32,
         ],
         |row| {
             let db_vault: DbVault = row.try_into()?;
-            let unvault_tx: Vec<u8> = row.get(11)?;
+            let unvault_tx = row_psbt(row, 12, db_key)?;
             let unvault_tx = UnvaultTransaction::from_psbt_serialized(&unvault_tx)
                 .expect("We store it with as_psbt_serialized");
 
@@ -355,19 +610,20 @@ pub fn db_unvaulted_vaults(
 /// transaction.
 pub fn db_spending_vaults(
     db_path: &Path,
+    db_key: Option<&DbKey>,
 ) -> Result<Vec<(DbVault, UnvaultTransaction)>, DatabaseError> {
     db_query(
         db_path,
         "SELECT vaults.*, ptx.psbt FROM vaults \
          INNER JOIN presigned_transactions as ptx ON ptx.vault_id = vaults.id \
          WHERE vaults.status = (?1) AND ptx.type = (?2)",
-        &[
+        [
             VaultStatus::Spending as u32,
             TransactionType::Unvault as u32,
         ],
         |row| {
             let db_vault: DbVault = row.try_into()?;
-            let unvault_tx: Vec<u8> = row.get(11)?;
+            let unvault_tx = row_psbt(row, 12, db_key)?;
             let unvault_tx = UnvaultTransaction::from_psbt_serialized(&unvault_tx)
                 .expect("We store it with as_psbt_serialized");
 
@@ -380,19 +636,20 @@ pub fn db_spending_vaults(
 /// transaction.
 pub fn db_canceling_vaults(
     db_path: &Path,
+    db_key: Option<&DbKey>,
 ) -> Result<Vec<(DbVault, CancelTransaction)>, DatabaseError> {
     db_query(
         db_path,
         "SELECT vaults.*, ptx.psbt FROM vaults \
          INNER JOIN presigned_transactions as ptx ON ptx.vault_id = vaults.id \
          WHERE vaults.status = (?1) AND ptx.type = (?2)",
-        &[
+        [
             VaultStatus::Canceling as u32,
             TransactionType::Cancel as u32,
         ],
         |row| {
             let db_vault: DbVault = row.try_into()?;
-            let cancel_tx: Vec<u8> = row.get(11)?;
+            let cancel_tx = row_psbt(row, 12, db_key)?;
             let cancel_tx = CancelTransaction::from_psbt_serialized(&cancel_tx)
                 .expect("We store it with as_psbt_serialized");
 
@@ -405,19 +662,20 @@ pub fn db_canceling_vaults(
 /// Emergency transaction.
 pub fn db_emering_vaults(
     db_path: &Path,
+    db_key: Option<&DbKey>,
 ) -> Result<Vec<(DbVault, EmergencyTransaction)>, DatabaseError> {
     db_query(
         db_path,
         "SELECT vaults.*, ptx.psbt FROM vaults \
          INNER JOIN presigned_transactions as ptx ON ptx.vault_id = vaults.id \
          WHERE vaults.status = (?1) AND ptx.type = (?2)",
-        &[
+        [
             VaultStatus::EmergencyVaulting as u32,
             TransactionType::Emergency as u32,
         ],
         |row| {
             let db_vault: DbVault = row.try_into()?;
-            let emer_tx: Vec<u8> = row.get(11)?;
+            let emer_tx = row_psbt(row, 12, db_key)?;
             let emer_tx = EmergencyTransaction::from_psbt_serialized(&emer_tx)
                 .expect("We store it with to_psbt_serialized");
 
@@ -430,19 +688,20 @@ pub fn db_emering_vaults(
 /// respective UnvaultEmergency transaction.
 pub fn db_unemering_vaults(
     db_path: &Path,
+    db_key: Option<&DbKey>,
 ) -> Result<Vec<(DbVault, UnvaultEmergencyTransaction)>, DatabaseError> {
     db_query(
         db_path,
         "SELECT vaults.*, ptx.psbt FROM vaults \
          INNER JOIN presigned_transactions as ptx ON ptx.vault_id = vaults.id \
          WHERE vaults.status = (?1) AND ptx.type = (?2)",
-        &[
+        [
             VaultStatus::UnvaultEmergencyVaulting as u32,
             TransactionType::UnvaultEmergency as u32,
         ],
         |row| {
             let db_vault: DbVault = row.try_into()?;
-            let unemer_tx: Vec<u8> = row.get(11)?;
+            let unemer_tx = row_psbt(row, 12, db_key)?;
             let unemer_tx = UnvaultEmergencyTransaction::from_psbt_serialized(&unemer_tx)
                 .expect("We store it with to_psbt_serialized");
 
@@ -451,81 +710,79 @@ pub fn db_unemering_vaults(
     )
 }
 
-impl TryFrom<&Row<'_>> for DbTransaction {
-    type Error = rusqlite::Error;
-
-    fn try_from(row: &Row) -> Result<Self, Self::Error> {
-        let id: u32 = row.get(0)?;
-        let vault_id: u32 = row.get(1)?;
-
-        let db_tx_type: u32 = row.get(2)?;
-        let tx_type: TransactionType = db_tx_type.try_into().map_err(|_| {
-            FromSqlError::Other(Box::new(DatabaseError(format!(
-                "Unsane db: got an invalid tx type: '{}'",
-                db_tx_type
-            ))))
-        })?;
-
-        let db_psbt: Vec<u8> = row.get(3)?;
-        let psbt = match tx_type {
-            // For the remaining transactions (which we do create), we store a PSBT.
-            TransactionType::Unvault => RevaultTx::Unvault(
-                UnvaultTransaction::from_psbt_serialized(&db_psbt)
-                    .map_err(|e| FromSqlError::Other(Box::new(e)))?,
-            ),
-            TransactionType::Cancel => RevaultTx::Cancel(
-                CancelTransaction::from_psbt_serialized(&db_psbt)
-                    .map_err(|e| FromSqlError::Other(Box::new(e)))?,
-            ),
-            TransactionType::Emergency => RevaultTx::Emergency(
-                EmergencyTransaction::from_psbt_serialized(&db_psbt)
-                    .map_err(|e| FromSqlError::Other(Box::new(e)))?,
-            ),
-            TransactionType::UnvaultEmergency => RevaultTx::UnvaultEmergency(
-                UnvaultEmergencyTransaction::from_psbt_serialized(&db_psbt)
-                    .map_err(|e| FromSqlError::Other(Box::new(e)))?,
-            ),
-        };
-
-        debug_assert_eq!(
-            encode::deserialize::<revault_tx::bitcoin::util::psbt::PartiallySignedTransaction>(
-                &db_psbt
-            )
-            .unwrap()
-            .global
-            .unsigned_tx
-            .txid()
-            .to_vec(),
-            row.get::<_, Vec<u8>>(4)?,
-            "Column txid and Psbt txid mismatch"
-        );
-
-        let is_fully_signed: bool = row.get(5)?;
+/// Get a presigned transaction out of a row of the "presigned_transactions" table
+pub fn db_transaction_from_row(
+    row: &Row,
+    db_key: Option<&DbKey>,
+) -> rusqlite::Result<DbTransaction> {
+    let id: u32 = row.get(0)?;
+    let vault_id: u32 = row.get(1)?;
+
+    let db_tx_type: u32 = row.get(2)?;
+    let tx_type: TransactionType = db_tx_type.try_into().map_err(|_| {
+        FromSqlError::Other(Box::new(DatabaseError::new(format!(
+            "Unsane db: got an invalid tx type: '{}'",
+            db_tx_type
+        ))))
+    })?;
 
-        Ok(DbTransaction {
-            id,
-            vault_id,
-            tx_type,
-            psbt,
-            is_fully_signed,
-        })
-    }
+    let db_psbt = row_psbt(row, 3, db_key)?;
+    let psbt = match tx_type {
+        // For the remaining transactions (which we do create), we store a PSBT.
+        TransactionType::Unvault => RevaultTx::Unvault(
+            UnvaultTransaction::from_psbt_serialized(&db_psbt)
+                .map_err(|e| FromSqlError::Other(Box::new(e)))?,
+        ),
+        TransactionType::Cancel => RevaultTx::Cancel(
+            CancelTransaction::from_psbt_serialized(&db_psbt)
+                .map_err(|e| FromSqlError::Other(Box::new(e)))?,
+        ),
+        TransactionType::Emergency => RevaultTx::Emergency(
+            EmergencyTransaction::from_psbt_serialized(&db_psbt)
+                .map_err(|e| FromSqlError::Other(Box::new(e)))?,
+        ),
+        TransactionType::UnvaultEmergency => RevaultTx::UnvaultEmergency(
+            UnvaultEmergencyTransaction::from_psbt_serialized(&db_psbt)
+                .map_err(|e| FromSqlError::Other(Box::new(e)))?,
+        ),
+    };
+
+    debug_assert_eq!(
+        encode::deserialize::<revault_tx::bitcoin::util::psbt::PartiallySignedTransaction>(
+            &db_psbt
+        )
+        .unwrap()
+        .global
+        .unsigned_tx
+        .txid()
+        .to_vec(),
+        row.get::<_, Vec<u8>>(4)?,
+        "Column txid and Psbt txid mismatch"
+    );
+
+    Ok(DbTransaction {
+        id,
+        vault_id,
+        tx_type,
+        psbt,
+    })
 }
 
 /// Get the Unvault transaction for this vault
 pub fn db_unvault_transaction(
     db_path: &Path,
+    db_key: Option<&DbKey>,
     vault_id: u32,
 ) -> Result<(u32, UnvaultTransaction), DatabaseError> {
     let mut rows: Vec<DbTransaction> = db_query(
         db_path,
         "SELECT * FROM presigned_transactions WHERE vault_id = (?1) AND type = (?2)",
         params![vault_id, TransactionType::Unvault as u32],
-        |row| row.try_into(),
+        |row| db_transaction_from_row(row, db_key),
     )?;
-    let db_tx = rows
-        .pop()
-        .ok_or_else(|| DatabaseError(format!("No unvault tx in db for vault id '{}'", vault_id)))?;
+    let db_tx = rows.pop().ok_or_else(|| {
+        DatabaseError::new(format!("No unvault tx in db for vault id '{}'", vault_id))
+    })?;
 
     Ok((
         db_tx.id,
@@ -536,13 +793,14 @@ pub fn db_unvault_transaction(
 /// Get the Unvault transaction for this vault from an existing database transaction
 pub fn db_unvault_dbtx(
     db_tx: &Transaction,
+    db_key: Option<&DbKey>,
     vault_id: u32,
 ) -> Result<Option<UnvaultTransaction>, DatabaseError> {
     db_query_tx(
         db_tx,
         "SELECT * FROM presigned_transactions WHERE vault_id = (?1) AND type = (?2)",
         params![vault_id, TransactionType::Unvault as u32],
-        |row| row.try_into(),
+        |row| db_transaction_from_row(row, db_key),
     )
     .map(|mut rows| {
         rows.pop()
@@ -554,6 +812,7 @@ pub fn db_unvault_dbtx(
 /// Note that unconfirmed vaults don't have the Unvault transaction stored in database.
 pub fn db_unvault_from_deposit(
     db_path: &Path,
+    db_key: Option<&DbKey>,
     deposit: &OutPoint,
 ) -> Result<Option<UnvaultTransaction>, DatabaseError> {
     let db_unvault: Option<DbTransaction> = db_query(
@@ -561,7 +820,7 @@ pub fn db_unvault_from_deposit(
         "SELECT * FROM presigned_transactions as ptx INNER JOIN vaults ON ptx.vault_id = vaults.id \
          WHERE vaults.deposit_txid = (?1) AND vaults.deposit_vout = (?2) AND ptx.type = (?3)",
         params![deposit.txid.to_vec(), deposit.vout, TransactionType::Unvault as u32],
-        |row| row.try_into()
+        |row| db_transaction_from_row(row, db_key)
     ).map(|mut rows| rows.pop())?;
 
     Ok(db_unvault.map(|db_tx| assert_tx_type!(db_tx.psbt, Unvault, "We just queried it")))
@@ -570,13 +829,14 @@ pub fn db_unvault_from_deposit(
 /// Get the Cancel transaction corresponding to this vault
 pub fn db_cancel_transaction(
     db_path: &Path,
+    db_key: Option<&DbKey>,
     vault_id: u32,
 ) -> Result<Option<(u32, CancelTransaction)>, DatabaseError> {
     let mut rows: Vec<DbTransaction> = db_query(
         db_path,
         "SELECT * FROM presigned_transactions WHERE vault_id = (?1) AND type = (?2)",
         params![vault_id, TransactionType::Cancel as u32],
-        |row| row.try_into(),
+        |row| db_transaction_from_row(row, db_key),
     )?;
     Ok(rows.pop().map(|db_tx| {
         (
@@ -589,13 +849,14 @@ pub fn db_cancel_transaction(
 /// Get the Cancel transaction corresponding to this vault
 pub fn db_cancel_dbtx(
     db_tx: &Transaction,
+    db_key: Option<&DbKey>,
     vault_id: u32,
 ) -> Result<Option<CancelTransaction>, DatabaseError> {
     db_query_tx(
         db_tx,
         "SELECT * FROM presigned_transactions WHERE vault_id = (?1) AND type = (?2)",
         params![vault_id, TransactionType::Cancel as u32],
-        |row| row.try_into(),
+        |row| db_transaction_from_row(row, db_key),
     )
     .map(|mut rows| {
         rows.pop()
@@ -607,13 +868,14 @@ pub fn db_cancel_dbtx(
 /// Will error if there are none, ie if called by a non-stakeholder!
 pub fn db_emer_transaction(
     db_path: &Path,
+    db_key: Option<&DbKey>,
     vault_id: u32,
 ) -> Result<Option<(u32, EmergencyTransaction)>, DatabaseError> {
     db_query(
         db_path,
         "SELECT * FROM presigned_transactions WHERE vault_id = (?1) AND type = (?2)",
         params![vault_id, TransactionType::Emergency as u32],
-        |row| row.try_into(),
+        |row| db_transaction_from_row(row, db_key),
     )
     .map(|mut rows| {
         rows.pop().map(|db_tx: DbTransaction| {
@@ -629,13 +891,14 @@ pub fn db_emer_transaction(
 /// Will error if there are none, ie if called by a non-stakeholder!
 pub fn db_unvault_emer_transaction(
     db_path: &Path,
+    db_key: Option<&DbKey>,
     vault_id: u32,
 ) -> Result<Option<(u32, UnvaultEmergencyTransaction)>, DatabaseError> {
     db_query(
         db_path,
         "SELECT * FROM presigned_transactions WHERE vault_id = (?1) AND type = (?2)",
         params![vault_id, TransactionType::UnvaultEmergency as u32],
-        |row| row.try_into(),
+        |row| db_transaction_from_row(row, db_key),
     )
     .map(|mut rows| {
         rows.pop().map(|db_tx: DbTransaction| {
@@ -650,11 +913,12 @@ pub fn db_unvault_emer_transaction(
 /// Get a vault and its Unvault transaction out of an Unvault txid
 pub fn db_vault_by_unvault_txid(
     db_path: &Path,
+    db_key: Option<&DbKey>,
     txid: &Txid,
 ) -> Result<Option<(DbVault, DbTransaction)>, DatabaseError> {
     Ok(db_query(
         db_path,
-        "SELECT vaults.*, ptx.id, ptx.psbt, ptx.fullysigned FROM presigned_transactions as ptx \
+        "SELECT vaults.*, ptx.id, ptx.psbt FROM presigned_transactions as ptx \
          INNER JOIN vaults ON vaults.id = ptx.vault_id \
          WHERE ptx.txid = (?1) and type = (?2)",
         params![txid.to_vec(), TransactionType::Unvault as u32],
@@ -663,16 +927,14 @@ pub fn db_vault_by_unvault_txid(
 
             // FIXME: there is probably a more extensible way to implement the from()s so we don't
             // have to change all those when adding a column
-            let id: u32 = row.get(11)?;
-            let psbt: Vec<u8> = row.get(12)?;
+            let id: u32 = row.get(12)?;
+            let psbt = row_psbt(row, 13, db_key)?;
             let psbt = UnvaultTransaction::from_psbt_serialized(&psbt).expect("We store it");
-            let is_fully_signed = row.get(13)?;
             let db_tx = DbTransaction {
                 id,
                 vault_id: db_vault.id,
                 tx_type: TransactionType::Unvault,
                 psbt: RevaultTx::Unvault(psbt),
-                is_fully_signed,
             };
 
             Ok((db_vault, db_tx))
@@ -684,28 +946,34 @@ pub fn db_vault_by_unvault_txid(
 /// Get all the presigned transactions for which we don't have all the sigs yet.
 /// Note that it will return the emergency transactions (if unsigned) only if we
 /// are a stakeholder.
-pub fn db_transactions_sig_missing(db_path: &Path) -> Result<Vec<DbTransaction>, DatabaseError> {
+pub fn db_transactions_sig_missing(
+    db_path: &Path,
+    db_key: Option<&DbKey>,
+) -> Result<Vec<DbTransaction>, DatabaseError> {
     db_query(
         db_path,
         "SELECT * FROM presigned_transactions WHERE fullysigned = 0",
         params![],
-        |row| row.try_into(),
+        |row| db_transaction_from_row(row, db_key),
     )
 }
 
 /// Get all the Emergency transactions of the "secured" (Emergency signed) vaults that were not yet
-/// Unvaulted.
-pub fn db_signed_emer_txs(db_path: &Path) -> Result<Vec<EmergencyTransaction>, DatabaseError> {
+/// Unvaulted, and that the user didn't exclude.
+pub fn db_signed_emer_txs(
+    db_path: &Path,
+    db_key: Option<&DbKey>,
+) -> Result<Vec<EmergencyTransaction>, DatabaseError> {
     db_query(
         db_path,
         "SELECT ptx.* FROM presigned_transactions as ptx INNER JOIN vaults as v ON ptx.vault_id = v.id \
-         WHERE ptx.fullysigned = 1 AND ptx.type = (?1) AND v.status < (?2)",
+         WHERE ptx.fullysigned = 1 AND ptx.type = (?1) AND v.status < (?2) AND v.excluded = 0",
         params![
             TransactionType::Emergency as u32,
             VaultStatus::Unvaulting as u32,
         ],
         |row| {
-            let db_tx: DbTransaction = row.try_into()?;
+            let db_tx = db_transaction_from_row(row, db_key)?;
             Ok(match db_tx.psbt {
                 RevaultTx::Emergency(tx) => tx,
                 _ => unreachable!("Inconsistency between TransactionType and RevaultTx variant?"),
@@ -717,6 +985,7 @@ pub fn db_signed_emer_txs(db_path: &Path) -> Result<Vec<EmergencyTransaction>, D
 /// Get all the UnvaultEmergency transactions of the Unvaulted vaults that were not yet Spent
 pub fn db_signed_unemer_txs(
     db_path: &Path,
+    db_key: Option<&DbKey>,
 ) -> Result<Vec<UnvaultEmergencyTransaction>, DatabaseError> {
     db_query(
         db_path,
@@ -730,7 +999,7 @@ pub fn db_signed_unemer_txs(
             VaultStatus::Canceling as u32,
         ],
         |row| {
-            let db_tx: DbTransaction = row.try_into()?;
+            let db_tx = db_transaction_from_row(row, db_key)?;
             Ok(match db_tx.psbt {
                 RevaultTx::UnvaultEmergency(tx) => tx,
                 _ => unreachable!("Inconsistency between TransactionType and RevaultTx variant?"),
@@ -739,34 +1008,32 @@ pub fn db_signed_unemer_txs(
     )
 }
 
-impl TryFrom<&Row<'_>> for DbSpendTransaction {
-    type Error = rusqlite::Error;
-
-    fn try_from(row: &Row) -> Result<Self, Self::Error> {
-        let id: i64 = row.get(0)?;
-        let psbt: Vec<u8> = row.get(1)?;
-        let broadcasted: Option<bool> = row.get(3)?; // 2 is 'txid'
-
-        let psbt = SpendTransaction::from_psbt_serialized(&psbt)
-            .expect("We store it with as_psbt_serialized");
-
-        debug_assert_eq!(
-            psbt.tx().txid().to_vec(),
-            row.get::<_, Vec<u8>>(2)?,
-            "Insane db, txid in column is not the same as psbt's one",
-        );
-
-        Ok(DbSpendTransaction {
-            id,
-            psbt,
-            broadcasted,
-        })
-    }
+// Get a Spend transaction out of a row of the "spend_transactions" table
+fn db_spend_from_row(row: &Row, db_key: Option<&DbKey>) -> rusqlite::Result<DbSpendTransaction> {
+    let id: i64 = row.get(0)?;
+    let psbt = row_psbt(row, 1, db_key)?;
+    let broadcasted: Option<bool> = row.get(3)?; // 2 is 'txid'
+
+    let psbt =
+        SpendTransaction::from_psbt_serialized(&psbt).expect("We store it with as_psbt_serialized");
+
+    debug_assert_eq!(
+        psbt.tx().txid().to_vec(),
+        row.get::<_, Vec<u8>>(2)?,
+        "Insane db, txid in column is not the same as psbt's one",
+    );
+
+    Ok(DbSpendTransaction {
+        id,
+        psbt,
+        broadcasted,
+    })
 }
 
 /// List all Spend transactions in DB along with the vault they are spending
 pub fn db_list_spends(
     db_path: &Path,
+    db_key: Option<&DbKey>,
 ) -> Result<HashMap<Txid, (DbSpendTransaction, Vec<OutPoint>)>, DatabaseError> {
     // SpendTransaction can't be Hash for the moment
     let mut res: HashMap<Txid, (DbSpendTransaction, Vec<OutPoint>)> = HashMap::with_capacity(128);
@@ -780,7 +1047,7 @@ pub fn db_list_spends(
          INNER JOIN vaults ON vaults.id = ptx.vault_id",
         params![],
         |row| {
-            let db_spend: DbSpendTransaction = row.try_into()?;
+            let db_spend = db_spend_from_row(row, db_key)?;
 
             let txid: Txid = encode::deserialize(&row.get::<_, Vec<u8>>(4)?).expect("We store it");
             let vout: u32 = row.get(5)?;
@@ -788,11 +1055,11 @@ pub fn db_list_spends(
 
             let spend_txid = db_spend.psbt.tx().txid();
 
-            if res.contains_key(&spend_txid) {
+            if let std::collections::hash_map::Entry::Vacant(e) = res.entry(spend_txid) {
+                e.insert((db_spend, vec![deposit_outpoint]));
+            } else {
                 let (_, outpoints) = res.get_mut(&spend_txid).unwrap();
                 outpoints.push(deposit_outpoint);
-            } else {
-                res.insert(spend_txid, (db_spend, vec![deposit_outpoint]));
             }
 
             Ok(())
@@ -804,25 +1071,27 @@ pub fn db_list_spends(
 
 pub fn db_broadcastable_spend_transactions(
     db_path: &Path,
+    db_key: Option<&DbKey>,
 ) -> Result<Vec<DbSpendTransaction>, DatabaseError> {
     db_query(
         db_path,
         "SELECT * FROM spend_transactions WHERE broadcasted = 0",
         params![],
-        |row| row.try_into(),
+        |row| db_spend_from_row(row, db_key),
     )
 }
 
 /// Get a single Spend transaction from DB by its txid
 pub fn db_spend_transaction(
     db_path: &Path,
+    db_key: Option<&DbKey>,
     spend_txid: &Txid,
 ) -> Result<Option<DbSpendTransaction>, DatabaseError> {
     Ok(db_query(
         db_path,
         "SELECT * FROM spend_transactions WHERE txid = (?1)",
         params![spend_txid.to_vec()],
-        |row| row.try_into(),
+        |row| db_spend_from_row(row, db_key),
     )?
     .pop())
 }
@@ -847,7 +1116,7 @@ pub fn db_vaults_from_spend(
         params![spend_txid.to_vec()],
         |row| {
             let db_vault: DbVault = row.try_into()?;
-            let txid: Txid = encode::deserialize(&row.get::<_, Vec<u8>>(11)?).expect("We store it");
+            let txid: Txid = encode::deserialize(&row.get::<_, Vec<u8>>(12)?).expect("We store it");
             db_vaults.insert(txid, db_vault);
             Ok(())
         },
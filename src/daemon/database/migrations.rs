@@ -0,0 +1,581 @@
+//! Upgrades of the database schema from the previous versions of revaultd.
+//!
+//! Each migration upgrades the database by a single version, inside the same transaction as
+//! the version bump. An upgrade interrupted midway therefore leaves the database at a
+//! consistent (older) version, from which it is resumed at the next startup.
+
+use crate::database::{
+    interface::db_exec,
+    schema::{
+        DEPOSIT_LABELS_SCHEMA, SCHEMA, VAULT_DEPOSIT_LABELS_SCHEMA, VAULT_LABELS_SCHEMA,
+        VAULT_STATUS_HISTORY_SCHEMA, WATCHTOWER_ACKS_SCHEMA,
+    },
+    DatabaseError, DB_VERSION,
+};
+
+use std::{collections::BTreeMap, path::Path};
+
+use rusqlite::{params, Connection, OpenFlags, Transaction, NO_PARAMS};
+
+type Migration = fn(&Transaction) -> rusqlite::Result<()>;
+
+/// The migration to the next version, indexed by the version it upgrades from. Bumping
+/// DB_VERSION requires adding the migration from the previous version here.
+const MIGRATIONS: [Migration; DB_VERSION as usize] = [
+    // 0 -> 1
+    |tx| {
+        tx.execute_batch(
+            "ALTER TABLE vaults ADD COLUMN \
+             excluded BOOLEAN NOT NULL DEFAULT 0 CHECK (excluded IN (0,1));",
+        )
+    },
+    // 1 -> 2
+    |tx| tx.execute_batch("ALTER TABLE wallets ADD COLUMN emergency_address TEXT;"),
+    // 2 -> 3. We don't know about the previous statuses, so start the history with the current
+    // one.
+    |tx| {
+        tx.execute_batch(VAULT_STATUS_HISTORY_SCHEMA)?;
+        tx.execute_batch(
+            "INSERT INTO vault_status_history (vault_id, status, blockheight, timestamp) \
+             SELECT id, status, (SELECT blockheight FROM tip), updated_at FROM vaults;",
+        )
+    },
+    // 3 -> 4
+    |tx| tx.execute_batch(DEPOSIT_LABELS_SCHEMA),
+    // 4 -> 5
+    |tx| {
+        tx.execute_batch(
+            "ALTER TABLE wallets ADD COLUMN \
+             maintenance BOOLEAN NOT NULL DEFAULT 0 CHECK (maintenance IN (0,1));",
+        )
+    },
+    // 5 -> 6
+    |tx| tx.execute_batch(WATCHTOWER_ACKS_SCHEMA),
+    // 6 -> 7
+    |tx| {
+        tx.execute_batch(
+            "ALTER TABLE wallets ADD COLUMN encryption_salt BLOB;
+             ALTER TABLE wallets ADD COLUMN encryption_check BLOB;",
+        )
+    },
+    // 7 -> 8. Vaults were looked up by deposit outpoint and derivation index with a full scan.
+    |tx| {
+        tx.execute_batch(
+            "CREATE INDEX vault_deposit ON vaults (deposit_txid, deposit_vout);
+             CREATE INDEX vault_derivation_index ON vaults (derivation_index);",
+        )
+    },
+    // 8 -> 9
+    |tx| tx.execute_batch(VAULT_LABELS_SCHEMA),
+    // 9 -> 10. We don't know the labels the addresses had when the existing deposits were
+    // received, assume they didn't change since then.
+    |tx| {
+        tx.execute_batch(VAULT_DEPOSIT_LABELS_SCHEMA)?;
+        tx.execute_batch(
+            "INSERT INTO vault_deposit_labels (vault_id, label) \
+             SELECT vaults.id, deposit_labels.label FROM vaults \
+             INNER JOIN deposit_labels ON deposit_labels.wallet_id = vaults.wallet_id \
+             AND deposit_labels.derivation_index = vaults.derivation_index;",
+        )
+    },
+];
+
+/// Create all the tables of the latest version of the schema.
+pub fn create_schema(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(SCHEMA)?;
+    tx.execute_batch(VAULT_STATUS_HISTORY_SCHEMA)?;
+    tx.execute_batch(DEPOSIT_LABELS_SCHEMA)?;
+    tx.execute_batch(WATCHTOWER_ACKS_SCHEMA)?;
+    tx.execute_batch(VAULT_LABELS_SCHEMA)?;
+    tx.execute_batch(VAULT_DEPOSIT_LABELS_SCHEMA)
+}
+
+fn version_dbtx(tx: &Transaction) -> Result<u32, DatabaseError> {
+    tx.query_row("SELECT version FROM version", NO_PARAMS, |row| row.get(0))
+        .map_err(|e| DatabaseError::sqlite("Getting database version", e))
+}
+
+// Upgrade the database from this version to the next one
+fn migrate_dbtx(tx: &Transaction, version: u32) -> Result<(), DatabaseError> {
+    MIGRATIONS[version as usize](tx).map_err(|e| {
+        DatabaseError::sqlite(
+            &format!(
+                "Migrating database from version {} to version {}",
+                version,
+                version + 1
+            ),
+            e,
+        )
+    })?;
+    tx.execute("UPDATE version SET version = (?1)", params![version + 1])
+        .map_err(|e| DatabaseError::sqlite("Updating version", e))?;
+
+    Ok(())
+}
+
+/// Upgrade the database schema from an older version, one version at a time.
+pub fn migrate_db(db_path: &Path, mut version: u32) -> Result<(), DatabaseError> {
+    while version < DB_VERSION {
+        log::info!(
+            "Upgrading database from version {} to version {}",
+            version,
+            version + 1
+        );
+        db_exec(db_path, |tx| migrate_dbtx(tx, version))?;
+        version += 1;
+    }
+
+    Ok(())
+}
+
+// For each table, its columns in order as (name, type, not null, default value, primary key).
+// The order matters as the rows of a 'SELECT *' are read by column index.
+type Columns = Vec<(String, String, bool, Option<String>, bool)>;
+
+fn schema_columns(conn: &Connection) -> Result<BTreeMap<String, Columns>, DatabaseError> {
+    let tables = conn
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table'")
+        .and_then(|mut stmt| {
+            stmt.query_map(NO_PARAMS, |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()
+        })
+        .map_err(|e| DatabaseError::sqlite("Listing tables", e))?;
+
+    tables
+        .into_iter()
+        .map(|table| {
+            let columns = conn
+                .prepare(&format!("PRAGMA table_info({})", table))
+                .and_then(|mut stmt| {
+                    stmt.query_map(NO_PARAMS, |row| {
+                        Ok((
+                            row.get(1)?,
+                            row.get(2)?,
+                            row.get(3)?,
+                            row.get(4)?,
+                            row.get::<_, i64>(5)? > 0,
+                        ))
+                    })?
+                    .collect::<rusqlite::Result<Columns>>()
+                })
+                .map_err(|e| {
+                    DatabaseError::sqlite(&format!("Listing columns of '{}'", table), e)
+                })?;
+            Ok((table, columns))
+        })
+        .collect()
+}
+
+// The indexes and triggers, as (type, definition) by name. The definitions are compared with
+// the whitespaces normalized, as the ones created by a migration may be indented differently.
+fn schema_objects(conn: &Connection) -> Result<BTreeMap<String, (String, String)>, DatabaseError> {
+    conn.prepare(
+        "SELECT name, type, sql FROM sqlite_master \
+         WHERE type IN ('index', 'trigger') AND sql IS NOT NULL",
+    )
+    .and_then(|mut stmt| {
+        stmt.query_map(NO_PARAMS, |row| {
+            let sql: String = row.get(2)?;
+            Ok((
+                row.get(0)?,
+                (
+                    row.get(1)?,
+                    sql.split_whitespace().collect::<Vec<&str>>().join(" "),
+                ),
+            ))
+        })?
+        .collect()
+    })
+    .map_err(|e| DatabaseError::sqlite("Listing indexes and triggers", e))
+}
+
+// Compare the tables, columns, indexes and triggers of the database with the ones of the latest
+// schema
+fn check_schema_dbtx(tx: &Transaction) -> Result<(), DatabaseError> {
+    let mut expected_conn = Connection::open_in_memory()
+        .map_err(|e| DatabaseError::sqlite("Opening in-memory database", e))?;
+    let expected_tx = expected_conn
+        .transaction()
+        .map_err(|e| DatabaseError::sqlite("Creating transaction", e))?;
+    create_schema(&expected_tx)
+        .map_err(|e| DatabaseError::sqlite("Creating in-memory database", e))?;
+    let expected = schema_columns(&expected_tx)?;
+    let actual = schema_columns(tx)?;
+
+    for (table, expected_columns) in expected.iter() {
+        match actual.get(table) {
+            None => return Err(DatabaseError::new(format!("Missing table '{}'", table))),
+            Some(columns) if columns != expected_columns => {
+                return Err(DatabaseError::new(format!(
+                    "Unexpected columns for table '{}': got '{:?}', expected '{:?}'",
+                    table, columns, expected_columns
+                )))
+            }
+            _ => {}
+        }
+    }
+    if let Some(table) = actual.keys().find(|table| !expected.contains_key(*table)) {
+        return Err(DatabaseError::new(format!("Unexpected table '{}'", table)));
+    }
+
+    let expected = schema_objects(&expected_tx)?;
+    let actual = schema_objects(tx)?;
+    for (name, (kind, expected_sql)) in expected.iter() {
+        match actual.get(name) {
+            None => return Err(DatabaseError::new(format!("Missing {} '{}'", kind, name))),
+            Some((_, sql)) if sql != expected_sql => {
+                return Err(DatabaseError::new(format!(
+                    "Unexpected definition for {} '{}': got '{}', expected '{}'",
+                    kind, name, sql, expected_sql
+                )))
+            }
+            _ => {}
+        }
+    }
+    if let Some((name, (kind, _))) = actual
+        .iter()
+        .find(|(name, _)| !expected.contains_key(*name))
+    {
+        return Err(DatabaseError::new(format!(
+            "Unexpected {} '{}'",
+            kind, name
+        )));
+    }
+
+    Ok(())
+}
+
+/// Check the database has a schema we can use, without modifying it. If it is from a previous
+/// version, the pending migrations are applied to check they would succeed but are not
+/// committed. Returns the current version of the database.
+pub fn check_db_schema(db_path: &Path) -> Result<u32, DatabaseError> {
+    if !db_path.exists() {
+        return Err(DatabaseError::new(format!("No database at {:?}", db_path)));
+    }
+    let mut conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_WRITE)
+        .map_err(|e| DatabaseError::sqlite("Opening database", e))?;
+    // Dropping it rolls back any change
+    let tx = conn
+        .transaction()
+        .map_err(|e| DatabaseError::sqlite("Creating transaction", e))?;
+
+    let version = version_dbtx(&tx)?;
+    if version > DB_VERSION {
+        return Err(DatabaseError::new(format!(
+            "Unexpected database version: got '{}', expected '{}'",
+            version, DB_VERSION
+        )));
+    }
+    for from_version in version..DB_VERSION {
+        migrate_dbtx(&tx, from_version)?;
+    }
+    check_schema_dbtx(&tx)?;
+
+    Ok(version)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{
+        database::{
+            actions::setup_db,
+            interface::{db_vault_status_history, db_vaults, db_version},
+        },
+        jsonrpc::UserRole,
+        utils::test_utils::{dummy_revaultd, test_datadir},
+    };
+
+    use std::fs;
+
+    /// Downgrade a database created from the latest schema to how it was in version 0, without
+    /// the 'excluded', 'emergency_address' and 'maintenance' columns nor the tables added since.
+    pub fn downgrade_to_v0(db_path: &Path) {
+        db_exec(db_path, |tx| {
+            tx.execute_batch(
+                "CREATE TABLE wallets_v0 AS SELECT id, timestamp, deposit_descriptor,
+                    unvault_descriptor, cpfp_descriptor, our_manager_xpub,
+                    our_stakeholder_xpub, deposit_derivation_index FROM wallets;
+                DROP TABLE vault_deposit_labels;
+                DROP TABLE vault_labels;
+                DROP TABLE watchtower_acks;
+                DROP TABLE deposit_labels;
+                DROP TABLE vault_status_history;
+                DROP TABLE vaults;
+                DROP TABLE wallets;
+                CREATE TABLE wallets (
+                    id INTEGER PRIMARY KEY NOT NULL,
+                    timestamp INTEGER NOT NULL,
+                    deposit_descriptor TEXT NOT NULL,
+                    unvault_descriptor TEXT NOT NULL,
+                    cpfp_descriptor TEXT NOT NULL,
+                    our_manager_xpub TEXT,
+                    our_stakeholder_xpub TEXT,
+                    deposit_derivation_index INTEGER NOT NULL
+                );
+                INSERT INTO wallets SELECT * FROM wallets_v0;
+                DROP TABLE wallets_v0;
+                CREATE TABLE vaults (
+                    id INTEGER PRIMARY KEY NOT NULL,
+                    wallet_id INTEGER NOT NULL,
+                    status INTEGER NOT NULL,
+                    blockheight INTEGER NOT NULL,
+                    deposit_txid BLOB NOT NULL,
+                    deposit_vout INTEGER NOT NULL,
+                    amount INTEGER NOT NULL,
+                    derivation_index INTEGER NOT NULL,
+                    received_at INTEGER NOT NULL,
+                    updated_at INTEGER NOT NULL,
+                    spend_txid BLOB,
+                    FOREIGN KEY (wallet_id) REFERENCES wallets (id)
+                        ON UPDATE RESTRICT
+                        ON DELETE RESTRICT
+                );
+                CREATE INDEX vault_status ON vaults (status);
+                UPDATE version SET version = 0;",
+            )
+            .unwrap();
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    // Bring a version 0 database to this version, as it was for the users of this version
+    fn upgrade_to(db_path: &Path, version: u32) {
+        db_exec(db_path, |tx| {
+            for from_version in 0..version {
+                migrate_dbtx(tx, from_version)?;
+            }
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn migrate_from_every_version() {
+        let datadir = test_datadir();
+
+        for version in 0..=DB_VERSION {
+            fs::remove_dir_all(&datadir).unwrap_or(());
+            let mut revaultd = dummy_revaultd(datadir.clone(), UserRole::ManagerStakeholder);
+            let db_path = revaultd.db_file();
+            setup_db(&mut revaultd).unwrap();
+
+            // A version 0 database with a vault, upgraded to the version under test
+            downgrade_to_v0(&db_path);
+            db_exec(&db_path, |tx| {
+                tx.execute(
+                    "INSERT INTO vaults (wallet_id, status, blockheight, deposit_txid, \
+                     deposit_vout, amount, derivation_index, received_at, updated_at) \
+                     VALUES (1, 0, 0, ?1, 0, 123456, 3, 1615297315, 1615297315)",
+                    params![vec![1u8; 32]],
+                )?;
+                Ok(())
+            })
+            .unwrap();
+            upgrade_to(&db_path, version);
+            assert_eq!(db_version(&db_path).unwrap(), version);
+
+            // We can check it without touching it
+            assert_eq!(check_db_schema(&db_path).unwrap(), version);
+            assert_eq!(db_version(&db_path).unwrap(), version);
+
+            // It gets upgraded at startup, without losing anything
+            setup_db(&mut revaultd).unwrap();
+            assert_eq!(db_version(&db_path).unwrap(), DB_VERSION);
+            assert_eq!(check_db_schema(&db_path).unwrap(), DB_VERSION);
+            let vaults = db_vaults(&db_path).unwrap();
+            assert_eq!(vaults.len(), 1);
+            assert_eq!(vaults[0].amount.as_sat(), 123456);
+            assert!(!vaults[0].excluded);
+            assert_eq!(
+                db_vault_status_history(&db_path, vaults[0].id)
+                    .unwrap()
+                    .len(),
+                1
+            );
+        }
+
+        fs::remove_dir_all(&datadir).unwrap_or(());
+    }
+
+    #[test]
+    fn vault_lookups_use_indexes() {
+        let datadir = test_datadir();
+        fs::remove_dir_all(&datadir).unwrap_or(());
+        let mut revaultd = dummy_revaultd(datadir.clone(), UserRole::ManagerStakeholder);
+        let db_path = revaultd.db_file();
+        let query_plan = |query: &str| -> String {
+            Connection::open(&db_path)
+                .unwrap()
+                .prepare(&format!("EXPLAIN QUERY PLAN {}", query))
+                .unwrap()
+                .query_map(NO_PARAMS, |row| row.get::<_, String>(3))
+                .unwrap()
+                .collect::<rusqlite::Result<Vec<String>>>()
+                .unwrap()
+                .join("\n")
+        };
+
+        // Both in a new database and in an upgraded one
+        setup_db(&mut revaultd).unwrap();
+        for _ in 0..2 {
+            assert!(query_plan("SELECT * FROM vaults WHERE status = 1")
+                .contains("USING INDEX vault_status"));
+            assert!(query_plan(
+                "SELECT * FROM vaults WHERE deposit_txid = x'00' AND deposit_vout = 0"
+            )
+            .contains("USING INDEX vault_deposit"));
+            assert!(
+                query_plan("SELECT * FROM vaults WHERE derivation_index = 0")
+                    .contains("USING INDEX vault_derivation_index")
+            );
+
+            downgrade_to_v0(&db_path);
+            setup_db(&mut revaultd).unwrap();
+        }
+
+        fs::remove_dir_all(&datadir).unwrap_or(());
+    }
+
+    #[test]
+    fn check_invalid_schemas() {
+        let datadir = test_datadir();
+        fs::remove_dir_all(&datadir).unwrap_or(());
+        let mut revaultd = dummy_revaultd(datadir.clone(), UserRole::Stakeholder);
+        let db_path = revaultd.db_file();
+
+        // No database yet, and we don't create one
+        check_db_schema(&db_path).unwrap_err();
+        assert!(!db_path.exists());
+        setup_db(&mut revaultd).unwrap();
+        assert_eq!(check_db_schema(&db_path).unwrap(), DB_VERSION);
+
+        // A database from the future
+        db_exec(&db_path, |tx| {
+            tx.execute("UPDATE version SET version = (?1)", params![DB_VERSION + 1])?;
+            Ok(())
+        })
+        .unwrap();
+        check_db_schema(&db_path).unwrap_err();
+        db_exec(&db_path, |tx| {
+            tx.execute("UPDATE version SET version = (?1)", params![DB_VERSION])?;
+            Ok(())
+        })
+        .unwrap();
+
+        // A table we don't know about
+        db_exec(&db_path, |tx| {
+            tx.execute_batch("CREATE TABLE unknown (id INTEGER PRIMARY KEY NOT NULL);")?;
+            Ok(())
+        })
+        .unwrap();
+        assert!(check_db_schema(&db_path)
+            .unwrap_err()
+            .to_string()
+            .contains("Unexpected table 'unknown'"));
+
+        // A missing table
+        db_exec(&db_path, |tx| {
+            tx.execute_batch("DROP TABLE unknown; DROP TABLE deposit_labels;")?;
+            Ok(())
+        })
+        .unwrap();
+        assert!(check_db_schema(&db_path)
+            .unwrap_err()
+            .to_string()
+            .contains("Missing table 'deposit_labels'"));
+
+        // A missing column
+        db_exec(&db_path, |tx| {
+            tx.execute_batch(DEPOSIT_LABELS_SCHEMA)?;
+            tx.execute_batch(
+                "DROP TABLE watchtower_acks;
+                 CREATE TABLE watchtower_acks (id INTEGER PRIMARY KEY NOT NULL);",
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        assert!(check_db_schema(&db_path)
+            .unwrap_err()
+            .to_string()
+            .contains("Unexpected columns for table 'watchtower_acks'"));
+
+        // The same columns, but in a different order
+        db_exec(&db_path, |tx| {
+            tx.execute_batch(
+                "DROP TABLE watchtower_acks;
+                 DROP TABLE deposit_labels;
+                 CREATE TABLE deposit_labels (
+                    wallet_id INTEGER NOT NULL,
+                    label TEXT NOT NULL,
+                    derivation_index INTEGER NOT NULL,
+                    PRIMARY KEY (wallet_id, derivation_index)
+                 );",
+            )?;
+            tx.execute_batch(WATCHTOWER_ACKS_SCHEMA)?;
+            Ok(())
+        })
+        .unwrap();
+        assert!(check_db_schema(&db_path)
+            .unwrap_err()
+            .to_string()
+            .contains("Unexpected columns for table 'deposit_labels'"));
+        db_exec(&db_path, |tx| {
+            tx.execute_batch("DROP TABLE deposit_labels;")?;
+            tx.execute_batch(DEPOSIT_LABELS_SCHEMA)?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(check_db_schema(&db_path).unwrap(), DB_VERSION);
+
+        // A missing trigger
+        db_exec(&db_path, |tx| {
+            tx.execute_batch("DROP TRIGGER vault_status_history_update;")?;
+            Ok(())
+        })
+        .unwrap();
+        assert!(check_db_schema(&db_path)
+            .unwrap_err()
+            .to_string()
+            .contains("Missing trigger 'vault_status_history_update'"));
+
+        // A trigger that doesn't do what we expect
+        db_exec(&db_path, |tx| {
+            tx.execute_batch(
+                "CREATE TRIGGER vault_status_history_update AFTER UPDATE OF status ON vaults
+                 BEGIN
+                    SELECT 1;
+                 END;",
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        assert!(check_db_schema(&db_path)
+            .unwrap_err()
+            .to_string()
+            .contains("Unexpected definition for trigger 'vault_status_history_update'"));
+
+        // An index we don't know about
+        db_exec(&db_path, |tx| {
+            tx.execute_batch(
+                "DROP TRIGGER vault_status_history_update;
+                 CREATE TRIGGER vault_status_history_update AFTER UPDATE OF status ON vaults
+                 WHEN NEW.status != OLD.status
+                 BEGIN
+                     INSERT INTO vault_status_history (vault_id, status, blockheight, timestamp)
+                     VALUES (NEW.id, NEW.status, (SELECT blockheight FROM tip), strftime('%s','now'));
+                 END;
+                 CREATE INDEX vault_amount ON vaults (amount);",
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        assert!(check_db_schema(&db_path)
+            .unwrap_err()
+            .to_string()
+            .contains("Unexpected index 'vault_amount'"));
+
+        fs::remove_dir_all(&datadir).unwrap_or(());
+    }
+}
@@ -1,37 +1,76 @@
 pub mod actions;
+pub mod encryption;
 pub mod interface;
+pub mod migrations;
 pub mod schema;
 
 use revault_tx::bitcoin::util::psbt::Error as PsbtError;
 
 // FIXME: make this an enum and have actual specific errors
 #[derive(PartialEq, Eq, Debug)]
-pub struct DatabaseError(pub String);
+pub struct DatabaseError {
+    message: String,
+    // Whether it was caused by another connection holding a lock on the database
+    locked: bool,
+}
 
 impl std::fmt::Display for DatabaseError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Database error: {}", self.0)
+        write!(f, "Database error: {}", self.message)
     }
 }
 
 impl std::error::Error for DatabaseError {}
 
+impl DatabaseError {
+    pub fn new(message: String) -> Self {
+        Self {
+            message,
+            locked: false,
+        }
+    }
+
+    /// An error returned by SQLite while doing this
+    pub fn sqlite(context: &str, e: rusqlite::Error) -> Self {
+        let locked = matches!(
+            e,
+            rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error {
+                    code: rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked,
+                    ..
+                },
+                _
+            )
+        );
+        Self {
+            message: format!("{}: {}", context, e),
+            locked,
+        }
+    }
+
+    /// Whether this error was caused by another connection holding a lock on the database, in
+    /// which case the operation may succeed if retried later.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+}
+
 impl From<revault_tx::Error> for DatabaseError {
     fn from(e: revault_tx::Error) -> Self {
-        Self(format!("Transaction error: {}", e))
+        Self::new(format!("Transaction error: {}", e))
     }
 }
 
 impl From<rusqlite::Error> for DatabaseError {
     fn from(e: rusqlite::Error) -> Self {
-        Self(format!("SQLite error: {}", e))
+        Self::sqlite("SQLite error", e)
     }
 }
 
 impl From<PsbtError> for DatabaseError {
     fn from(e: PsbtError) -> Self {
-        Self(format!("PSBT error: {}", e))
+        Self::new(format!("PSBT error: {}", e))
     }
 }
 
-pub const DB_VERSION: u32 = 0;
+pub const DB_VERSION: u32 = 10;
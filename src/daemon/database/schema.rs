@@ -1,10 +1,7 @@
 use crate::revaultd::VaultStatus;
 use revault_tx::{
-    bitcoin::{
-        util::bip32::{ChildNumber, ExtendedPubKey},
-        Amount, OutPoint, Txid,
-    },
-    scripts::{CpfpDescriptor, DepositDescriptor, UnvaultDescriptor},
+    bitcoin::{util::bip32::ChildNumber, Amount, OutPoint, Txid},
+    scripts::{CpfpDescriptor, DepositDescriptor, EmergencyAddress, UnvaultDescriptor},
     transactions::{
         CancelTransaction, EmergencyTransaction, SpendTransaction, UnvaultEmergencyTransaction,
         UnvaultTransaction,
@@ -27,6 +24,12 @@ CREATE TABLE tip (
 /* This stores metadata about our wallet. We only support single wallet for
  * now (and the foreseeable future). This MUST be in sync with bitcoind's
  * wallet.
+ * The emergency_address is set when a stakeholder rotated it, and takes
+ * precedence over the one from the configuration.
+ * The maintenance column is set when the user asked us not to broadcast any
+ * transaction.
+ * The encryption_salt and encryption_check columns are set if the transactions
+ * are encrypted, see the 'encryption' module.
  */
 CREATE TABLE wallets (
     id INTEGER PRIMARY KEY NOT NULL,
@@ -36,7 +39,11 @@ CREATE TABLE wallets (
     cpfp_descriptor TEXT NOT NULL,
     our_manager_xpub TEXT,
     our_stakeholder_xpub TEXT,
-    deposit_derivation_index INTEGER NOT NULL
+    deposit_derivation_index INTEGER NOT NULL,
+    emergency_address TEXT,
+    maintenance BOOLEAN NOT NULL DEFAULT 0 CHECK (maintenance IN (0,1)),
+    encryption_salt BLOB,
+    encryption_check BLOB
 );
 
 /* This stores the vaults we heard about. The deposit may be unconfirmed,
@@ -46,6 +53,7 @@ CREATE TABLE wallets (
  * The spend_txid is stored to not harass bitcoind trying to guess the spending
  * txid out of a deposit outpoint. It MUST be NOT NULL if status is 'spending'
  * or 'spent'.
+ * The excluded column is set when the user asked us not to manage this vault.
  */
 CREATE TABLE vaults (
     id INTEGER PRIMARY KEY NOT NULL,
@@ -59,6 +67,7 @@ CREATE TABLE vaults (
     received_at INTEGER NOT NULL,
     updated_at INTEGER NOT NULL,
     spend_txid BLOB,
+    excluded BOOLEAN NOT NULL DEFAULT 0 CHECK (excluded IN (0,1)),
     FOREIGN KEY (wallet_id) REFERENCES wallets (id)
         ON UPDATE RESTRICT
         ON DELETE RESTRICT
@@ -113,9 +122,108 @@ CREATE TABLE spend_transactions (
 );
 
 CREATE INDEX vault_status ON vaults (status);
+CREATE INDEX vault_deposit ON vaults (deposit_txid, deposit_vout);
+CREATE INDEX vault_derivation_index ON vaults (derivation_index);
 CREATE INDEX vault_transactions ON presigned_transactions (vault_id);
 ";
 
+/// The vaults' status history, introduced in version 3. It is kept separate so it can be
+/// created by the migration from earlier versions as well.
+pub const VAULT_STATUS_HISTORY_SCHEMA: &str = "\
+/* This stores every status a vault went through, along with our tip and the
+ * time at which it was set. It is filled by the triggers below, so there is
+ * no need to insert into it manually.
+ */
+CREATE TABLE vault_status_history (
+    id INTEGER PRIMARY KEY NOT NULL,
+    vault_id INTEGER NOT NULL,
+    status INTEGER NOT NULL,
+    blockheight INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    FOREIGN KEY (vault_id) REFERENCES vaults (id)
+        ON UPDATE RESTRICT
+        ON DELETE RESTRICT
+);
+
+CREATE TRIGGER vault_status_history_insert AFTER INSERT ON vaults
+BEGIN
+    INSERT INTO vault_status_history (vault_id, status, blockheight, timestamp)
+    VALUES (NEW.id, NEW.status, (SELECT blockheight FROM tip), strftime('%s','now'));
+END;
+
+CREATE TRIGGER vault_status_history_update AFTER UPDATE OF status ON vaults
+WHEN NEW.status != OLD.status
+BEGIN
+    INSERT INTO vault_status_history (vault_id, status, blockheight, timestamp)
+    VALUES (NEW.id, NEW.status, (SELECT blockheight FROM tip), strftime('%s','now'));
+END;
+
+CREATE INDEX vault_history ON vault_status_history (vault_id);
+";
+
+pub const DEPOSIT_LABELS_SCHEMA: &str = "\
+/* An optional label given to a deposit address when handing it out, for instance
+ * to tell which depositor it was given to.
+ */
+CREATE TABLE deposit_labels (
+    wallet_id INTEGER NOT NULL,
+    derivation_index INTEGER NOT NULL,
+    label TEXT NOT NULL,
+    PRIMARY KEY (wallet_id, derivation_index),
+    FOREIGN KEY (wallet_id) REFERENCES wallets (id)
+        ON UPDATE RESTRICT
+        ON DELETE RESTRICT
+);
+";
+
+pub const VAULT_DEPOSIT_LABELS_SCHEMA: &str = "\
+/* The label of the deposit address at the time we received the deposit of a vault. The
+ * address may be relabeled afterwards, this doesn't change who the deposit came from.
+ */
+CREATE TABLE vault_deposit_labels (
+    vault_id INTEGER PRIMARY KEY NOT NULL,
+    label TEXT NOT NULL,
+    FOREIGN KEY (vault_id) REFERENCES vaults (id)
+        ON UPDATE RESTRICT
+        ON DELETE RESTRICT
+);
+
+CREATE TRIGGER vault_deposit_labels_insert AFTER INSERT ON vaults
+BEGIN
+    INSERT INTO vault_deposit_labels (vault_id, label)
+    SELECT NEW.id, label FROM deposit_labels
+    WHERE wallet_id = NEW.wallet_id AND derivation_index = NEW.derivation_index;
+END;
+";
+
+/* The vaults each watchtower acknowledged it is guarding, keyed by the watchtower's
+ * Noise static public key.
+ */
+pub const WATCHTOWER_ACKS_SCHEMA: &str = "\
+CREATE TABLE watchtower_acks (
+    vault_id INTEGER NOT NULL,
+    watchtower_key BLOB NOT NULL,
+    acked_at INTEGER NOT NULL,
+    PRIMARY KEY (vault_id, watchtower_key),
+    FOREIGN KEY (vault_id) REFERENCES vaults (id)
+        ON UPDATE RESTRICT
+        ON DELETE RESTRICT
+);
+";
+
+/* An optional label given to a vault, for instance to tell what its funds are for. */
+pub const VAULT_LABELS_SCHEMA: &str = "\
+CREATE TABLE vault_labels (
+    vault_id INTEGER PRIMARY KEY NOT NULL,
+    label TEXT NOT NULL,
+    FOREIGN KEY (vault_id) REFERENCES vaults (id)
+        ON UPDATE RESTRICT
+        ON DELETE RESTRICT
+);
+
+CREATE INDEX vault_label ON vault_labels (label);
+";
+
 /// A row in the "wallets" table
 #[derive(Clone)]
 pub struct DbWallet {
@@ -124,16 +232,17 @@ pub struct DbWallet {
     pub deposit_descriptor: DepositDescriptor,
     pub unvault_descriptor: UnvaultDescriptor,
     pub cpfp_descriptor: CpfpDescriptor,
-    pub our_man_xpub: Option<ExtendedPubKey>,
-    pub our_stk_xpub: Option<ExtendedPubKey>,
     pub deposit_derivation_index: ChildNumber,
+    pub emergency_address: Option<EmergencyAddress>,
+    pub maintenance: bool,
+    /// The salt and check value of the database key, if the transactions are encrypted
+    pub encryption: Option<(Vec<u8>, Vec<u8>)>,
 }
 
 /// A row of the "vaults" table
 #[derive(Debug, Clone, Copy)]
 pub struct DbVault {
     pub id: u32, // FIXME: should be an i64
-    pub wallet_id: u32,
     pub status: VaultStatus,
     pub blockheight: u32,
     pub deposit_outpoint: OutPoint,
@@ -142,6 +251,15 @@ pub struct DbVault {
     pub received_at: u32,
     pub updated_at: u32,
     pub spend_txid: Option<Txid>,
+    pub excluded: bool,
+}
+
+/// A row of the "vault_status_history" table
+#[derive(Debug, Clone, Copy)]
+pub struct DbVaultStatusChange {
+    pub status: VaultStatus,
+    pub blockheight: u32,
+    pub timestamp: u32,
 }
 
 /// The type of the transaction, as stored in the "presigned_transactions" table
@@ -209,15 +327,6 @@ pub struct DbTransaction {
     pub vault_id: u32,
     pub tx_type: TransactionType,
     pub psbt: RevaultTx,
-    pub is_fully_signed: bool,
-}
-
-/// A row in the "spend_inputs" table
-#[derive(Debug)]
-pub struct DbSpendInput {
-    pub id: i64,
-    pub unvault_id: u32,
-    pub spend_id: u32,
 }
 
 /// A row in the "spend_transactions" table
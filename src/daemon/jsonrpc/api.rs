@@ -4,36 +4,48 @@
 
 use crate::{
     control::{
-        announce_spend_transaction, bitcoind_broadcast, check_revocation_signatures,
-        check_spend_signatures, check_spend_transaction_size, check_unvault_signatures,
-        coordinator_status, cosigners_status, fetch_cosigs_signatures, finalized_emer_txs,
+        announce_spend_transaction, bitcoind_broadcast, bitcoind_estimate_feerate,
+        bitcoind_force_sync, bitcoind_labeled_utxos, bitcoind_relay_feerate, bitcoind_txout_proof,
+        check_revocation_feerate, check_revocation_signatures, check_spend_signatures,
+        check_spend_transaction_size, check_unvault_signatures, coordinator_status,
+        cosigners_status, fee_reserve, fetch_cosigs_signatures, finalized_emer_txs,
         listvaults_from_db, onchain_txs, presigned_txs, share_rev_signatures,
-        share_unvault_signatures, vaults_from_deposits, watchtowers_status, ListSpendEntry,
-        ListSpendStatus, RpcUtils,
+        share_unvault_signatures, sort_vaults, unexpected_spends, vaults_csv, vaults_from_deposits,
+        watchtowers_status, HistoryStart, ListSpendEntry, ListSpendStatus, ListVaultsOptions,
+        ListVaultsSortKey, RpcUtils,
     },
     database::{
         actions::{
-            db_delete_spend, db_insert_spend, db_mark_activating_vault,
-            db_mark_broadcastable_spend, db_mark_securing_vault, db_update_presigned_tx,
-            db_update_spend,
+            db_backup, db_delete_spend, db_exclude_vault, db_insert_spend,
+            db_label_deposit_address, db_label_vault, db_mark_activating_vault,
+            db_mark_broadcastable_spend, db_mark_securing_vault, db_set_maintenance,
+            db_update_emergency_address, db_update_presigned_tx, db_update_spend,
         },
         interface::{
-            db_cancel_transaction, db_emer_transaction, db_list_spends, db_spend_transaction,
-            db_tip, db_unvault_emer_transaction, db_unvault_transaction, db_vault_by_deposit,
-            db_vault_by_unvault_txid, db_vaults, db_vaults_from_spend, db_vaults_min_status,
+            db_cancel_transaction, db_deposit_totals, db_emer_transaction, db_history_events,
+            db_list_spends, db_spend_transaction, db_tip, db_unvault_emer_transaction,
+            db_unvault_transaction, db_vault_by_deposit, db_vault_by_unvault_txid,
+            db_vault_status_history, db_vaults, db_vaults_by_derivation_index,
+            db_vaults_from_spend, db_vaults_min_status,
         },
     },
     jsonrpc::UserRole,
-    revaultd::{BlockchainTip, VaultStatus},
+    revaultd::{BlockchainTip, ConfigReload, VaultStatus},
     threadmessages::*,
+    watchtower::watchtowers_sync_status,
 };
-use common::VERSION;
+use common::{config::Config, VERSION};
+use revault_net::noise::PublicKey as NoisePubKey;
 
 use revault_tx::{
     bitcoin::{
-        util::bip32, Address, Amount, OutPoint, Transaction as BitcoinTransaction, TxOut, Txid,
+        consensus::encode,
+        hashes::hex::{FromHex, ToHex},
+        util::bip32,
+        Address, Amount, OutPoint, Transaction as BitcoinTransaction, TxOut, Txid,
     },
     miniscript::DescriptorTrait,
+    scripts::EmergencyAddress,
     transactions::{
         spend_tx_from_deposits, transaction_chain, CancelTransaction, EmergencyTransaction,
         RevaultTransaction, SpendTransaction, UnvaultEmergencyTransaction, UnvaultTransaction,
@@ -43,18 +55,53 @@ use revault_tx::{
 };
 
 use std::{
+    cmp,
     collections::BTreeMap,
+    net::SocketAddr,
+    path::PathBuf,
     str::FromStr,
     sync::{
         atomic::{AtomicBool, Ordering},
         mpsc, Arc,
     },
+    time::{Duration, Instant},
 };
 
 use jsonrpc_core::Error as JsonRpcError;
 use jsonrpc_derive::rpc;
 use serde_json::json;
 
+// The confirmation target used to estimate the Spend feerate if none is given
+const DEFAULT_SPEND_CONF_TARGET: u32 = 6;
+// The maximum confirmation target bitcoind can estimate a feerate for
+const MAX_SPEND_CONF_TARGET: u32 = 1008;
+// bitcoind's default 'maxfeerate' for 'sendrawtransaction', in sat/vB
+const MAX_SPEND_FEERATE: u64 = 10_000;
+// How long to wait for a new block if no timeout is given, in seconds
+const DEFAULT_WAIT_TIMEOUT_SECS: u64 = 30;
+// The longest we let a client wait for a new block, in seconds. It holds a request handler
+// meanwhile.
+const MAX_WAIT_TIMEOUT_SECS: u64 = 300;
+// How many vaults' onchain transactions to fetch from bitcoind at once
+const ONCHAIN_TXS_CHUNK_SIZE: usize = 100;
+// The kinds of events of the wallet history, and the vault statuses they are recorded at
+const HISTORY_KINDS: &[(&str, &[VaultStatus])] = &[
+    ("deposit", &[VaultStatus::Funded]),
+    ("secure", &[VaultStatus::Secured]),
+    ("unvault", &[VaultStatus::Unvaulting]),
+    ("spend", &[VaultStatus::Spent]),
+    ("cancel", &[VaultStatus::Canceled]),
+    (
+        "emergency",
+        &[
+            VaultStatus::EmergencyVaulting,
+            VaultStatus::UnvaultEmergencyVaulting,
+        ],
+    ),
+    ("abort", &[VaultStatus::Aborted]),
+    ("unexpectedspend", &[VaultStatus::UnexpectedSpend]),
+];
+
 #[derive(Clone)]
 pub struct JsonRpcMetaData {
     pub shutdown: Arc<AtomicBool>,
@@ -79,6 +126,49 @@ impl JsonRpcMetaData {
     pub fn shutdown(&self) {
         // Relaxed is fine, worse case we just stop at the next iteration on ARM
         self.shutdown.store(true, Ordering::Relaxed);
+        // Don't let the requests waiting for an event hold the shutdown
+        self.rpc_utils
+            .revaultd
+            .read()
+            .unwrap()
+            .events
+            .publish(BitcoindEvent::Shutdown);
+    }
+
+    /// Tell the bitcoind and signature fetcher threads to stop, and the JSONRPC server to stop
+    /// once it answered the pending requests.
+    pub fn stop_daemon(&self) -> Result<(), String> {
+        self.rpc_utils
+            .bitcoind_tx
+            .send(BitcoindMessageOut::Shutdown)
+            .map_err(|e| e.to_string())?;
+        self.rpc_utils
+            .sigfetcher_tx
+            .send(SigFetcherMessageOut::Shutdown)
+            .map_err(|e| e.to_string())?;
+        self.shutdown();
+
+        Ok(())
+    }
+
+    /// Read the configuration file again and apply the settings that can be changed at runtime
+    pub fn reload_config(&self) -> Result<ConfigReload, String> {
+        let config_file = self.rpc_utils.revaultd.read().unwrap().config_file.clone();
+        let config = Config::from_file(config_file).map_err(|e| e.to_string())?;
+        let reload = self
+            .rpc_utils
+            .revaultd
+            .write()
+            .unwrap()
+            .reload_config(config)
+            .map_err(|e| e.to_string())?;
+        log::info!(
+            "Reloaded configuration. Applied changes to {:?}, ignored changes to {:?} until restart",
+            reload.applied,
+            reload.ignored
+        );
+
+        Ok(reload)
     }
 }
 
@@ -94,27 +184,80 @@ pub trait RpcApi {
     #[rpc(meta, name = "getinfo")]
     fn getinfo(&self, meta: Self::Metadata) -> jsonrpc_core::Result<serde_json::Value>;
 
+    /// Get the configuration the daemon is running with
+    #[rpc(meta, name = "getconfig")]
+    fn getconfig(&self, meta: Self::Metadata) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// Read the configuration file again and apply the changes that don't require a restart
+    #[rpc(meta, name = "reloadconfig")]
+    fn reloadconfig(&self, meta: Self::Metadata) -> jsonrpc_core::Result<serde_json::Value>;
+
     /// Print all available commands
     #[rpc(meta, name = "help")]
     fn help(&self, meta: Self::Metadata) -> jsonrpc_core::Result<serde_json::Value>;
 
-    /// Get a list of current vaults, which can be sorted by txids or status
+    /// Get a page of the current vaults, which can be filtered and sorted
     #[rpc(meta, name = "listvaults")]
     fn listvaults(
         &self,
         meta: Self::Metadata,
         statuses: Option<Vec<String>>,
         outpoints: Option<Vec<OutPoint>>,
+        options: Option<ListVaultsOptions>,
+    ) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// Stop managing a vault: it won't be listed by default, nor be part of a Spend or an
+    /// Emergency.
+    #[rpc(meta, name = "excludevault")]
+    fn excludevault(
+        &self,
+        meta: Self::Metadata,
+        outpoint: OutPoint,
+    ) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// Label a vault, or remove its label
+    #[rpc(meta, name = "labelvault")]
+    fn labelvault(
+        &self,
+        meta: Self::Metadata,
+        outpoint: OutPoint,
+        label: Option<String>,
     ) -> jsonrpc_core::Result<serde_json::Value>;
 
-    /// Get an address to receive funds to the stakeholders' descriptor
+    /// Manage a previously excluded vault again
+    #[rpc(meta, name = "includevault")]
+    fn includevault(
+        &self,
+        meta: Self::Metadata,
+        outpoint: OutPoint,
+    ) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// Get the list of all vaults as CSV, optionally filtered by status
+    #[rpc(meta, name = "exportvaults")]
+    fn exportvaults(
+        &self,
+        meta: Self::Metadata,
+        statuses: Option<Vec<String>>,
+    ) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// Get an address to receive funds to the stakeholders' descriptor, optionally labeling it
     #[rpc(meta, name = "getdepositaddress")]
     fn getdepositaddress(
         &self,
         meta: Self::Metadata,
         index: Option<bip32::ChildNumber>,
+        label: Option<String>,
     ) -> jsonrpc_core::Result<serde_json::Value>;
 
+    /// Get the total of the confirmed deposits by deposit address label
+    #[rpc(meta, name = "getdeposittotals")]
+    fn getdeposittotals(&self, meta: Self::Metadata) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// Get the deposit addresses we can hand out at once, along with their derivation index
+    #[rpc(meta, name = "listdepositaddresses")]
+    fn listdepositaddresses(&self, meta: Self::Metadata)
+        -> jsonrpc_core::Result<serde_json::Value>;
+
     /// Get the cancel and both emergency transactions for a vault identified by its deposit
     /// outpoint.
     #[rpc(meta, name = "getrevocationtxs")]
@@ -155,6 +298,34 @@ pub trait RpcApi {
         unvault_tx: UnvaultTransaction,
     ) -> jsonrpc_core::Result<serde_json::Value>;
 
+    /// Get the fully-signed cancel, emergency, and unvault_emergency transactions as hex for
+    /// a vault identified by its deposit outpoint.
+    #[rpc(meta, name = "getrawrevocationtxs")]
+    fn getrawrevocationtxs(
+        &self,
+        meta: Self::Metadata,
+        outpoint: OutPoint,
+    ) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// Get the fully-signed Unvault transaction as hex for a vault identified by its deposit
+    /// outpoint.
+    #[rpc(meta, name = "getrawunvaulttx")]
+    fn getrawunvaulttx(
+        &self,
+        meta: Self::Metadata,
+        outpoint: OutPoint,
+    ) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// Get a fully-signed presigned transaction of a vault as hex, to broadcast it through
+    /// another node.
+    #[rpc(meta, name = "getbroadcasthex")]
+    fn getbroadcasthex(
+        &self,
+        meta: Self::Metadata,
+        outpoint: OutPoint,
+        tx_type: String,
+    ) -> jsonrpc_core::Result<serde_json::Value>;
+
     /// Retrieve the presigned transactions of a list of vaults
     #[rpc(meta, name = "listpresignedtransactions")]
     fn listpresignedtransactions(
@@ -163,12 +334,15 @@ pub trait RpcApi {
         outpoints: Option<Vec<OutPoint>>,
     ) -> jsonrpc_core::Result<serde_json::Value>;
 
-    /// Retrieve the onchain transactions of a list of vaults
+    /// Retrieve the onchain transactions of a list of vaults, a page at a time
     #[rpc(meta, name = "listonchaintransactions")]
     fn listonchaintransactions(
         &self,
         meta: Self::Metadata,
         outpoints: Option<Vec<OutPoint>>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+        include_hex: Option<bool>,
     ) -> jsonrpc_core::Result<serde_json::Value>;
 
     #[rpc(meta, name = "getspendtx")]
@@ -177,7 +351,8 @@ pub trait RpcApi {
         meta: Self::Metadata,
         outpoint: Vec<OutPoint>,
         outputs: BTreeMap<Address, u64>,
-        feerate: u64,
+        feerate: Option<u64>,
+        conf_target: Option<u32>,
     ) -> jsonrpc_core::Result<serde_json::Value>;
 
     #[rpc(meta, name = "updatespendtx")]
@@ -208,6 +383,18 @@ pub trait RpcApi {
         spend_txid: Txid,
     ) -> jsonrpc_core::Result<serde_json::Value>;
 
+    /// Get the accounting events of the vaults between two dates, from the oldest
+    #[rpc(meta, name = "gethistory")]
+    fn gethistory(
+        &self,
+        meta: Self::Metadata,
+        start_ts: Option<HistoryStart>,
+        end_ts: Option<u32>,
+        limit: Option<usize>,
+        label: Option<String>,
+        kind: Option<Vec<String>>,
+    ) -> jsonrpc_core::Result<serde_json::Value>;
+
     #[rpc(meta, name = "revault")]
     fn revault(
         &self,
@@ -220,6 +407,80 @@ pub trait RpcApi {
 
     #[rpc(meta, name = "getserverstatus")]
     fn getserverstatus(&self, meta: Self::Metadata) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// List the watchonly wallet utxos of a given category (eg "deposit" or "unvault")
+    #[rpc(meta, name = "listutxos")]
+    fn listutxos(
+        &self,
+        meta: Self::Metadata,
+        category: String,
+    ) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// Poll bitcoind right away instead of waiting for the next poll
+    #[rpc(meta, name = "forcesync")]
+    fn forcesync(&self, meta: Self::Metadata) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// Wait until we processed a new block, or for the timeout (in seconds) to expire
+    #[rpc(meta, name = "waitfornewblock")]
+    fn waitfornewblock(
+        &self,
+        meta: Self::Metadata,
+        timeout: Option<u64>,
+    ) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// Use a new Emergency address for the vaults created from now on
+    #[rpc(meta, name = "setemergencyaddress")]
+    fn setemergencyaddress(
+        &self,
+        meta: Self::Metadata,
+        address: String,
+        confirm: Option<bool>,
+    ) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// Get the merkle proof of the inclusion of a deposit transaction in its block
+    #[rpc(meta, name = "getdepositproof")]
+    fn getdepositproof(
+        &self,
+        meta: Self::Metadata,
+        outpoint: OutPoint,
+    ) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// Get the fees it may take to bump the Unvault and Cancel transactions of all active vaults
+    #[rpc(meta, name = "getfeereserve")]
+    fn getfeereserve(
+        &self,
+        meta: Self::Metadata,
+        feerate_vb: Option<u64>,
+        conf_target: Option<u32>,
+    ) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// Get the ordered list of the statuses a vault went through
+    #[rpc(meta, name = "getvaulthistory")]
+    fn getvaulthistory(
+        &self,
+        meta: Self::Metadata,
+        outpoint: OutPoint,
+    ) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// Stop (or resume) broadcasting any transaction
+    #[rpc(meta, name = "setmaintenance")]
+    fn setmaintenance(
+        &self,
+        meta: Self::Metadata,
+        enabled: bool,
+    ) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// Which of the vaults to guard each watchtower acknowledged
+    #[rpc(meta, name = "listwatchtowers")]
+    fn listwatchtowers(&self, meta: Self::Metadata) -> jsonrpc_core::Result<serde_json::Value>;
+
+    /// Snapshot the database to a new file without stopping
+    #[rpc(meta, name = "backupdb")]
+    fn backupdb(
+        &self,
+        meta: Self::Metadata,
+        path: PathBuf,
+    ) -> jsonrpc_core::Result<serde_json::Value>;
 }
 
 // TODO: we should probably make these proc macros and apply them above?
@@ -278,6 +539,12 @@ macro_rules! unknown_outpoint {
     };
 }
 
+macro_rules! excluded_vault {
+    ($outpoint: expr) => {
+        JsonRpcError::invalid_params(format!("Vault at '{}' is excluded", $outpoint))
+    };
+}
+
 macro_rules! invalid_status {
     ($current: expr, $required: expr) => {
         JsonRpcError::invalid_params(format!(
@@ -287,22 +554,159 @@ macro_rules! invalid_status {
     };
 }
 
+// The number of entries to return in a page. It is never larger than the configured maximum.
+fn page_limit(limit: Option<usize>, max_entries: usize) -> usize {
+    cmp::min(limit.unwrap_or(max_entries), max_entries)
+}
+
+// A page of a listing
+struct Page<T> {
+    entries: Vec<T>,
+    // The number of entries in the whole listing
+    total: usize,
+    // Whether there are more entries after this page
+    truncated: bool,
+}
+
+fn paginate<T>(
+    entries: Vec<T>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    max_entries: usize,
+) -> Page<T> {
+    let total = entries.len();
+    let offset = offset.unwrap_or(0);
+    let entries: Vec<T> = entries
+        .into_iter()
+        .skip(offset)
+        .take(page_limit(limit, max_entries))
+        .collect();
+    let truncated = offset.saturating_add(entries.len()) < total;
+    Page {
+        entries,
+        total,
+        truncated,
+    }
+}
+
+// Parse the vault statuses to filter a listing with.
+fn statuses_filter(
+    statuses: Option<Vec<String>>,
+) -> jsonrpc_core::Result<Option<Vec<VaultStatus>>> {
+    if let Some(statuses) = statuses {
+        // If they give an empty array, it's not that they don't want any result, but rather
+        // that they don't want this filter to be taken into account!
+        if !statuses.is_empty() {
+            return Ok(Some(
+                statuses
+                    .into_iter()
+                    .map(|status_str| parse_vault_status!(status_str))
+                    .collect::<jsonrpc_core::Result<Vec<VaultStatus>>>()?,
+            ));
+        }
+    }
+
+    Ok(None)
+}
+
+// Parse the event kinds to filter the history with, into the vault statuses to query.
+fn history_filter(kinds: Option<Vec<String>>) -> jsonrpc_core::Result<Vec<VaultStatus>> {
+    let kinds = kinds.unwrap_or_default();
+    let mut statuses = Vec::new();
+    for (kind, kind_statuses) in HISTORY_KINDS {
+        // Same as for the statuses, an empty array means no filter
+        if kinds.is_empty() || kinds.iter().any(|k| k == kind) {
+            statuses.extend_from_slice(kind_statuses);
+        }
+    }
+    if let Some(unknown) = kinds
+        .iter()
+        .find(|k| !HISTORY_KINDS.iter().any(|(kind, _)| kind == k))
+    {
+        return Err(JsonRpcError::invalid_params(format!(
+            "Unknown event kind '{}'",
+            unknown
+        )));
+    }
+
+    Ok(statuses)
+}
+
+// Parse a history cursor, as returned by 'gethistory', into the timestamp and id of the last
+// event received.
+fn history_cursor(cursor: &str) -> jsonrpc_core::Result<(u32, u32)> {
+    let invalid_cursor = || JsonRpcError::invalid_params(format!("Invalid cursor '{}'", cursor));
+    let mut parts = cursor.splitn(2, ':');
+    let timestamp = parts
+        .next()
+        .and_then(|ts| ts.parse::<u32>().ok())
+        .ok_or_else(invalid_cursor)?;
+    let id = parts
+        .next()
+        .and_then(|id| id.parse::<u32>().ok())
+        .ok_or_else(invalid_cursor)?;
+
+    Ok((timestamp, id))
+}
+
+// Use the feerate they gave us, or ask bitcoind for an estimate for this confirmation target. If
+// it has none, use the configured fallback feerate.
+fn feerate_or_estimate(
+    meta: &JsonRpcMetaData,
+    feerate_vb: Option<u64>,
+    conf_target: Option<u32>,
+) -> jsonrpc_core::Result<u64> {
+    if let Some(feerate_vb) = feerate_vb {
+        return Ok(feerate_vb);
+    }
+
+    let bitcoind_tx = &meta.rpc_utils.bitcoind_tx;
+    let conf_target = conf_target.unwrap_or(DEFAULT_SPEND_CONF_TARGET);
+    if !(1..=MAX_SPEND_CONF_TARGET).contains(&conf_target) {
+        return Err(JsonRpcError::invalid_params(format!(
+            "Confirmation target must be between 1 and {}",
+            MAX_SPEND_CONF_TARGET
+        )));
+    }
+    let estimate =
+        bitcoind_estimate_feerate(bitcoind_tx, conf_target).map_err(|e| internal_error!(e))?;
+    let fallback = meta
+        .rpc_utils
+        .revaultd
+        .read()
+        .unwrap()
+        .bitcoind_config
+        .fallback_feerate_sat_vb;
+    match (estimate, fallback) {
+        (Some(feerate_vb), _) => Ok(feerate_vb),
+        // Make sure the fallback is usable, as they can't do anything about it.
+        (None, Some(fallback)) => {
+            let relay_feerate_vb =
+                bitcoind_relay_feerate(bitcoind_tx).map_err(|e| internal_error!(e))?;
+            let feerate_vb = cmp::min(cmp::max(fallback, relay_feerate_vb), MAX_SPEND_FEERATE);
+            log::warn!(
+                "No feerate estimate available for a confirmation target of {} blocks, using \
+                 the fallback feerate of {} sat/vB.",
+                conf_target,
+                feerate_vb
+            );
+            Ok(feerate_vb)
+        }
+        (None, None) => Err(JsonRpcError::invalid_params(format!(
+            "No feerate estimate available for a confirmation target of {} blocks, please \
+             provide a feerate",
+            conf_target
+        ))),
+    }
+}
+
 pub struct RpcImpl;
 impl RpcApi for RpcImpl {
     type Metadata = JsonRpcMetaData;
 
     fn stop(&self, meta: JsonRpcMetaData) -> jsonrpc_core::Result<()> {
         log::info!("Stopping revaultd");
-
-        meta.rpc_utils
-            .bitcoind_tx
-            .send(BitcoindMessageOut::Shutdown)
-            .map_err(|e| internal_error!(e))?;
-        meta.rpc_utils
-            .sigfetcher_tx
-            .send(SigFetcherMessageOut::Shutdown)
-            .map_err(|e| internal_error!(e))?;
-        meta.shutdown();
+        meta.stop_daemon().map_err(|e| internal_error!(e))?;
 
         Ok(())
     }
@@ -321,7 +725,8 @@ impl RpcApi for RpcImpl {
         let cpfp_desc = &revaultd.cpfp_descriptor.to_string();
         let unvault_desc = &revaultd.unvault_descriptor.to_string();
 
-        // This means blockheight == 0 for IBD.
+        // Height 0 is either the genesis block (on a fresh regtest chain) or that we didn't fetch
+        // the tip from bitcoind yet. The sync progress tells them apart.
         let BlockchainTip {
             height: blockheight,
             ..
@@ -335,18 +740,44 @@ impl RpcApi for RpcImpl {
                     && l.status != VaultStatus::Canceled
                     && l.status != VaultStatus::Unvaulted
                     && l.status != VaultStatus::EmergencyVaulted
+                    && l.status != VaultStatus::Aborted
             })
             .count();
 
         let managers_threshold = meta.rpc_utils.revaultd.read().unwrap().managers_threshold();
+        let coordinator_health = *revaultd.coordinator_health.lock().unwrap();
+        let unexpected_spends: Vec<String> = unexpected_spends(&revaultd)
+            .map_err(|e| internal_error!(e))?
+            .iter()
+            .map(|outpoint| outpoint.to_string())
+            .collect();
 
         Ok(json!({
             "version": VERSION.to_string(),
             "network": revaultd.bitcoind_config.network.to_string(),
             "blockheight": blockheight,
+            "finalized_blockheight": revaultd.finalized_height(blockheight),
             "sync": progress,
             "vaults": number_of_vaults,
+            "remaining_deposit_indexes": revaultd.remaining_deposit_indexes(),
             "managers_threshold": managers_threshold,
+            "unexpected_spends": unexpected_spends,
+            "invalid_signatures": revaultd
+                .invalid_signatures
+                .iter()
+                .map(|outpoint| outpoint.to_string())
+                .collect::<Vec<String>>(),
+            "deposits_discrepancy": revaultd.deposits_discrepancy.map(|(db_count, wallet_count)| {
+                json!({
+                    "vaults": db_count,
+                    "wallet_utxos": wallet_count,
+                })
+            }),
+            "maintenance": revaultd.is_maintenance(),
+            "coordinator": {
+                "last_success": coordinator_health.last_success,
+                "consecutive_failures": coordinator_health.consecutive_failures,
+            },
             "descriptors": {
                 "deposit": deposit_desc,
                 "unvault": unvault_desc,
@@ -355,6 +786,109 @@ impl RpcApi for RpcImpl {
         }))
     }
 
+    fn getconfig(&self, meta: Self::Metadata) -> jsonrpc_core::Result<serde_json::Value> {
+        let revaultd = meta.rpc_utils.revaultd.read().unwrap();
+        let bitcoind_config = &revaultd.bitcoind_config;
+
+        // We never expose the bitcoind RPC password (nor the content of the cookie file), nor our
+        // Noise private key.
+        fn servers_json(servers: &Option<Vec<(SocketAddr, NoisePubKey)>>) -> serde_json::Value {
+            servers
+                .as_ref()
+                .map(|servers| {
+                    servers
+                        .iter()
+                        .map(|(host, noise_key)| {
+                            json!({
+                                "host": host.to_string(),
+                                "noise_key": noise_key.0.to_hex(),
+                            })
+                        })
+                        .collect()
+                })
+                .unwrap_or(serde_json::Value::Null)
+        }
+
+        Ok(json!({
+            "data_dir": revaultd.data_dir,
+            "daemon": revaultd.daemon,
+            "log_level": revaultd.config.log_level.to_string().to_lowercase(),
+            "min_conf": revaultd.min_conf,
+            "cancel_min_conf": revaultd.cancel_min_conf,
+            "tip_finality_depth": revaultd.tip_finality_depth,
+            "revocation_feerate_min_sat_vb": revaultd.revocation_feerate_range.start(),
+            "revocation_feerate_max_sat_vb": revaultd.revocation_feerate_range.end(),
+            "rpc_max_entries": revaultd.rpc_max_entries,
+            "rpc_log_level": revaultd.rpc_log_level.to_string().to_lowercase(),
+            "precreate_deposit_addresses": revaultd.precreate_deposit_addresses,
+            "verify_signatures_on_startup": revaultd.verify_signatures_on_startup,
+            "sqlite_wal_mode": revaultd.sqlite_wal_mode,
+            "rpc_abstract_socket": revaultd.rpc_abstract_socket,
+            "ready_file": revaultd.ready_file,
+            "db_encryption": revaultd.db_encryption,
+            "db_passphrase_file": revaultd.config.db_passphrase_file,
+            "bitcoind_config": {
+                "network": bitcoind_config.network.to_string(),
+                "cookie_path": bitcoind_config.cookie_path,
+                "rpc_user": bitcoind_config.rpc_user,
+                "rpc_password_file": bitcoind_config.rpc_password_file,
+                "addr": bitcoind_config.addr.to_string(),
+                "poll_interval_secs": bitcoind_config.poll_interval_secs.as_secs(),
+                "chaininfo_cache_secs": bitcoind_config.chaininfo_cache_secs.as_secs(),
+                "wallet_tx_cache_size": bitcoind_config.wallet_tx_cache_size,
+                "broadcast_batch_size": bitcoind_config.broadcast_batch_size,
+                "rebroadcast_interval_secs": bitcoind_config.rebroadcast_interval_secs.as_secs(),
+                "fresh_wallet_lookback_secs": bitcoind_config.fresh_wallet_lookback_secs.as_secs(),
+                "import_retries": bitcoind_config.import_retries,
+                "import_retry_interval_secs": bitcoind_config.import_retry_interval_secs.as_secs(),
+                "address_import_flush_secs": bitcoind_config.address_import_flush_secs.as_secs(),
+                "lazy_unvault_import": bitcoind_config.lazy_unvault_import,
+                "observer_mode": bitcoind_config.observer_mode,
+                "load_wallet_on_startup": bitcoind_config.load_wallet_on_startup,
+                "unload_wallet_on_shutdown": bitcoind_config.unload_wallet_on_shutdown,
+                "clock_skew_threshold_secs": bitcoind_config.clock_skew_threshold_secs.as_secs(),
+                "fallback_feerate_sat_vb": bitcoind_config.fallback_feerate_sat_vb,
+                "min_version": bitcoind_config.min_version,
+                "allow_untested_version": bitcoind_config.allow_untested_version,
+            },
+            "scripts_config": {
+                "deposit_descriptor": revaultd.deposit_descriptor.to_string(),
+                "unvault_descriptor": revaultd.unvault_descriptor.to_string(),
+                "cpfp_descriptor": revaultd.cpfp_descriptor.to_string(),
+                "deposit_fingerprints": revaultd.deposit_fingerprints.as_ref().map(|fingerprints| {
+                    fingerprints
+                        .iter()
+                        .map(|f| f.to_string())
+                        .collect::<Vec<String>>()
+                }),
+            },
+            "coordinator_host": revaultd.coordinator_host.to_string(),
+            "coordinator_noise_key": revaultd.coordinator_noisekey.0.to_hex(),
+            "coordinator_poll_seconds": revaultd.coordinator_poll_interval.as_secs(),
+            "stakeholder_config": revaultd.our_stk_xpub.map(|xpub| json!({
+                "xpub": xpub.to_string(),
+                "watchtowers": servers_json(&revaultd.watchtowers),
+                "emergency_address": revaultd
+                    .emergency_address
+                    .as_ref()
+                    .map(|addr| addr.address().to_string()),
+            })),
+            "manager_config": revaultd.our_man_xpub.map(|xpub| json!({
+                "xpub": xpub.to_string(),
+                "cosigners": servers_json(&revaultd.cosigs),
+            })),
+        }))
+    }
+
+    fn reloadconfig(&self, meta: Self::Metadata) -> jsonrpc_core::Result<serde_json::Value> {
+        let reload = meta.reload_config().map_err(|e| internal_error!(e))?;
+
+        Ok(json!({
+            "applied": reload.applied,
+            "ignored": reload.ignored,
+        }))
+    }
+
     fn help(&self, _: Self::Metadata) -> jsonrpc_core::Result<serde_json::Value> {
         Ok(json!(
         {
@@ -364,6 +898,34 @@ impl RpcApi for RpcImpl {
                   "parameters": [],
                   "description": "Display general information",
                 },
+                {
+                    "name": "getconfig",
+                    "parameters": [],
+                    "description": "Display the configuration in use"
+                },
+                {
+                    "name": "reloadconfig",
+                    "parameters": [],
+                    "description": "Read the configuration file again and apply the changes that don't require a restart"
+                },
+                {
+                    "name": "getdepositaddress",
+                    "parameters": [
+                        "[index]",
+                        "[label]"
+                    ],
+                    "description": "Get an address to receive a deposit, optionally labeling it"
+                },
+                {
+                    "name": "getdeposittotals",
+                    "parameters": [],
+                    "description": "Get the total of the confirmed deposits by address label"
+                },
+                {
+                    "name": "listdepositaddresses",
+                    "parameters": [],
+                    "description": "List the deposit addresses we can hand out at once"
+                },
                 {
                     "name": "getrevocationtxs",
                     "parameters": [
@@ -378,12 +940,35 @@ impl RpcApi for RpcImpl {
                     ],
                     "description": "Retrieve the Revault unvault transaction to sign"
                 },
+                {
+                    "name": "getrawrevocationtxs",
+                    "parameters": [
+                        "outpoint"
+                    ],
+                    "description": "Retrieve the fully signed revocation transactions as hex"
+                },
+                {
+                    "name": "getrawunvaulttx",
+                    "parameters": [
+                        "outpoint"
+                    ],
+                    "description": "Retrieve the fully signed unvault transaction as hex"
+                },
+                {
+                    "name": "getbroadcasthex",
+                    "parameters": [
+                        "outpoint",
+                        "tx_type"
+                    ],
+                    "description": "Retrieve a fully signed presigned transaction as hex"
+                },
                 {
                     "name": "getspendtx",
                     "parameters": [
                         "outpoints",
                         "outputs",
-                        "feerate",
+                        "[feerate]",
+                        "[conf_target]"
                     ],
                     "description": "Retrieve the Revault spend transaction to sign"
                 },
@@ -397,7 +982,10 @@ impl RpcApi for RpcImpl {
                 {
                     "name": "listonchaintransactions",
                     "parameters": [
-                        "[outpoints]"
+                        "[outpoints]",
+                        "[offset]",
+                        "[limit]",
+                        "[include_hex]"
                     ],
                     "description": "List broadcast transactions of a vault"
                 },
@@ -405,10 +993,40 @@ impl RpcApi for RpcImpl {
                     "name": "listvaults",
                     "parameters": [
                         "[status]",
-                        "[outpoints]"
+                        "[outpoints]",
+                        "[options]"
                     ],
                     "description": "Display a paginated list of vaults"
                 },
+                {
+                    "name": "excludevault",
+                    "parameters": [
+                        "outpoint"
+                    ],
+                    "description": "Stop managing a vault"
+                },
+                {
+                    "name": "includevault",
+                    "parameters": [
+                        "outpoint"
+                    ],
+                    "description": "Manage a previously excluded vault again"
+                },
+                {
+                    "name": "labelvault",
+                    "parameters": [
+                        "outpoint",
+                        "[label]"
+                    ],
+                    "description": "Label a vault, or remove its label"
+                },
+                {
+                    "name": "exportvaults",
+                    "parameters": [
+                        "[status]"
+                    ],
+                    "description": "Export the list of vaults as CSV"
+                },
                 {
                     "name": "revocationtxs",
                     "parameters": [],
@@ -441,13 +1059,94 @@ impl RpcApi for RpcImpl {
                 },
                 {
                     "name": "gethistory",
-                    "parameters": [],
+                    "parameters": [
+                        "[start_ts]",
+                        "[end_ts]",
+                        "[limit]",
+                        "[label]",
+                        "[kind]"
+                    ],
                     "description": "Retrieve history of funds"
                 },
+                {
+                    "name": "revault",
+                    "parameters": [
+                        "outpoint"
+                    ],
+                    "description": "Broadcast the Cancel transaction of an unvaulting vault"
+                },
                 {
                     "name": "emergency",
                     "parameters": [],
                     "description": "Broadcast all Emergency signed transactions"
+                },
+                {
+                    "name": "listutxos",
+                    "parameters": [
+                        "category"
+                    ],
+                    "description": "List the watchonly wallet utxos of this category"
+                },
+                {
+                    "name": "forcesync",
+                    "parameters": [],
+                    "description": "Poll bitcoind right away"
+                },
+                {
+                    "name": "waitfornewblock",
+                    "parameters": [
+                        "[timeout]"
+                    ],
+                    "description": "Wait until we processed a new block"
+                },
+                {
+                    "name": "setemergencyaddress",
+                    "parameters": [
+                        "address",
+                        "confirm"
+                    ],
+                    "description": "Rotate the Emergency address used for new vaults"
+                },
+                {
+                    "name": "getdepositproof",
+                    "parameters": [
+                        "outpoint"
+                    ],
+                    "description": "Get the merkle proof of a deposit's confirmation"
+                },
+                {
+                    "name": "getvaulthistory",
+                    "parameters": [
+                        "outpoint"
+                    ],
+                    "description": "Get the status transitions of a vault"
+                },
+                {
+                    "name": "getfeereserve",
+                    "parameters": [
+                        "[feerate]",
+                        "[conf_target]"
+                    ],
+                    "description": "Estimate the fees needed to bump the transactions of all active vaults"
+                },
+                {
+                    "name": "setmaintenance",
+                    "parameters": [
+                        "enabled"
+                    ],
+                    "description": "Stop or resume broadcasting transactions"
+                },
+                {
+                    "name": "listwatchtowers",
+                    "parameters": [],
+                    "description": "List the vaults acknowledged by each watchtower"
+                },
+                {
+                    "name": "backupdb",
+                    "parameters": [
+                        "path"
+                    ],
+                    "description": "Snapshot the database to a new file"
                 }
             ]
         }
@@ -459,30 +1158,48 @@ impl RpcApi for RpcImpl {
         meta: Self::Metadata,
         statuses: Option<Vec<String>>,
         outpoints: Option<Vec<OutPoint>>,
+        options: Option<ListVaultsOptions>,
     ) -> jsonrpc_core::Result<serde_json::Value> {
-        let statuses = if let Some(statuses) = statuses {
-            // If they give an empty array, it's not that they don't want any result, but rather
-            // that they don't want this filter to be taken into account!
-            if !statuses.is_empty() {
-                Some(
-                    statuses
-                        .into_iter()
-                        .map(|status_str| parse_vault_status!(status_str))
-                        .collect::<jsonrpc_core::Result<Vec<VaultStatus>>>()?,
-                )
-            } else {
-                None
-            }
-        } else {
-            None
-        };
-
-        let vaults = listvaults_from_db(
-            &meta.rpc_utils.revaultd.read().unwrap(),
-            statuses,
-            outpoints,
-        )
-        .map_err(|e| internal_error!(e))?;
+        let statuses = statuses_filter(statuses)?;
+        let options = options.unwrap_or_default();
+        let bounds = options.bounds();
+        if bounds.is_empty() {
+            return Err(JsonRpcError::invalid_params(
+                "A minimum is greater than its maximum".to_string(),
+            ));
+        }
+        // Excluded vaults are only listed if explicitly asked for
+        let include_excluded = options.include_excluded.unwrap_or(false) || outpoints.is_some();
+        let revaultd = meta.rpc_utils.revaultd.read().unwrap();
+        let mut vaults =
+            listvaults_from_db(&revaultd, statuses, outpoints).map_err(|e| internal_error!(e))?;
+        vaults.retain(|entry| {
+            (include_excluded || !entry.excluded)
+                && bounds.contains(entry)
+                && (options.label.is_none() || entry.label == options.label)
+        });
+        let finalized_height = revaultd.finalized_height(
+            db_tip(&revaultd.db_file())
+                .map_err(|e| internal_error!(e))?
+                .height,
+        );
+        sort_vaults(
+            &mut vaults,
+            options
+                .sort_by
+                .unwrap_or(ListVaultsSortKey::DerivationIndex),
+            options.descending.unwrap_or(false),
+        );
+        let Page {
+            entries: vaults,
+            total,
+            truncated,
+        } = paginate(
+            vaults,
+            options.offset,
+            options.limit,
+            revaultd.rpc_max_entries,
+        );
 
         let vaults: Vec<serde_json::Value> = vaults
             .into_iter()
@@ -498,26 +1215,202 @@ impl RpcApi for RpcImpl {
                     "address": entry.address.to_string(),
                     "received_at": entry.received_at,
                     "updated_at": entry.updated_at,
+                    "excluded": entry.excluded,
+                    "spend_txid": entry.spend_txid.map(|txid| txid.to_string()),
+                    "final": entry.blockheight > 0 && entry.blockheight <= finalized_height,
+                    "label": entry.label,
                 })
             })
             .collect();
 
-        Ok(json!({ "vaults": vaults }))
+        // Whatever the filters, always point out the vaults that may have been stolen
+        let unexpected_spends: Vec<String> = unexpected_spends(&revaultd)
+            .map_err(|e| internal_error!(e))?
+            .iter()
+            .map(|outpoint| outpoint.to_string())
+            .collect();
+
+        Ok(json!({
+            "vaults": vaults,
+            "total": total,
+            "truncated": truncated,
+            "unexpected_spends": unexpected_spends,
+        }))
+    }
+
+    fn excludevault(
+        &self,
+        meta: Self::Metadata,
+        outpoint: OutPoint,
+    ) -> jsonrpc_core::Result<serde_json::Value> {
+        let db_file = meta.rpc_utils.revaultd.read().unwrap().db_file();
+        let vault = db_vault_by_deposit(&db_file, &outpoint)
+            .map_err(|e| internal_error!(e))?
+            .ok_or_else(|| unknown_outpoint!(outpoint))?;
+        // Once it started moving onchain, it's too late.
+        if vault.status as u32 > VaultStatus::Active as u32 {
+            return Err(JsonRpcError::invalid_params(format!(
+                "Invalid vault status: '{}'. Can't exclude a vault that was unvaulted, \
+                 emergencied or spent.",
+                vault.status
+            )));
+        }
+
+        db_exclude_vault(&db_file, vault.id, true).map_err(|e| internal_error!(e))?;
+        Ok(json!({}))
+    }
+
+    fn labelvault(
+        &self,
+        meta: Self::Metadata,
+        outpoint: OutPoint,
+        label: Option<String>,
+    ) -> jsonrpc_core::Result<serde_json::Value> {
+        if label.as_deref() == Some("") {
+            return Err(JsonRpcError::invalid_params(
+                "The label must not be empty".to_string(),
+            ));
+        }
+        let db_file = meta.rpc_utils.revaultd.read().unwrap().db_file();
+        let vault = db_vault_by_deposit(&db_file, &outpoint)
+            .map_err(|e| internal_error!(e))?
+            .ok_or_else(|| unknown_outpoint!(outpoint))?;
+
+        db_label_vault(&db_file, vault.id, label.as_deref()).map_err(|e| internal_error!(e))?;
+        Ok(json!({}))
+    }
+
+    fn includevault(
+        &self,
+        meta: Self::Metadata,
+        outpoint: OutPoint,
+    ) -> jsonrpc_core::Result<serde_json::Value> {
+        let db_file = meta.rpc_utils.revaultd.read().unwrap().db_file();
+        let vault = db_vault_by_deposit(&db_file, &outpoint)
+            .map_err(|e| internal_error!(e))?
+            .ok_or_else(|| unknown_outpoint!(outpoint))?;
+
+        db_exclude_vault(&db_file, vault.id, false).map_err(|e| internal_error!(e))?;
+        Ok(json!({}))
+    }
+
+    fn exportvaults(
+        &self,
+        meta: Self::Metadata,
+        statuses: Option<Vec<String>>,
+    ) -> jsonrpc_core::Result<serde_json::Value> {
+        let statuses = statuses_filter(statuses)?;
+        let mut vaults =
+            listvaults_from_db(&meta.rpc_utils.revaultd.read().unwrap(), statuses, None)
+                .map_err(|e| internal_error!(e))?;
+        vaults.retain(|entry| !entry.excluded);
+        sort_vaults(&mut vaults, ListVaultsSortKey::DerivationIndex, false);
+
+        Ok(json!({ "csv": vaults_csv(&vaults) }))
     }
 
     fn getdepositaddress(
         &self,
         meta: Self::Metadata,
         index: Option<bip32::ChildNumber>,
+        label: Option<String>,
     ) -> jsonrpc_core::Result<serde_json::Value> {
-        let address = if let Some(index) = index {
-            meta.rpc_utils.revaultd.read().unwrap().vault_address(index)
+        let revaultd = meta.rpc_utils.revaultd.read().unwrap();
+        let (index, address) = if let Some(index) = index {
+            if index.is_hardened() {
+                return Err(JsonRpcError::invalid_params(format!(
+                    "Derivation index '{}' is out of the deposit descriptor range, which only \
+                     contains unhardened indexes",
+                    index
+                )));
+            }
+            // We wouldn't notice a deposit to an address we don't watch
+            let raw_index: u32 = index.into();
+            if raw_index >= revaultd.watched_range_end() {
+                return Err(JsonRpcError::invalid_params(format!(
+                    "Derivation index '{}' is past the watched ones, which end at '{}'",
+                    index,
+                    revaultd.watched_range_end()
+                )));
+            }
+            (index, revaultd.vault_address(index))
         } else {
-            meta.rpc_utils.revaultd.read().unwrap().deposit_address()
+            // Once the last address of the range was used, we can't hand out a fresh one anymore
+            if revaultd.remaining_deposit_indexes() <= 1
+                && !db_vaults_by_derivation_index(
+                    &revaultd.db_file(),
+                    revaultd.current_unused_index,
+                )
+                .map_err(|e| internal_error!(e))?
+                .is_empty()
+            {
+                return Err(JsonRpcError::invalid_params(
+                    "The deposit descriptor range is exhausted. New deposits must be made to a \
+                     descriptor with fresh keys or another derivation path."
+                        .to_string(),
+                ));
+            }
+            (revaultd.current_unused_index, revaultd.deposit_address())
         };
+
+        if let Some(label) = label {
+            if label.is_empty() {
+                return Err(JsonRpcError::invalid_params(
+                    "The label must not be empty".to_string(),
+                ));
+            }
+            let wallet_id = revaultd
+                .wallet_id
+                .expect("Wallet id is set at startup in setup_db()");
+            db_label_deposit_address(&revaultd.db_file(), wallet_id, index, &label)
+                .map_err(|e| internal_error!(e))?;
+        }
+
         Ok(json!({ "address": address.to_string() }))
     }
 
+    fn getdeposittotals(&self, meta: Self::Metadata) -> jsonrpc_core::Result<serde_json::Value> {
+        let db_path = meta.rpc_utils.revaultd.read().unwrap().db_file();
+        let mut untagged = Amount::from_sat(0);
+        let mut totals = Vec::new();
+        for (label, amount) in db_deposit_totals(&db_path).map_err(|e| internal_error!(e))? {
+            if let Some(label) = label {
+                totals.push(json!({
+                    "label": label,
+                    "amount": amount.as_sat(),
+                }));
+            } else {
+                untagged = amount;
+            }
+        }
+
+        Ok(json!({
+            "totals": totals,
+            "untagged": untagged.as_sat(),
+        }))
+    }
+
+    fn listdepositaddresses(
+        &self,
+        meta: Self::Metadata,
+    ) -> jsonrpc_core::Result<serde_json::Value> {
+        let addresses: Vec<serde_json::Value> = meta
+            .rpc_utils
+            .revaultd
+            .read()
+            .unwrap()
+            .precreated_deposit_addresses()
+            .into_iter()
+            .map(|(index, address)| {
+                json!({
+                    "index": index,
+                    "address": address.to_string(),
+                })
+            })
+            .collect();
+        Ok(json!({ "addresses": addresses }))
+    }
+
     fn getrevocationtxs(
         &self,
         meta: Self::Metadata,
@@ -593,9 +1486,10 @@ impl RpcApi for RpcImpl {
 
         // Sanity check they didn't send us garbaged PSBTs
         // FIXME: this may not hold true in all cases, see https://github.com/revault/revaultd/issues/145
-        let (cancel_db_id, db_cancel_tx) = db_cancel_transaction(&db_path, db_vault.id)
-            .map_err(|e| internal_error!(e))?
-            .expect("must be here if at least in 'Funded' state");
+        let (cancel_db_id, db_cancel_tx) =
+            db_cancel_transaction(&db_path, revaultd.db_key.as_ref(), db_vault.id)
+                .map_err(|e| internal_error!(e))?
+                .expect("must be here if at least in 'Funded' state");
         let rpc_txid = cancel_tx.tx().wtxid();
         let db_txid = db_cancel_tx.tx().wtxid();
         if rpc_txid != db_txid {
@@ -605,9 +1499,10 @@ impl RpcApi for RpcImpl {
             )));
         }
         // FIXME: this *might* not hold true in all cases, see https://github.com/revault/revaultd/issues/145
-        let (emer_db_id, db_emergency_tx) = db_emer_transaction(&revaultd.db_file(), db_vault.id)
-            .map_err(|e| internal_error!(e))?
-            .expect("Must be here if 'funded'");
+        let (emer_db_id, db_emergency_tx) =
+            db_emer_transaction(&revaultd.db_file(), revaultd.db_key.as_ref(), db_vault.id)
+                .map_err(|e| internal_error!(e))?
+                .expect("Must be here if 'funded'");
         let rpc_txid = emergency_tx.tx().wtxid();
         let db_txid = db_emergency_tx.tx().wtxid();
         if rpc_txid != db_txid {
@@ -618,7 +1513,7 @@ impl RpcApi for RpcImpl {
         }
         // FIXME: this *might* not hold true in all cases, see https://github.com/revault/revaultd/issues/145
         let (unvault_emer_db_id, db_unemergency_tx) =
-            db_unvault_emer_transaction(&revaultd.db_file(), db_vault.id)
+            db_unvault_emer_transaction(&revaultd.db_file(), revaultd.db_key.as_ref(), db_vault.id)
                 .map_err(|e| internal_error!(e))?
                 .expect("Must be here if 'funded'");
         let rpc_txid = unvault_emergency_tx.tx().wtxid();
@@ -630,25 +1525,53 @@ impl RpcApi for RpcImpl {
             )));
         }
 
+        // Don't rely on revocation transactions that would not confirm, or burn all the funds.
+        let feerate_range = &revaultd.revocation_feerate_range;
+        let feerate_error = |tx_name: &str, feerate: u64| {
+            JsonRpcError::invalid_params(format!(
+                "Invalid {} tx: its feerate of {} sat/vB is out of the acceptable range \
+                 ({}-{} sat/vB)",
+                tx_name,
+                feerate,
+                feerate_range.start(),
+                feerate_range.end()
+            ))
+        };
+        let unvault_satisfaction_weight = revaultd.unvault_satisfaction_weight();
+        check_revocation_feerate(&cancel_tx, unvault_satisfaction_weight, feerate_range)
+            .map_err(|feerate| feerate_error("Cancel", feerate))?;
+        check_revocation_feerate(
+            &emergency_tx,
+            revaultd.deposit_satisfaction_weight(),
+            feerate_range,
+        )
+        .map_err(|feerate| feerate_error("Emergency", feerate))?;
+        check_revocation_feerate(
+            &unvault_emergency_tx,
+            unvault_satisfaction_weight,
+            feerate_range,
+        )
+        .map_err(|feerate| feerate_error("Unvault Emergency", feerate))?;
+
         let deriv_index = db_vault.derivation_index;
         let cancel_sigs = cancel_tx
             .psbt()
             .inputs
-            .get(0)
+            .first()
             .expect("Cancel tx has a single input, inbefore fee bumping.")
             .partial_sigs
             .clone();
         let emer_sigs = emergency_tx
             .psbt()
             .inputs
-            .get(0)
+            .first()
             .expect("Emergency tx has a single input, inbefore fee bumping.")
             .partial_sigs
             .clone();
         let unvault_emer_sigs = unvault_emergency_tx
             .psbt()
             .inputs
-            .get(0)
+            .first()
             .expect("UnvaultEmergency tx has a single input, inbefore fee bumping.")
             .partial_sigs
             .clone();
@@ -726,6 +1649,7 @@ impl RpcApi for RpcImpl {
         // Ok, signatures look legit. Add them to the PSBTs in database.
         db_update_presigned_tx(
             &revaultd.db_file(),
+            revaultd.db_key.as_ref(),
             db_vault.id,
             cancel_db_id,
             cancel_sigs.clone(),
@@ -734,6 +1658,7 @@ impl RpcApi for RpcImpl {
         .map_err(|e| internal_error!(e))?;
         db_update_presigned_tx(
             &revaultd.db_file(),
+            revaultd.db_key.as_ref(),
             db_vault.id,
             emer_db_id,
             emer_sigs.clone(),
@@ -742,6 +1667,7 @@ impl RpcApi for RpcImpl {
         .map_err(|e| internal_error!(e))?;
         db_update_presigned_tx(
             &revaultd.db_file(),
+            revaultd.db_key.as_ref(),
             db_vault.id,
             unvault_emer_db_id,
             unvault_emer_sigs.clone(),
@@ -839,7 +1765,8 @@ impl RpcApi for RpcImpl {
 
         // Sanity check they didn't send us a garbaged PSBT
         let (unvault_db_id, db_unvault_tx) =
-            db_unvault_transaction(&db_path, db_vault.id).map_err(|e| internal_error!(e))?;
+            db_unvault_transaction(&db_path, revaultd.db_key.as_ref(), db_vault.id)
+                .map_err(|e| internal_error!(e))?;
         let rpc_txid = unvault_tx.tx().wtxid();
         let db_txid = db_unvault_tx.tx().wtxid();
         if rpc_txid != db_txid {
@@ -852,7 +1779,7 @@ impl RpcApi for RpcImpl {
         let sigs = &unvault_tx
             .psbt()
             .inputs
-            .get(0)
+            .first()
             .expect("UnvaultTransaction always has 1 input")
             .partial_sigs;
         let stk_keys = revaultd.stakeholders_xpubs_at(db_vault.derivation_index);
@@ -886,33 +1813,165 @@ impl RpcApi for RpcImpl {
             ))
         })?;
 
-        // Sanity checks passed. Store it then share it.
-        db_update_presigned_tx(
+        // Sanity checks passed. Store it then share it.
+        db_update_presigned_tx(
+            &revaultd.db_file(),
+            revaultd.db_key.as_ref(),
+            db_vault.id,
+            unvault_db_id,
+            sigs.clone(),
+            secp_ctx,
+        )
+        .map_err(|e| internal_error!(e))?;
+        share_unvault_signatures(
+            revaultd.coordinator_host,
+            &revaultd.noise_secret,
+            &revaultd.coordinator_noisekey,
+            &unvault_tx,
+        )
+        .map_err(|e| {
+            JsonRpcError::invalid_params(format!(
+                "Communication error while sharing Unvault signatures with coordinator: '{}'",
+                e
+            ))
+        })?;
+
+        // NOTE: it will only mark it as 'unvaulting' if it was 'secured', not if it was
+        // marked as 'activated' by db_update_presigned_tx() !
+        db_mark_activating_vault(&db_path, db_vault.id).map_err(|e| internal_error!(e))?;
+
+        Ok(json!({}))
+    }
+
+    fn getrawrevocationtxs(
+        &self,
+        meta: Self::Metadata,
+        outpoint: OutPoint,
+    ) -> jsonrpc_core::Result<serde_json::Value> {
+        stakeholder_only!(meta);
+        let revaultd = meta.rpc_utils.revaultd.read().unwrap();
+
+        let db_vaults = vaults_from_deposits(
+            &revaultd.db_file(),
+            &[outpoint],
+            &[VaultStatus::Unconfirmed],
+        )
+        .map_err(|e| JsonRpcError::invalid_params(e.to_string()))?;
+        let presigned_txs = presigned_txs(&revaultd, db_vaults)
+            .map_err(|e| internal_error!(e))?
+            .pop()
+            .expect("We queried a single vault");
+
+        let emergency = presigned_txs
+            .emergency
+            .expect("The JSONRPC API checked we were a stakeholder");
+        let unvault_emergency = presigned_txs
+            .unvault_emergency
+            .expect("The JSONRPC API checked we were a stakeholder");
+        match (
+            presigned_txs.cancel.transaction,
+            emergency.transaction,
+            unvault_emergency.transaction,
+        ) {
+            (Some(cancel_tx), Some(emergency_tx), Some(unvault_emergency_tx)) => Ok(json!({
+                "cancel_tx": encode::serialize_hex(&cancel_tx),
+                "emergency_tx": encode::serialize_hex(&emergency_tx),
+                "emergency_unvault_tx": encode::serialize_hex(&unvault_emergency_tx),
+            })),
+            _ => Err(JsonRpcError::invalid_params(format!(
+                "Revocation transactions for vault at '{}' are not fully signed yet",
+                outpoint
+            ))),
+        }
+    }
+
+    fn getrawunvaulttx(
+        &self,
+        meta: Self::Metadata,
+        outpoint: OutPoint,
+    ) -> jsonrpc_core::Result<serde_json::Value> {
+        let revaultd = meta.rpc_utils.revaultd.read().unwrap();
+
+        let db_vaults = vaults_from_deposits(
+            &revaultd.db_file(),
+            &[outpoint],
+            &[VaultStatus::Unconfirmed],
+        )
+        .map_err(|e| JsonRpcError::invalid_params(e.to_string()))?;
+        let presigned_txs = presigned_txs(&revaultd, db_vaults)
+            .map_err(|e| internal_error!(e))?
+            .pop()
+            .expect("We queried a single vault");
+
+        let unvault_tx = presigned_txs.unvault.transaction.ok_or_else(|| {
+            JsonRpcError::invalid_params(format!(
+                "Unvault transaction for vault at '{}' is not fully signed yet",
+                outpoint
+            ))
+        })?;
+
+        Ok(json!({
+            "unvault_tx": encode::serialize_hex(&unvault_tx),
+        }))
+    }
+
+    fn getbroadcasthex(
+        &self,
+        meta: Self::Metadata,
+        outpoint: OutPoint,
+        tx_type: String,
+    ) -> jsonrpc_core::Result<serde_json::Value> {
+        if matches!(tx_type.as_str(), "emergency" | "unvault_emergency") {
+            stakeholder_only!(meta);
+        }
+        let revaultd = meta.rpc_utils.revaultd.read().unwrap();
+
+        let db_vaults = vaults_from_deposits(
             &revaultd.db_file(),
-            db_vault.id,
-            unvault_db_id,
-            sigs.clone(),
-            secp_ctx,
+            &[outpoint],
+            // An aborted vault may never have had its presigned transactions
+            &[VaultStatus::Unconfirmed, VaultStatus::Aborted],
         )
-        .map_err(|e| internal_error!(e))?;
-        share_unvault_signatures(
-            revaultd.coordinator_host,
-            &revaultd.noise_secret,
-            &revaultd.coordinator_noisekey,
-            &unvault_tx,
-        )
-        .map_err(|e| {
+        .map_err(|e| JsonRpcError::invalid_params(e.to_string()))?;
+        let presigned_txs = presigned_txs(&revaultd, db_vaults)
+            .map_err(|e| internal_error!(e))?
+            .pop()
+            .expect("We queried a single vault");
+
+        let transaction = match tx_type.as_str() {
+            "unvault" => presigned_txs.unvault.transaction,
+            "cancel" => presigned_txs.cancel.transaction,
+            "emergency" => {
+                presigned_txs
+                    .emergency
+                    .expect("The JSONRPC API checked we were a stakeholder")
+                    .transaction
+            }
+            "unvault_emergency" => {
+                presigned_txs
+                    .unvault_emergency
+                    .expect("The JSONRPC API checked we were a stakeholder")
+                    .transaction
+            }
+            _ => {
+                return Err(JsonRpcError::invalid_params(format!(
+                    "Invalid transaction type '{}', must be one of 'unvault', 'cancel', \
+                     'emergency' or 'unvault_emergency'",
+                    tx_type
+                )))
+            }
+        }
+        .ok_or_else(|| {
             JsonRpcError::invalid_params(format!(
-                "Communication error while sharing Unvault signatures with coordinator: '{}'",
-                e
+                "The '{}' transaction for vault at '{}' is not fully signed yet",
+                tx_type, outpoint
             ))
         })?;
 
-        // NOTE: it will only mark it as 'unvaulting' if it was 'secured', not if it was
-        // marked as 'activated' by db_update_presigned_tx() !
-        db_mark_activating_vault(&db_path, db_vault.id).map_err(|e| internal_error!(e))?;
-
-        Ok(json!({}))
+        Ok(json!({
+            "txid": transaction.txid().to_string(),
+            "hex": encode::serialize_hex(&transaction),
+        }))
     }
 
     fn listpresignedtransactions(
@@ -930,6 +1989,11 @@ impl RpcApi for RpcImpl {
         } else {
             db_vaults_min_status(&db_path, VaultStatus::Funded).map_err(|e| internal_error!(e))?
         };
+        let Page {
+            entries: db_vaults,
+            truncated,
+            ..
+        } = paginate(db_vaults, None, None, revaultd.rpc_max_entries);
         let vaults = presigned_txs(&revaultd, db_vaults).map_err(|e| internal_error!(e))?;
 
         let vaults: Vec<serde_json::Value> = vaults
@@ -945,16 +2009,20 @@ impl RpcApi for RpcImpl {
             })
             .collect();
 
-        Ok(json!({ "presigned_transactions": vaults }))
+        Ok(json!({ "presigned_transactions": vaults, "truncated": truncated }))
     }
 
     fn listonchaintransactions(
         &self,
         meta: Self::Metadata,
         outpoints: Option<Vec<OutPoint>>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+        include_hex: Option<bool>,
     ) -> jsonrpc_core::Result<serde_json::Value> {
         let revaultd = meta.rpc_utils.revaultd.read().unwrap();
         let db_path = revaultd.db_file();
+        let include_hex = include_hex.unwrap_or(true);
 
         // If they didn't provide us with a list of outpoints, catch'em all!
         let db_vaults = if let Some(outpoints) = outpoints {
@@ -964,23 +2032,44 @@ impl RpcApi for RpcImpl {
         } else {
             db_vaults(&db_path).map_err(|e| internal_error!(e))?
         };
-        let vaults = onchain_txs(
-            &meta.rpc_utils.revaultd.read().unwrap(),
-            &meta.rpc_utils.bitcoind_tx,
-            db_vaults,
-        )
-        .map_err(|e| internal_error!(e))?;
-
-        fn wallet_tx_to_json(tx: WalletTransaction) -> serde_json::Value {
-            json!({
+        let Page {
+            entries: db_vaults,
+            total,
+            truncated,
+        } = paginate(db_vaults, offset, limit, revaultd.rpc_max_entries);
+
+        let tip_height = db_tip(&db_path).map_err(|e| internal_error!(e))?.height;
+        let wallet_tx_to_json = |tx: WalletTransaction| -> serde_json::Value {
+            let confirmations = tx
+                .blockheight
+                .map(|height| (tip_height + 1).saturating_sub(height))
+                .unwrap_or(0);
+            // The feerate is only known if bitcoind could tell us the fee
+            let feerate = tx.fee.and_then(|fee| {
+                let tx: BitcoinTransaction =
+                    encode::deserialize(&Vec::from_hex(&tx.hex).ok()?).ok()?;
+                let vsize = ((tx.get_weight() + 3) / 4) as u64;
+                Some(fee / vsize)
+            });
+            let mut json_tx = json!({
                 "blockheight": tx.blockheight.map(serde_json::Number::from),
                 "received_at": serde_json::Number::from(tx.received_time),
-                "hex": serde_json::Value::String(tx.hex),
-            })
-        }
-        let vaults: Vec<serde_json::Value> = vaults
-            .into_iter()
-            .map(|v| {
+                "confirmations": confirmations,
+                "fee": tx.fee,
+                "feerate": feerate,
+            });
+            if include_hex {
+                json_tx["hex"] = serde_json::Value::String(tx.hex);
+            }
+            json_tx
+        };
+        // Query bitcoind for a chunk of vaults at a time, so we only ever hold the raw
+        // transactions of a single chunk besides the response we are building.
+        let mut vaults = Vec::with_capacity(db_vaults.len());
+        for chunk in db_vaults.chunks(ONCHAIN_TXS_CHUNK_SIZE) {
+            let chunk_txs = onchain_txs(&revaultd, &meta.rpc_utils.bitcoind_tx, chunk.to_vec())
+                .map_err(|e| internal_error!(e))?;
+            vaults.extend(chunk_txs.into_iter().map(|v| {
                 json!({
                     "vault_outpoint": v.outpoint,
                     "deposit": wallet_tx_to_json(v.deposit),
@@ -990,11 +2079,13 @@ impl RpcApi for RpcImpl {
                     "unvault_emergency": v.unvault_emergency.map(wallet_tx_to_json),
                     "spend": v.spend.map(wallet_tx_to_json),
                 })
-            })
-            .collect();
+            }));
+        }
 
         Ok(json!({
             "onchain_transactions": vaults,
+            "total": total,
+            "truncated": truncated,
         }))
     }
 
@@ -1003,15 +2094,34 @@ impl RpcApi for RpcImpl {
         meta: Self::Metadata,
         outpoints: Vec<OutPoint>,
         destinations: BTreeMap<Address, u64>,
-        feerate_vb: u64,
+        feerate_vb: Option<u64>,
+        conf_target: Option<u32>,
     ) -> jsonrpc_core::Result<serde_json::Value> {
         manager_only!(meta);
+        let bitcoind_tx = &meta.rpc_utils.bitcoind_tx;
+        let feerate_vb = feerate_or_estimate(&meta, feerate_vb, conf_target)?;
 
         if feerate_vb < 1 {
             return Err(JsonRpcError::invalid_params(
                 "Feerate can't be <1".to_string(),
             ));
         }
+        // The Spend would not be relayed
+        let relay_feerate_vb =
+            bitcoind_relay_feerate(bitcoind_tx).map_err(|e| internal_error!(e))?;
+        if feerate_vb < relay_feerate_vb {
+            return Err(JsonRpcError::invalid_params(format!(
+                "Feerate ('{}') is below the minimum relay feerate ('{}')",
+                feerate_vb, relay_feerate_vb
+            )));
+        }
+        // bitcoind would refuse to broadcast it
+        if feerate_vb > MAX_SPEND_FEERATE {
+            return Err(JsonRpcError::invalid_params(format!(
+                "Feerate ('{}') is above the maximum of '{}'",
+                feerate_vb, MAX_SPEND_FEERATE
+            )));
+        }
 
         let revaultd = meta.rpc_utils.revaultd.read().unwrap();
         let db_file = &revaultd.db_file();
@@ -1023,9 +2133,12 @@ impl RpcApi for RpcImpl {
         // disrepancy between our indexes.
         let mut change_index = bip32::ChildNumber::from(0);
         for outpoint in outpoints.iter() {
-            let vault = db_vault_by_deposit(db_file, &outpoint)
+            let vault = db_vault_by_deposit(db_file, outpoint)
                 .map_err(|e| internal_error!(e))?
                 .ok_or_else(|| unknown_outpoint!(outpoint))?;
+            if vault.excluded {
+                return Err(excluded_vault!(outpoint));
+            }
             if matches!(vault.status, VaultStatus::Active) {
                 if vault.derivation_index > change_index {
                     change_index = vault.derivation_index;
@@ -1146,14 +2259,16 @@ impl RpcApi for RpcImpl {
         })?;
 
         if !check_spend_transaction_size(&revaultd, tx_res.clone()) {
-            return Err(JsonRpcError::invalid_params(format!(
-                "Spend transaction is too large, try spending less outpoints"
-            )));
+            return Err(JsonRpcError::invalid_params(
+                "Spend transaction is too large, try spending less outpoints".to_string(),
+            ));
         };
         log::debug!("Final Spend transaction: '{:?}'", tx_res);
 
         Ok(json!({
             "spend_tx": tx_res.as_psbt_string(),
+            "feerate": feerate_vb,
+            "fees": tx_res.fees(),
         }))
     }
 
@@ -1171,16 +2286,22 @@ impl RpcApi for RpcImpl {
         let spend_inputs = &spend_tx.tx().input;
         let mut db_unvaults = Vec::with_capacity(spend_inputs.len());
         for txin in spend_inputs.iter() {
-            let (db_vault, db_unvault) =
-                db_vault_by_unvault_txid(&db_path, &txin.previous_output.txid)
-                    .map_err(|e| internal_error!(e))?
-                    .ok_or_else(|| {
-                        JsonRpcError::invalid_params(format!(
-                            "Spend transaction refers an unknown Unvault: '{}'",
-                            txin.previous_output.txid
-                        ))
-                    })?;
+            let (db_vault, db_unvault) = db_vault_by_unvault_txid(
+                &db_path,
+                revaultd.db_key.as_ref(),
+                &txin.previous_output.txid,
+            )
+            .map_err(|e| internal_error!(e))?
+            .ok_or_else(|| {
+                JsonRpcError::invalid_params(format!(
+                    "Spend transaction refers an unknown Unvault: '{}'",
+                    txin.previous_output.txid
+                ))
+            })?;
 
+            if db_vault.excluded {
+                return Err(excluded_vault!(db_vault.deposit_outpoint));
+            }
             if !matches!(db_vault.status, VaultStatus::Active) {
                 return Err(invalid_status!(db_vault.status, VaultStatus::Active));
             }
@@ -1188,15 +2309,17 @@ impl RpcApi for RpcImpl {
             db_unvaults.push(db_unvault);
         }
 
-        if db_spend_transaction(&db_path, &spend_txid)
+        if db_spend_transaction(&db_path, revaultd.db_key.as_ref(), &spend_txid)
             .map_err(|e| internal_error!(e))?
             .is_some()
         {
             log::debug!("Updating Spend transaction '{}'", spend_txid);
-            db_update_spend(&db_path, &spend_tx).map_err(|e| internal_error!(e))?;
+            db_update_spend(&db_path, revaultd.db_key.as_ref(), &spend_tx)
+                .map_err(|e| internal_error!(e))?;
         } else {
             log::debug!("Storing new Spend transaction '{}'", spend_txid);
-            db_insert_spend(&db_path, &db_unvaults, &spend_tx).map_err(|e| internal_error!(e))?;
+            db_insert_spend(&db_path, revaultd.db_key.as_ref(), &db_unvaults, &spend_tx)
+                .map_err(|e| internal_error!(e))?;
         }
 
         Ok(json!({}))
@@ -1227,7 +2350,8 @@ impl RpcApi for RpcImpl {
         let revaultd = meta.rpc_utils.revaultd.read().unwrap();
         let db_path = revaultd.db_file();
 
-        let spend_tx_map = db_list_spends(&db_path).map_err(|e| internal_error!(e))?;
+        let spend_tx_map =
+            db_list_spends(&db_path, revaultd.db_key.as_ref()).map_err(|e| internal_error!(e))?;
         let mut listspend_entries = Vec::with_capacity(spend_tx_map.len());
         for (_, (db_spend, dep_outpoints)) in spend_tx_map {
             // Filter by status
@@ -1331,8 +2455,15 @@ impl RpcApi for RpcImpl {
         let revaultd = meta.rpc_utils.revaultd.read().unwrap();
         let db_path = revaultd.db_file();
 
+        // Don't announce a Spend to the coordinator if we won't broadcast its Unvaults
+        if revaultd.is_maintenance() {
+            return Err(JsonRpcError::invalid_params(
+                "Maintenance mode is enabled, not broadcasting any transaction".to_string(),
+            ));
+        }
+
         // Get the Spend they reference from DB
-        let mut spend_tx = db_spend_transaction(&db_path, &spend_txid)
+        let mut spend_tx = db_spend_transaction(&db_path, revaultd.db_key.as_ref(), &spend_txid)
             .map_err(|e| internal_error!(e))?
             .ok_or_else(|| JsonRpcError::invalid_params("Unknown Spend transaction".to_string()))?;
 
@@ -1364,20 +2495,21 @@ impl RpcApi for RpcImpl {
         .map_err(|e| {
             JsonRpcError::invalid_params(format!(
                 "Error checking Spend transaction signature: '{}'",
-                e.to_string()
+                e
             ))
         })?;
 
         // Check that we can actually send the tx to the coordinator...
         if !check_spend_transaction_size(&revaultd, spend_tx.psbt.clone()) {
-            return Err(JsonRpcError::invalid_params(format!(
-                "Spend transaction is too large, try spending less outpoints"
-            )));
+            return Err(JsonRpcError::invalid_params(
+                "Spend transaction is too large, try spending less outpoints".to_string(),
+            ));
         };
 
         // Now we can ask all the cosigning servers for their signatures
         log::debug!("Fetching signatures from Cosigning servers");
         fetch_cosigs_signatures(
+            &revaultd.secp_ctx,
             &revaultd.noise_secret,
             &mut spend_tx.psbt,
             revaultd.cosigs.as_ref().expect("We are manager"),
@@ -1415,7 +2547,8 @@ impl RpcApi for RpcImpl {
                 e
             ))
         })?;
-        db_update_spend(&db_path, &spend_tx.psbt).map_err(|e| internal_error!(e))?;
+        db_update_spend(&db_path, revaultd.db_key.as_ref(), &spend_tx.psbt)
+            .map_err(|e| internal_error!(e))?;
 
         // Finally we can broadcast the Unvault(s) transaction(s) and store the Spend
         // transaction for later broadcast
@@ -1425,10 +2558,10 @@ impl RpcApi for RpcImpl {
         );
         let bitcoin_txs = spent_vaults
             .values()
-            .into_iter()
             .map(|db_vault| {
-                let (_, mut unvault_tx) = db_unvault_transaction(&db_path, db_vault.id)
-                    .map_err(|e| internal_error!(e))?;
+                let (_, mut unvault_tx) =
+                    db_unvault_transaction(&db_path, revaultd.db_key.as_ref(), db_vault.id)
+                        .map_err(|e| internal_error!(e))?;
                 unvault_tx
                     .finalize(&revaultd.secp_ctx)
                     .map_err(|e| internal_error!(e))?;
@@ -1443,6 +2576,107 @@ impl RpcApi for RpcImpl {
         Ok(json!({}))
     }
 
+    fn gethistory(
+        &self,
+        meta: Self::Metadata,
+        start_ts: Option<HistoryStart>,
+        end_ts: Option<u32>,
+        limit: Option<usize>,
+        label: Option<String>,
+        kind: Option<Vec<String>>,
+    ) -> jsonrpc_core::Result<serde_json::Value> {
+        let revaultd = meta.rpc_utils.revaultd.read().unwrap();
+        let db_path = revaultd.db_file();
+        let db_key = revaultd.db_key.as_ref();
+        let statuses = history_filter(kind)?;
+        // Resuming from a cursor only returns the events past the last one received
+        let (start_ts, after) = match start_ts {
+            None => (0, None),
+            Some(HistoryStart::Timestamp(start_ts)) => (start_ts, None),
+            Some(HistoryStart::Cursor(cursor)) => {
+                let (timestamp, id) = history_cursor(&cursor)?;
+                (timestamp, Some((timestamp, id)))
+            }
+        };
+        let end_ts = end_ts.unwrap_or(u32::MAX);
+        if start_ts > end_ts {
+            return Err(JsonRpcError::invalid_params(format!(
+                "Start timestamp '{}' is after end timestamp '{}'",
+                start_ts, end_ts
+            )));
+        }
+        let limit = page_limit(limit, revaultd.rpc_max_entries);
+
+        let history = db_history_events(
+            &db_path,
+            &statuses,
+            start_ts,
+            end_ts,
+            limit,
+            label.as_deref(),
+            after,
+        )
+        .map_err(|e| internal_error!(e))?;
+        // Where to resume from to get the next events
+        let next_cursor = history
+            .last()
+            .map(|event| format!("{}:{}", event.change.timestamp, event.id));
+        let mut events = Vec::with_capacity(history.len());
+        for event in history {
+            let (kind, _) = HISTORY_KINDS
+                .iter()
+                .find(|(_, statuses)| statuses.contains(&event.change.status))
+                .expect("We only query accounting events");
+            // The transaction behind this event, and its fee if it's one of ours
+            let (txid, fee) = match event.change.status {
+                VaultStatus::Funded => (Some(event.deposit_outpoint.txid), None),
+                VaultStatus::Unvaulting => {
+                    let (_, unvault_tx) = db_unvault_transaction(&db_path, db_key, event.vault_id)
+                        .map_err(|e| internal_error!(e))?;
+                    (Some(unvault_tx.txid()), Some(unvault_tx.fees()))
+                }
+                VaultStatus::Spent | VaultStatus::UnexpectedSpend => match event.spend_txid {
+                    Some(spend_txid) => (
+                        Some(spend_txid),
+                        db_spend_transaction(&db_path, db_key, &spend_txid)
+                            .map_err(|e| internal_error!(e))?
+                            .map(|db_spend| db_spend.psbt.fees()),
+                    ),
+                    None => (None, None),
+                },
+                VaultStatus::Canceled => db_cancel_transaction(&db_path, db_key, event.vault_id)
+                    .map_err(|e| internal_error!(e))?
+                    .map(|(_, tx)| (Some(tx.txid()), Some(tx.fees())))
+                    .unwrap_or((None, None)),
+                VaultStatus::EmergencyVaulting => {
+                    db_emer_transaction(&db_path, db_key, event.vault_id)
+                        .map_err(|e| internal_error!(e))?
+                        .map(|(_, tx)| (Some(tx.txid()), Some(tx.fees())))
+                        .unwrap_or((None, None))
+                }
+                VaultStatus::UnvaultEmergencyVaulting => {
+                    db_unvault_emer_transaction(&db_path, db_key, event.vault_id)
+                        .map_err(|e| internal_error!(e))?
+                        .map(|(_, tx)| (Some(tx.txid()), Some(tx.fees())))
+                        .unwrap_or((None, None))
+                }
+                _ => (None, None),
+            };
+            events.push(json!({
+                "kind": kind,
+                "date": event.change.timestamp,
+                "blockheight": event.change.blockheight,
+                "outpoint": event.deposit_outpoint,
+                "amount": event.amount.as_sat(),
+                "txid": txid,
+                "fee": fee,
+                "label": event.label,
+            }));
+        }
+
+        Ok(json!({ "events": events, "cursor": next_cursor }))
+    }
+
     fn revault(
         &self,
         meta: Self::Metadata,
@@ -1464,9 +2698,10 @@ impl RpcApi for RpcImpl {
             return Err(invalid_status!(vault.status, VaultStatus::Unvaulting));
         }
 
-        let (_, mut cancel_tx) = db_cancel_transaction(&db_path, vault.id)
-            .map_err(|e| internal_error!(e))?
-            .expect("Must be in DB post 'Secured' status");
+        let (_, mut cancel_tx) =
+            db_cancel_transaction(&db_path, revaultd.db_key.as_ref(), vault.id)
+                .map_err(|e| internal_error!(e))?
+                .expect("Must be in DB post 'Secured' status");
 
         cancel_tx
             .finalize(&revaultd.secp_ctx)
@@ -1508,4 +2743,315 @@ impl RpcApi for RpcImpl {
             "watchtowers": watchtowers,
         }))
     }
+
+    fn listutxos(
+        &self,
+        meta: Self::Metadata,
+        category: String,
+    ) -> jsonrpc_core::Result<serde_json::Value> {
+        let network = meta
+            .rpc_utils
+            .revaultd
+            .read()
+            .unwrap()
+            .bitcoind_config
+            .network;
+        let utxos = bitcoind_labeled_utxos(&meta.rpc_utils.bitcoind_tx, &category)
+            .map_err(|e| internal_error!(e))?
+            .into_iter()
+            .map(|utxo| {
+                json!({
+                    "outpoint": utxo.outpoint.to_string(),
+                    "amount": utxo.txo.value,
+                    "address": Address::from_script(&utxo.txo.script_pubkey, network)
+                        .map(|a| a.to_string()),
+                    "confirmations": utxo.confirmations,
+                })
+            })
+            .collect::<Vec<serde_json::Value>>();
+
+        Ok(json!({ "utxos": utxos }))
+    }
+
+    fn forcesync(&self, meta: Self::Metadata) -> jsonrpc_core::Result<serde_json::Value> {
+        let changes =
+            bitcoind_force_sync(&meta.rpc_utils.bitcoind_tx).map_err(|e| internal_error!(e))?;
+        Ok(json!({ "changes": changes }))
+    }
+
+    fn waitfornewblock(
+        &self,
+        meta: Self::Metadata,
+        timeout: Option<u64>,
+    ) -> jsonrpc_core::Result<serde_json::Value> {
+        let timeout = timeout.unwrap_or(DEFAULT_WAIT_TIMEOUT_SECS);
+        if timeout > MAX_WAIT_TIMEOUT_SECS {
+            return Err(JsonRpcError::invalid_params(format!(
+                "Timeout must not be greater than {} seconds",
+                MAX_WAIT_TIMEOUT_SECS
+            )));
+        }
+        let deadline = Instant::now()
+            .checked_add(Duration::from_secs(timeout))
+            .ok_or_else(|| JsonRpcError::invalid_params("Invalid timeout".to_string()))?;
+        // Subscribe before reading the current tip, not to miss a block in between
+        let (events, mut tip) = {
+            let revaultd = meta.rpc_utils.revaultd.read().unwrap();
+            let events = revaultd.events.subscribe();
+            (
+                events,
+                db_tip(&revaultd.db_file()).map_err(|e| internal_error!(e))?,
+            )
+        };
+
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            match events.recv_timeout(remaining) {
+                Ok(BitcoindEvent::NewTip(new_tip)) => {
+                    tip = new_tip;
+                    break;
+                }
+                Ok(BitcoindEvent::Shutdown) => break,
+                Ok(_) => continue,
+                Err(mpsc::RecvTimeoutError::Timeout) => break,
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err(internal_error!("The bitcoind poller went away"));
+                }
+            }
+        }
+
+        Ok(json!({
+            "height": tip.height,
+            "hash": tip.hash.to_string(),
+        }))
+    }
+
+    fn setemergencyaddress(
+        &self,
+        meta: Self::Metadata,
+        address: String,
+        confirm: Option<bool>,
+    ) -> jsonrpc_core::Result<serde_json::Value> {
+        stakeholder_only!(meta);
+        if confirm != Some(true) {
+            return Err(JsonRpcError::invalid_params(
+                "Changing the Emergency address requires 'confirm' to be set to true".to_string(),
+            ));
+        }
+
+        let mut revaultd = meta.rpc_utils.revaultd.write().unwrap();
+        let db_path = revaultd.db_file();
+
+        let address = Address::from_str(&address).map_err(|e| {
+            JsonRpcError::invalid_params(format!("Invalid address '{}': {}", address, e))
+        })?;
+        let network = revaultd.bitcoind_config.network;
+        if address.network != network {
+            return Err(JsonRpcError::invalid_params(format!(
+                "Address '{}' is for '{}' but bitcoind is on '{}'",
+                address, address.network, network
+            )));
+        }
+        let new_address = EmergencyAddress::from(address).map_err(|_| {
+            JsonRpcError::invalid_params("The Emergency address must be a P2WSH".to_string())
+        })?;
+        let old_address = revaultd
+            .emergency_address
+            .clone()
+            .expect("The JSONRPC API checked we were a stakeholder");
+        if new_address == old_address {
+            return Err(JsonRpcError::invalid_params(format!(
+                "'{}' is already the Emergency address",
+                new_address
+            )));
+        }
+
+        let wallet_id = revaultd
+            .wallet_id
+            .expect("Wallet id is set at startup in setup_db()");
+        db_update_emergency_address(&db_path, wallet_id, &new_address)
+            .map_err(|e| internal_error!(e))?;
+        revaultd.emergency_address = Some(new_address.clone());
+        log::info!(
+            "Rotated the Emergency address from '{}' to '{}'",
+            old_address,
+            new_address
+        );
+
+        // The presigned transactions of existing vaults are left untouched, report which
+        // ones still pay to the previous address.
+        let old_spk = old_address.address().script_pubkey();
+        let mut vaults = Vec::new();
+        for vault in db_vaults(&db_path).map_err(|e| internal_error!(e))? {
+            if matches!(
+                vault.status,
+                VaultStatus::Canceled
+                    | VaultStatus::EmergencyVaulted
+                    | VaultStatus::Aborted
+                    | VaultStatus::UnvaultEmergencyVaulted
+                    | VaultStatus::Spent
+            ) {
+                continue;
+            }
+            if let Some((_, emer_tx)) =
+                db_emer_transaction(&db_path, revaultd.db_key.as_ref(), vault.id)
+                    .map_err(|e| internal_error!(e))?
+            {
+                if emer_tx
+                    .tx()
+                    .output
+                    .iter()
+                    .any(|txo| txo.script_pubkey == old_spk)
+                {
+                    vaults.push(vault.deposit_outpoint.to_string());
+                }
+            }
+        }
+
+        Ok(json!({
+            "previous_address": old_address.to_string(),
+            "emergency_address": new_address.to_string(),
+            "vaults": vaults,
+        }))
+    }
+
+    fn getdepositproof(
+        &self,
+        meta: Self::Metadata,
+        outpoint: OutPoint,
+    ) -> jsonrpc_core::Result<serde_json::Value> {
+        let vault = {
+            let revaultd = meta.rpc_utils.revaultd.read().unwrap();
+            db_vault_by_deposit(&revaultd.db_file(), &outpoint)
+                .map_err(|e| internal_error!(e))?
+                .ok_or_else(|| unknown_outpoint!(outpoint))?
+        };
+        if matches!(vault.status, VaultStatus::Unconfirmed) {
+            return Err(JsonRpcError::invalid_params(format!(
+                "Deposit '{}' is not confirmed",
+                outpoint
+            )));
+        }
+
+        let (block_hash, proof) = bitcoind_txout_proof(
+            &meta.rpc_utils.bitcoind_tx,
+            outpoint.txid,
+            vault.blockheight,
+        )
+        .map_err(|e| internal_error!(e))?;
+
+        Ok(json!({
+            "blockhash": block_hash.to_string(),
+            "blockheight": vault.blockheight,
+            "proof": proof,
+        }))
+    }
+
+    fn getfeereserve(
+        &self,
+        meta: Self::Metadata,
+        feerate_vb: Option<u64>,
+        conf_target: Option<u32>,
+    ) -> jsonrpc_core::Result<serde_json::Value> {
+        let feerate_vb = feerate_or_estimate(&meta, feerate_vb, conf_target)?;
+        if feerate_vb < 1 {
+            return Err(JsonRpcError::invalid_params(
+                "Feerate can't be <1".to_string(),
+            ));
+        }
+
+        let (vaults, reserve) = fee_reserve(&meta.rpc_utils.revaultd.read().unwrap(), feerate_vb)
+            .map_err(|e| internal_error!(e))?;
+
+        Ok(json!({
+            "feerate": feerate_vb,
+            "vaults": vaults,
+            "reserve": reserve,
+        }))
+    }
+
+    fn getvaulthistory(
+        &self,
+        meta: Self::Metadata,
+        outpoint: OutPoint,
+    ) -> jsonrpc_core::Result<serde_json::Value> {
+        let revaultd = meta.rpc_utils.revaultd.read().unwrap();
+        let db_path = revaultd.db_file();
+        let vault = db_vault_by_deposit(&db_path, &outpoint)
+            .map_err(|e| internal_error!(e))?
+            .ok_or_else(|| unknown_outpoint!(outpoint))?;
+        let history =
+            db_vault_status_history(&db_path, vault.id).map_err(|e| internal_error!(e))?;
+        if history.is_empty() {
+            return Err(unknown_outpoint!(outpoint));
+        }
+
+        let history: Vec<serde_json::Value> = history
+            .into_iter()
+            .map(|change| {
+                json!({
+                    "status": change.status.to_string(),
+                    "blockheight": change.blockheight,
+                    "timestamp": change.timestamp,
+                })
+            })
+            .collect();
+
+        Ok(json!({ "history": history }))
+    }
+
+    fn setmaintenance(
+        &self,
+        meta: Self::Metadata,
+        enabled: bool,
+    ) -> jsonrpc_core::Result<serde_json::Value> {
+        let revaultd = meta.rpc_utils.revaultd.read().unwrap();
+        let wallet_id = revaultd
+            .wallet_id
+            .expect("Wallet id is set at startup in setup_db()");
+
+        db_set_maintenance(&revaultd.db_file(), wallet_id, enabled)
+            .map_err(|e| internal_error!(e))?;
+        revaultd.maintenance.store(enabled, Ordering::Relaxed);
+        if enabled {
+            log::info!("Maintenance mode enabled, not broadcasting any transaction");
+        } else {
+            log::info!("Maintenance mode disabled, resuming broadcasts");
+        }
+
+        Ok(json!({ "maintenance": enabled }))
+    }
+
+    fn listwatchtowers(&self, meta: Self::Metadata) -> jsonrpc_core::Result<serde_json::Value> {
+        stakeholder_only!(meta);
+        let revaultd = meta.rpc_utils.revaultd.read().unwrap();
+        let watchtowers = watchtowers_sync_status(&revaultd).map_err(|e| internal_error!(e))?;
+
+        Ok(json!({ "watchtowers": watchtowers }))
+    }
+
+    fn backupdb(
+        &self,
+        meta: Self::Metadata,
+        path: PathBuf,
+    ) -> jsonrpc_core::Result<serde_json::Value> {
+        // We may not be running from the directory they expect
+        if !path.is_absolute() {
+            return Err(JsonRpcError::invalid_params(
+                "The backup path must be absolute".to_string(),
+            ));
+        }
+        if path.exists() {
+            return Err(JsonRpcError::invalid_params(format!(
+                "There is already a file at '{}'",
+                path.display()
+            )));
+        }
+        // Don't hold the lock while copying
+        let db_path = meta.rpc_utils.revaultd.read().unwrap().db_file();
+
+        db_backup(&db_path, &path).map_err(|e| internal_error!(e))?;
+        log::info!("Backed up the database to '{}'", path.display());
+
+        Ok(json!({ "path": path }))
+    }
 }
@@ -7,13 +7,14 @@ use crate::{
         api::{JsonRpcMetaData, RpcApi, RpcImpl},
         UserRole,
     },
+    signals::{reload_requested, shutdown_requested},
 };
 use common::assume_some;
 
 use std::{
     collections::{HashMap, VecDeque},
     io::{self, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
     process,
     sync::{Arc, RwLock},
     thread,
@@ -24,14 +25,24 @@ use mio::{
     net::{UnixListener, UnixStream},
     Events, Interest, Poll, Token,
 };
+#[cfg(not(windows))]
+use std::time::{Duration, Instant};
 #[cfg(windows)]
 use uds_windows::{UnixListener, UnixStream};
 
-use jsonrpc_core::{futures::Future, Call, MethodCall, Response};
+use jsonrpc_core::{futures::Future, Call, MethodCall, Output, Response};
 
 // Maximum number of concurrent handlers for incoming RPC commands
 const MAX_HANDLER_THREADS: usize = 4;
 
+// How long we may block waiting for events before checking whether we were told to shut down
+#[cfg(not(windows))]
+const SHUTDOWN_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+// Once shutting down, how long we keep trying to send the pending responses before giving up
+#[cfg(not(windows))]
+const SHUTDOWN_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
 // Remove trailing newlines from utf-8 byte stream
 fn trimmed(mut vec: Vec<u8>, bytes_read: usize) -> Vec<u8> {
     vec.truncate(bytes_read);
@@ -39,7 +50,7 @@ fn trimmed(mut vec: Vec<u8>, bytes_read: usize) -> Vec<u8> {
     // Until there is some whatever-newline character, pop.
     while let Some(byte) = vec.last() {
         // Of course, we assume utf-8
-        if byte < &0x0a || byte > &0x0d {
+        if !(&0x0a..=&0x0d).contains(&byte) {
             break;
         }
         vec.pop();
@@ -140,12 +151,55 @@ fn write_byte_stream(stream: &mut UnixStream, resp: Vec<u8>) -> Result<Option<Ve
 #[cfg(not(windows))]
 type ConnectionMap = HashMap<Token, (UnixStream, Arc<RwLock<VecDeque<Vec<u8>>>>)>;
 
+// Parameters whose name contains any of these are never logged
+const SENSITIVE_PARAMS: &[&str] = &["passphrase", "password", "secret", "seed", "xpriv"];
+
+// Replace the value of any sensitive named parameter. None of our commands take secrets as
+// positional parameters.
+fn redacted(params: serde_json::Value) -> serde_json::Value {
+    match params {
+        serde_json::Value::Object(map) => map
+            .into_iter()
+            .map(|(key, value)| {
+                let lowercase_key = key.to_lowercase();
+                if SENSITIVE_PARAMS.iter().any(|s| lowercase_key.contains(s)) {
+                    (key, serde_json::Value::String("<redacted>".to_string()))
+                } else {
+                    (key, redacted(value))
+                }
+            })
+            .collect(),
+        serde_json::Value::Array(values) => values.into_iter().map(redacted).collect(),
+        value => value,
+    }
+}
+
 fn handle_single_request(
     jsonrpc_io: Arc<RwLock<jsonrpc_core::MetaIoHandler<JsonRpcMetaData>>>,
     metadata: JsonRpcMetaData,
     resp_queue: Arc<RwLock<VecDeque<Vec<u8>>>>,
     message: MethodCall,
 ) {
+    // For auditing purposes, we may have been asked to log every request
+    let log_level = metadata
+        .rpc_utils
+        .revaultd
+        .read()
+        .unwrap()
+        .rpc_log_level
+        .to_level();
+    let method = message.method.clone();
+    if let Some(level) = log_level {
+        let params = serde_json::to_value(&message.params)
+            .expect("jsonrpc_core says: This should never fail.");
+        log::log!(
+            level,
+            "JSONRPC request '{}' with params '{}'",
+            method,
+            redacted(params)
+        );
+    }
+
     let res = assume_some!(
         jsonrpc_io
             .read()
@@ -155,6 +209,18 @@ fn handle_single_request(
             .expect("jsonrpc_core says: Handler calls can never fail."),
         "This is a method call, there is always a response."
     );
+    if let Some(level) = log_level {
+        match res {
+            Output::Success(_) => log::log!(level, "JSONRPC request '{}' succeeded", method),
+            Output::Failure(ref f) => log::log!(
+                level,
+                "JSONRPC request '{}' failed with code '{}': '{}'",
+                method,
+                f.error.code.code(),
+                f.error.message
+            ),
+        }
+    }
     let resp = Response::Single(res);
     let resp_bytes = serde_json::to_vec(&resp).expect("jsonrpc_core says: This should never fail.");
 
@@ -214,9 +280,13 @@ fn read_handle_request(
                             .unwrap();
                     }
 
-                    handler_threads.push_back(thread::spawn(move || {
-                        handle_single_request(t_io_handler, t_meta, t_queue, m)
-                    }));
+                    handler_threads.push_back(
+                        thread::Builder::new()
+                            .name("jsonrpc-handler".to_string())
+                            .spawn(move || {
+                                handle_single_request(t_io_handler, t_meta, t_queue, m)
+                            })?,
+                    );
                 }
             }
             // Parsing error? Assume it's a message we'll be able to read later.
@@ -269,8 +339,33 @@ fn mio_loop(
         .registry()
         .register(&mut listener, JSONRPC_SERVER, Interest::READABLE)?;
 
+    // When we noticed we were told to shut down
+    let mut shutdown_time: Option<Instant> = None;
+
     loop {
-        poller.poll(&mut events, None)?;
+        // A termination signal is handled the same way as the 'stop' command
+        if !metadata.is_shutdown() && shutdown_requested() {
+            log::info!("Received a termination signal, stopping revaultd");
+            if let Err(e) = metadata.stop_daemon() {
+                log::error!("Error stopping revaultd: '{}'", e);
+                metadata.shutdown();
+            }
+        }
+        if reload_requested() {
+            log::info!("Received a reload signal, reading the configuration file again");
+            if let Err(e) = metadata.reload_config() {
+                log::error!("Error reloading the configuration: '{}'", e);
+            }
+        }
+
+        // Don't block forever, so we can check for shutdown even if no client is talking to us.
+        if let Err(e) = poller.poll(&mut events, Some(SHUTDOWN_CHECK_INTERVAL)) {
+            // We were interrupted by a signal, just poll again.
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
 
         for event in &events {
             // A connection was established; loop to process all the messages
@@ -299,15 +394,19 @@ fn mio_loop(
 
                             read_cache_map.insert(curr_token, Vec::with_capacity(1024));
                         }
-                        Err(e) => {
+                        Err(e) => match e.kind() {
                             // Ok; next time then!
-                            if e.kind() == io::ErrorKind::WouldBlock {
-                                break;
+                            io::ErrorKind::WouldBlock => break,
+                            // Try again right away
+                            io::ErrorKind::Interrupted => continue,
+                            // They gave up before we accepted, no big deal.
+                            io::ErrorKind::ConnectionAborted => {
+                                log::debug!("Connection aborted before being accepted.");
+                                continue;
                             }
-
                             // This one is not expected!
-                            return Err(e);
-                        }
+                            _ => return Err(e),
+                        },
                     }
                 }
             } else if connections_map.contains_key(&event.token()) {
@@ -369,18 +468,29 @@ fn mio_loop(
                 if event.is_read_closed() || event.is_error() {
                     log::trace!("Dropping connection for {:?}", event.token());
                     connections_map.remove(&event.token());
-
-                    // If this was the last connection alive and we are shutting down,
-                    // actually shut down.
-                    if metadata.is_shutdown() && connections_map.is_empty() {
-                        while let Some(t) = handler_threads.pop_front() {
-                            t.join().unwrap();
-                        }
-                        return Ok(());
-                    }
                 }
             }
         }
+
+        // If we are shutting down, stop as soon as we answered all the requests we got. We don't
+        // wait for the clients to hang up, as an idle one would otherwise keep us around forever.
+        if metadata.is_shutdown() {
+            let shutdown_time = *shutdown_time.get_or_insert_with(Instant::now);
+            while let Some(t) = handler_threads.pop_front() {
+                t.join().unwrap();
+            }
+
+            let pending_resp = connections_map
+                .values()
+                .any(|(_, resp_queue)| !resp_queue.read().unwrap().is_empty());
+            if !pending_resp {
+                return Ok(());
+            }
+            if shutdown_time.elapsed() > SHUTDOWN_FLUSH_TIMEOUT {
+                log::warn!("Shutting down the JSONRPC server with unsent responses.");
+                return Ok(());
+            }
+        }
     }
 }
 
@@ -430,14 +540,26 @@ fn windows_loop(
     Ok(())
 }
 
+// Abstract sockets are not on the filesystem, their name starts with a NUL byte.
+#[cfg(unix)]
+fn is_abstract(socket_path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    socket_path.as_os_str().as_bytes().first() == Some(&0)
+}
+
+#[cfg(not(unix))]
+fn is_abstract(_: &Path) -> bool {
+    false
+}
+
 // Tries to bind to the socket, if we are told it's already in use try to connect
 // to check there is actually someone listening and it's not a leftover from a
-// crash.
+// crash. An abstract socket is released as soon as it's closed, so it can't be a leftover.
 fn bind(socket_path: PathBuf) -> Result<UnixListener, io::Error> {
     match UnixListener::bind(&socket_path) {
         Ok(l) => Ok(l),
         Err(e) => {
-            if e.kind() == io::ErrorKind::AddrInUse {
+            if e.kind() == io::ErrorKind::AddrInUse && !is_abstract(&socket_path) {
                 return match UnixStream::connect(&socket_path) {
                     Ok(_) => Err(e),
                     Err(_) => {
@@ -454,7 +576,8 @@ fn bind(socket_path: PathBuf) -> Result<UnixListener, io::Error> {
     }
 }
 
-/// Bind to the UDS at `socket_path`
+/// Bind to the UDS at `socket_path`, which may be an abstract socket name prefixed with a NUL
+/// byte on Linux.
 pub fn rpcserver_setup(socket_path: PathBuf) -> Result<UnixListener, io::Error> {
     // Create the socket with RW permissions only for the user
     // FIXME: find a workaround for Windows...
@@ -488,7 +611,9 @@ pub fn rpcserver_loop(
 
 #[cfg(test)]
 mod tests {
-    use super::{read_bytes_from_stream, rpcserver_loop, rpcserver_setup, trimmed, UserRole};
+    use super::{
+        read_bytes_from_stream, redacted, rpcserver_loop, rpcserver_setup, trimmed, UserRole,
+    };
     use crate::utils::test_utils::{dummy_rpcutil, test_datadir};
 
     use std::{
@@ -516,7 +641,7 @@ mod tests {
         let socket = rpcserver_setup(rpc_socket_path.clone()).unwrap();
         let server_loop_thread = thread::spawn(move || {
             rpcserver_loop(socket, UserRole::Stakeholder, rpcutils).unwrap_or_else(|e| {
-                panic!("Error in JSONRPC server event loop: {}", e.to_string());
+                panic!("Error in JSONRPC server event loop: {}", e);
             })
         });
 
@@ -540,7 +665,7 @@ mod tests {
         let invalid_msg =
             String::from(r#"{"jsonrpc": "2.0", "id": 0, "method": "stop", "params": {"a": "b"}}"#);
         let mut response = vec![0; 256];
-        sock.write(invalid_msg.as_bytes()).unwrap();
+        sock.write_all(invalid_msg.as_bytes()).unwrap();
         let read = sock.read(&mut response).unwrap();
         assert_eq!(
             String::from_utf8(trimmed(response, read)).unwrap(),
@@ -557,7 +682,7 @@ mod tests {
                 r#"{"jsonrpc": "2.0", "id": 1, "method": "aaa", "params": []} {"jsonrpc": "2.0", "id": 2, "#,
             );
             let mut response = vec![0; 256];
-            sock.write(msg.as_bytes()).unwrap();
+            sock.write_all(msg.as_bytes()).unwrap();
             let read = sock.read(&mut response).unwrap();
             assert_eq!(
             response[..read],
@@ -570,7 +695,7 @@ mod tests {
             // Write the other half of the message
             let msg = String::from(r#" "method": "bbbb", "params": []}"#);
             let mut response = vec![0; 256];
-            sock.write(msg.as_bytes()).unwrap();
+            sock.write_all(msg.as_bytes()).unwrap();
             let read = sock.read(&mut response).unwrap();
             assert_eq!(
             response[..read],
@@ -583,7 +708,7 @@ mod tests {
 
         // Tell it to stop, should send us a Shutdown message
         let msg = String::from(r#"{"jsonrpc": "2.0", "id": 0, "method": "stop", "params": []}"#);
-        sock.write(msg.as_bytes()).unwrap();
+        sock.write_all(msg.as_bytes()).unwrap();
         sock.flush().unwrap();
         drop(sock);
         server_loop_thread.join().unwrap();
@@ -591,6 +716,58 @@ mod tests {
         fs::remove_dir_all(&datadir).unwrap();
     }
 
+    // An idle client must not prevent us from shutting down
+    #[cfg(not(windows))]
+    #[test]
+    fn shutdown_with_idle_client() {
+        let datadir = test_datadir();
+        let rpcutils = dummy_rpcutil(datadir.clone(), UserRole::ManagerStakeholder);
+        let mut rpc_socket_path = rpcutils.revaultd.read().unwrap().data_dir.clone();
+        rpc_socket_path.push("revaultd_rpc");
+
+        let socket = rpcserver_setup(rpc_socket_path.clone()).unwrap();
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            rpcserver_loop(socket, UserRole::Stakeholder, rpcutils).unwrap();
+            done_tx.send(()).unwrap();
+        });
+
+        // This one never says anything, nor hangs up
+        let _idle_sock = UnixStream::connect(&rpc_socket_path).unwrap();
+
+        let mut sock = UnixStream::connect(&rpc_socket_path).unwrap();
+        let msg = String::from(r#"{"jsonrpc": "2.0", "id": 0, "method": "stop", "params": []}"#);
+        sock.write_all(msg.as_bytes()).unwrap();
+        let mut response = vec![0; 256];
+        let read = sock.read(&mut response).unwrap();
+        assert_eq!(
+            String::from_utf8(trimmed(response, read)).unwrap(),
+            String::from(r#"{"jsonrpc":"2.0","result":null,"id":0}"#)
+        );
+
+        // Both connections are still open, yet the loop must exit
+        done_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        fs::remove_dir_all(&datadir).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn abstract_socket_bind() {
+        let socket_path =
+            std::path::PathBuf::from(format!("\0revaultd_test_{}", std::process::id()));
+
+        // It's not on the filesystem, and we must not try to remove it if it's in use
+        let listener = rpcserver_setup(socket_path.clone()).unwrap();
+        assert!(!socket_path.exists());
+        let err = rpcserver_setup(socket_path.clone()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+
+        // But it's released as soon as it's closed
+        drop(listener);
+        rpcserver_setup(socket_path).unwrap();
+    }
+
     #[test]
     fn test_bytes_reader() {
         let samples = [vec![22; 22], vec![1; 522], vec![189; 28903]];
@@ -602,4 +779,24 @@ mod tests {
             assert_eq!(&res.unwrap().unwrap(), data);
         }
     }
+
+    #[test]
+    fn test_redacted_params() {
+        let params = serde_json::json!({
+            "outpoint": "a:0",
+            "Passphrase": "hunter2",
+            "nested": [{"seed": "abandon", "psbt": "cHNidP8"}],
+        });
+        assert_eq!(
+            redacted(params),
+            serde_json::json!({
+                "outpoint": "a:0",
+                "Passphrase": "<redacted>",
+                "nested": [{"seed": "<redacted>", "psbt": "cHNidP8"}],
+            })
+        );
+
+        let params = serde_json::json!(["cHNidP8", 12]);
+        assert_eq!(redacted(params.clone()), params);
+    }
 }
@@ -4,29 +4,36 @@ mod database;
 mod jsonrpc;
 mod revaultd;
 mod sigfetcher;
+mod signals;
 mod threadmessages;
 mod utils;
+mod watchtower;
 
 use crate::{
     bitcoind::{bitcoind_main_loop, start_bitcoind},
-    control::RpcUtils,
-    database::actions::setup_db,
+    control::{verify_stored_signatures, RpcUtils},
+    database::{
+        actions::{db_restore, db_rotate_passphrase, setup_db},
+        migrations::check_db_schema,
+        DB_VERSION,
+    },
     jsonrpc::{
         server::{rpcserver_loop, rpcserver_setup},
         UserRole,
     },
-    revaultd::RevaultD,
+    revaultd::{read_passphrase_file, RevaultD},
     sigfetcher::signature_fetcher_loop,
+    signals::setup_signal_handlers,
 };
 use common::{assume_ok, config::Config};
 use revault_net::sodiumoxide;
 use revault_tx::bitcoin::hashes::hex::ToHex;
 
 use std::{
-    env,
+    env, fs,
     io::{self, Write},
     panic,
-    path::PathBuf,
+    path::{Path, PathBuf},
     process,
     sync::{mpsc, Arc, RwLock},
     thread, time,
@@ -34,18 +41,114 @@ use std::{
 
 use daemonize_simple::Daemonize;
 
-fn parse_args(args: Vec<String>) -> Option<PathBuf> {
-    if args.len() == 1 {
-        return None;
+struct Args {
+    conf_file: Option<PathBuf>,
+    /// Only check the database can be used by this version, without starting
+    check_db: bool,
+    /// Re-encrypt the database with the passphrase from this file, without starting
+    rotate_db_passphrase: Option<PathBuf>,
+    /// Replace the database with this backup before starting
+    restore: Option<PathBuf>,
+}
+
+fn parse_args(args: Vec<String>) -> Args {
+    let mut parsed = Args {
+        conf_file: None,
+        check_db: false,
+        rotate_db_passphrase: None,
+        restore: None,
+    };
+
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--conf" if parsed.conf_file.is_none() && i + 1 < args.len() => {
+                parsed.conf_file = Some(PathBuf::from(args[i + 1].to_owned()));
+                i += 2;
+            }
+            "--check-db" if !parsed.check_db => {
+                parsed.check_db = true;
+                i += 1;
+            }
+            "--rotate-db-passphrase"
+                if parsed.rotate_db_passphrase.is_none() && i + 1 < args.len() =>
+            {
+                parsed.rotate_db_passphrase = Some(PathBuf::from(args[i + 1].to_owned()));
+                i += 2;
+            }
+            "--restore" if parsed.restore.is_none() && i + 1 < args.len() => {
+                parsed.restore = Some(PathBuf::from(args[i + 1].to_owned()));
+                i += 2;
+            }
+            _ => {
+                eprintln!("Unknown arguments '{:?}'.", args);
+                eprintln!(
+                    "Only '--conf <configuration file path>', '--check-db', \
+                     '--rotate-db-passphrase <new passphrase file path>' and \
+                     '--restore <database backup path>' are supported."
+                );
+                process::exit(1);
+            }
+        }
     }
 
-    if args.len() != 3 {
-        eprintln!("Unknown arguments '{:?}'.", args);
-        eprintln!("Only '--conf <configuration file path>' is supported.");
-        process::exit(1);
+    parsed
+}
+
+// Whether a process with this PID currently exists.
+#[cfg(unix)]
+fn process_is_alive(pid: i32) -> bool {
+    if pid <= 0 {
+        return false;
     }
+    // Signal 0 performs the existence and permission checks without sending anything. EPERM
+    // means the process exists but belongs to another user.
+    let res = unsafe { libc::kill(pid, 0) };
+    res == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
 
-    Some(PathBuf::from(args[2].to_owned()))
+#[cfg(not(unix))]
+fn process_is_alive(_pid: i32) -> bool {
+    false
+}
+
+/// Check for a PID file left over by a previous run before daemonizing. Refuses to start if the
+/// process it references is still running, otherwise removes the stale file.
+fn check_pid_file(pid_file: &Path) -> Result<(), String> {
+    let content = match fs::read_to_string(pid_file) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => {
+            return Err(format!(
+                "Reading PID file at '{}': {}",
+                pid_file.display(),
+                e
+            ))
+        }
+    };
+
+    match content.trim().parse::<i32>() {
+        Ok(pid) if process_is_alive(pid) => {
+            return Err(format!(
+                "Another instance of revaultd seems to be running with PID {} (PID file at '{}')",
+                pid,
+                pid_file.display()
+            ));
+        }
+        Ok(pid) => log::warn!(
+            "Removing stale PID file at '{}': process {} is not running",
+            pid_file.display(),
+            pid
+        ),
+        Err(_) => log::warn!(
+            "Removing unreadable PID file at '{}' (content: '{}')",
+            pid_file.display(),
+            content.trim()
+        ),
+    }
+
+    fs::remove_file(pid_file)
+        .map_err(|e| format!("Removing PID file at '{}': {}", pid_file.display(), e))
 }
 
 fn daemon_main(mut revaultd: RevaultD) {
@@ -56,10 +159,30 @@ fn daemon_main(mut revaultd: RevaultD) {
         _ => unreachable!(),
     };
 
+    // A previous instance may not have been shut down cleanly
+    assume_ok!(revaultd.remove_ready_file(), "Removing leftover ready file");
+
     // First and foremost
     log::info!("Setting up database");
     assume_ok!(setup_db(&mut revaultd), "Error setting up database");
 
+    if revaultd.verify_signatures_on_startup {
+        log::info!("Verifying the presigned transactions in database");
+        let checked = assume_ok!(
+            verify_stored_signatures(&mut revaultd),
+            "Error verifying presigned transactions"
+        );
+        if revaultd.invalid_signatures.is_empty() {
+            log::info!("Presigned transactions of {} vaults verified", checked);
+        } else {
+            log::error!(
+                "Presigned transactions of {} out of {} vaults failed verification",
+                revaultd.invalid_signatures.len(),
+                checked
+            );
+        }
+    }
+
     log::info!("Setting up bitcoind connection");
     let bitcoind = assume_ok!(start_bitcoind(&mut revaultd), "Error setting up bitcoind");
 
@@ -83,35 +206,45 @@ fn daemon_main(mut revaultd: RevaultD) {
 
     let revaultd = Arc::new(RwLock::new(revaultd));
     let bit_revaultd = revaultd.clone();
-    let bitcoind_thread = thread::spawn(move || {
-        assume_ok!(
-            bitcoind_main_loop(bitcoind_rx, bit_revaultd, Arc::new(RwLock::new(bitcoind))),
-            "Error in bitcoind main loop"
-        );
-    });
+    let bitcoind_thread = assume_ok!(
+        thread::Builder::new()
+            .name("bitcoind".to_string())
+            .spawn(move || {
+                assume_ok!(
+                    bitcoind_main_loop(bitcoind_rx, bit_revaultd, Arc::new(RwLock::new(bitcoind))),
+                    "Error in bitcoind main loop"
+                );
+            }),
+        "Spawning bitcoind thread"
+    );
 
     let sigfetcher_revaultd = revaultd.clone();
-    let sigfetcher_thread = thread::spawn(move || {
-        assume_ok!(
-            signature_fetcher_loop(sigfetcher_rx, sigfetcher_revaultd),
-            "Error in signature fetcher thread"
-        )
-    });
+    let sigfetcher_thread = assume_ok!(
+        thread::Builder::new()
+            .name("sigfetcher".to_string())
+            .spawn(move || {
+                assume_ok!(
+                    signature_fetcher_loop(sigfetcher_rx, sigfetcher_revaultd),
+                    "Error in signature fetcher thread"
+                )
+            }),
+        "Spawning signature fetcher thread"
+    );
 
     log::info!(
         "revaultd started on network {}",
         revaultd.read().unwrap().bitcoind_config.network
     );
 
+    // From now on, a termination signal makes the RPC server loop shut us down cleanly.
+    assume_ok!(setup_signal_handlers(), "Setting up signal handlers");
+
     // Handle RPC commands until we die.
-    let bitcoind_thread = Arc::new(RwLock::new(bitcoind_thread));
-    let sigfetcher_thread = Arc::new(RwLock::new(sigfetcher_thread));
+    let rpc_revaultd = revaultd.clone();
     let rpc_utils = RpcUtils {
         revaultd,
         bitcoind_tx,
-        bitcoind_thread: bitcoind_thread.clone(),
         sigfetcher_tx,
-        sigfetcher_thread: sigfetcher_thread.clone(),
     };
     assume_ok!(
         rpcserver_loop(socket, user_role, rpc_utils),
@@ -119,16 +252,17 @@ fn daemon_main(mut revaultd: RevaultD) {
     );
 
     // If the RPC server loop stops, we've been told to shutdown!
-    let bitcoind_thread = unsafe { Arc::into_raw(bitcoind_thread).read().into_inner() };
-    let sigfetcher_thread = unsafe { Arc::into_raw(sigfetcher_thread).read().into_inner() };
-    bitcoind_thread
-        .unwrap()
-        .join()
-        .expect("Joining bitcoind thread");
-    sigfetcher_thread
-        .unwrap()
-        .join()
-        .expect("Joining sigfetcher thread");
+    bitcoind_thread.join().expect("Joining bitcoind thread");
+    sigfetcher_thread.join().expect("Joining sigfetcher thread");
+    if let Err(e) = rpc_revaultd.read().unwrap().remove_ready_file() {
+        log::error!("Error removing the ready file: {}", e);
+    }
+    if rpc_revaultd.read().unwrap().daemon {
+        let pid_file = rpc_revaultd.read().unwrap().pid_file();
+        if let Err(e) = fs::remove_file(&pid_file) {
+            log::error!("Error removing PID file at '{}': {}", pid_file.display(), e);
+        }
+    }
 
     // We are always logging to stdout, should it be then piped to the log file (if daemon) or
     // not. So just make sure that all messages were actually written.
@@ -141,7 +275,7 @@ fn setup_logger(log_level: log::LevelFilter) -> Result<(), fern::InitError> {
     let dispatcher = fern::Dispatch::new()
         .format(|out, message, record| {
             out.finish(format_args!(
-                "[{}][{}][{}] {}",
+                "[{}][{}][{}][{}] {}",
                 time::SystemTime::now()
                     .duration_since(time::UNIX_EPOCH)
                     .unwrap_or_else(|e| {
@@ -149,14 +283,17 @@ fn setup_logger(log_level: log::LevelFilter) -> Result<(), fern::InitError> {
                         time::Duration::from_secs(0)
                     })
                     .as_secs(),
+                thread::current().name().unwrap_or("unnamed"),
                 record.target(),
                 record.level(),
                 message
             ))
         })
-        .level(log_level);
+        // Filter through the global maximum level instead, which can be changed on reload
+        .level(log::LevelFilter::Trace);
 
     dispatcher.chain(std::io::stdout()).apply()?;
+    log::set_max_level(log_level);
 
     Ok(())
 }
@@ -173,19 +310,149 @@ fn setup_panic_hook() {
             .map(|l| l.line().to_string())
             .unwrap_or_else(|| "'unknown'".to_string());
 
+        let thread = thread::current();
+        let thread_name = thread.name().unwrap_or("unnamed");
+
         if let Some(s) = panic_info.payload().downcast_ref::<&str>() {
-            log::error!("panic occurred at line {} of file {}: {:?}", line, file, s);
+            log::error!(
+                "panic occurred in thread '{}' at line {} of file {}: {:?}",
+                thread_name,
+                line,
+                file,
+                s
+            );
         } else {
-            log::error!("panic occurred at line {} of file {}", line, file);
+            log::error!(
+                "panic occurred in thread '{}' at line {} of file {}",
+                thread_name,
+                line,
+                file
+            );
         }
 
         process::exit(1);
     }));
 }
 
+// Check the database could be used by this version of revaultd, without modifying it
+fn check_db_and_exit(revaultd: &RevaultD) -> ! {
+    let db_path = revaultd.db_file();
+    match check_db_schema(&db_path) {
+        Ok(version) if version < DB_VERSION => {
+            println!(
+                "Database at {:?} is valid. It is at version {} and will be upgraded to \
+                 version {} at startup.",
+                db_path, version, DB_VERSION
+            );
+            process::exit(0);
+        }
+        Ok(version) => {
+            println!("Database at {:?} is valid (version {}).", db_path, version);
+            process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("Invalid database at {:?}: {}", db_path, e);
+            process::exit(1);
+        }
+    }
+}
+
+// Read a line from the terminal without echoing it
+#[cfg(unix)]
+fn prompt_passphrase(prompt: &str) -> Result<String, String> {
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+        return Err("Not on a terminal".to_string());
+    }
+    eprint!("{}", prompt);
+
+    let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) } != 0 {
+        return Err(format!(
+            "Getting terminal attributes: {}",
+            io::Error::last_os_error()
+        ));
+    }
+    let original = unsafe { termios.assume_init() };
+    let mut no_echo = original;
+    no_echo.c_lflag &= !libc::ECHO;
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &no_echo) } != 0 {
+        return Err(format!(
+            "Setting terminal attributes: {}",
+            io::Error::last_os_error()
+        ));
+    }
+
+    let mut passphrase = String::new();
+    let res = io::stdin().read_line(&mut passphrase);
+    unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &original) };
+    eprintln!();
+    res.map_err(|e| format!("Reading passphrase: {}", e))?;
+
+    Ok(passphrase.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
+#[cfg(not(unix))]
+fn prompt_passphrase(_: &str) -> Result<String, String> {
+    Err("Not supported on this platform".to_string())
+}
+
+// Re-encrypt the database with a new passphrase. Must not be run while revaultd is running.
+fn rotate_db_passphrase_and_exit(mut revaultd: RevaultD, passphrase_file: &Path) -> ! {
+    if let Err(e) = check_pid_file(&revaultd.pid_file()) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    let new_passphrase = read_passphrase_file(passphrase_file).unwrap_or_else(|e| {
+        eprintln!("Reading new passphrase file: {}", e);
+        process::exit(1);
+    });
+
+    let db_path = revaultd.db_file();
+    let res =
+        setup_db(&mut revaultd).and_then(|_| db_rotate_passphrase(&mut revaultd, &new_passphrase));
+    match res {
+        Ok(()) => {
+            println!(
+                "Database at {:?} is now encrypted with the new passphrase. Don't forget to \
+                 update 'db_passphrase_file'.",
+                db_path
+            );
+            process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("Error rotating the database passphrase: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// Replace the database with a backup before starting. Must not be run while revaultd is running.
+fn restore_db(revaultd: &RevaultD, backup_path: &Path) {
+    check_pid_file(&revaultd.pid_file()).unwrap_or_else(|e| {
+        log::error!("{}", e);
+        process::exit(1);
+    });
+    match db_restore(revaultd, backup_path) {
+        Ok(Some(previous_db)) => log::info!(
+            "Restored the database from {:?}. The previous one was moved to {:?}.",
+            backup_path,
+            previous_db
+        ),
+        Ok(None) => log::info!("Restored the database from {:?}", backup_path),
+        Err(e) => {
+            log::error!("Error restoring the database from {:?}: {}", backup_path, e);
+            process::exit(1);
+        }
+    }
+}
+
 fn main() {
-    let args = env::args().collect();
-    let conf_file = parse_args(args);
+    let Args {
+        conf_file,
+        check_db,
+        rotate_db_passphrase,
+        restore,
+    } = parse_args(env::args().collect());
 
     // We use libsodium for Noise keys and Noise channels (through revault_net)
     sodiumoxide::init().unwrap_or_else(|_| {
@@ -193,7 +460,7 @@ fn main() {
         process::exit(1);
     });
 
-    let config = Config::from_file(conf_file).unwrap_or_else(|e| {
+    let config = Config::from_file(conf_file.clone()).unwrap_or_else(|e| {
         eprintln!("Error parsing config: {}", e);
         process::exit(1);
     });
@@ -202,10 +469,32 @@ fn main() {
         process::exit(1);
     });
     // FIXME: should probably be from_db(), would allow us to not use Option members
-    let revaultd = RevaultD::from_config(config).unwrap_or_else(|e| {
+    let mut revaultd = RevaultD::from_config(config).unwrap_or_else(|e| {
         log::error!("Error creating global state: {}", e);
         process::exit(1);
     });
+    revaultd.config_file = conf_file;
+    if check_db {
+        check_db_and_exit(&revaultd);
+    }
+
+    // We can't ask for it once daemonized
+    if revaultd.db_encryption && revaultd.db_passphrase.is_none() {
+        let passphrase = prompt_passphrase("Database passphrase: ").unwrap_or_else(|e| {
+            log::error!(
+                "Error asking for the database passphrase ({}). Set 'db_passphrase_file'.",
+                e
+            );
+            process::exit(1);
+        });
+        revaultd.db_passphrase = Some(passphrase);
+    }
+    if let Some(backup_path) = restore {
+        restore_db(&revaultd, &backup_path);
+    }
+    if let Some(passphrase_file) = rotate_db_passphrase {
+        rotate_db_passphrase_and_exit(revaultd, &passphrase_file);
+    }
 
     log::info!(
         "Using Noise static public key: '{}'",
@@ -219,6 +508,10 @@ fn main() {
     setup_panic_hook();
 
     if revaultd.daemon {
+        check_pid_file(&revaultd.pid_file()).unwrap_or_else(|e| {
+            log::error!("{}", e);
+            process::exit(1);
+        });
         let log_file = revaultd.log_file();
         let daemon = Daemonize {
             // TODO: Make this configurable for inits
@@ -238,3 +531,31 @@ fn main() {
 
     daemon_main(revaultd);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::check_pid_file;
+    use std::{fs, process};
+
+    #[cfg(unix)]
+    #[test]
+    fn pid_file_check() {
+        let pid_file = std::env::temp_dir().join(format!("revaultd-pidtest-{}.pid", process::id()));
+
+        // No PID file, nothing to do
+        check_pid_file(&pid_file).unwrap();
+
+        // Our own PID: another instance is running
+        fs::write(&pid_file, process::id().to_string()).unwrap();
+        check_pid_file(&pid_file).unwrap_err();
+        assert!(pid_file.exists());
+
+        // A process that can't exist or garbage: the file is stale and removed
+        fs::write(&pid_file, i32::MAX.to_string()).unwrap();
+        check_pid_file(&pid_file).unwrap();
+        assert!(!pid_file.exists());
+        fs::write(&pid_file, "not a pid").unwrap();
+        check_pid_file(&pid_file).unwrap();
+        assert!(!pid_file.exists());
+    }
+}
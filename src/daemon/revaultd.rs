@@ -1,13 +1,20 @@
-use common::config::{config_folder_path, BitcoindConfig, Config, ConfigError};
+use crate::{database::encryption::DbKey, threadmessages::EventBus};
+use common::config::{config_folder_path, BitcoindConfig, Config, ConfigError, StakeholderConfig};
 
 use std::{
+    cmp,
     collections::HashMap,
     convert::TryFrom,
     fmt, fs,
     io::{self, Read, Write},
     net::SocketAddr,
-    path::PathBuf,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
     str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     time,
     vec::Vec,
 };
@@ -19,10 +26,13 @@ use revault_net::{
 use revault_tx::{
     bitcoin::{
         secp256k1,
-        util::bip32::{ChildNumber, ExtendedPubKey},
-        Address, BlockHash, PublicKey as BitcoinPublicKey, Script, TxOut,
+        util::bip32::{ChildNumber, ExtendedPubKey, Fingerprint},
+        Address, BlockHash, OutPoint, PublicKey as BitcoinPublicKey, Script, TxOut,
+    },
+    miniscript::{
+        descriptor::{DescriptorPublicKey, DescriptorTrait},
+        policy::{semantic::Policy as SemanticPolicy, Liftable},
     },
-    miniscript::descriptor::{DescriptorPublicKey, DescriptorTrait},
     scripts::{
         CpfpDescriptor, DepositDescriptor, DerivedCpfpDescriptor, DerivedDepositDescriptor,
         DerivedUnvaultDescriptor, EmergencyAddress, UnvaultDescriptor,
@@ -72,6 +82,12 @@ pub enum VaultStatus {
     // TODO: At what depth do we forget it ?
     /// The spend transaction is confirmed
     Spent,
+    /// The unvault transaction was spent by a transaction that is neither one of the
+    /// revocation transactions nor a spend through the managers' path
+    UnexpectedSpend,
+    /// The deposit transaction was spent before the vault was secured, by a transaction that is
+    /// neither the Unvault nor the Emergency
+    Aborted,
 }
 
 impl TryFrom<u32> for VaultStatus {
@@ -95,6 +111,8 @@ impl TryFrom<u32> for VaultStatus {
             13 => Ok(Self::UnvaultEmergencyVaulted),
             14 => Ok(Self::Spending),
             15 => Ok(Self::Spent),
+            16 => Ok(Self::UnexpectedSpend),
+            17 => Ok(Self::Aborted),
             _ => Err(()),
         }
     }
@@ -121,6 +139,8 @@ impl FromStr for VaultStatus {
             "unvaultemergencyvaulted" => Ok(Self::UnvaultEmergencyVaulted),
             "spending" => Ok(Self::Spending),
             "spent" => Ok(Self::Spent),
+            "unexpectedspend" => Ok(Self::UnexpectedSpend),
+            "aborted" => Ok(Self::Aborted),
             _ => Err(()),
         }
     }
@@ -148,6 +168,8 @@ impl fmt::Display for VaultStatus {
                 Self::UnvaultEmergencyVaulted => "unvaultemergencyvaulted",
                 Self::Spending => "spending",
                 Self::Spent => "spent",
+                Self::UnexpectedSpend => "unexpectedspend",
+                Self::Aborted => "aborted",
             }
         )
     }
@@ -171,6 +193,13 @@ impl fmt::Display for KeyError {
 
 impl std::error::Error for KeyError {}
 
+/// Read a passphrase from this file, without the trailing newline
+pub fn read_passphrase_file(path: &Path) -> Result<String, io::Error> {
+    Ok(fs::read_to_string(path)?
+        .trim_end_matches(&['\r', '\n'][..])
+        .to_string())
+}
+
 // The communication keys are (for now) hot, so we just create it ourselves on first run.
 fn read_or_create_noise_key(secret_file: PathBuf) -> Result<NoisePrivKey, KeyError> {
     let mut noise_secret = NoisePrivKey([0; 32]);
@@ -193,7 +222,7 @@ fn read_or_create_noise_key(secret_file: PathBuf) -> Result<NoisePrivKey, KeyErr
         }
 
         let mut fd = options.open(secret_file).map_err(KeyError::WritingKey)?;
-        fd.write_all(&noise_secret.as_ref())
+        fd.write_all(noise_secret.as_ref())
             .map_err(KeyError::WritingKey)?;
     } else {
         let mut noise_secret_fd = fs::File::open(secret_file).map_err(KeyError::ReadingKey)?;
@@ -230,6 +259,40 @@ pub struct BlockchainTip {
     pub hash: BlockHash,
 }
 
+impl BlockchainTip {
+    /// Whether this is the dummy tip (height 0 and a null hash) a new database is created with,
+    /// before we first hear from bitcoind. Note that a tip at height 0 is not necessarily the
+    /// dummy one: it's the genesis block on a fresh regtest chain.
+    pub fn is_unset(&self) -> bool {
+        self.hash == BlockHash::default()
+    }
+}
+
+/// How the communication with the Coordinator went lately, as seen by the signature fetcher
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+pub struct CoordinatorHealth {
+    /// Timestamp of the last time we successfully fetched signatures from the Coordinator
+    pub last_success: Option<u64>,
+    /// How many polls failed in a row since then
+    pub consecutive_failures: u32,
+}
+
+/// The outcome of re-reading the configuration file
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct ConfigReload {
+    /// The settings that changed and are now in use
+    pub applied: Vec<&'static str>,
+    /// The settings that changed but only take effect after a restart
+    pub ignored: Vec<&'static str>,
+}
+
+/// How many addresses after the first unused one we watch by default
+pub const DEFAULT_GAP_LIMIT: u32 = 100;
+
+/// The first derivation index out of the descriptors' range. We can't derive hardened children of
+/// the xpubs, so the wildcard only ranges over the unhardened indexes.
+pub const DERIVATION_RANGE_END: u32 = 1 << 31;
+
 /// Our global state
 pub struct RevaultD {
     // Bitcoind stuff
@@ -239,6 +302,12 @@ pub struct RevaultD {
     pub tip: Option<BlockchainTip>,
     /// Minimum confirmations before considering a deposit as mature
     pub min_conf: u32,
+    /// Minimum confirmations before considering a Cancel as final
+    pub cancel_min_conf: u32,
+    /// Minimum confirmations before considering a block as final
+    pub tip_finality_depth: u32,
+    /// The range of feerates (in sat/vB) we accept for the revocation transactions
+    pub revocation_feerate_range: RangeInclusive<u64>,
 
     // Scripts stuff
     /// Who am i, and where am i in all this mess ?
@@ -246,6 +315,8 @@ pub struct RevaultD {
     pub our_man_xpub: Option<ExtendedPubKey>,
     /// The miniscript descriptor of vault's outputs scripts
     pub deposit_descriptor: DepositDescriptor,
+    /// The fingerprints of the stakeholders' keys we were told to expect in the deposit descriptor
+    pub deposit_fingerprints: Option<Vec<Fingerprint>>,
     /// The miniscript descriptor of unvault's outputs scripts
     pub unvault_descriptor: UnvaultDescriptor,
     /// The miniscript descriptor of CPFP output scripts (in unvault and spend transaction)
@@ -290,9 +361,178 @@ pub struct RevaultD {
     pub data_dir: PathBuf,
     /// Should we run as a daemon? (Default: yes)
     pub daemon: bool,
+    /// Above how many entries should a listing RPC command truncate its result
+    pub rpc_max_entries: usize,
+    /// At which level to log every JSONRPC request and response, if at all
+    pub rpc_log_level: log::LevelFilter,
+    /// How many deposit addresses we watch ahead to hand them out at once
+    pub precreate_deposit_addresses: u32,
+    /// Whether to check the presigned transactions in database at startup
+    pub verify_signatures_on_startup: bool,
+    /// Whether the database is in WAL journal mode
+    pub sqlite_wal_mode: bool,
+    /// Whether to encrypt the transactions stored in database
+    pub db_encryption: bool,
+    /// The passphrase the database key is derived from, if it is encrypted. Read from the
+    /// configured file, or asked on the terminal at startup.
+    pub db_passphrase: Option<String>,
+    /// The key derived from this passphrase, to pass to the database functions reading or
+    /// writing transactions. Set when setting up the database.
+    pub db_key: Option<DbKey>,
+    /// The deposit outpoints of the vaults whose presigned transactions failed this check
+    pub invalid_signatures: Vec<OutPoint>,
+    /// The number of deposits in database and of deposit utxos in the wallet, if they didn't
+    /// match at startup
+    pub deposits_discrepancy: Option<(usize, usize)>,
+    /// Bind the JSONRPC server to this abstract socket instead of the socket file
+    pub rpc_abstract_socket: Option<String>,
+    /// The file signaling to others we are ready
+    pub ready_file: Option<PathBuf>,
+    /// Whether we must refuse to broadcast any transaction. Shared with the bitcoind
+    /// connection.
+    pub maintenance: Arc<AtomicBool>,
+    /// The changes to our view of the chain, published by the bitcoind poller thread
+    pub events: Arc<EventBus>,
+    /// Updated by the signature fetcher thread
+    pub coordinator_health: Arc<Mutex<CoordinatorHealth>>,
+    /// The configuration file we were started with, if not the default one. Read again on
+    /// reload.
+    pub config_file: Option<PathBuf>,
+    /// The configuration we are running with, to tell what changed on reload
+    pub config: Config,
     // TODO: servers connection stuff
 }
 
+// The name of the JSONRPC socket file, in the data directory
+const RPC_SOCKET_FILE_NAME: &str = "revaultd_rpc";
+
+// The maximum length of a Unix socket path: the size of 'sun_path' minus the terminating NUL byte.
+#[cfg(not(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd")))]
+const MAX_SOCKET_PATH_LEN: usize = 107;
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd"))]
+const MAX_SOCKET_PATH_LEN: usize = 103;
+
+// Binding to a socket path that's too long fails with a confusing error, if it is not silently
+// truncated. Catch it early.
+fn check_socket_path_len(socket_path: &Path) -> Result<(), ConfigError> {
+    let len = socket_path.as_os_str().len();
+    if len > MAX_SOCKET_PATH_LEN {
+        return Err(ConfigError(format!(
+            "The RPC socket path '{}' is {} bytes long, but the limit on this platform is {} \
+             bytes. Please use a shorter data directory.",
+            socket_path.display(),
+            len,
+            MAX_SOCKET_PATH_LEN
+        )));
+    }
+
+    Ok(())
+}
+
+// An abstract socket name is stored in 'sun_path' after a leading NUL byte, and isn't
+// NUL-terminated.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn check_abstract_socket_name(name: &str) -> Result<(), ConfigError> {
+    if name.is_empty() || name.contains('\0') {
+        return Err(ConfigError(format!(
+            "Invalid abstract RPC socket name '{}'",
+            name
+        )));
+    }
+    if name.len() > MAX_SOCKET_PATH_LEN {
+        return Err(ConfigError(format!(
+            "The abstract RPC socket name '{}' is {} bytes long, but the limit is {} bytes.",
+            name,
+            name.len(),
+            MAX_SOCKET_PATH_LEN
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn check_abstract_socket_name(_: &str) -> Result<(), ConfigError> {
+    Err(ConfigError(
+        "Abstract Unix sockets are only supported on Linux, please remove \
+         'rpc_abstract_socket' from the configuration."
+            .to_string(),
+    ))
+}
+
+// A misconstructed deposit descriptor could silently make the vaults spendable by less than all
+// the stakeholders. Make sure it's a N-of-N between distinct keys, optionally the ones we expect.
+// Returns the master fingerprints of the participants' keys.
+fn check_deposit_descriptor(
+    deposit_descriptor: &DepositDescriptor,
+    expected_fingerprints: Option<&[Fingerprint]>,
+) -> Result<Vec<Fingerprint>, ConfigError> {
+    let policy = deposit_descriptor
+        .inner()
+        .lift()
+        .map_err(|e| ConfigError(format!("Invalid deposit descriptor policy: {}", e)))?
+        .normalized();
+    let is_n_of_n = match policy {
+        SemanticPolicy::Threshold(k, ref subs) => {
+            k == subs.len() && subs.iter().all(|s| matches!(s, SemanticPolicy::KeyHash(_)))
+        }
+        _ => false,
+    };
+    if !is_n_of_n {
+        return Err(ConfigError(format!(
+            "The deposit descriptor must be a N-of-N between the stakeholders, but its policy is \
+             '{}'",
+            policy
+        )));
+    }
+
+    let xpubs = deposit_descriptor.xpubs();
+    if xpubs.len() < 2 {
+        return Err(ConfigError(
+            "The deposit descriptor must have at least 2 stakeholders".to_string(),
+        ));
+    }
+    let mut fingerprints: Vec<Fingerprint> = Vec::with_capacity(xpubs.len());
+    for (i, xpub) in xpubs.iter().enumerate() {
+        let fingerprint = xpub.master_fingerprint();
+        let is_dup_key = xpubs[..i].iter().any(|other| match (xpub, other) {
+            (DescriptorPublicKey::XPub(a), DescriptorPublicKey::XPub(b)) => a.xkey == b.xkey,
+            _ => xpub == other,
+        });
+        if is_dup_key || fingerprints.contains(&fingerprint) {
+            return Err(ConfigError(format!(
+                "Stakeholder key with fingerprint '{}' appears more than once in the deposit \
+                 descriptor",
+                fingerprint
+            )));
+        }
+        fingerprints.push(fingerprint);
+    }
+
+    if let Some(expected) = expected_fingerprints {
+        let (mut ours, mut theirs) = (fingerprints.clone(), expected.to_vec());
+        ours.sort_unstable();
+        theirs.sort_unstable();
+        if ours != theirs {
+            return Err(ConfigError(format!(
+                "The deposit descriptor keys' fingerprints ({}) are not the expected ones ({})",
+                fingerprints_list(&fingerprints),
+                fingerprints_list(expected)
+            )));
+        }
+    }
+
+    Ok(fingerprints)
+}
+
+fn fingerprints_list(fingerprints: &[Fingerprint]) -> String {
+    fingerprints
+        .iter()
+        .map(|f| f.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
 fn create_datadir(datadir_path: &PathBuf) -> Result<(), std::io::Error> {
     #[cfg(unix)]
     return {
@@ -310,6 +550,99 @@ fn create_datadir(datadir_path: &PathBuf) -> Result<(), std::io::Error> {
     };
 }
 
+// The settings that differ between two configurations, except those we can change at runtime.
+fn restart_required_changes(old: &Config, new: &Config) -> Vec<&'static str> {
+    // Destructure it so that we don't forget to sort out a new setting
+    let Config {
+        bitcoind_config,
+        scripts_config,
+        stakeholder_config,
+        manager_config,
+        coordinator_host: _,
+        coordinator_noise_key: _,
+        coordinator_poll_seconds: _,
+        data_dir,
+        daemon,
+        log_level: _,
+        min_conf,
+        cancel_min_conf,
+        tip_finality_depth,
+        precreate_deposit_addresses,
+        revocation_feerate_min_sat_vb,
+        revocation_feerate_max_sat_vb,
+        rpc_max_entries: _,
+        rpc_log_level: _,
+        verify_signatures_on_startup,
+        rpc_abstract_socket,
+        sqlite_wal_mode,
+        ready_file,
+        db_encryption,
+        db_passphrase_file,
+    } = new;
+
+    let bitcoind_config = BitcoindConfig {
+        poll_interval_secs: old.bitcoind_config.poll_interval_secs,
+        rebroadcast_interval_secs: old.bitcoind_config.rebroadcast_interval_secs,
+        ..bitcoind_config.clone()
+    };
+    let stakeholder_config = match (stakeholder_config, &old.stakeholder_config) {
+        (Some(new_stk), Some(old_stk)) => Some(StakeholderConfig {
+            watchtowers: old_stk.watchtowers.clone(),
+            ..new_stk.clone()
+        }),
+        (new_stk, _) => new_stk.clone(),
+    };
+
+    [
+        ("bitcoind_config", bitcoind_config != old.bitcoind_config),
+        ("scripts_config", *scripts_config != old.scripts_config),
+        (
+            "stakeholder_config",
+            stakeholder_config != old.stakeholder_config,
+        ),
+        ("manager_config", *manager_config != old.manager_config),
+        ("data_dir", *data_dir != old.data_dir),
+        ("daemon", *daemon != old.daemon),
+        ("min_conf", *min_conf != old.min_conf),
+        ("cancel_min_conf", *cancel_min_conf != old.cancel_min_conf),
+        (
+            "tip_finality_depth",
+            *tip_finality_depth != old.tip_finality_depth,
+        ),
+        (
+            "precreate_deposit_addresses",
+            *precreate_deposit_addresses != old.precreate_deposit_addresses,
+        ),
+        (
+            "revocation_feerate_min_sat_vb",
+            *revocation_feerate_min_sat_vb != old.revocation_feerate_min_sat_vb,
+        ),
+        (
+            "revocation_feerate_max_sat_vb",
+            *revocation_feerate_max_sat_vb != old.revocation_feerate_max_sat_vb,
+        ),
+        (
+            "verify_signatures_on_startup",
+            *verify_signatures_on_startup != old.verify_signatures_on_startup,
+        ),
+        (
+            "rpc_abstract_socket",
+            *rpc_abstract_socket != old.rpc_abstract_socket,
+        ),
+        ("sqlite_wal_mode", *sqlite_wal_mode != old.sqlite_wal_mode),
+        ("ready_file", *ready_file != old.ready_file),
+        ("db_encryption", *db_encryption != old.db_encryption),
+        (
+            "db_passphrase_file",
+            *db_passphrase_file != old.db_passphrase_file,
+        ),
+    ]
+    .iter()
+    .filter(|(_, changed)| *changed)
+    .map(|(name, _)| *name)
+    .collect()
+}
+
 impl RevaultD {
     /// Creates our global state by consuming the static configuration
     pub fn from_config(config: Config) -> Result<RevaultD, Box<dyn std::error::Error>> {
@@ -317,8 +650,16 @@ impl RevaultD {
         let our_stk_xpub = config.stakeholder_config.as_ref().map(|x| x.xpub);
         // Config should have checked that!
         assert!(our_man_xpub.is_some() || our_stk_xpub.is_some());
+        let loaded_config = config.clone();
 
         let deposit_descriptor = config.scripts_config.deposit_descriptor;
+        let deposit_fingerprints = config.scripts_config.deposit_fingerprints;
+        let participants =
+            check_deposit_descriptor(&deposit_descriptor, deposit_fingerprints.as_deref())?;
+        log::info!(
+            "Deposit descriptor stakeholders: {}",
+            fingerprints_list(&participants)
+        );
         let unvault_descriptor = config.scripts_config.unvault_descriptor;
         let cpfp_descriptor = config.scripts_config.cpfp_descriptor;
         let emergency_address = config
@@ -332,8 +673,7 @@ impl RevaultD {
             if let Err(e) = create_datadir(&data_dir) {
                 return Err(Box::from(ConfigError(format!(
                     "Could not create data dir '{:?}': {}.",
-                    data_dir,
-                    e.to_string()
+                    data_dir, e
                 ))));
             }
         }
@@ -342,8 +682,21 @@ impl RevaultD {
         let data_dir_str = data_dir
             .to_str()
             .expect("Impossible: the datadir path is valid unicode");
+        if let Some(ref name) = config.rpc_abstract_socket {
+            check_abstract_socket_name(name)?;
+        } else {
+            let rpc_socket_file: PathBuf = [data_dir_str, RPC_SOCKET_FILE_NAME].iter().collect();
+            check_socket_path_len(&rpc_socket_file)?;
+        }
         let noise_secret_file = [data_dir_str, "noise_secret"].iter().collect();
         let noise_secret = read_or_create_noise_key(noise_secret_file)?;
+        let db_passphrase = match config.db_passphrase_file {
+            Some(ref path) => Some(
+                read_passphrase_file(path)
+                    .map_err(|e| format!("Reading database passphrase file: {}", e))?,
+            ),
+            None => None,
+        };
 
         // TODO: support hidden services
         let coordinator_host = SocketAddr::from_str(&config.coordinator_host)?;
@@ -376,6 +729,7 @@ impl RevaultD {
             deposit_descriptor,
             unvault_descriptor,
             cpfp_descriptor,
+            deposit_fingerprints,
             secp_ctx,
             data_dir,
             daemon,
@@ -388,6 +742,26 @@ impl RevaultD {
             watchtowers,
             lock_time: 0,
             min_conf: config.min_conf,
+            cancel_min_conf: config.cancel_min_conf,
+            tip_finality_depth: config.tip_finality_depth,
+            revocation_feerate_range: config.revocation_feerate_min_sat_vb
+                ..=config.revocation_feerate_max_sat_vb,
+            rpc_max_entries: config.rpc_max_entries,
+            rpc_log_level: config.rpc_log_level,
+            precreate_deposit_addresses: config.precreate_deposit_addresses,
+            verify_signatures_on_startup: config.verify_signatures_on_startup,
+            sqlite_wal_mode: config.sqlite_wal_mode,
+            db_encryption: config.db_encryption,
+            db_passphrase,
+            db_key: None,
+            invalid_signatures: Vec::new(),
+            deposits_discrepancy: None,
+            rpc_abstract_socket: config.rpc_abstract_socket,
+            ready_file: config.ready_file,
+            // Will be updated by the database
+            maintenance: Arc::new(AtomicBool::new(false)),
+            events: Arc::new(EventBus::default()),
+            coordinator_health: Arc::new(Mutex::new(CoordinatorHealth::default())),
             bitcoind_config: config.bitcoind_config,
             tip: None,
             // Will be updated by the database
@@ -396,9 +770,103 @@ impl RevaultD {
             derivation_index_map: HashMap::new(),
             // Will be updated soon (:tm:)
             wallet_id: None,
+            config_file: None,
+            config: loaded_config,
         })
     }
 
+    /// Apply the settings of a re-read configuration that can be changed at runtime. Changes to
+    /// the other settings are reported, but only take effect after a restart.
+    pub fn reload_config(&mut self, config: Config) -> Result<ConfigReload, ConfigError> {
+        let coordinator_host = SocketAddr::from_str(&config.coordinator_host).map_err(|e| {
+            ConfigError(format!(
+                "Invalid coordinator host '{}': {}",
+                config.coordinator_host, e
+            ))
+        })?;
+        let mut reload = ConfigReload {
+            applied: Vec::new(),
+            ignored: restart_required_changes(&self.config, &config),
+        };
+
+        if config.log_level != self.config.log_level {
+            log::set_max_level(config.log_level);
+            reload.applied.push("log_level");
+        }
+        if config.rpc_log_level != self.config.rpc_log_level {
+            self.rpc_log_level = config.rpc_log_level;
+            reload.applied.push("rpc_log_level");
+        }
+        if config.rpc_max_entries != self.config.rpc_max_entries {
+            self.rpc_max_entries = config.rpc_max_entries;
+            reload.applied.push("rpc_max_entries");
+        }
+        if config.bitcoind_config.poll_interval_secs
+            != self.config.bitcoind_config.poll_interval_secs
+        {
+            self.bitcoind_config.poll_interval_secs = config.bitcoind_config.poll_interval_secs;
+            reload.applied.push("poll_interval_secs");
+        }
+        if config.bitcoind_config.rebroadcast_interval_secs
+            != self.config.bitcoind_config.rebroadcast_interval_secs
+        {
+            self.bitcoind_config.rebroadcast_interval_secs =
+                config.bitcoind_config.rebroadcast_interval_secs;
+            reload.applied.push("rebroadcast_interval_secs");
+        }
+        if config.coordinator_poll_seconds != self.config.coordinator_poll_seconds {
+            self.coordinator_poll_interval = config.coordinator_poll_seconds;
+            reload.applied.push("coordinator_poll_seconds");
+        }
+        if config.coordinator_host != self.config.coordinator_host {
+            self.coordinator_host = coordinator_host;
+            reload.applied.push("coordinator_host");
+        }
+        if config.coordinator_noise_key != self.config.coordinator_noise_key {
+            self.coordinator_noisekey = config.coordinator_noise_key;
+            reload.applied.push("coordinator_noise_key");
+        }
+        // Switching roles requires a restart, but we can change our watchtowers at any time. The
+        // vaults are shared with the new ones on the next signature fetcher poll.
+        if let (Some(old_stk), Some(new_stk)) =
+            (&self.config.stakeholder_config, &config.stakeholder_config)
+        {
+            if new_stk.watchtowers != old_stk.watchtowers {
+                self.watchtowers = Some(
+                    new_stk
+                        .watchtowers
+                        .iter()
+                        .map(|config| (config.host, config.noise_key))
+                        .collect(),
+                );
+                reload.applied.push("watchtowers");
+            }
+        }
+
+        // Keep the settings we did not apply as they were, so we keep reporting them as ignored
+        // until the restart.
+        self.config = Config {
+            log_level: config.log_level,
+            rpc_log_level: config.rpc_log_level,
+            rpc_max_entries: config.rpc_max_entries,
+            coordinator_host: config.coordinator_host,
+            coordinator_noise_key: config.coordinator_noise_key,
+            coordinator_poll_seconds: config.coordinator_poll_seconds,
+            ..self.config.clone()
+        };
+        self.config.bitcoind_config.poll_interval_secs = config.bitcoind_config.poll_interval_secs;
+        self.config.bitcoind_config.rebroadcast_interval_secs =
+            config.bitcoind_config.rebroadcast_interval_secs;
+        if let (Some(old_stk), Some(new_stk)) = (
+            &mut self.config.stakeholder_config,
+            config.stakeholder_config,
+        ) {
+            old_stk.watchtowers = new_stk.watchtowers;
+        }
+
+        Ok(reload)
+    }
+
     fn file_from_datadir(&self, file_name: &str) -> PathBuf {
         let data_dir_str = self
             .data_dir
@@ -430,8 +898,47 @@ impl RevaultD {
             .expect("unvault_descriptor is a wsh")
     }
 
+    /// The height of the last block we consider as final given the current tip height. Zero if
+    /// none is.
+    pub fn finalized_height(&self, tip_height: u32) -> u32 {
+        (tip_height + 1).saturating_sub(self.tip_finality_depth)
+    }
+
+    /// How many addresses after the first unused one we watch. Larger than the default if we
+    /// were told to pre-create more deposit addresses.
     pub fn gap_limit(&self) -> u32 {
-        100
+        cmp::max(DEFAULT_GAP_LIMIT, self.precreate_deposit_addresses)
+    }
+
+    /// The end of the range of derivation indexes we watch, capped to the descriptors' range.
+    pub fn watched_range_end(&self) -> u32 {
+        let raw_index: u32 = self.current_unused_index.into();
+        cmp::min(
+            raw_index.saturating_add(self.gap_limit()),
+            DERIVATION_RANGE_END,
+        )
+    }
+
+    /// How many derivation indexes are left in the descriptors' range, starting from the first
+    /// unused one.
+    pub fn remaining_deposit_indexes(&self) -> u32 {
+        let raw_index: u32 = self.current_unused_index.into();
+        DERIVATION_RANGE_END.saturating_sub(raw_index)
+    }
+
+    /// The deposit addresses we can hand out at once, along with their derivation index
+    pub fn precreated_deposit_addresses(&self) -> Vec<(ChildNumber, Address)> {
+        let raw_index: u32 = self.current_unused_index.into();
+        let end = cmp::min(
+            raw_index.saturating_add(cmp::max(1, self.precreate_deposit_addresses)),
+            DERIVATION_RANGE_END,
+        );
+        (raw_index..end)
+            .map(|raw_index| {
+                let index = ChildNumber::from(raw_index);
+                (index, self.vault_address(index))
+            })
+            .collect()
     }
 
     pub fn watchonly_wallet_name(&self) -> Option<String> {
@@ -460,8 +967,36 @@ impl RevaultD {
         })
     }
 
+    /// The path of the JSONRPC socket. For an abstract socket, this is its name prefixed with a
+    /// NUL byte.
     pub fn rpc_socket_file(&self) -> PathBuf {
-        self.file_from_datadir("revaultd_rpc")
+        match self.rpc_abstract_socket {
+            Some(ref name) => PathBuf::from(format!("\0{}", name)),
+            None => self.file_from_datadir(RPC_SOCKET_FILE_NAME),
+        }
+    }
+
+    /// Create the ready file, if configured to.
+    pub fn create_ready_file(&self) -> io::Result<()> {
+        if let Some(ref path) = self.ready_file {
+            fs::File::create(path)?;
+        }
+        Ok(())
+    }
+
+    /// Remove the ready file, if configured to and it exists.
+    pub fn remove_ready_file(&self) -> io::Result<()> {
+        match self.ready_file {
+            Some(ref path) => match fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_maintenance(&self) -> bool {
+        self.maintenance.load(Ordering::Relaxed)
     }
 
     pub fn is_stakeholder(&self) -> bool {
@@ -476,20 +1011,32 @@ impl RevaultD {
         self.vault_address(self.current_unused_index)
     }
 
-    pub fn last_deposit_address(&self) -> Address {
-        let raw_index: u32 = self.current_unused_index.into();
-        // FIXME: this should fail instead of creating a hardened index
-        self.vault_address(ChildNumber::from(raw_index + self.gap_limit()))
+    /// Move the first unused derivation index forward, and start watching the deposit addresses
+    /// entering the gap limit window. Returns the derivation indexes of these addresses, which
+    /// need to be imported in the watchonly wallet.
+    pub fn set_unused_index(&mut self, index: ChildNumber) -> Vec<ChildNumber> {
+        let prev_range_end = self.watched_range_end();
+        self.current_unused_index = index;
+
+        let new_indexes: Vec<ChildNumber> = (prev_range_end..self.watched_range_end())
+            .map(ChildNumber::from)
+            .collect();
+        self.watch_deposit_indexes(&new_indexes);
+
+        new_indexes
     }
 
-    pub fn last_unvault_address(&self) -> Address {
-        let raw_index: u32 = self.current_unused_index.into();
-        // FIXME: this should fail instead of creating a hardened index
-        self.unvault_address(ChildNumber::from(raw_index + self.gap_limit()))
+    /// Derive the deposit scriptPubKeys at these indexes once and for all, so the poller can
+    /// match the deposit utxos to their derivation index without deriving anything.
+    pub fn watch_deposit_indexes(&mut self, indexes: &[ChildNumber]) {
+        for index in indexes {
+            let script_pubkey = self.vault_address(*index).script_pubkey();
+            self.derivation_index_map.insert(script_pubkey, *index);
+        }
     }
 
     /// All deposit addresses as strings up to the gap limit (100)
-    pub fn all_deposit_addresses(&mut self) -> Vec<String> {
+    pub fn all_deposit_addresses(&self) -> Vec<String> {
         self.derivation_index_map
             .keys()
             .map(|s| {
@@ -500,18 +1047,47 @@ impl RevaultD {
             .collect()
     }
 
-    /// All unvault addresses as strings up to the gap limit (100)
-    pub fn all_unvault_addresses(&mut self) -> Vec<String> {
+    /// The deposit and Unvault addresses we watch in addition to the default gap limit window,
+    /// because we were told to pre-create more deposit addresses.
+    pub fn precreated_extra_addresses(&self) -> (Vec<String>, Vec<String>) {
         let raw_index: u32 = self.current_unused_index.into();
-        (0..raw_index + self.gap_limit())
+        (raw_index.saturating_add(DEFAULT_GAP_LIMIT)..self.watched_range_end())
+            .map(|raw_index| {
+                let index = ChildNumber::from(raw_index);
+                (
+                    self.vault_address(index).to_string(),
+                    self.unvault_address(index).to_string(),
+                )
+            })
+            .unzip()
+    }
+
+    /// All unvault addresses as strings up to the gap limit (100)
+    pub fn all_unvault_addresses(&self) -> Vec<String> {
+        (0..self.watched_range_end())
             .map(|raw_index| {
-                // FIXME: this should fail instead of creating a hardened index
                 self.unvault_address(ChildNumber::from(raw_index))
                     .to_string()
             })
             .collect()
     }
 
+    /// The maximum weight of the witness of a transaction input spending a deposit
+    pub fn deposit_satisfaction_weight(&self) -> usize {
+        self.deposit_descriptor
+            .inner()
+            .max_satisfaction_weight()
+            .expect("Script must be satisfiable")
+    }
+
+    /// The maximum weight of the witness of a transaction input spending an Unvault output
+    pub fn unvault_satisfaction_weight(&self) -> usize {
+        self.unvault_descriptor
+            .inner()
+            .max_satisfaction_weight()
+            .expect("Script must be satisfiable")
+    }
+
     pub fn derived_deposit_descriptor(&self, index: ChildNumber) -> DerivedDepositDescriptor {
         self.deposit_descriptor.derive(index, &self.secp_ctx)
     }
@@ -580,10 +1156,42 @@ impl RevaultD {
 
 #[cfg(test)]
 mod tests {
-    use super::RevaultD;
+    use super::{
+        check_abstract_socket_name, check_deposit_descriptor, check_socket_path_len, BlockchainTip,
+        ConfigReload, RevaultD, VaultStatus, DERIVATION_RANGE_END, MAX_SOCKET_PATH_LEN,
+    };
+    use crate::{
+        jsonrpc::UserRole,
+        utils::test_utils::{dummy_revaultd, test_datadir},
+    };
     use common::config::Config;
+    use revault_tx::{
+        bitcoin::{
+            blockdata::constants::genesis_block,
+            util::bip32::{ChildNumber, Fingerprint},
+            BlockHash, Network,
+        },
+        scripts::DepositDescriptor,
+    };
 
-    use std::path::PathBuf;
+    use std::{convert::TryFrom, fs, path::PathBuf, str::FromStr, time::Duration};
+
+    #[test]
+    fn unset_tip() {
+        let unset = BlockchainTip {
+            height: 0,
+            hash: BlockHash::default(),
+        };
+        assert!(unset.is_unset());
+
+        // A fresh regtest chain is at height 0, that's a legit tip
+        let regtest_genesis = BlockchainTip {
+            height: 0,
+            hash: genesis_block(Network::Regtest).block_hash(),
+        };
+        assert!(!regtest_genesis.is_unset());
+        assert_ne!(unset, regtest_genesis);
+    }
 
     #[test]
     fn test_from_config() {
@@ -598,4 +1206,287 @@ mod tests {
         RevaultD::from_config(config).expect("Creating state from config");
         // TODO: test actual fields..
     }
+
+    #[test]
+    fn deposit_descriptor_checks() {
+        let xpub_a = "xpub6AHA9hZDN11k2ijHMeS5QqHx2KP9aMBRhTDqANMnwVtdyw2TDYRmF8PjpvwUFcL1Et8Hj59S3gTSMcUQ5gAqTz3Wd8EsMTmF3DChhqPQBnU/*";
+        let xpub_b = "xpub6AaffFGfH6WXfm6pwWzmUMuECQnoLeB3agMKaLyEBZ5ZVfwtnS5VJKqXBt8o5ooCWVy2H87GsZshp7DeKE25eWLyd1Ccuh2ZubQUkgpiVux/*";
+
+        let desc =
+            DepositDescriptor::from_str(&format!("wsh(multi(2,{},{}))", xpub_a, xpub_b)).unwrap();
+        let fingerprints = check_deposit_descriptor(&desc, None).unwrap();
+        assert_eq!(fingerprints.len(), 2);
+        assert_ne!(fingerprints[0], fingerprints[1]);
+        // The order doesn't matter
+        let expected = vec![fingerprints[1], fingerprints[0]];
+        check_deposit_descriptor(&desc, Some(&expected)).unwrap();
+        let wrong = vec![fingerprints[0], Fingerprint::default()];
+        check_deposit_descriptor(&desc, Some(&wrong)).unwrap_err();
+
+        // Not all of the stakeholders are needed
+        let desc =
+            DepositDescriptor::from_str(&format!("wsh(multi(1,{},{}))", xpub_a, xpub_b)).unwrap();
+        check_deposit_descriptor(&desc, None).unwrap_err();
+
+        // A single stakeholder
+        let desc = DepositDescriptor::from_str(&format!("wsh(pk({}))", xpub_a)).unwrap();
+        check_deposit_descriptor(&desc, None).unwrap_err();
+
+        // The same key under another derivation path
+        let xpub_a_child = xpub_a.replace("/*", "/1/*");
+        let desc =
+            DepositDescriptor::from_str(&format!("wsh(multi(2,{},{}))", xpub_a, xpub_a_child))
+                .unwrap();
+        check_deposit_descriptor(&desc, None).unwrap_err();
+
+        // Two keys from the same master key
+        let desc = DepositDescriptor::from_str(&format!(
+            "wsh(multi(2,[00000001/1]{},[00000001/2]{}))",
+            xpub_a, xpub_b
+        ))
+        .unwrap();
+        check_deposit_descriptor(&desc, None).unwrap_err();
+    }
+
+    #[test]
+    fn test_vault_status_roundtrip() {
+        // These are part of the API, and stored in the database as their index. Never change
+        // nor reorder them.
+        let names = [
+            "unconfirmed",
+            "funded",
+            "securing",
+            "secured",
+            "activating",
+            "active",
+            "unvaulting",
+            "unvaulted",
+            "canceling",
+            "canceled",
+            "emergencyvaulting",
+            "emergencyvaulted",
+            "unvaultemergencyvaulting",
+            "unvaultemergencyvaulted",
+            "spending",
+            "spent",
+            "unexpectedspend",
+            "aborted",
+        ];
+
+        for (n, name) in names.iter().enumerate() {
+            let status = VaultStatus::try_from(n as u32).expect("Known status");
+            assert_eq!(status as u32, n as u32);
+            assert_eq!(&status.to_string(), name);
+            assert_eq!(VaultStatus::from_str(name), Ok(status));
+        }
+        // If you added a status, add it to the list above.
+        VaultStatus::try_from(names.len() as u32).unwrap_err();
+
+        VaultStatus::from_str("Funded").unwrap_err();
+        VaultStatus::from_str("").unwrap_err();
+    }
+
+    #[test]
+    fn test_socket_path_len() {
+        let path = PathBuf::from("/".repeat(MAX_SOCKET_PATH_LEN));
+        check_socket_path_len(&path).unwrap();
+
+        let path = PathBuf::from("/".repeat(MAX_SOCKET_PATH_LEN + 1));
+        let err = check_socket_path_len(&path).unwrap_err().to_string();
+        assert!(err.contains(&format!("{} bytes long", MAX_SOCKET_PATH_LEN + 1)));
+        assert!(err.contains(&format!(
+            "limit on this platform is {} bytes",
+            MAX_SOCKET_PATH_LEN
+        )));
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn test_abstract_socket_name() {
+        check_abstract_socket_name("revaultd_rpc").unwrap();
+        check_abstract_socket_name(&"a".repeat(MAX_SOCKET_PATH_LEN)).unwrap();
+
+        check_abstract_socket_name("").unwrap_err();
+        check_abstract_socket_name("revaultd\0rpc").unwrap_err();
+        let err = check_abstract_socket_name(&"a".repeat(MAX_SOCKET_PATH_LEN + 1))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains(&format!("limit is {} bytes", MAX_SOCKET_PATH_LEN)));
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    #[test]
+    fn test_abstract_socket_name() {
+        check_abstract_socket_name("revaultd_rpc").unwrap_err();
+    }
+
+    #[test]
+    fn test_finalized_height() {
+        let mut path = PathBuf::from(file!()).parent().unwrap().to_path_buf();
+        path.push("../../test_data/valid_config.toml");
+        let config = Config::from_file(Some(path)).expect("Parsing valid config file");
+        let mut revaultd = RevaultD::from_config(config).expect("Creating state from config");
+
+        revaultd.tip_finality_depth = 1;
+        assert_eq!(revaultd.finalized_height(0), 0);
+        assert_eq!(revaultd.finalized_height(100), 100);
+
+        revaultd.tip_finality_depth = 6;
+        assert_eq!(revaultd.finalized_height(3), 0);
+        assert_eq!(revaultd.finalized_height(6), 1);
+        assert_eq!(revaultd.finalized_height(100), 95);
+    }
+
+    #[test]
+    fn ready_file() {
+        let datadir = test_datadir();
+        let mut revaultd = dummy_revaultd(datadir.clone(), UserRole::Stakeholder);
+
+        // Nothing to do if not configured
+        revaultd.create_ready_file().unwrap();
+        revaultd.remove_ready_file().unwrap();
+
+        let ready_file = datadir.join("ready");
+        revaultd.ready_file = Some(ready_file.clone());
+        revaultd.create_ready_file().unwrap();
+        assert!(ready_file.exists());
+        // Creating it twice is fine
+        revaultd.create_ready_file().unwrap();
+        revaultd.remove_ready_file().unwrap();
+        assert!(!ready_file.exists());
+        // Removing it when it doesn't exist, too
+        revaultd.remove_ready_file().unwrap();
+
+        fs::remove_dir_all(&datadir).unwrap_or(());
+    }
+
+    #[test]
+    fn reload_config() {
+        let datadir = test_datadir();
+        let mut revaultd = dummy_revaultd(datadir.clone(), UserRole::Stakeholder);
+
+        // Nothing changed, nothing to do
+        let config = revaultd.config.clone();
+        assert_eq!(
+            revaultd.reload_config(config.clone()).unwrap(),
+            ConfigReload::default()
+        );
+
+        // Some settings are applied right away, the others only reported
+        let mut new_config = config.clone();
+        new_config.coordinator_host = "127.0.0.1:2".to_string();
+        new_config.coordinator_poll_seconds = Duration::from_secs(2);
+        new_config.bitcoind_config.poll_interval_secs = Duration::from_secs(3);
+        new_config.bitcoind_config.rebroadcast_interval_secs = Duration::from_secs(4);
+        new_config.rpc_max_entries = 10;
+        new_config.min_conf = 2;
+        new_config.bitcoind_config.import_retries = 0;
+        let stk_config = new_config.stakeholder_config.as_mut().unwrap();
+        stk_config
+            .watchtowers
+            .push(stk_config.watchtowers[0].clone());
+        let reload = revaultd.reload_config(new_config.clone()).unwrap();
+        assert_eq!(
+            reload.applied,
+            vec![
+                "rpc_max_entries",
+                "poll_interval_secs",
+                "rebroadcast_interval_secs",
+                "coordinator_poll_seconds",
+                "coordinator_host",
+                "watchtowers"
+            ]
+        );
+        assert_eq!(reload.ignored, vec!["bitcoind_config", "min_conf"]);
+        assert_eq!(revaultd.coordinator_host.port(), 2);
+        assert_eq!(revaultd.coordinator_poll_interval, Duration::from_secs(2));
+        assert_eq!(
+            revaultd.bitcoind_config.poll_interval_secs,
+            Duration::from_secs(3)
+        );
+        assert_eq!(
+            revaultd.bitcoind_config.rebroadcast_interval_secs,
+            Duration::from_secs(4)
+        );
+        assert_eq!(revaultd.rpc_max_entries, 10);
+        assert_eq!(revaultd.watchtowers.as_ref().unwrap().len(), 2);
+        assert_eq!(revaultd.min_conf, config.min_conf);
+
+        // The ignored ones keep being reported until we restart
+        let reload = revaultd.reload_config(new_config.clone()).unwrap();
+        assert!(reload.applied.is_empty());
+        assert_eq!(reload.ignored, vec!["bitcoind_config", "min_conf"]);
+
+        // Switching roles requires a restart
+        new_config.stakeholder_config = None;
+        let reload = revaultd.reload_config(new_config.clone()).unwrap();
+        assert_eq!(
+            reload.ignored,
+            vec!["bitcoind_config", "stakeholder_config", "min_conf"]
+        );
+        assert!(revaultd.watchtowers.is_some());
+
+        new_config.coordinator_host = "not an address".to_string();
+        revaultd.reload_config(new_config).unwrap_err();
+
+        fs::remove_dir_all(&datadir).unwrap_or(());
+    }
+
+    #[test]
+    fn derivation_range_end() {
+        let mut path = PathBuf::from(file!()).parent().unwrap().to_path_buf();
+        path.push("../../test_data/valid_config.toml");
+        let config = Config::from_file(Some(path)).expect("Parsing valid config file");
+        let mut revaultd = RevaultD::from_config(config).expect("Creating state from config");
+        revaultd.precreate_deposit_addresses = 150;
+
+        // Far from the end of the range, we watch the whole gap limit window
+        revaultd.current_unused_index = ChildNumber::from(DERIVATION_RANGE_END - 200);
+        assert_eq!(revaultd.remaining_deposit_indexes(), 200);
+        assert_eq!(revaultd.watched_range_end(), DERIVATION_RANGE_END - 50);
+        // A deposit a few indexes ahead makes us watch as many new addresses
+        let new_indexes = revaultd.set_unused_index(ChildNumber::from(DERIVATION_RANGE_END - 190));
+        assert_eq!(
+            new_indexes,
+            (DERIVATION_RANGE_END - 50..DERIVATION_RANGE_END - 40)
+                .map(ChildNumber::from)
+                .collect::<Vec<ChildNumber>>()
+        );
+        for index in new_indexes {
+            let script_pubkey = revaultd.vault_address(index).script_pubkey();
+            assert_eq!(
+                revaultd.derivation_index_map.get(&script_pubkey),
+                Some(&index)
+            );
+        }
+        revaultd.current_unused_index = ChildNumber::from(DERIVATION_RANGE_END - 200);
+        assert_eq!(revaultd.precreated_deposit_addresses().len(), 150);
+        assert_eq!(revaultd.precreated_extra_addresses().0.len(), 50);
+
+        // Up to the last index of the range
+        revaultd.current_unused_index = ChildNumber::from(DERIVATION_RANGE_END - 150);
+        assert!(revaultd
+            .set_unused_index(ChildNumber::from(DERIVATION_RANGE_END - 150))
+            .is_empty());
+        assert_eq!(revaultd.watched_range_end(), DERIVATION_RANGE_END);
+        let precreated = revaultd.precreated_deposit_addresses();
+        assert_eq!(precreated.len(), 150);
+        assert_eq!(
+            precreated.last().unwrap().0,
+            ChildNumber::from_normal_idx(DERIVATION_RANGE_END - 1).unwrap()
+        );
+        assert_eq!(revaultd.precreated_extra_addresses().0.len(), 50);
+
+        // But not past it
+        revaultd.current_unused_index = ChildNumber::from(DERIVATION_RANGE_END - 1);
+        assert_eq!(revaultd.remaining_deposit_indexes(), 1);
+        assert_eq!(revaultd.watched_range_end(), DERIVATION_RANGE_END);
+        assert!(revaultd
+            .set_unused_index(ChildNumber::from(DERIVATION_RANGE_END - 1))
+            .is_empty());
+        assert_eq!(revaultd.precreated_deposit_addresses().len(), 1);
+        assert!(revaultd.precreated_extra_addresses().0.is_empty());
+        revaultd.deposit_address();
+        assert!(revaultd.current_unused_index.increment().is_err());
+    }
 }
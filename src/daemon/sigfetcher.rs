@@ -1,4 +1,4 @@
-///! Background thread that will poll the coordinator for signatures
+//! Background thread that will poll the coordinator for signatures
 use crate::{
     control::{get_presigs, CommunicationError},
     database::{
@@ -7,8 +7,9 @@ use crate::{
         schema::{DbTransaction, RevaultTx, TransactionType},
         DatabaseError,
     },
-    revaultd::RevaultD,
+    revaultd::{CoordinatorHealth, RevaultD},
     threadmessages::SigFetcherMessageOut,
+    watchtower::{sync_watchtowers, WatchtowersConfig},
 };
 use revault_tx::{bitcoin::PublicKey as BitcoinPubKey, transactions::RevaultTransaction};
 
@@ -18,6 +19,27 @@ use std::{
     thread, time,
 };
 
+// After a failed poll, we wait twice as long as the previous time before polling again. Up to
+// 2^MAX_BACKOFF_EXPONENT times the poll interval.
+const MAX_BACKOFF_EXPONENT: u32 = 5;
+
+// How long to wait before the next poll given the number of failed polls in a row
+fn poll_delay(poll_interval: time::Duration, consecutive_failures: u32) -> time::Duration {
+    poll_interval * 2u32.pow(consecutive_failures.min(MAX_BACKOFF_EXPONENT))
+}
+
+fn update_health(health: &mut CoordinatorHealth, success: bool) {
+    if success {
+        health.last_success = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .ok();
+        health.consecutive_failures = 0;
+    } else {
+        health.consecutive_failures = health.consecutive_failures.saturating_add(1);
+    }
+}
+
 #[derive(Debug)]
 pub enum SignatureFetcherError {
     DbError(DatabaseError),
@@ -78,7 +100,7 @@ fn get_sigs(
 ) -> Result<(), SignatureFetcherError> {
     let db_path = &revaultd.db_file();
     let secp_ctx = &revaultd.secp_ctx;
-    let db_vault = db_vault(&db_path, vault_id)?.expect("Presigned transactions without vault?");
+    let db_vault = db_vault(db_path, vault_id)?.expect("Presigned transactions without vault?");
     let stk_keys = revaultd.stakeholders_xpubs_at(db_vault.derivation_index);
 
     let signatures = get_presigs(
@@ -128,6 +150,7 @@ fn get_sigs(
         // transactions wiped from the database. Would be a quite edgy case though.
         if let Err(e) = db_update_presigned_tx(
             db_path,
+            revaultd.db_key.as_ref(),
             vault_id,
             tx_db_id,
             tx.psbt().inputs[0].partial_sigs.clone(),
@@ -179,7 +202,7 @@ pub fn signature_fetcher_loop(
     revaultd: Arc<RwLock<RevaultD>>,
 ) -> Result<(), SignatureFetcherError> {
     let mut last_poll = time::Instant::now();
-    let poll_interval = revaultd.read().unwrap().coordinator_poll_interval;
+    let mut last_watchtowers_sync = time::Instant::now();
 
     log::info!("Signature fetcher thread started.");
 
@@ -197,19 +220,81 @@ pub fn signature_fetcher_loop(
         }
 
         let elapsed = last_poll.elapsed();
+        // May be changed by a configuration reload
+        let poll_interval = revaultd.read().unwrap().coordinator_poll_interval;
+        let failures = revaultd
+            .read()
+            .unwrap()
+            .coordinator_health
+            .lock()
+            .unwrap()
+            .consecutive_failures;
         // If enough time has elapsed, poll the sigs
-        if elapsed >= poll_interval {
+        if elapsed >= poll_delay(poll_interval, failures) {
             // This will ignore emergency transactions if we are manager-only
-            let txs = db_transactions_sig_missing(&revaultd.read().unwrap().db_file())?;
-            log::trace!("Fetching transactions for {:#?}", txs);
-            fetch_all_signatures(&revaultd.read().unwrap(), txs).unwrap_or_else(|e| {
-                log::warn!("Error while fetching signatures: '{}'", e);
-            });
+            let (db_path, db_key) = {
+                let revaultd = revaultd.read().unwrap();
+                (revaultd.db_file(), revaultd.db_key.clone())
+            };
+            let txs = db_transactions_sig_missing(&db_path, db_key.as_ref())?;
+            // We only talk to the Coordinator if there is something to fetch
+            if !txs.is_empty() {
+                log::trace!("Fetching transactions for {:#?}", txs);
+                let revaultd = revaultd.read().unwrap();
+                let res = fetch_all_signatures(&revaultd, txs);
+                if let Err(ref e) = res {
+                    log::warn!(
+                        "Error while fetching signatures: '{}'. Next poll in {} seconds.",
+                        e,
+                        poll_delay(poll_interval, failures + 1).as_secs()
+                    );
+                }
+                update_health(
+                    &mut revaultd.coordinator_health.lock().unwrap(),
+                    res.is_ok(),
+                );
+            }
 
             last_poll = time::Instant::now();
         }
 
+        // Newly secured vaults need to be guarded by our watchtowers. This does not involve the
+        // Coordinator, so it's not subject to its backoff.
+        if last_watchtowers_sync.elapsed() >= poll_interval {
+            let config = WatchtowersConfig::from_revaultd(&revaultd.read().unwrap());
+            if let Some(config) = config {
+                sync_watchtowers(&config)?;
+            }
+            last_watchtowers_sync = time::Instant::now();
+        }
+
         // Avoid clogging the CPU by sleeping for a while
         thread::sleep(time::Duration::from_millis(500));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{poll_delay, update_health};
+    use crate::revaultd::CoordinatorHealth;
+    use std::time::Duration;
+
+    #[test]
+    fn coordinator_backoff() {
+        let interval = Duration::from_secs(30);
+        assert_eq!(poll_delay(interval, 0), interval);
+        assert_eq!(poll_delay(interval, 1), Duration::from_secs(60));
+        assert_eq!(poll_delay(interval, 3), Duration::from_secs(240));
+        assert_eq!(poll_delay(interval, 5), Duration::from_secs(960));
+        assert_eq!(poll_delay(interval, 100), Duration::from_secs(960));
+
+        let mut health = CoordinatorHealth::default();
+        update_health(&mut health, false);
+        update_health(&mut health, false);
+        assert_eq!(health.consecutive_failures, 2);
+        assert!(health.last_success.is_none());
+        update_health(&mut health, true);
+        assert_eq!(health.consecutive_failures, 0);
+        assert!(health.last_success.is_some());
+    }
+}
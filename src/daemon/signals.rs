@@ -0,0 +1,58 @@
+//! Signals handling. Receiving SIGTERM or SIGINT makes us go through the same shutdown path as
+//! the `stop` command, instead of being killed in the middle of a write. Receiving SIGHUP makes
+//! us re-read the configuration file, as the `reloadconfig` command.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Set from the signal handlers, polled by the JSONRPC server loop
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(not(windows))]
+extern "C" fn request_shutdown(_signum: libc::c_int) {
+    // Storing to an atomic is async-signal-safe, don't do anything more here.
+    SHUTDOWN_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+#[cfg(not(windows))]
+extern "C" fn request_reload(_signum: libc::c_int) {
+    RELOAD_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+#[cfg(not(windows))]
+fn set_handler(signum: libc::c_int, handler: extern "C" fn(libc::c_int)) -> std::io::Result<()> {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(signum, &action, std::ptr::null_mut()) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Register our handlers for the termination and reload signals.
+#[cfg(not(windows))]
+pub fn setup_signal_handlers() -> Result<(), std::io::Error> {
+    set_handler(libc::SIGTERM, request_shutdown)?;
+    set_handler(libc::SIGINT, request_shutdown)?;
+    set_handler(libc::SIGHUP, request_reload)
+}
+
+#[cfg(windows)]
+pub fn setup_signal_handlers() -> Result<(), std::io::Error> {
+    Ok(())
+}
+
+/// Whether we were sent a termination signal
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Whether we were sent a reload signal since the last call
+pub fn reload_requested() -> bool {
+    RELOAD_REQUESTED.swap(false, Ordering::Relaxed)
+}
@@ -1,18 +1,43 @@
-use crate::bitcoind::BitcoindError;
-use revault_tx::bitcoin::{Transaction as BitcoinTransaction, Txid};
+use crate::{
+    bitcoind::{interface::LabeledUtxo, BitcoindError},
+    revaultd::BlockchainTip,
+};
+use revault_tx::bitcoin::{BlockHash, OutPoint, Transaction as BitcoinTransaction, Txid};
 
-use std::sync::mpsc::SyncSender;
+use std::sync::{
+    mpsc::{self, Receiver, Sender, SyncSender},
+    Mutex,
+};
 
 /// Outgoing to the bitcoind poller thread
 #[derive(Debug)]
 pub enum BitcoindMessageOut {
     Shutdown,
     SyncProgress(SyncSender<f64>),
-    WalletTransaction(Txid, SyncSender<Option<WalletTransaction>>),
+    // These transactions from the watchonly wallet, None for those it doesn't know about
+    WalletTransactions(
+        Vec<Txid>,
+        SyncSender<Result<Vec<Option<WalletTransaction>>, BitcoindError>>,
+    ),
     BroadcastTransactions(
         Vec<BitcoinTransaction>,
         SyncSender<Result<(), BitcoindError>>,
     ),
+    // The wallet utxos under this label
+    LabeledUtxos(String, SyncSender<Result<Vec<LabeledUtxo>, BitcoindError>>),
+    // Poll bitcoind right away, replies with the number of utxo changes processed
+    ForceSync(SyncSender<Result<usize, BitcoindError>>),
+    // The minimum relay feerate, in sat/vB
+    RelayFeerate(SyncSender<Result<u64, BitcoindError>>),
+    // The estimated feerate in sat/vB for this confirmation target, if bitcoind has one
+    EstimateFeerate(u32, SyncSender<Result<Option<u64>, BitcoindError>>),
+    // The hash of the block at this height and the merkle proof of this transaction's inclusion
+    // in it
+    TxOutProof(
+        Txid,
+        u32,
+        SyncSender<Result<(BlockHash, String), BitcoindError>>,
+    ),
 }
 
 /// Outgoing to the signature fetcher thread
@@ -21,10 +46,86 @@ pub enum SigFetcherMessageOut {
     Shutdown,
 }
 
-#[derive(Debug)]
+/// A change in our view of the chain, published by the bitcoind poller thread
+#[derive(Debug, Clone, PartialEq)]
+pub enum BitcoindEvent {
+    /// We processed a new tip (which may be after a reorg), and the utxos up to it
+    NewTip(BlockchainTip),
+    /// The deposit at this outpoint got its 'min_conf' confirmations
+    DepositConfirmed(OutPoint),
+    /// This Unvault output was spent by neither a revocation transaction nor a Spend
+    UnexpectedSpend {
+        unvault_outpoint: OutPoint,
+        spender_txid: Txid,
+    },
+    /// The daemon is shutting down, published by the JSONRPC thread to wake up those waiting
+    /// for an event
+    Shutdown,
+}
+
+/// Fans out the events published by the bitcoind poller thread to all the subscribers. Unlike
+/// the request channels of [BitcoindMessageOut], nobody has to ask for them.
+#[derive(Debug, Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<Sender<BitcoindEvent>>>,
+}
+
+impl EventBus {
+    /// Get all the events published from now on. Drop the receiver to unsubscribe.
+    pub fn subscribe(&self) -> Receiver<BitcoindEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// Send this event to all the subscribers, forgetting about those who went away
+    pub fn publish(&self, event: BitcoindEvent) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|sub| sub.send(event.clone()).is_ok());
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct WalletTransaction {
     pub hex: String,
     // None if unconfirmed
     pub blockheight: Option<u32>,
     pub received_time: u32,
+    // In sats, None if bitcoind can't tell (the inputs aren't ours)
+    pub fee: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BitcoindEvent, EventBus};
+    use crate::revaultd::BlockchainTip;
+    use revault_tx::bitcoin::{BlockHash, OutPoint};
+
+    #[test]
+    fn event_bus() {
+        let bus = EventBus::default();
+        // Publishing without subscribers is fine
+        bus.publish(BitcoindEvent::DepositConfirmed(OutPoint::default()));
+
+        let (sub_a, sub_b) = (bus.subscribe(), bus.subscribe());
+        let tip = BlockchainTip {
+            height: 1,
+            hash: BlockHash::default(),
+        };
+        bus.publish(BitcoindEvent::NewTip(tip));
+        assert_eq!(sub_a.try_recv().unwrap(), BitcoindEvent::NewTip(tip));
+        assert_eq!(sub_b.try_recv().unwrap(), BitcoindEvent::NewTip(tip));
+        assert!(sub_a.try_recv().is_err());
+
+        // A subscriber going away doesn't prevent the others from getting the events
+        drop(sub_a);
+        bus.publish(BitcoindEvent::DepositConfirmed(OutPoint::default()));
+        assert_eq!(
+            sub_b.try_recv().unwrap(),
+            BitcoindEvent::DepositConfirmed(OutPoint::default())
+        );
+        assert_eq!(bus.subscribers.lock().unwrap().len(), 1);
+    }
 }
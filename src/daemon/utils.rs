@@ -63,7 +63,7 @@ addr = "127.0.0.1:8332"
         };
 
         // Just in case there is a leftover from a previous run
-        fs::remove_dir_all(&datadir).unwrap_or_else(|_| ());
+        fs::remove_dir_all(&datadir).unwrap_or(());
 
         let mut config: Config = toml::from_str(&config).expect("Parsing valid config file");
         config.data_dir = Some(datadir);
@@ -78,28 +78,26 @@ addr = "127.0.0.1:8332"
         let (bitcoind_tx, bitcoind_rx) = mpsc::channel();
         let (sigfetcher_tx, sigfetcher_rx) = mpsc::channel();
 
-        let bitcoind_thread = Arc::from(RwLock::from(thread::spawn(move || {
-            for msg in bitcoind_rx {
+        thread::spawn(move || {
+            if let Some(msg) = bitcoind_rx.into_iter().next() {
                 match msg {
-                    BitcoindMessageOut::Shutdown => return,
+                    BitcoindMessageOut::Shutdown => {}
                     _ => unreachable!(),
                 }
             }
-        })));
-        let sigfetcher_thread = Arc::from(RwLock::from(thread::spawn(move || {
-            for msg in sigfetcher_rx {
+        });
+        thread::spawn(move || {
+            if let Some(msg) = sigfetcher_rx.into_iter().next() {
                 match msg {
-                    SigFetcherMessageOut::Shutdown => return,
+                    SigFetcherMessageOut::Shutdown => {}
                 }
             }
-        })));
+        });
 
         RpcUtils {
             revaultd,
             bitcoind_tx,
-            bitcoind_thread,
             sigfetcher_tx,
-            sigfetcher_thread,
         }
     }
 }
@@ -0,0 +1,341 @@
+//! Routines for sharing the revocation transactions signatures with our watchtowers, so that
+//! they can guard the vaults. Called by the signature fetcher thread for each vault that
+//! reached the 'secured' state.
+
+use crate::{
+    control::CommunicationError,
+    database::{
+        actions::db_mark_watchtower_ack,
+        encryption::DbKey,
+        interface::{
+            db_cancel_transaction, db_emer_transaction, db_unvault_emer_transaction,
+            db_vaults_to_guard, db_watchtower_acked_vaults,
+        },
+        schema::DbVault,
+        DatabaseError,
+    },
+    revaultd::RevaultD,
+};
+
+use revault_net::{
+    message::watchtower::{Sig, SigResult},
+    noise::{PublicKey as NoisePubKey, SecretKey as NoisePrivKey},
+    transport::KKTransport,
+};
+use revault_tx::{
+    bitcoin::{
+        hashes::hex::ToHex,
+        secp256k1::{PublicKey, Signature},
+        OutPoint, PublicKey as BitcoinPubKey, SigHashType, Txid,
+    },
+    transactions::RevaultTransaction,
+};
+
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+/// The signatures of a revocation transaction, by stakeholder public key
+type RevocationSigs = (Txid, BTreeMap<BitcoinPubKey, Vec<u8>>);
+
+/// How far a watchtower is in guarding the vaults we manage
+#[derive(Debug, Serialize)]
+pub struct WatchtowerSyncStatus {
+    pub host: String,
+    pub noise_key: String,
+    /// Number of vaults it acknowledged guarding
+    pub synced: usize,
+    /// Deposit outpoints of the vaults it did not acknowledge guarding yet
+    pub unsynced: Vec<OutPoint>,
+}
+
+// The Emergency, Cancel and Unvault Emergency transactions of this vault along with their
+// signatures. None if we don't have them all.
+fn vault_revocation_sigs(
+    db_path: &Path,
+    db_key: Option<&DbKey>,
+    vault_id: u32,
+) -> Result<Option<[RevocationSigs; 3]>, DatabaseError> {
+    let emer = db_emer_transaction(db_path, db_key, vault_id)?;
+    let cancel = db_cancel_transaction(db_path, db_key, vault_id)?;
+    let unvault_emer = db_unvault_emer_transaction(db_path, db_key, vault_id)?;
+
+    Ok(match (emer, cancel, unvault_emer) {
+        (Some((_, emer)), Some((_, cancel)), Some((_, unvault_emer))) => Some([
+            (emer.txid(), emer.psbt().inputs[0].partial_sigs.clone()),
+            (cancel.txid(), cancel.psbt().inputs[0].partial_sigs.clone()),
+            (
+                unvault_emer.txid(),
+                unvault_emer.psbt().inputs[0].partial_sigs.clone(),
+            ),
+        ]),
+        _ => None,
+    })
+}
+
+// Send a `sig` (https://github.com/revault/practical-revault/blob/master/messages.md#sig)
+// message to the watchtower for each of the revocation transactions of this vault.
+//
+// `revocation_sigs` MUST contain valid signatures (including the attached sighash type)
+fn share_vault_signatures(
+    transport: &mut KKTransport,
+    db_vault: &DbVault,
+    revocation_sigs: [RevocationSigs; 3],
+) -> Result<(), CommunicationError> {
+    for (txid, sigs) in revocation_sigs.iter() {
+        let signatures: BTreeMap<PublicKey, Signature> = sigs
+            .iter()
+            .map(|(pubkey, sig)| {
+                let (sigtype, sig) = sig
+                    .split_last()
+                    .expect("They must provide valid signatures");
+                assert_eq!(*sigtype, SigHashType::AllPlusAnyoneCanPay as u8);
+                let signature =
+                    Signature::from_der(sig).expect("They must provide valid signatures");
+                (pubkey.key, signature)
+            })
+            .collect();
+        let sig_msg = Sig {
+            signatures,
+            txid: *txid,
+            deposit_outpoint: db_vault.deposit_outpoint,
+            derivation_index: db_vault.derivation_index,
+        };
+
+        log::debug!("Sending sig '{:?}' to watchtower", sig_msg);
+        let sig_result: SigResult = transport.send_req(&sig_msg.into())?;
+        log::debug!("Got from watchtower: '{:?}'", sig_result);
+        if !sig_result.ack {
+            return Err(CommunicationError::WatchtowerNack);
+        }
+    }
+
+    Ok(())
+}
+
+/// What we need to sync our watchtowers, copied out of the global state so that we don't hold
+/// its lock while connecting to them.
+#[derive(Debug, Clone)]
+pub struct WatchtowersConfig {
+    watchtowers: Vec<(SocketAddr, NoisePubKey)>,
+    noise_secret: NoisePrivKey,
+    db_path: PathBuf,
+    db_key: Option<DbKey>,
+}
+
+impl WatchtowersConfig {
+    /// None if we don't have any watchtower to sync
+    pub fn from_revaultd(revaultd: &RevaultD) -> Option<WatchtowersConfig> {
+        let watchtowers = revaultd.watchtowers.clone()?;
+        Some(WatchtowersConfig {
+            watchtowers,
+            noise_secret: revaultd.noise_secret.clone(),
+            db_path: revaultd.db_file(),
+            db_key: revaultd.db_key.clone(),
+        })
+    }
+}
+
+/// Send the revocation signatures of the vaults to guard to each watchtower that did not
+/// acknowledge guarding them yet. A watchtower failing to answer is not fatal, we'll retry
+/// on the next call.
+pub fn sync_watchtowers(config: &WatchtowersConfig) -> Result<(), DatabaseError> {
+    let db_path = config.db_path.as_path();
+    let to_guard = db_vaults_to_guard(db_path)?;
+    if to_guard.is_empty() {
+        return Ok(());
+    }
+
+    for (host, noise_key) in config.watchtowers.iter() {
+        let acked = db_watchtower_acked_vaults(db_path, &noise_key.0)?;
+        let unsynced: Vec<&DbVault> = to_guard
+            .iter()
+            .filter(|db_vault| !acked.contains(&db_vault.id))
+            .collect();
+        if unsynced.is_empty() {
+            continue;
+        }
+
+        let mut transport = match KKTransport::connect(*host, &config.noise_secret, noise_key) {
+            Ok(transport) => transport,
+            Err(e) => {
+                log::warn!("Could not connect to watchtower '{}': '{}'", host, e);
+                continue;
+            }
+        };
+        for db_vault in unsynced {
+            let revocation_sigs =
+                match vault_revocation_sigs(db_path, config.db_key.as_ref(), db_vault.id)? {
+                    Some(sigs) => sigs,
+                    None => {
+                        log::error!(
+                            "Vault '{}' is '{}' but misses revocation transactions",
+                            db_vault.deposit_outpoint,
+                            db_vault.status
+                        );
+                        continue;
+                    }
+                };
+
+            match share_vault_signatures(&mut transport, db_vault, revocation_sigs) {
+                Ok(()) => {
+                    log::debug!(
+                        "Watchtower '{}' is now guarding vault '{}'",
+                        host,
+                        db_vault.deposit_outpoint
+                    );
+                    db_mark_watchtower_ack(db_path, db_vault.id, &noise_key.0)?;
+                }
+                Err(CommunicationError::WatchtowerNack) => {
+                    log::warn!(
+                        "Watchtower '{}' refused to guard vault '{}'",
+                        host,
+                        db_vault.deposit_outpoint
+                    );
+                }
+                Err(e) => {
+                    log::warn!(
+                        "Error while sharing signatures with watchtower '{}': '{}'",
+                        host,
+                        e
+                    );
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Get, for each of our watchtowers, which of the vaults to guard it acknowledged
+pub fn watchtowers_sync_status(
+    revaultd: &RevaultD,
+) -> Result<Vec<WatchtowerSyncStatus>, DatabaseError> {
+    let watchtowers = match revaultd.watchtowers {
+        Some(ref watchtowers) => watchtowers,
+        None => return Ok(Vec::new()),
+    };
+    let db_path = revaultd.db_file();
+    let to_guard = db_vaults_to_guard(&db_path)?;
+
+    watchtowers
+        .iter()
+        .map(|(host, noise_key)| {
+            let acked = db_watchtower_acked_vaults(&db_path, &noise_key.0)?;
+            let (synced, unsynced): (Vec<&DbVault>, Vec<&DbVault>) = to_guard
+                .iter()
+                .partition(|db_vault| acked.contains(&db_vault.id));
+
+            Ok(WatchtowerSyncStatus {
+                host: host.to_string(),
+                noise_key: noise_key.0.to_hex(),
+                synced: synced.len(),
+                unsynced: unsynced
+                    .into_iter()
+                    .map(|db_vault| db_vault.deposit_outpoint)
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::share_vault_signatures;
+    use crate::{control::CommunicationError, database::schema::DbVault, revaultd::VaultStatus};
+    use revault_net::{
+        message::{self, watchtower::Sig},
+        sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::gen_keypair,
+        transport::KKTransport,
+    };
+    use revault_tx::bitcoin::{
+        hashes::hex::FromHex, secp256k1, util::bip32::ChildNumber, Amount, OutPoint,
+        PublicKey as BitcoinPubKey, Txid,
+    };
+    use std::{collections::BTreeMap, net::TcpListener, str::FromStr, thread};
+
+    #[test]
+    fn test_share_vault_signatures() {
+        let secp = secp256k1::Secp256k1::new();
+        let privkey = secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let pubkey = BitcoinPubKey {
+            compressed: true,
+            key: secp256k1::PublicKey::from_secret_key(&secp, &privkey),
+        };
+        let signature = Vec::<u8>::from_hex("304402207e9a0839ad5f8a91c4e6987e5f451844ddccee05eb56c2d9129a6933faa05f35022046a7348028e487939524a0d5c71645be11b1c2e2a96c1a152a25960ffd66051681").unwrap();
+        let sigs: BTreeMap<BitcoinPubKey, Vec<u8>> =
+            vec![(pubkey, signature.clone())].into_iter().collect();
+        let txids: Vec<Txid> = (1..4u8)
+            .map(|i| Txid::from_hex(&format!("{:02x}", i).repeat(32)).unwrap())
+            .collect();
+        let db_vault = DbVault {
+            id: 1,
+            status: VaultStatus::Secured,
+            blockheight: 100,
+            deposit_outpoint: OutPoint::from_str(
+                "4d799e993665149109682555ba482b386aea03c5dbd62c059b48eb8f40f2f040:0",
+            )
+            .unwrap(),
+            amount: Amount::from_sat(123456),
+            derivation_index: ChildNumber::from(3),
+            received_at: 1615297315,
+            updated_at: 1615297315,
+            spend_txid: None,
+            excluded: false,
+        };
+        let expected_msg = |txid: Txid| {
+            message::RequestParams::WtSig(Sig {
+                signatures: vec![(
+                    pubkey.key,
+                    secp256k1::Signature::from_der(&signature[..signature.len() - 1]).unwrap(),
+                )]
+                .into_iter()
+                .collect(),
+                txid,
+                deposit_outpoint: db_vault.deposit_outpoint,
+                derivation_index: db_vault.derivation_index,
+            })
+        };
+
+        let ((client_pubkey, client_privkey), (server_pubkey, server_privkey)) =
+            (gen_keypair(), gen_keypair());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // The watchtower acks the first two, but not the last one
+        let (cli_vault, cli_txids, cli_sigs) = (db_vault, txids.clone(), sigs.clone());
+        let cli_thread = thread::spawn(move || {
+            let mut transport = KKTransport::connect(addr, &client_privkey, &server_pubkey)
+                .expect("Client channel connecting");
+            let revocation_sigs = [
+                (cli_txids[0], cli_sigs.clone()),
+                (cli_txids[1], cli_sigs.clone()),
+                (cli_txids[2], cli_sigs),
+            ];
+            assert!(matches!(
+                share_vault_signatures(&mut transport, &cli_vault, revocation_sigs),
+                Err(CommunicationError::WatchtowerNack)
+            ));
+        });
+
+        let mut server_transport =
+            KKTransport::accept(&listener, &server_privkey, &[client_pubkey])
+                .expect("Server channel binding and accepting");
+        for (i, txid) in txids.into_iter().enumerate() {
+            server_transport
+                .read_req(|params| {
+                    assert_eq!(params, expected_msg(txid));
+                    Some(message::ResponseResult::WtSig(
+                        message::watchtower::SigResult { ack: i < 2, txid },
+                    ))
+                })
+                .unwrap();
+        }
+        cli_thread.join().unwrap();
+    }
+}
@@ -132,6 +132,17 @@ def bitcoind(directory):
     bitcoind.cleanup()
 
 
+@pytest.fixture
+def fresh_bitcoind(directory):
+    """A bitcoind on a new regtest chain, with only the genesis block"""
+    bitcoind = BitcoinD(bitcoin_dir=directory)
+    bitcoind.startup()
+
+    yield bitcoind
+
+    bitcoind.cleanup()
+
+
 @pytest.fixture
 def revaultd_stakeholder(bitcoind, directory):
     datadir = os.path.join(directory, "revaultd")
@@ -175,8 +186,8 @@ def revaultd_stakeholder(bitcoind, directory):
     revaultd.cleanup()
 
 
-@pytest.fixture
-def revaultd_manager(bitcoind, directory):
+def start_manager(bitcoind, directory):
+    """Start a manager revaultd, in a setup with 2 stakeholders and 3 managers"""
     datadir = os.path.join(directory, "revaultd")
     os.makedirs(datadir, exist_ok=True)
     (stks, cosigs, mans, _, _, _) = get_participants(2, 3)
@@ -212,6 +223,21 @@ def revaultd_manager(bitcoind, directory):
         man_config=man_config,
     )
     revaultd.start()
+    return revaultd
+
+
+@pytest.fixture
+def revaultd_manager(bitcoind, directory):
+    revaultd = start_manager(bitcoind, directory)
+
+    yield revaultd
+
+    revaultd.cleanup()
+
+
+@pytest.fixture
+def revaultd_manager_fresh_chain(fresh_bitcoind, directory):
+    revaultd = start_manager(fresh_bitcoind, directory)
 
     yield revaultd
 
@@ -1,8 +1,9 @@
 import bitcoin
 import logging
 import os
+import time
 
-from bitcoin.rpc import RawProxy as BitcoinProxy
+from bitcoin.rpc import JSONRPCError, RawProxy as BitcoinProxy
 from decimal import Decimal
 from ephemeral_port_reserve import reserve
 from test_framework.utils import TailableProc, wait_for, TIMEOUT
@@ -146,6 +147,27 @@ class BitcoinD(TailableProc):
         for txid in txids:
             self.rpc.prioritisetransaction(txid, None, fee_delta)
 
+    def expire_mempool(self):
+        """Evict all the mempool transactions by making them older than the mempool
+        expiry (two weeks by default), and abandon them in every wallet."""
+        txids = self.rpc.getrawmempool()
+        self.rpc.setmocktime(int(time.time()) + 15 * 24 * 3600)
+        # Expiry is only enforced when a new transaction enters the mempool
+        self.rpc.sendtoaddress(self.rpc.getnewaddress(), 0.001)
+        self.rpc.setmocktime(0)
+        wait_for(lambda: not any(txid in self.rpc.getrawmempool() for txid in txids))
+
+        # Otherwise the wallets would still consider their coins spent by them
+        for wallet_name in self.rpc.listwallets():
+            wallet_rpc = SimpleBitcoinProxy(self.bitcoin_dir, self.rpcport)
+            wallet_rpc.wallet_name = wallet_name
+            for txid in txids:
+                try:
+                    wallet_rpc.abandontransaction(txid)
+                except JSONRPCError:
+                    # Not a transaction of this wallet
+                    pass
+
     def simple_reorg(self, height, shift=0):
         """
         Reorganize chain by creating a fork at height={height} and:
@@ -3,6 +3,10 @@ import logging
 from test_framework import revaultd
 import pytest
 import random
+import signal
+import sqlite3
+import subprocess
+import time
 import os
 
 from bitcoin.core import COIN
@@ -18,13 +22,23 @@ from test_framework.utils import (
 
 
 def test_getinfo(revaultd_manager, bitcoind):
+    # We don't run bitcoind with a txindex in the test framework
+    revaultd_manager.wait_for_log("bitcoind's txindex is not enabled")
+
     res = revaultd_manager.rpc.call("getinfo")
     assert res["network"] == "regtest"
     assert res["sync"] == 1.0
     assert res["version"] == "0.0.2"
     assert res["vaults"] == 0
+    assert res["remaining_deposit_indexes"] == 2**31
     # revaultd_manager always deploys with N = 2, M = 3, threshold = M
     assert res["managers_threshold"] == 3
+    assert res["unexpected_spends"] == []
+    assert res["invalid_signatures"] == []
+    assert res["deposits_discrepancy"] is None
+    assert res["maintenance"] is False
+    # Nothing to fetch from the Coordinator yet
+    assert res["coordinator"] == {"last_success": None, "consecutive_failures": 0}
     # test descriptors: RPC call & which Revaultd's were configured
     assert res["descriptors"]["cpfp"] == revaultd_manager.cpfp_desc
     assert res["descriptors"]["deposit"] == revaultd_manager.deposit_desc
@@ -34,6 +48,20 @@ def test_getinfo(revaultd_manager, bitcoind):
     height = revaultd_manager.rpc.call("getinfo")["blockheight"]
     bitcoind.generate_block(1)
     wait_for(lambda: revaultd_manager.rpc.call("getinfo")["blockheight"] == height + 1)
+    # By default a block is final after 6 confirmations
+    res = revaultd_manager.rpc.call("getinfo")
+    assert res["finalized_blockheight"] == max(res["blockheight"] - 5, 0)
+
+
+def test_getconfig(revaultd_manager):
+    config = revaultd_manager.rpc.call("getconfig")
+    assert config["bitcoind_config"]["network"] == "regtest"
+    assert config["scripts_config"]["cpfp_descriptor"] == revaultd_manager.cpfp_desc
+    assert config["scripts_config"]["deposit_descriptor"] == revaultd_manager.deposit_desc
+    assert config["scripts_config"]["unvault_descriptor"] == revaultd_manager.unvault_desc
+    assert config["stakeholder_config"] is None
+    assert config["manager_config"]["xpub"] is not None
+    assert config["min_conf"] == 6
 
 
 def test_listvaults(revaultd_manager, bitcoind):
@@ -54,6 +82,7 @@ def test_listvaults(revaultd_manager, bitcoind):
     assert vault_list[0]["derivation_index"] == 0
     assert vault_list[0]["updated_at"] == vault_list[0]["received_at"]
     assert vault_list[0]["blockheight"] == 0
+    assert not vault_list[0]["final"]
     assert revaultd_manager.rpc.call("getinfo")["vaults"] == 1
 
     # Generate 5 blocks, it is still unconfirmed
@@ -107,6 +136,69 @@ def test_listvaults(revaultd_manager, bitcoind):
     vault_list = revaultd_manager.rpc.call("listvaults", [[], [outpoint]])["vaults"]
     assert len(vault_list) == 0
 
+    # Or by amount and derivation index ranges, bounds included
+    amount = int(amount_sent * 10 ** 8)
+    options = {
+        "min_amount": amount,
+        "max_amount": amount,
+        "min_derivation_index": 0,
+        "max_derivation_index": 0,
+    }
+    vault_list = revaultd_manager.rpc.call("listvaults", [[], None, options])["vaults"]
+    assert len(vault_list) == 1
+    options = {"min_amount": amount + 1}
+    assert revaultd_manager.rpc.call("listvaults", [[], None, options])["vaults"] == []
+    options = {"min_derivation_index": 1}
+    assert revaultd_manager.rpc.call("listvaults", [[], None, options])["vaults"] == []
+    with pytest.raises(RpcError, match="minimum is greater than its maximum"):
+        options = {"min_amount": amount, "max_amount": amount - 1}
+        revaultd_manager.rpc.call("listvaults", [[], None, options])
+    with pytest.raises(RpcError, match="unknown field"):
+        revaultd_manager.rpc.call("listvaults", [[], None, {"min_amnt": amount}])
+
+    # And get them a page at a time
+    res = revaultd_manager.rpc.call("listvaults", [[], None, {"offset": 0, "limit": 1}])
+    assert len(res["vaults"]) == 1
+    assert res["total"] == 1
+    assert not res["truncated"]
+    res = revaultd_manager.rpc.call("listvaults", [[], None, {"offset": 1, "limit": 1}])
+    assert res["vaults"] == []
+    assert res["total"] == 1
+
+    # We can label them, and filter the vaults and their history by label
+    vault = revaultd_manager.rpc.call("listvaults")["vaults"][0]
+    assert vault["label"] is None
+    outpoint = f"{txid}:{vault['vout']}"
+    revaultd_manager.rpc.call("labelvault", [outpoint, "treasury"])
+    params = [[], None, {"label": "treasury"}]
+    vault_list = revaultd_manager.rpc.call("listvaults", params)["vaults"]
+    assert len(vault_list) == 1
+    assert vault_list[0]["label"] == "treasury"
+    params = [[], None, {"label": "payroll"}]
+    assert revaultd_manager.rpc.call("listvaults", params)["vaults"] == []
+    events = revaultd_manager.rpc.call("gethistory", [0, None, None, "treasury"])[
+        "events"
+    ]
+    assert len(events) > 0
+    assert all(e["label"] == "treasury" for e in events)
+    assert revaultd_manager.rpc.call("gethistory", [0, None, None, "payroll"])[
+        "events"
+    ] == []
+    with pytest.raises(RpcError, match="label must not be empty"):
+        revaultd_manager.rpc.call("labelvault", [outpoint, ""])
+    revaultd_manager.rpc.call("labelvault", [outpoint, None])
+    assert revaultd_manager.rpc.call("listvaults")["vaults"][0]["label"] is None
+
+    # We can also export them as CSV
+    vault = revaultd_manager.rpc.call("listvaults")["vaults"][0]
+    csv = revaultd_manager.rpc.call("exportvaults")["csv"].splitlines()
+    assert csv == [
+        "outpoint,amount,status,derivation_index,address,received_at,updated_at",
+        f"{txid}:{vault['vout']},{vault['amount']},funded,0,{addr},{vault['received_at']},{vault['updated_at']}",
+    ]
+    csv = revaultd_manager.rpc.call("exportvaults", [["unconfirmed"]])["csv"]
+    assert len(csv.splitlines()) == 1
+
 
 def test_largewallets(revaultd_stakeholder, bitcoind):
     """Test a wallet with 1000 deposits and 10 dust deposits"""
@@ -138,6 +230,9 @@ def test_getdepositaddress(revault_network, bitcoind):
     rn.deploy(4, 2)
     stk = rn.stk(0)
     addr = stk.rpc.call("getdepositaddress")["address"]
+    # We don't pre-create any address by default, so only the current one is listed
+    addresses = stk.rpc.call("listdepositaddresses")["addresses"]
+    assert addresses == [{"index": 0, "address": addr}]
 
     # If we don't use it, we'll get the same. From us and everyone else
     for n in rn.participants():
@@ -156,6 +251,749 @@ def test_getdepositaddress(revault_network, bitcoind):
             ["Got a new unconfirmed deposit", "Incremented deposit derivation index"]
         )
         assert addr2 == w.rpc.call("getdepositaddress")["address"]
+    assert stk.rpc.call("listdepositaddresses")["addresses"] == [
+        {"index": 1, "address": addr2}
+    ]
+
+    # A deposit further in the gap limit window moves the first unused index past it
+    addr7 = stk.rpc.call("getdepositaddress", [7])["address"]
+    bitcoind.rpc.sendtoaddress(addr7, 0.22222)
+    stk.wait_for_log("Incremented deposit derivation index from 1 to 8")
+    assert stk.rpc.call("listdepositaddresses")["addresses"] == [
+        {"index": 8, "address": stk.rpc.call("getdepositaddress", [8])["address"]}
+    ]
+
+    # We only hand out the addresses we watch, up to the gap limit after the first unused one
+    stk.rpc.call("getdepositaddress", [8 + 99])
+    with pytest.raises(RpcError, match="past the watched ones, which end at '108'"):
+        stk.rpc.call("getdepositaddress", [8 + 100])
+    with pytest.raises(RpcError, match="out of the deposit descriptor range"):
+        stk.rpc.call("getdepositaddress", [2**31])
+
+
+def test_deposit_address_reuse(revaultd_manager, bitcoind):
+    addr = revaultd_manager.rpc.call("getdepositaddress")["address"]
+    txid_a = bitcoind.rpc.sendtoaddress(addr, 0.5)
+    revaultd_manager.wait_for_logs(
+        ["Got a new unconfirmed deposit", "Incremented deposit derivation index"]
+    )
+
+    # Sending to the same address again creates a second, independent, vault
+    txid_b = bitcoind.rpc.sendtoaddress(addr, 0.7)
+    revaultd_manager.wait_for_logs(
+        ["Deposit address reuse", "Got a new unconfirmed deposit"]
+    )
+    vaults = revaultd_manager.rpc.call("listvaults")["vaults"]
+    assert len(vaults) == 2
+    assert {v["txid"] for v in vaults} == {txid_a, txid_b}
+    assert all(v["address"] == addr for v in vaults)
+    assert all(v["derivation_index"] == 0 for v in vaults)
+    # And it didn't bump the derivation index again
+    addresses = revaultd_manager.rpc.call("listdepositaddresses")["addresses"]
+    assert [a["index"] for a in addresses] == [1]
+
+    bitcoind.generate_block(6, wait_for_mempool=[txid_a, txid_b])
+    deposits = [f"{v['txid']}:{v['vout']}" for v in vaults]
+    revaultd_manager.wait_for_deposits(deposits)
+
+
+def test_precreate_deposit_addresses(revaultd_manager, bitcoind):
+    # Ask for more deposit addresses than the default gap limit of 100
+    revaultd_manager.stop()
+    with open(revaultd_manager.conf_file, "r") as f:
+        conf = f.read()
+    with open(revaultd_manager.conf_file, "w") as f:
+        f.write(conf.replace("log_level = ", "precreate_deposit_addresses = 150\nlog_level = "))
+    revaultd_manager.start()
+    revaultd_manager.wait_for_log("Importing 50 pre-created deposit addresses")
+    addresses = revaultd_manager.rpc.call("listdepositaddresses")["addresses"]
+    assert [a["index"] for a in addresses] == list(range(150))
+
+    # They are all watched now, we won't import them again
+    revaultd_manager.stop()
+    log_start = len(revaultd_manager.logs)
+    revaultd_manager.start()
+    assert not revaultd_manager.is_in_log(
+        "Importing .* pre-created deposit addresses", start=log_start
+    )
+
+    # And a deposit to the last one is detected
+    bitcoind.rpc.sendtoaddress(addresses[-1]["address"], 0.5)
+    revaultd_manager.wait_for_logs(
+        ["Got a new unconfirmed deposit", "Incremented deposit derivation index from 0 to 150"]
+    )
+
+
+def test_exclude_vault(revaultd_manager, bitcoind):
+    addr = revaultd_manager.rpc.call("getdepositaddress")["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 0.5)
+    revaultd_manager.wait_for_log("Got a new unconfirmed deposit")
+    vault = revaultd_manager.rpc.listvaults()["vaults"][0]
+    assert not vault["excluded"]
+    deposit = f"{txid}:{vault['vout']}"
+
+    # Once excluded, it's not listed by default
+    revaultd_manager.rpc.excludevault(deposit)
+    assert revaultd_manager.rpc.listvaults()["vaults"] == []
+    assert txid not in revaultd_manager.rpc.exportvaults()["csv"]
+    # Unless explicitly asked for
+    vaults = revaultd_manager.rpc.listvaults([], [deposit])["vaults"]
+    assert len(vaults) == 1 and vaults[0]["excluded"]
+    options = {"include_excluded": True}
+    vaults = revaultd_manager.rpc.call("listvaults", [[], None, options])["vaults"]
+    assert len(vaults) == 1 and vaults[0]["excluded"]
+
+    # We can't spend it
+    with pytest.raises(RpcError, match="is excluded"):
+        revaultd_manager.rpc.getspendtx(
+            [deposit], {bitcoind.rpc.getnewaddress(): 10_000}, 1
+        )
+
+    # But we can take it back
+    revaultd_manager.rpc.includevault(deposit)
+    vaults = revaultd_manager.rpc.listvaults()["vaults"]
+    assert len(vaults) == 1 and not vaults[0]["excluded"]
+
+    with pytest.raises(RpcError, match="No vault at"):
+        revaultd_manager.rpc.excludevault(f"{txid}:{vault['vout'] + 1}")
+
+
+def test_forcesync(revaultd_manager, bitcoind):
+    assert revaultd_manager.rpc.forcesync()["changes"] == 0
+
+    # Once it returns, the new deposit was processed
+    addr = revaultd_manager.rpc.call("getdepositaddress")["address"]
+    bitcoind.rpc.sendtoaddress(addr, 0.5)
+    bitcoind.rpc.syncwithvalidationinterfacequeue()
+    revaultd_manager.rpc.forcesync()
+    assert len(revaultd_manager.rpc.listvaults()["vaults"]) == 1
+
+    bitcoind.generate_block(6)
+    bitcoind.rpc.syncwithvalidationinterfacequeue()
+    revaultd_manager.rpc.forcesync()
+    assert revaultd_manager.rpc.listvaults()["vaults"][0]["status"] == "funded"
+
+
+def test_waitfornewblock(revaultd_manager, bitcoind, executor):
+    tip = revaultd_manager.rpc.waitfornewblock(0)
+    assert tip["height"] == bitcoind.rpc.getblockcount()
+
+    fut = executor.submit(revaultd_manager.rpc.waitfornewblock, 60)
+    bitcoind.generate_block(1)
+    new_tip = fut.result(timeout=60)
+    assert new_tip["height"] == tip["height"] + 1
+    assert new_tip["hash"] == bitcoind.rpc.getbestblockhash()
+
+    # We don't let a client hold a request handler for too long
+    with pytest.raises(RpcError, match="Timeout must not be greater than 300 seconds"):
+        revaultd_manager.rpc.waitfornewblock(2**64 - 1)
+
+    # A waiting request doesn't hold the shutdown
+    fut = executor.submit(revaultd_manager.rpc.waitfornewblock, 300)
+    time.sleep(1)
+    start = time.time()
+    revaultd_manager.stop()
+    assert fut.result(timeout=10)["height"] == new_tip["height"]
+    assert time.time() - start < 60
+    revaultd_manager.start()
+
+
+def test_rpc_during_sync(revaultd_manager, bitcoind, executor):
+    """The RPC server must keep answering while we process a large number of deposits"""
+    n_deposits = 100
+    txids = []
+    for _ in range(n_deposits):
+        addr = revaultd_manager.rpc.call("getdepositaddress")["address"]
+        txids.append(bitcoind.rpc.sendtoaddress(addr, 0.01))
+    bitcoind.generate_block(6, wait_for_mempool=txids)
+
+    def read_until_synced():
+        n_calls = 0
+        while revaultd_manager.rpc.getinfo()["vaults"] < n_deposits:
+            revaultd_manager.rpc.listvaults()
+            n_calls += 1
+        return n_calls
+
+    readers = [executor.submit(read_until_synced) for _ in range(5)]
+    for reader in readers:
+        reader.result(TIMEOUT)
+    wait_for(
+        lambda: len(revaultd_manager.rpc.listvaults(["funded"])["vaults"])
+        == n_deposits
+    )
+
+
+def spend_deposit_before_secured(revault_network, bitcoind, vault):
+    """Have the stakeholders spend the deposit between them, to an address that is not the
+    Emergency one. Returns the signed transaction."""
+    stks = revault_network.stks()
+    deposit = f"{vault['txid']}:{vault['vout']}"
+
+    # Re-use the Emergency PSBT for the input's witness script and amount.
+    psbt = serializations.PSBT()
+    psbt.deserialize(stks[0].rpc.getrevocationtxs(deposit)["emergency_tx"])
+    addr = bitcoind.rpc.getnewaddress()
+    psbt.tx.vout[0].scriptPubKey = bytes.fromhex(
+        bitcoind.rpc.getaddressinfo(addr)["scriptPubKey"]
+    )
+    psbt = psbt.serialize()
+    signed_psbts = [
+        stk.stk_keychain.sign_unvault_psbt(psbt, vault["derivation_index"])
+        for stk in stks
+    ]
+    spend_psbt = bitcoind.rpc.combinepsbt(signed_psbts)
+    return bitcoind.rpc.finalizepsbt(spend_psbt)["hex"]
+
+
+def test_deposit_spent_before_secured(revault_network, bitcoind):
+    revault_network.deploy(2, 1)
+    vault = revault_network.fund(0.5)
+    deposit = f"{vault['txid']}:{vault['vout']}"
+
+    spend_tx = spend_deposit_before_secured(revault_network, bitcoind, vault)
+    spend_txid = bitcoind.rpc.sendrawtransaction(spend_tx)
+    bitcoind.generate_block(1, wait_for_mempool=spend_txid)
+
+    for w in revault_network.participants():
+        w.wait_for_log(f"Deposit at '{deposit}' was spent before the vault got secured")
+        assert w.rpc.listvaults([], [deposit])["vaults"][0]["status"] == "aborted"
+        assert w.rpc.getinfo()["vaults"] == 0
+        # We don't report any transaction but the deposit for it
+        txs = w.rpc.listonchaintransactions([deposit])["onchain_transactions"]
+        assert txs[0]["unvault"] is None
+        with pytest.raises(RpcError, match="Invalid vault status 'aborted'"):
+            w.rpc.getbroadcasthex(deposit, "cancel")
+        # It's an outflow in the history
+        events = w.rpc.call("gethistory", [None, None, None, None, ["abort"]])["events"]
+        assert [(e["outpoint"], e["txid"]) for e in events] == [(deposit, None)]
+
+    # If the spending transaction gets reorged out, the vault is restored
+    bitcoind.simple_reorg(bitcoind.rpc.getblockcount(), shift=-1)
+    for w in revault_network.participants():
+        w.wait_for_log(f"The spender of aborted vault '{deposit}' got unconfirmed")
+        assert w.rpc.listvaults([], [deposit])["vaults"][0]["status"] == "funded"
+
+    # And it's usable again once the spending transaction gets evicted
+    bitcoind.expire_mempool()
+    for w in revault_network.participants():
+        w.rpc.forcesync()
+        assert w.rpc.listvaults([], [deposit])["vaults"][0]["status"] == "funded"
+        assert w.rpc.getinfo()["vaults"] == 1
+    revault_network.secure_vault(vault)
+    for w in revault_network.participants():
+        wait_for(
+            lambda: w.rpc.listvaults([], [deposit])["vaults"][0]["status"] == "secured"
+        )
+
+
+def test_deposit_spender_evicted(revault_network, bitcoind):
+    revault_network.deploy(2, 1)
+    vault = revault_network.fund(0.5)
+    deposit = f"{vault['txid']}:{vault['vout']}"
+
+    # Spend the deposit before the vault gets secured, as in the test above, but don't
+    # confirm the spending transaction.
+    spend_tx = spend_deposit_before_secured(revault_network, bitcoind, vault)
+    bitcoind.rpc.sendrawtransaction(spend_tx)
+
+    # The vault isn't aborted as long as the spending transaction is unconfirmed
+    for w in revault_network.participants():
+        w.wait_for_log(
+            f"Deposit at '{deposit}' was spent by an unconfirmed transaction before the "
+            "vault got secured"
+        )
+        assert w.rpc.listvaults([], [deposit])["vaults"][0]["status"] == "funded"
+
+    # And it's still usable once the spending transaction gets evicted
+    bitcoind.expire_mempool()
+    for w in revault_network.participants():
+        w.rpc.forcesync()
+        assert w.rpc.listvaults([], [deposit])["vaults"][0]["status"] == "funded"
+        assert w.rpc.getinfo()["vaults"] == 1
+    revault_network.secure_vault(vault)
+    for w in revault_network.participants():
+        wait_for(
+            lambda: w.rpc.listvaults([], [deposit])["vaults"][0]["status"] == "secured"
+        )
+
+
+def test_getdepositproof(revaultd_manager, bitcoind):
+    addr = revaultd_manager.rpc.call("getdepositaddress")["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 0.5)
+    revaultd_manager.wait_for_log("Got a new unconfirmed deposit")
+    vault = revaultd_manager.rpc.listvaults()["vaults"][0]
+    deposit = f"{txid}:{vault['vout']}"
+
+    with pytest.raises(RpcError, match="is not confirmed"):
+        revaultd_manager.rpc.getdepositproof(deposit)
+    with pytest.raises(RpcError, match="No vault at"):
+        revaultd_manager.rpc.getdepositproof(f"{txid}:{vault['vout'] + 1}")
+
+    bitcoind.generate_block(6, wait_for_mempool=txid)
+    revaultd_manager.wait_for_deposits([deposit])
+    res = revaultd_manager.rpc.getdepositproof(deposit)
+    height = bitcoind.rpc.gettransaction(txid)["blockheight"]
+    assert res["blockheight"] == height
+    assert res["blockhash"] == bitcoind.rpc.getblockhash(height)
+    assert bitcoind.rpc.verifytxoutproof(res["proof"]) == [txid]
+
+
+def test_getvaulthistory(revaultd_manager, bitcoind):
+    addr = revaultd_manager.rpc.call("getdepositaddress")["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 0.5)
+    revaultd_manager.wait_for_log("Got a new unconfirmed deposit")
+    vault = revaultd_manager.rpc.listvaults()["vaults"][0]
+    deposit = f"{txid}:{vault['vout']}"
+
+    with pytest.raises(RpcError, match="No vault at"):
+        revaultd_manager.rpc.getvaulthistory(f"{txid}:{vault['vout'] + 1}")
+    history = revaultd_manager.rpc.getvaulthistory(deposit)["history"]
+    assert [h["status"] for h in history] == ["unconfirmed"]
+
+    bitcoind.generate_block(6, wait_for_mempool=txid)
+    revaultd_manager.wait_for_deposits([deposit])
+    history = revaultd_manager.rpc.getvaulthistory(deposit)["history"]
+    assert [h["status"] for h in history] == ["unconfirmed", "funded"]
+    assert history[0]["timestamp"] <= history[1]["timestamp"]
+    assert history[0]["blockheight"] < history[1]["blockheight"]
+
+
+def test_getdeposittotals(revaultd_manager, bitcoind):
+    man = revaultd_manager
+    assert man.rpc.getdeposittotals() == {"totals": [], "untagged": 0}
+
+    with pytest.raises(RpcError, match="must not be empty"):
+        man.rpc.call("getdepositaddress", [None, ""])
+    alice_addr = man.rpc.call("getdepositaddress", [None, "alice"])["address"]
+    txids = [bitcoind.rpc.sendtoaddress(alice_addr, 0.5)]
+    man.wait_for_log("Incremented deposit derivation index")
+    bob_addr = man.rpc.call("getdepositaddress", [5, "bob"])["address"]
+    txids.append(bitcoind.rpc.sendtoaddress(bob_addr, 0.2))
+    man.wait_for_log("Got a new unconfirmed deposit")
+    # Relabeling an address only applies to the deposits received afterwards
+    carol_addr = man.rpc.call("getdepositaddress", [5, "carol"])["address"]
+    assert carol_addr == bob_addr
+    txids.append(bitcoind.rpc.sendtoaddress(carol_addr, 0.3))
+    untagged_addr = man.rpc.call("getdepositaddress", [3])["address"]
+    txids.append(bitcoind.rpc.sendtoaddress(untagged_addr, 0.4))
+    man.wait_for_logs(["Got a new unconfirmed deposit"] * 2)
+
+    # Unconfirmed deposits aren't accounted
+    assert man.rpc.getdeposittotals() == {"totals": [], "untagged": 0}
+    bitcoind.generate_block(6, wait_for_mempool=txids)
+    deposits = [f"{v['txid']}:{v['vout']}" for v in man.rpc.listvaults()["vaults"]]
+    assert len(deposits) == 4
+    man.wait_for_deposits(deposits)
+    assert man.rpc.getdeposittotals() == {
+        "totals": [
+            {"label": "alice", "amount": 50_000_000},
+            {"label": "bob", "amount": 20_000_000},
+            {"label": "carol", "amount": 30_000_000},
+        ],
+        "untagged": 40_000_000,
+    }
+
+
+def test_fresh_chain_start(revaultd_manager_fresh_chain, fresh_bitcoind):
+    """On a new regtest chain the tip is the genesis block, at the same height as the
+    placeholder tip of a new database. This must not be mistaken for a reorg."""
+    man = revaultd_manager_fresh_chain
+    assert fresh_bitcoind.rpc.getblockcount() == 0
+    info = man.rpc.getinfo()
+    assert info["sync"] == 1.0
+    assert info["blockheight"] == 0
+
+    # We keep moving forward from there
+    addr = "bcrt1qewc2348370pgw8kjz8gy09z8xyh0d9fxde6nzamd3txc9gkmjqmq8m4cdq"
+    fresh_bitcoind.rpc.generatetoaddress(1, addr)
+    wait_for(lambda: man.rpc.getinfo()["blockheight"] == 1)
+    assert not man.is_in_log("Detected reorg")
+
+
+def test_clock_skew_warning(revaultd_manager, bitcoind):
+    # Mine a block two hours in our future, as if our clock was late
+    bitcoind.rpc.setmocktime(int(time.time()) + 2 * 60 * 60)
+    bitcoind.generate_block(1)
+    bitcoind.rpc.setmocktime(0)
+
+    revaultd_manager.rpc.call("stop")
+    revaultd_manager.proc.wait(TIMEOUT)
+    revaultd_manager.start()
+    revaultd_manager.wait_for_log("The tip of the chain is .* seconds ahead of our clock")
+
+
+def test_termination_signals(revaultd_manager):
+    # A SIGTERM goes through the same shutdown path as the 'stop' command
+    revaultd_manager.proc.terminate()
+    revaultd_manager.wait_for_logs(
+        [
+            "Received a termination signal, stopping revaultd",
+            "Bitcoind received shutdown.",
+            "Signature fetcher thread received shutdown.",
+        ]
+    )
+    assert revaultd_manager.proc.wait(TIMEOUT) == 0
+
+    # And so does a SIGINT
+    revaultd_manager.start()
+    revaultd_manager.proc.send_signal(signal.SIGINT)
+    revaultd_manager.wait_for_log("Received a termination signal, stopping revaultd")
+    assert revaultd_manager.proc.wait(TIMEOUT) == 0
+    revaultd_manager.start()
+
+
+def test_check_db(revaultd_manager):
+    check_db_cmd = revaultd_manager.cmd_line + ["--check-db"]
+
+    # The database is valid, and checking it doesn't need to stop revaultd
+    res = subprocess.run(check_db_cmd, capture_output=True, text=True, timeout=TIMEOUT)
+    assert res.returncode == 0, res.stderr
+    assert "is valid" in res.stdout
+
+    # A database from the future is rejected, and left untouched
+    revaultd_manager.stop()
+    db_path = os.path.join(revaultd_manager.datadir_with_network, "revaultd.sqlite3")
+    conn = sqlite3.connect(db_path)
+    (version,) = conn.execute("SELECT version FROM version").fetchone()
+    conn.execute("UPDATE version SET version = ?", (version + 1,))
+    conn.commit()
+    res = subprocess.run(check_db_cmd, capture_output=True, text=True, timeout=TIMEOUT)
+    assert res.returncode == 1
+    assert "Unexpected database version" in res.stderr
+    assert conn.execute("SELECT version FROM version").fetchone() == (version + 1,)
+
+    conn.execute("UPDATE version SET version = ?", (version,))
+    conn.commit()
+    conn.close()
+    revaultd_manager.start()
+
+
+def test_backupdb(revaultd_manager):
+    backup_path = os.path.join(revaultd_manager.datadir_with_network, "backup.sqlite3")
+    with pytest.raises(RpcError, match="must be absolute"):
+        revaultd_manager.rpc.call("backupdb", ["backup.sqlite3"])
+
+    # Back up the database while running, the backup is checked
+    assert revaultd_manager.rpc.call("backupdb", [backup_path]) == {"path": backup_path}
+    conn = sqlite3.connect(backup_path)
+    assert conn.execute("PRAGMA integrity_check").fetchone() == ("ok",)
+    conn.close()
+    with pytest.raises(RpcError, match="There is already a file"):
+        revaultd_manager.rpc.call("backupdb", [backup_path])
+
+    # Restoring it at startup brings back the state at the time of the backup
+    revaultd_manager.rpc.call("setmaintenance", [True])
+    assert revaultd_manager.rpc.call("getinfo")["maintenance"]
+    revaultd_manager.stop()
+    cmd_line = revaultd_manager.cmd_line
+    revaultd_manager.cmd_line = cmd_line + ["--restore", backup_path]
+    revaultd_manager.start()
+    revaultd_manager.cmd_line = cmd_line
+    assert revaultd_manager.is_in_log("Restored the database from")
+    assert not revaultd_manager.rpc.call("getinfo")["maintenance"]
+
+    # A corrupted backup is refused
+    revaultd_manager.stop()
+    with open(backup_path, "r+b") as f:
+        f.seek(0)
+        f.write(b"\xff" * 100)
+    res = subprocess.run(
+        cmd_line + ["--restore", backup_path],
+        capture_output=True,
+        text=True,
+        timeout=TIMEOUT,
+    )
+    assert res.returncode == 1
+    revaultd_manager.start()
+
+
+def test_reloadconfig(revaultd_manager):
+    with open(revaultd_manager.conf_file, "r") as f:
+        conf = f.read()
+    assert revaultd_manager.rpc.reloadconfig() == {"applied": [], "ignored": []}
+
+    # The poll intervals are applied at runtime, the confirmations requirement needs a restart
+    with open(revaultd_manager.conf_file, "w") as f:
+        f.write(
+            conf.replace(
+                "coordinator_poll_seconds = 5\n",
+                "coordinator_poll_seconds = 7\nmin_conf = 3\n",
+            ).replace("poll_interval_secs = 10\n", "poll_interval_secs = 12\n")
+        )
+    assert revaultd_manager.rpc.reloadconfig() == {
+        "applied": ["poll_interval_secs", "coordinator_poll_seconds"],
+        "ignored": ["min_conf"],
+    }
+    config = revaultd_manager.rpc.getconfig()
+    assert config["coordinator_poll_seconds"] == 7
+    assert config["bitcoind_config"]["poll_interval_secs"] == 12
+    assert config["min_conf"] == 6
+
+    # A SIGHUP does the same
+    with open(revaultd_manager.conf_file, "w") as f:
+        f.write(conf.replace("log_level = ", "log_level = 'trace'\n#"))
+    revaultd_manager.proc.send_signal(signal.SIGHUP)
+    revaultd_manager.wait_for_logs(
+        [
+            "Received a reload signal, reading the configuration file again",
+            r"Reloaded configuration. Applied changes to \[.*\"log_level\".*\]",
+        ]
+    )
+    assert revaultd_manager.rpc.getconfig()["log_level"] == "trace"
+    assert revaultd_manager.rpc.getconfig()["coordinator_poll_seconds"] == 5
+
+    # An invalid configuration is not applied
+    with open(revaultd_manager.conf_file, "w") as f:
+        f.write(conf.replace("coordinator_poll_seconds = 5", "coordinator_poll_seconds = -1"))
+    with pytest.raises(RpcError, match="Parsing configuration file"):
+        revaultd_manager.rpc.reloadconfig()
+    assert revaultd_manager.rpc.getconfig()["coordinator_poll_seconds"] == 5
+
+    with open(revaultd_manager.conf_file, "w") as f:
+        f.write(conf)
+
+
+def test_bitcoind_version(revaultd_manager, bitcoind):
+    revaultd_manager.wait_for_log("Detected bitcoind version .*, supported versions are")
+
+    # Refuse to start if the version is below the configured minimum
+    revaultd_manager.stop()
+    with open(revaultd_manager.conf_file, "r") as f:
+        conf = f.read()
+    with open(revaultd_manager.conf_file, "w") as f:
+        f.write(
+            conf.replace("[bitcoind_config]\n", "[bitcoind_config]\nmin_version = 990000\n")
+        )
+    TailableProc.start(revaultd_manager)
+    revaultd_manager.wait_for_log(
+        "bitcoind version .* is not supported, the minimum is 99.0 .'min_version'."
+    )
+    revaultd_manager.proc.wait(TIMEOUT)
+
+
+def test_bitcoind_rpc_auth(revaultd_manager, bitcoind):
+    # Use the credentials from the cookie file as if they were bitcoind's 'rpcuser'
+    cookie_path = os.path.join(bitcoind.bitcoin_dir, "regtest", ".cookie")
+    with open(cookie_path, "r") as f:
+        user, password = f.read().split(":")
+    cookie_line = f"cookie_path = '{cookie_path}'\n"
+    revaultd_manager.stop()
+    with open(revaultd_manager.conf_file, "r") as f:
+        conf = f.read()
+    assert cookie_line in conf
+
+    with open(revaultd_manager.conf_file, "w") as f:
+        f.write(
+            conf.replace(
+                cookie_line, f"rpc_user = '{user}'\nrpc_password = '{password}'\n"
+            )
+        )
+    revaultd_manager.start()
+    config = revaultd_manager.rpc.getconfig()["bitcoind_config"]
+    assert config["rpc_user"] == user
+    assert "rpc_password" not in config
+    assert config["cookie_path"] is None
+
+    # Or read the password from a file
+    password_file = os.path.join(revaultd_manager.datadir_with_network, "rpc_password")
+    with open(password_file, "w") as f:
+        f.write(f"{password}\n")
+    revaultd_manager.stop()
+    with open(revaultd_manager.conf_file, "w") as f:
+        f.write(
+            conf.replace(
+                cookie_line,
+                f"rpc_user = '{user}'\nrpc_password_file = '{password_file}'\n",
+            )
+        )
+    revaultd_manager.start()
+    assert (
+        revaultd_manager.rpc.getconfig()["bitcoind_config"]["rpc_password_file"]
+        == password_file
+    )
+
+    # But we need a single way of authenticating
+    revaultd_manager.stop()
+    with open(revaultd_manager.conf_file, "w") as f:
+        f.write(conf.replace(cookie_line, cookie_line + f"rpc_user = '{user}'\n"))
+    TailableProc.start(revaultd_manager)
+    revaultd_manager.wait_for_log(
+        "bitcoind RPC authentication must be set with either a .cookie_path."
+    )
+    revaultd_manager.proc.wait(TIMEOUT)
+
+
+def test_observer_mode(revaultd_manager, bitcoind):
+    # Let it create and fill its watchonly wallet, then only observe it
+    revaultd_manager.stop()
+    with open(revaultd_manager.conf_file, "r") as f:
+        conf = f.read()
+    with open(revaultd_manager.conf_file, "w") as f:
+        f.write(
+            conf.replace("[bitcoind_config]\n", "[bitcoind_config]\nobserver_mode = true\n")
+        )
+    revaultd_manager.start()
+    revaultd_manager.wait_for_log(
+        "Observer mode: the watchonly wallet watches all our addresses"
+    )
+    assert revaultd_manager.rpc.getconfig()["bitcoind_config"]["observer_mode"]
+
+    # We still track the deposits, but don't import the new addresses
+    addr = revaultd_manager.rpc.call("getdepositaddress")["address"]
+    bitcoind.rpc.sendtoaddress(addr, 0.5)
+    revaultd_manager.wait_for_logs(
+        [
+            "Got a new unconfirmed deposit",
+            "Not importing .* descriptors in observer mode",
+        ]
+    )
+
+
+def test_ready_file(revaultd_manager):
+    ready_file = os.path.join(revaultd_manager.datadir_with_network, "ready")
+    revaultd_manager.stop()
+    with open(revaultd_manager.conf_file, "r") as f:
+        conf = f.read()
+    with open(revaultd_manager.conf_file, "w") as f:
+        f.write(
+            conf.replace(
+                "daemon = false\n", f"daemon = false\nready_file = '{ready_file}'\n"
+            )
+        )
+
+    revaultd_manager.start()
+    assert os.path.isfile(ready_file)
+    assert revaultd_manager.rpc.getconfig()["ready_file"] == ready_file
+
+    revaultd_manager.stop()
+    assert not os.path.exists(ready_file)
+
+
+def test_maintenance(revault_network, bitcoind):
+    rn = revault_network
+    rn.deploy(2, 1)
+    stk = rn.stk(0)
+    assert stk.rpc.getinfo()["maintenance"] is False
+
+    vault = rn.fund(8)
+    deposit = f"{vault['txid']}:{vault['vout']}"
+    rn.secure_vault(vault)
+
+    assert stk.rpc.call("setmaintenance", [True]) == {"maintenance": True}
+    assert stk.rpc.getinfo()["maintenance"] is True
+    with pytest.raises(RpcError, match="Maintenance mode is enabled"):
+        stk.rpc.emergency()
+    assert len(bitcoind.rpc.getrawmempool()) == 0
+
+    # We keep syncing in the meantime, and it's persisted across restarts
+    bitcoind.generate_block(1)
+    wait_for(lambda: stk.rpc.getinfo()["blockheight"] == bitcoind.rpc.getblockcount())
+    stk.stop()
+    stk.start()
+    assert stk.rpc.getinfo()["maintenance"] is True
+    with pytest.raises(RpcError, match="Maintenance mode is enabled"):
+        stk.rpc.emergency()
+
+    assert stk.rpc.call("setmaintenance", [False]) == {"maintenance": False}
+    assert stk.rpc.getinfo()["maintenance"] is False
+    stk.rpc.emergency()
+    wait_for(
+        lambda: len(stk.rpc.listvaults(["emergencyvaulting"], [deposit])["vaults"])
+        == 1
+    )
+
+
+def test_gethistory(revault_network):
+    rn = revault_network
+    rn.deploy(2, 1)
+    stk = rn.stk(0)
+    vault = rn.fund(4)
+    deposit = f"{vault['txid']}:{vault['vout']}"
+    rn.secure_vault(vault)
+
+    events = stk.rpc.call("gethistory")["events"]
+    assert [e["kind"] for e in events] == ["deposit", "secure"]
+    assert all(e["outpoint"] == deposit for e in events)
+    assert all(e["amount"] == 4 * COIN for e in events)
+    assert all(e["fee"] is None for e in events)
+    assert events[0]["txid"] == vault["txid"]
+    assert events[0]["date"] <= events[1]["date"]
+
+    # We can filter them by kind
+    events = stk.rpc.call("gethistory", [None, None, None, None, ["secure"]])["events"]
+    assert [e["kind"] for e in events] == ["secure"]
+    assert len(stk.rpc.call("gethistory", [None, None, None, None, []])["events"]) == 2
+    with pytest.raises(RpcError, match="Unknown event kind 'withdrawal'"):
+        stk.rpc.call("gethistory", [None, None, None, None, ["withdrawal"]])
+
+    assert len(stk.rpc.call("gethistory", [None, None, 1])["events"]) == 1
+    assert stk.rpc.call("gethistory", [0, 1])["events"] == []
+
+    # We can page through the events with the returned cursor, even if they all happened the
+    # same second
+    paged_events = []
+    res = stk.rpc.call("gethistory", [None, None, 1])
+    while res["events"]:
+        paged_events += res["events"]
+        res = stk.rpc.call("gethistory", [res["cursor"], None, 1])
+    assert res["cursor"] is None
+    assert paged_events == stk.rpc.call("gethistory")["events"]
+    with pytest.raises(RpcError, match="Invalid cursor 'abc'"):
+        stk.rpc.call("gethistory", ["abc"])
+    with pytest.raises(RpcError, match="is after end timestamp"):
+        stk.rpc.call("gethistory", [2, 1])
+
+
+def test_listwatchtowers(revault_network):
+    rn = revault_network
+    rn.deploy(2, 1)
+    stk = rn.stk(0)
+    with pytest.raises(RpcError, match="This is a stakeholder command"):
+        rn.man(0).rpc.call("listwatchtowers")
+
+    # The watchtower isn't running, so it never acknowledges the secured vault
+    vault = rn.fund(3)
+    rn.secure_vault(vault)
+    wts = stk.rpc.call("listwatchtowers")["watchtowers"]
+    assert len(wts) == 1
+    assert wts[0]["host"] == "127.0.0.1:1"
+    assert wts[0]["synced"] == 0
+    assert wts[0]["unsynced"] == [f"{vault['txid']}:{vault['vout']}"]
+    stk.wait_for_log("Could not connect to watchtower '127.0.0.1:1'")
+
+
+def test_setemergencyaddress(revaultd_stakeholder, revaultd_manager, bitcoind):
+    stk = revaultd_stakeholder
+    old_addr = stk.rpc.getconfig()["stakeholder_config"]["emergency_address"]
+    new_addr = "bcrt1q5k05km5zn2g7kp0c230r0g8znuhlk4yynne3pwklh6xl82ed087sgr902c"
+
+    addr = stk.rpc.call("getdepositaddress")["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 0.5)
+    stk.wait_for_log("Got a new unconfirmed deposit")
+    vault = stk.rpc.listvaults()["vaults"][0]
+    deposit = f"{txid}:{vault['vout']}"
+    bitcoind.generate_block(6, wait_for_mempool=txid)
+    stk.wait_for_deposits([deposit])
+
+    with pytest.raises(RpcError, match="requires 'confirm'"):
+        stk.rpc.setemergencyaddress(new_addr)
+    with pytest.raises(RpcError, match="must be a P2WSH"):
+        stk.rpc.setemergencyaddress(bitcoind.rpc.getnewaddress(), True)
+    with pytest.raises(RpcError, match="already the Emergency address"):
+        stk.rpc.setemergencyaddress(old_addr, True)
+    with pytest.raises(RpcError, match="This is a stakeholder command"):
+        revaultd_manager.rpc.setemergencyaddress(new_addr, True)
+
+    # The existing vault keeps its presigned Emergency transaction
+    res = stk.rpc.setemergencyaddress(new_addr, True)
+    assert res["previous_address"] == old_addr
+    assert res["emergency_address"] == new_addr
+    assert res["vaults"] == [deposit]
+
+    # The new address survives a restart
+    stk.stop()
+    stk.start()
+    assert stk.rpc.getconfig()["stakeholder_config"]["emergency_address"] == new_addr
 
 
 @pytest.mark.skipif(not POSTGRES_IS_SETUP, reason="Needs Postgres for servers db")
@@ -257,6 +1095,10 @@ def test_listpresignedtransactions(revault_network):
     assert man_res["cancel"] is not None
     assert man_res["emergency"] is None
     assert man_res["unvault_emergency"] is None
+    for tx in ("unvault", "cancel", "emergency", "unvault_emergency"):
+        assert stk_res[tx]["is_signed"] is False
+        assert len(stk_res[tx]["signatures"]) == len(stks)
+        assert not any(stk_res[tx]["signatures"].values())
 
     # Sanity check they all generated the same unsigned PSBTs
     for w in stks[1:] + mans:
@@ -279,6 +1121,10 @@ def test_listpresignedtransactions(revault_network):
     assert stk_res["cancel"]["hex"] is not None
     assert stk_res["emergency"]["hex"] is not None
     assert stk_res["unvault_emergency"]["hex"] is not None
+    assert stk_res["unvault"]["is_signed"] is False
+    for tx in ("cancel", "emergency", "unvault_emergency"):
+        assert stk_res[tx]["is_signed"] is True
+        assert all(stk_res[tx]["signatures"].values())
 
     # If the vault gets activated the unvault transaction will then be available
     revault_network.activate_vault(vaultA)
@@ -286,6 +1132,206 @@ def test_listpresignedtransactions(revault_network):
         "presigned_transactions"
     ][0]
     assert man_res["unvault"]["hex"] is not None
+    assert man_res["unvault"]["is_signed"] is True
+
+
+@pytest.mark.skipif(not POSTGRES_IS_SETUP, reason="Needs Postgres for servers db")
+def test_getrawpresignedtxs(revault_network, bitcoind):
+    revault_network.deploy(2, 1)
+    vault = revault_network.fund(0.5)
+    deposit = f"{vault['txid']}:{vault['vout']}"
+    stk = revault_network.stks()[0]
+    man = revault_network.mans()[0]
+    stk.wait_for_deposits([deposit])
+
+    # They are not signed yet
+    with pytest.raises(RpcError, match="not fully signed yet"):
+        stk.rpc.getrawrevocationtxs(deposit)
+    with pytest.raises(RpcError, match="not fully signed yet"):
+        stk.rpc.getrawunvaulttx(deposit)
+    with pytest.raises(RpcError, match="This is a stakeholder command"):
+        man.rpc.getrawrevocationtxs(deposit)
+    with pytest.raises(RpcError, match="not fully signed yet"):
+        stk.rpc.getbroadcasthex(deposit, "cancel")
+    with pytest.raises(RpcError, match="This is a stakeholder command"):
+        man.rpc.getbroadcasthex(deposit, "emergency")
+    with pytest.raises(RpcError, match="Invalid transaction type 'spend'"):
+        stk.rpc.getbroadcasthex(deposit, "spend")
+
+    # Once secured we can get the revocation transactions, which match the ones from
+    # the PSBTs.
+    revault_network.secure_vault(vault)
+    presigned = stk.rpc.listpresignedtransactions([deposit])["presigned_transactions"][0]
+    raw_txs = stk.rpc.getrawrevocationtxs(deposit)
+    assert raw_txs["cancel_tx"] == presigned["cancel"]["hex"]
+    assert raw_txs["emergency_tx"] == presigned["emergency"]["hex"]
+    assert raw_txs["emergency_unvault_tx"] == presigned["unvault_emergency"]["hex"]
+    for tx_type in ("cancel", "emergency", "unvault_emergency"):
+        res = stk.rpc.getbroadcasthex(deposit, tx_type)
+        assert res["hex"] == presigned[tx_type]["hex"]
+        decoded = bitcoind.rpc.decoderawtransaction(res["hex"])
+        assert res["txid"] == decoded["txid"]
+    with pytest.raises(RpcError, match="not fully signed yet"):
+        stk.rpc.getrawunvaulttx(deposit)
+    with pytest.raises(RpcError, match="not fully signed yet"):
+        stk.rpc.getbroadcasthex(deposit, "unvault")
+
+    # Once active the Unvault as well
+    revault_network.activate_vault(vault)
+    presigned = man.rpc.listpresignedtransactions([deposit])["presigned_transactions"][0]
+    assert man.rpc.getrawunvaulttx(deposit)["unvault_tx"] == presigned["unvault"]["hex"]
+    res = man.rpc.getbroadcasthex(deposit, "unvault")
+    assert res["hex"] == presigned["unvault"]["hex"]
+
+
+@pytest.mark.skipif(not POSTGRES_IS_SETUP, reason="Needs Postgres for servers db")
+def test_getfeereserve(revault_network):
+    revault_network.deploy(2, 1)
+    man = revault_network.man(0)
+    assert man.rpc.getfeereserve(10) == {"feerate": 10, "vaults": 0, "reserve": 0}
+    # There is no estimate on regtest
+    with pytest.raises(RpcError, match="No feerate estimate available"):
+        man.rpc.getfeereserve(None, 2)
+    with pytest.raises(RpcError, match="Confirmation target must be between"):
+        man.rpc.getfeereserve(None, 0)
+
+    # Only the active vaults are accounted
+    vaults = revault_network.fundmany([0.5, 0.7])
+    revault_network.secure_vault(vaults[0])
+    assert man.rpc.getfeereserve(1_000)["vaults"] == 0
+    revault_network.activate_vault(vaults[0])
+    reserve = man.rpc.getfeereserve(1_000)
+    assert reserve["vaults"] == 1 and reserve["reserve"] > 0
+    # The presigned transactions already pay more than the minimum feerate
+    assert man.rpc.getfeereserve(1)["reserve"] == 0
+
+
+@pytest.mark.skipif(not POSTGRES_IS_SETUP, reason="Needs Postgres for servers db")
+def test_replaced_unvault(revault_network, bitcoind):
+    rn = revault_network
+    rn.deploy(2, 1)
+    vault = rn.fund(0.5)
+    rn.secure_vault(vault)
+    rn.activate_vault(vault)
+    deposit = f"{vault['txid']}:{vault['vout']}"
+    stk = rn.stks()[0]
+
+    unvault_tx = stk.rpc.getrawunvaulttx(deposit)["unvault_tx"]
+    emer_tx = stk.rpc.getrawrevocationtxs(deposit)["emergency_tx"]
+    unvault_txid = bitcoind.rpc.sendrawtransaction(unvault_tx)
+    for w in rn.participants():
+        wait_for(
+            lambda: len(w.rpc.listvaults(["unvaulting"], [deposit])["vaults"]) == 1
+        )
+
+    # The Unvault signals for RBF, and the Emergency pays a much higher fee: it replaces
+    # the Unvault in the mempool. We must not be stuck in 'unvaulting'.
+    emer_txid = bitcoind.rpc.sendrawtransaction(emer_tx)
+    stk.wait_for_log(
+        f"Unvault transaction '{unvault_txid}' of vault at '{deposit}' left the mempool"
+    )
+    wait_for(
+        lambda: len(stk.rpc.listvaults(["emergencyvaulting"], [deposit])["vaults"]) == 1
+    )
+    bitcoind.generate_block(1, wait_for_mempool=[emer_txid])
+    wait_for(
+        lambda: len(stk.rpc.listvaults(["emergencyvaulted"], [deposit])["vaults"]) == 1
+    )
+
+
+@pytest.mark.skipif(not POSTGRES_IS_SETUP, reason="Needs Postgres for servers db")
+def test_unexpected_unvault_spend(revault_network, bitcoind):
+    rn = revault_network
+    rn.deploy(2, 1)
+    vault = rn.fund(0.5)
+    rn.secure_vault(vault)
+    rn.activate_vault(vault)
+    deposit = f"{vault['txid']}:{vault['vout']}"
+    stk = rn.stks()[0]
+
+    unvault_tx = stk.rpc.getrawunvaulttx(deposit)["unvault_tx"]
+    unvault_txid = bitcoind.rpc.sendrawtransaction(unvault_tx)
+    bitcoind.generate_block(1, wait_for_mempool=unvault_txid)
+    for w in rn.participants():
+        wait_for(
+            lambda: len(w.rpc.listvaults(["unvaulted"], [deposit])["vaults"]) == 1
+        )
+
+    # The revocation transactions are signed with ALL | ANYONECANPAY, so the stakeholders'
+    # signatures of the Cancel remain valid for a transaction with an additional input. It's
+    # neither the Cancel nor a Spend we know of, and it doesn't go through the managers' path.
+    fee_addr = bitcoind.rpc.getnewaddress()
+    fee_txid = bitcoind.rpc.sendtoaddress(fee_addr, 0.0001)
+    bitcoind.generate_block(1, wait_for_mempool=fee_txid)
+    fee_vout = next(
+        d["vout"]
+        for d in bitcoind.rpc.gettransaction(fee_txid)["details"]
+        if d["address"] == fee_addr and d["category"] == "receive"
+    )
+    tx = serializations.CTransaction()
+    serializations.FromHex(tx, stk.rpc.getrawrevocationtxs(deposit)["cancel_tx"])
+    prevout = serializations.COutPoint(int(fee_txid, 16), fee_vout)
+    tx.vin.append(serializations.CTxIn(prevout, nSequence=0xFFFFFFFD))
+    tx.wit.vtxinwit.append(serializations.CTxInWitness())
+    tx_hex = tx.serialize_with_witness().hex()
+    # The Cancel input can't be signed by the wallet, but it already has its witness
+    tx_hex = bitcoind.rpc.signrawtransactionwithwallet(tx_hex)["hex"]
+    spender_txid = bitcoind.rpc.sendrawtransaction(tx_hex)
+    bitcoind.generate_block(1, wait_for_mempool=spender_txid)
+
+    for w in rn.participants():
+        w.wait_for_log(
+            f"Unvault transaction at {unvault_txid}:0 is being spent by '{spender_txid}', "
+            "which is neither a revocation transaction nor a Spend through the managers' path"
+        )
+        wait_for(
+            lambda: len(w.rpc.listvaults(["unexpectedspend"], [deposit])["vaults"]) == 1
+        )
+        vault = w.rpc.listvaults([], [deposit])["vaults"][0]
+        assert vault["spend_txid"] == spender_txid
+        assert w.rpc.getinfo()["unexpected_spends"] == [deposit]
+        events = w.rpc.call(
+            "gethistory", [None, None, None, None, ["unexpectedspend"]]
+        )["events"]
+        assert [(e["outpoint"], e["txid"]) for e in events] == [(deposit, spender_txid)]
+
+
+@pytest.mark.skipif(not POSTGRES_IS_SETUP, reason="Needs Postgres for servers db")
+def test_lazy_unvault_import(revault_network, bitcoind):
+    rn = revault_network
+    rn.deploy(2, 1)
+    for w in rn.participants():
+        w.stop()
+        with open(w.conf_file, "r") as f:
+            conf = f.read()
+        with open(w.conf_file, "w") as f:
+            f.write(
+                conf.replace(
+                    "[bitcoind_config]\n",
+                    "[bitcoind_config]\nlazy_unvault_import = true\n",
+                )
+            )
+        w.start()
+
+    vault = rn.fund(0.5)
+    deposit = f"{vault['txid']}:{vault['vout']}"
+    rn.secure_vault(vault)
+    # The Unvault descriptor is only imported once the vault is secured
+    for w in rn.participants():
+        w.wait_for_log(f"Importing unvault descriptor for vault at '{deposit}'")
+    rn.activate_vault(vault)
+
+    unvault_tx = rn.stks()[0].rpc.getrawunvaulttx(deposit)["unvault_tx"]
+    unvault_txid = bitcoind.rpc.sendrawtransaction(unvault_tx)
+    for w in rn.participants():
+        wait_for(
+            lambda: len(w.rpc.listvaults(["unvaulting"], [deposit])["vaults"]) == 1
+        )
+    bitcoind.generate_block(1, wait_for_mempool=unvault_txid)
+    for w in rn.participants():
+        wait_for(
+            lambda: len(w.rpc.listvaults(["unvaulted"], [deposit])["vaults"]) == 1
+        )
 
 
 @pytest.mark.skipif(not POSTGRES_IS_SETUP, reason="Needs Postgres for servers db")
@@ -384,6 +1430,8 @@ def test_listspendtxs(revault_network, bitcoind):
             lambda: len(w.rpc.listvaults(["spent"], deposits)["vaults"])
             == len(deposits)
         )
+        for v in w.rpc.listvaults(["spent"], deposits)["vaults"]:
+            assert v["spend_txid"] == spend_psbt.tx.hash
 
     vaults = rn.fundmany([3, 4, 5])
     for v in vaults:
@@ -493,6 +1541,26 @@ def test_listonchaintransactions(revault_network):
         assert res["unvault_emergency"] is None
         assert res["spend"] is None
 
+    # The deposit was not paid by the watchonly wallet, bitcoind can't tell its fee
+    res = rn.stk(0).rpc.listonchaintransactions([depositC])["onchain_transactions"][0]
+    assert res["deposit"]["confirmations"] > 0
+    assert res["deposit"]["fee"] is None
+    assert res["deposit"]["feerate"] is None
+
+    # We can get them a page at a time, and without the hex
+    # (The Cancel created a new vault, too)
+    stk = rn.stk(0)
+    total = len(stk.rpc.listvaults()["vaults"])
+    assert total > 2
+    res = stk.rpc.call("listonchaintransactions", [None, 0, 2, False])
+    assert res["total"] == total and res["truncated"] is True
+    assert len(res["onchain_transactions"]) == 2
+    assert "hex" not in res["onchain_transactions"][0]["deposit"]
+    res = stk.rpc.call("listonchaintransactions", [None, 2, total])
+    assert res["total"] == total and res["truncated"] is False
+    assert len(res["onchain_transactions"]) == total - 2
+    assert res["onchain_transactions"][0]["deposit"]["hex"] is not None
+
 
 def psbt_add_input(psbt_str):
     psbt = serializations.PSBT()
@@ -1468,9 +2536,35 @@ def test_getspendtx(revault_network, bitcoind):
 
     # The amount was not enough to afford a change output, everything went to
     # fees.
+    res = man.rpc.getspendtx(spent_vaults, destination, feerate)
     psbt = serializations.PSBT()
-    psbt.deserialize(man.rpc.getspendtx(spent_vaults, destination, feerate)["spend_tx"])
+    psbt.deserialize(res["spend_tx"])
     assert len(psbt.inputs) == 1 and len(psbt.outputs) == 2
+    assert res["feerate"] == feerate
+    assert res["fees"] >= fees
+
+    # Without a feerate, we need bitcoind to be able to estimate one
+    with pytest.raises(RpcError, match="No feerate estimate available"):
+        man.rpc.getspendtx(spent_vaults, destination)
+    # Unless we configured a fallback feerate
+    man.stop()
+    with open(man.conf_file, "r") as f:
+        conf = f.read()
+    with open(man.conf_file, "w") as f:
+        f.write(
+            conf.replace(
+                "[bitcoind_config]\n", "[bitcoind_config]\nfallback_feerate_sat_vb = 3\n"
+            )
+        )
+    man.start()
+    assert man.rpc.getspendtx(spent_vaults, destination)["feerate"] == 3
+    man.wait_for_log("using the fallback feerate of 3 sat/vB")
+    man.stop()
+    with open(man.conf_file, "w") as f:
+        f.write(conf)
+    man.start()
+    with pytest.raises(RpcError, match="Confirmation target must be between"):
+        man.rpc.getspendtx(spent_vaults, destination, None, 0)
 
     # But if we decrease it enough, it'll create a change output
     destinations = {addr: vault["amount"] - fees - 1_000_000}
@@ -1485,12 +2579,12 @@ def test_getspendtx(revault_network, bitcoind):
         RpcError,
         match="Required feerate .* is significantly higher than actual feerate",
     ):
-        man.rpc.getspendtx(spent_vaults, destinations, 100_000)
+        man.rpc.getspendtx(spent_vaults, destinations, 10_000)
 
-    # We'll stubbornly refuse they shoot themselves in the foot
+    # We'll stubbornly refuse a feerate bitcoind would refuse to broadcast
     with pytest.raises(
         RpcError,
-        match="Fees larger than 20000000 sats",
+        match="Feerate .* is above the maximum of '10000'",
     ):
         destinations = {addr: vault["amount"] // 10}
         man.rpc.getspendtx(spent_vaults, destinations, 100_000)
@@ -1524,6 +2618,22 @@ def test_getspendtx(revault_network, bitcoind):
             len(psbt.inputs) == len(deposits) and len(psbt.outputs) == 2
         ), "unexpected change output"
 
+    # But past a certain size, even the maximum feerate gets us insane fees. We'll
+    # stubbornly refuse they shoot themselves in the foot.
+    vaults = revault_network.fundmany([10] * 5)
+    revault_network.activate_fresh_vaults(vaults)
+    many_deposits = deposits + [f"{v['txid']}:{v['vout']}" for v in vaults]
+    sent_amount = (
+        sum(amounts)
+        + sum(v["amount"] for v in vaults)
+        - revault_network.compute_spendtx_fees(10_000, len(many_deposits), 1)
+    )
+    with pytest.raises(
+        RpcError,
+        match="Fees larger than 20000000 sats",
+    ):
+        man.rpc.getspendtx(many_deposits, {addr: sent_amount}, 10_000)
+
     # And we can spend to many destinations
     deposits = [deposit]
     destinations = {}
@@ -2659,6 +3769,48 @@ def test_retrieve_vault_status(revault_network, bitcoind):
     )
 
 
+@pytest.mark.skipif(not POSTGRES_IS_SETUP, reason="Needs Postgres for servers db")
+def test_unvault_confirmed_while_down(revault_network, bitcoind):
+    """Test we catch up with an Unvault that got confirmed while we were not running."""
+    CSV = 12
+    revault_network.deploy(2, 1, csv=CSV)
+    # We don't use stks() here as we need a reference to the actual list in order to
+    # modify it.
+    stks = revault_network.stk_wallets
+    man = revault_network.man(0)
+
+    vault = revault_network.fund(0.5)
+    revault_network.secure_vault(vault)
+    revault_network.activate_vault(vault)
+    deposits = [f"{vault['txid']}:{vault['vout']}"]
+
+    # Stop one of the stakeholders, and Unvault the vault behind its back.
+    stk = stks.pop(0)
+    stk.stop()
+    destinations = {bitcoind.rpc.getnewaddress(): vault["amount"] // 2}
+    spend_tx = man.rpc.getspendtx(deposits, destinations, 1)["spend_tx"]
+    spend_tx = man.man_keychain.sign_spend_psbt(spend_tx, [vault["derivation_index"]])
+    man.rpc.updatespendtx(spend_tx)
+    spend_psbt = serializations.PSBT()
+    spend_psbt.deserialize(spend_tx)
+    spend_psbt.tx.calc_sha256()
+    man.rpc.setspendtx(spend_psbt.tx.hash)
+    bitcoind.generate_block(1, wait_for_mempool=len(deposits))
+    for w in [man] + stks:
+        wait_for(
+            lambda: len(w.rpc.listvaults(["unvaulted"], deposits)["vaults"])
+            == len(deposits)
+        )
+
+    # Once restarted, it should notice the Unvault was confirmed in the meantime.
+    stks.insert(0, stk)
+    stk.start()
+    wait_for(
+        lambda: len(stk.rpc.listvaults(["unvaulted"], deposits)["vaults"])
+        == len(deposits)
+    )
+
+
 @pytest.mark.skipif(not POSTGRES_IS_SETUP, reason="Needs Postgres for servers db")
 def test_sigfetcher(revault_network, bitcoind, executor):
     rn = revault_network
@@ -2791,3 +3943,30 @@ def test_getserverstatus(revault_network, bitcoind):
             assert not cosigner["reachable"]
             # Sadly we don't persist the cosigner ports
             assert cosigner["host"].startswith("127.0.0.1:")
+
+
+def test_listutxos(revaultd_manager, bitcoind):
+    assert revaultd_manager.rpc.call("listutxos", ["deposit"])["utxos"] == []
+
+    amount_sent = 0.42
+    addr = revaultd_manager.rpc.call("getdepositaddress")["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, amount_sent)
+    revaultd_manager.wait_for_log("Got a new unconfirmed deposit")
+    utxos = revaultd_manager.rpc.call("listutxos", ["deposit"])["utxos"]
+    assert len(utxos) == 1
+    assert utxos[0]["outpoint"].startswith(txid)
+    assert utxos[0]["amount"] == amount_sent * 10 ** 8
+    assert utxos[0]["address"] == addr
+    assert utxos[0]["confirmations"] == 0
+
+    bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(
+        lambda: revaultd_manager.rpc.call("listutxos", ["deposit"])["utxos"][0][
+            "confirmations"
+        ]
+        == 1
+    )
+
+    # Other categories don't see it
+    assert revaultd_manager.rpc.call("listutxos", ["unvault"])["utxos"] == []
+    assert revaultd_manager.rpc.call("listutxos", ["change"])["utxos"] == []